@@ -0,0 +1,761 @@
+use argh::FromArgs;
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+use std::collections::BTreeMap;
+use std::error;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use store::store::{DedupReport, DiffStat, Store, TimeFrame};
+
+use crate::{crash, Config};
+
+#[derive(FromArgs)]
+/// Stashes every change of the files below the watch path.
+pub struct Args {
+    /// path of the config file, config.toml by default
+    #[argh(option, default = "String::from(\"config.toml\")")]
+    pub config: String,
+
+    /// skip dotfiles and dot-directories unless a pattern names them
+    #[argh(switch)]
+    pub exclude_hidden: bool,
+
+    /// record without the TUI, SIGUSR1 records every changed file right away
+    #[argh(switch)]
+    pub headless: bool,
+
+    /// watch the path even if it contains the root or home directory
+    #[argh(switch)]
+    pub force: bool,
+
+    /// only show the versions since then, e.g. 9am, 2h (ago) or 2021-05-01,
+    /// as an extra tab selected on startup
+    #[argh(option)]
+    pub since: Option<String>,
+
+    /// stream every recorded version to stdout, only jsonl for now,
+    /// implies --headless
+    #[argh(option)]
+    pub output: Option<OutputFormat>,
+
+    /// write a local report of a fatal error to attach to a bug report,
+    /// like setting AUTO_STASH_CRASH_REPORT
+    #[argh(switch)]
+    pub crash_report: bool,
+
+    /// open stores written by a newer auto-stash read-only, as well as
+    /// this one can read them
+    #[argh(switch)]
+    pub read_newer: bool,
+
+    #[argh(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    /// one JSON object per line
+    Jsonl,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<OutputFormat, String> {
+        match format {
+            "jsonl" => Ok(OutputFormat::Jsonl),
+            _ => Err(format!("unknown output format {}, expected jsonl", format)),
+        }
+    }
+}
+
+impl Args {
+    /// the TUI would write to stdout as well, so an output runs headless
+    pub fn is_headless(&self) -> bool {
+        self.headless || self.output.is_some()
+    }
+
+    pub fn wants_crash_report(&self) -> bool {
+        self.crash_report || crash::requested_by_env()
+    }
+
+    /// the tab `--since` asks for, labeled with the given time
+    pub fn launch_tab(&self) -> Result<Option<(String, TimeFrame)>, Box<dyn error::Error>> {
+        let since = match &self.since {
+            Some(since) => since,
+            None => return Ok(None),
+        };
+        let time_frame = since_time_frame(since, Local::now())?;
+        Ok(Some((format!("since {}", since.trim()), time_frame)))
+    }
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+pub enum Command {
+    Changed(Changed),
+    Stat(Stat),
+    Dedupe(Dedupe),
+    Relocate(Relocate),
+    Fsck(Fsck),
+    Meta(Meta),
+    Apply(Apply),
+    Export(Export),
+    Disable(Disable),
+    Enable(Enable),
+    Trim(Trim),
+    Fork(Fork),
+    Recent(Recent),
+    Analyze(Analyze),
+}
+
+#[derive(FromArgs)]
+/// Lists the files that changed in a time window.
+#[argh(subcommand, name = "changed")]
+pub struct Changed {
+    /// start of the window, e.g. 9am, 14:30, 2h (ago) or 2021-05-01
+    #[argh(option)]
+    pub since: String,
+    /// end of the window in the same format, now by default
+    #[argh(option)]
+    pub until: Option<String>,
+}
+
+#[derive(FromArgs)]
+/// Lists the latest versions across all files, the latest first.
+#[argh(subcommand, name = "recent")]
+pub struct Recent {
+    /// number of versions to list, 20 by default
+    #[argh(option, default = "20")]
+    pub limit: usize,
+}
+
+#[derive(FromArgs)]
+/// Sums up the changed lines per day and the most churned files.
+#[argh(subcommand, name = "stat")]
+pub struct Stat {
+    /// number of files to list, 10 by default
+    #[argh(option, default = "10")]
+    pub top: usize,
+}
+
+#[derive(FromArgs)]
+/// Estimates what keeping each recorded line only once would save, without
+/// changing the stores.
+#[argh(subcommand, name = "analyze")]
+pub struct Analyze {}
+
+#[derive(FromArgs)]
+/// Merges consecutive versions with the same content into one.
+#[argh(subcommand, name = "dedupe")]
+pub struct Dedupe {
+    /// only the versions of this file, every file by default
+    #[argh(option)]
+    pub path: Option<String>,
+}
+
+#[derive(FromArgs)]
+/// Moves the history of the files below one path to another, e.g. after
+/// the project was moved.
+#[argh(subcommand, name = "relocate")]
+pub struct Relocate {
+    /// where the files were tracked
+    #[argh(positional)]
+    pub from: String,
+    /// where they are now
+    #[argh(positional)]
+    pub to: String,
+}
+
+#[derive(FromArgs)]
+/// Copies the history of a file to another one, e.g. after copying the file
+/// to start a variant of it.
+#[argh(subcommand, name = "fork")]
+pub struct Fork {
+    /// the file with the history
+    #[argh(positional)]
+    pub src: String,
+    /// the copy, it starts out with the same versions
+    #[argh(positional)]
+    pub dst: String,
+    /// forget the history the copy has already
+    #[argh(switch)]
+    pub overwrite: bool,
+}
+
+#[derive(FromArgs)]
+/// Checks that the store is consistent.
+#[argh(subcommand, name = "fsck")]
+pub struct Fsck {
+    /// repair what can be repaired safely, after a backup of the store
+    #[argh(switch)]
+    pub fix: bool,
+    /// only check that the store files are readable, without opening them,
+    /// fails if one is corrupt
+    #[argh(switch)]
+    pub preflight: bool,
+}
+
+#[derive(FromArgs)]
+/// Stamps a version with key=value pairs, lists its metadata without any.
+#[argh(subcommand, name = "meta")]
+pub struct Meta {
+    /// the tracked file
+    #[argh(positional)]
+    pub path: String,
+    /// the version, 0 is the baseline
+    #[argh(positional)]
+    pub version: usize,
+    /// key=value pairs to set
+    #[argh(positional)]
+    pub pairs: Vec<String>,
+}
+
+#[derive(FromArgs)]
+/// Records a unified diff applied to a file as its new version, the file stays untouched.
+#[argh(subcommand, name = "apply")]
+pub struct Apply {
+    /// the tracked file
+    #[argh(positional)]
+    pub path: String,
+    /// the file holding the unified diff
+    #[argh(positional)]
+    pub patch: String,
+}
+
+#[derive(FromArgs)]
+/// Stops recording a file until it is enabled again, its history stays.
+#[argh(subcommand, name = "disable")]
+pub struct Disable {
+    /// the tracked file, or a glob relative to the watch path with --pattern
+    #[argh(positional)]
+    pub target: String,
+    /// disable every file matching the glob, files created later included
+    #[argh(switch)]
+    pub pattern: bool,
+}
+
+#[derive(FromArgs)]
+/// Records a disabled file again, starting with what changed in the meantime.
+#[argh(subcommand, name = "enable")]
+pub struct Enable {
+    /// the disabled file, or a disabled glob with --pattern
+    #[argh(positional)]
+    pub target: String,
+    /// enable the files of a glob given to disable --pattern
+    #[argh(switch)]
+    pub pattern: bool,
+}
+
+#[derive(FromArgs)]
+/// Forgets the files that aren't on disk anymore, removed or not, with
+/// their whole history.
+#[argh(subcommand, name = "trim")]
+pub struct Trim {
+    /// only the files below this path, can be repeated, the roots of the
+    /// stores by default
+    #[argh(option)]
+    pub root: Vec<String>,
+    /// list the files instead of forgetting them
+    #[argh(switch)]
+    pub dry_run: bool,
+}
+
+#[derive(FromArgs)]
+/// Writes the versions of every file to stdout, one row per version.
+#[argh(subcommand, name = "export")]
+pub struct Export {
+    /// only csv for now
+    #[argh(option)]
+    pub format: ExportFormat,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    /// path, version, timestamp, added, removed, modified and label
+    Csv,
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<ExportFormat, String> {
+        match format {
+            "csv" => Ok(ExportFormat::Csv),
+            _ => Err(format!("unknown export format {}, expected csv", format)),
+        }
+    }
+}
+
+impl Command {
+    pub fn run(&self, config: &Config) -> Result<(), Box<dyn error::Error>> {
+        match self {
+            Command::Changed(changed) => changed.run(config),
+            Command::Stat(stat) => stat.run(config),
+            Command::Dedupe(dedupe) => dedupe.run(config),
+            Command::Relocate(relocate) => relocate.run(config),
+            Command::Fsck(fsck) => fsck.run(config),
+            Command::Meta(meta) => meta.run(config),
+            Command::Apply(apply) => apply.run(config),
+            Command::Export(export) => export.run(config),
+            Command::Disable(disable) => disable.run(config),
+            Command::Enable(enable) => enable.run(config),
+            Command::Trim(trim) => trim.run(config),
+            Command::Fork(fork) => fork.run(config),
+            Command::Recent(recent) => recent.run(config),
+            Command::Analyze(analyze) => analyze.run(config),
+        }
+    }
+}
+
+impl Dedupe {
+    fn run(&self, config: &Config) -> Result<(), Box<dyn error::Error>> {
+        let merged = match &self.path {
+            Some(path) => config
+                .open_store_for(path)?
+                .merge_consecutive_duplicates(Some(path))?,
+            None => config.open_stores()?.all().try_fold(0, |merged, store| {
+                let store_merged = store.lock().unwrap().merge_consecutive_duplicates(None)?;
+                Ok::<usize, Box<dyn error::Error>>(merged + store_merged)
+            })?,
+        };
+        println!("Merged {} duplicate versions", merged);
+        Ok(())
+    }
+}
+
+impl Stat {
+    fn run(&self, config: &Config) -> Result<(), Box<dyn error::Error>> {
+        let mut stat = DiffStat::default();
+        for store in config.read_stores()?.all() {
+            stat += store.lock().unwrap().diff_stat();
+        }
+
+        println!("Total  {}", stat.total);
+        println!();
+        stat.per_day.iter().for_each(|(date, summary)| {
+            println!("{}  {}", date, summary);
+        });
+        println!();
+        stat.top_files(self.top).iter().for_each(|(path, summary)| {
+            println!("{:>5}  {}  ({})", summary.churn(), path, summary);
+        });
+        Ok(())
+    }
+}
+
+impl Analyze {
+    fn run(&self, config: &Config) -> Result<(), Box<dyn error::Error>> {
+        let mut report = DedupReport::default();
+        for store in config.read_stores()?.all() {
+            report += store.lock().unwrap().dedup_report();
+        }
+
+        let share = |part: u64, whole: u64| match whole {
+            0 => 0.0,
+            _ => part as f64 * 100.0 / whole as f64,
+        };
+        println!(
+            "Lines         {}, {} unique ({:.1}%)",
+            report.lines,
+            report.unique_lines,
+            share(report.unique_lines as u64, report.lines as u64)
+        );
+        println!(
+            "Bytes         {}, {} unique ({:.1}%)",
+            report.bytes,
+            report.unique_bytes,
+            share(report.unique_bytes, report.bytes)
+        );
+        println!(
+            "Interning     would save about {} bytes ({:.1}%)",
+            report.saved_bytes(),
+            share(report.saved_bytes(), report.bytes)
+        );
+        Ok(())
+    }
+}
+
+impl Fsck {
+    fn run(&self, config: &Config) -> Result<(), Box<dyn error::Error>> {
+        if self.preflight {
+            return Fsck::preflight(config);
+        }
+        for store in config.open_stores()?.all() {
+            self.check(&mut store.lock().unwrap())?;
+        }
+        Ok(())
+    }
+
+    /// a store of a newer auto-stash fails with its own error
+    fn preflight(config: &Config) -> Result<(), Box<dyn error::Error>> {
+        let store_paths = std::iter::once(&config.store_path)
+            .chain(config.stores.iter().map(|route| &route.store_path));
+        let mut corrupt = 0;
+        for store_path in store_paths {
+            let path = std::path::Path::new(store_path);
+            if !path.exists() {
+                println!("{}: not created yet", store_path);
+            } else if Store::is_corrupt(path)? {
+                println!("{}: corrupt", store_path);
+                corrupt += 1;
+            } else {
+                println!("{}: ok", store_path);
+            }
+        }
+        if corrupt > 0 {
+            return Err(format!("{} corrupt stores", corrupt).into());
+        }
+        Ok(())
+    }
+
+    fn check(&self, store: &mut Store) -> Result<(), Box<dyn error::Error>> {
+        if !self.fix {
+            let issues = store.verify_integrity();
+            if issues.is_empty() {
+                println!("No issues");
+            }
+            issues.iter().for_each(|issue| println!("{}", issue));
+            return Ok(());
+        }
+
+        let report = store.repair()?;
+        if let Some(backup) = &report.backup {
+            println!("Backed up the store to {}", backup);
+        }
+        report
+            .repaired
+            .iter()
+            .for_each(|issue| println!("Repaired  {}", issue));
+        report
+            .remaining
+            .iter()
+            .for_each(|issue| println!("Left      {}", issue));
+        if report.repaired.is_empty() && report.remaining.is_empty() {
+            println!("No issues");
+        }
+        Ok(())
+    }
+}
+
+impl Meta {
+    fn run(&self, config: &Config) -> Result<(), Box<dyn error::Error>> {
+        let mut store = config.open_store_for(&self.path)?;
+        if self.pairs.is_empty() {
+            let meta = store.version_meta(&self.path, self.version)?;
+            for (key, value) in meta.into_iter().collect::<BTreeMap<String, String>>() {
+                println!("{}={}", key, value);
+            }
+            return Ok(());
+        }
+
+        let meta = self
+            .pairs
+            .iter()
+            .map(|pair| {
+                pair.split_once('=')
+                    .ok_or_else(|| format!("expected key=value, got {}", pair))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        store.set_version_meta(&self.path, self.version, &meta)
+    }
+}
+
+impl Apply {
+    fn run(&self, config: &Config) -> Result<(), Box<dyn error::Error>> {
+        let patch = std::fs::read_to_string(&self.patch)?;
+        let version = config
+            .open_store_for(&self.path)?
+            .apply_patch(&self.path, &patch)?;
+        println!("Recorded the patch as version {} of {}", version, self.path);
+        Ok(())
+    }
+}
+
+impl Disable {
+    fn run(&self, config: &Config) -> Result<(), Box<dyn error::Error>> {
+        if !self.pattern {
+            let target = config.tracked_path(&self.target);
+            return config.open_store_for(&target)?.disable(&target);
+        }
+        for store in config.open_stores()?.all() {
+            store.lock().unwrap().disable_matching(&self.target)?;
+        }
+        Ok(())
+    }
+}
+
+impl Enable {
+    fn run(&self, config: &Config) -> Result<(), Box<dyn error::Error>> {
+        let mut recorded = vec![];
+        if self.pattern {
+            for store in config.open_stores()?.all() {
+                recorded.extend(store.lock().unwrap().enable_matching(&self.target)?);
+            }
+        } else {
+            let target = config.tracked_path(&self.target);
+            if config.open_store_for(&target)?.enable(&target)? {
+                recorded.push(target);
+            }
+        }
+        recorded.iter().for_each(|path| {
+            println!("Recorded what changed in {} while it was disabled", path);
+        });
+        Ok(())
+    }
+}
+
+impl Trim {
+    fn run(&self, config: &Config) -> Result<(), Box<dyn error::Error>> {
+        let roots = if self.root.is_empty() {
+            std::iter::once(PathBuf::from(&config.watch_path))
+                .chain(config.store_roots())
+                .collect()
+        } else {
+            self.root.iter().map(PathBuf::from).collect::<Vec<_>>()
+        };
+        let roots = roots.iter().map(PathBuf::as_path).collect::<Vec<_>>();
+        let mut trimmed = 0;
+        for store in config.open_stores()?.all() {
+            let mut store = store.lock().unwrap();
+            if self.dry_run {
+                let strays = store.working_set_strays(&roots);
+                strays.iter().for_each(|path| println!("{}", path));
+                trimmed += strays.len();
+            } else {
+                trimmed += store.trim_to_working_set(&roots)?;
+            }
+        }
+        if self.dry_run {
+            println!("Would forget {} files", trimmed);
+        } else {
+            println!("Forgot {} files", trimmed);
+        }
+        Ok(())
+    }
+}
+
+impl Export {
+    fn run(&self, config: &Config) -> Result<(), Box<dyn error::Error>> {
+        let stdout = std::io::stdout();
+        let mut writer = std::io::BufWriter::new(stdout.lock());
+        match self.format {
+            ExportFormat::Csv => {
+                for (index, store) in config.read_stores()?.all().enumerate() {
+                    let store = store.lock().unwrap();
+                    match index {
+                        0 => store.export_csv(&mut writer)?,
+                        _ => store.export_csv_rows(&mut writer)?,
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Relocate {
+    fn run(&self, config: &Config) -> Result<(), Box<dyn error::Error>> {
+        let moved = config
+            .open_store_for(&self.from)?
+            .rewrite_paths(self.from.as_str(), self.to.as_str())?;
+        println!("Moved the history of {} files", moved);
+        Ok(())
+    }
+}
+
+impl Fork {
+    fn run(&self, config: &Config) -> Result<(), Box<dyn error::Error>> {
+        let stores = config.open_stores()?;
+        let store = stores.for_path(&self.src);
+        if !std::ptr::eq(store, stores.for_path(&self.dst)) {
+            return Err(
+                format!("{} and {} are kept in different stores", self.src, self.dst).into(),
+            );
+        }
+        let mut store = store.lock().unwrap();
+        if self.overwrite && store.is_tracked(&self.dst) {
+            store.purge(&self.dst)?;
+        }
+        store.fork(&self.src, &self.dst)?;
+        println!("Forked the history of {} to {}", self.src, self.dst);
+        Ok(())
+    }
+}
+
+impl Changed {
+    fn run(&self, config: &Config) -> Result<(), Box<dyn error::Error>> {
+        let now = Local::now();
+        let since = parse_time(self.since.as_str(), now)?;
+        let until = match &self.until {
+            Some(until) => parse_time(until.as_str(), now)?,
+            None => to_system_time(now),
+        };
+
+        let mut changed = Vec::new();
+        for store in config.read_stores()?.all() {
+            changed.extend(store.lock().unwrap().changed_files_between(since, until));
+        }
+        changed.sort();
+        print_changed(&changed);
+        Ok(())
+    }
+}
+
+impl Recent {
+    fn run(&self, config: &Config) -> Result<(), Box<dyn error::Error>> {
+        let recent = config.read_stores()?.recent(self.limit);
+        if recent.is_empty() {
+            println!("No versions");
+        }
+        for (path, version, timestamp) in recent {
+            let timestamp: DateTime<Local> = timestamp.into();
+            println!(
+                "{}  {:>5}  {}",
+                timestamp.format("%Y-%m-%d %H:%M:%S"),
+                version,
+                path
+            );
+        }
+        Ok(())
+    }
+}
+
+fn print_changed(changed: &[(String, usize)]) {
+    if changed.is_empty() {
+        println!("No changes");
+    }
+    changed.iter().for_each(|(path, versions)| {
+        println!("{:>5}  {}", versions, path);
+    });
+}
+
+fn to_system_time(time: DateTime<Local>) -> SystemTime {
+    UNIX_EPOCH + std::time::Duration::from_secs(time.timestamp().max(0) as u64)
+}
+
+///
+/// understands durations before `now` (`30m`, `2h`, `1d`), times of today
+/// (`9am`, `9:30pm`, `14:30`), dates (`2021-05-01`) and both combined
+/// (`2021-05-01 14:30`)
+///
+fn parse_time(input: &str, now: DateTime<Local>) -> Result<SystemTime, Box<dyn error::Error>> {
+    let input = input.trim().to_lowercase();
+    let invalid = || format!("couldn't understand the time {}", input);
+
+    if let Some(ago) = parse_duration(input.as_str()) {
+        return Ok(to_system_time(now - ago));
+    }
+    let local = if let Ok(datetime) = NaiveDateTime::parse_from_str(&input, "%Y-%m-%d %H:%M") {
+        datetime
+    } else if let Ok(date) = NaiveDate::parse_from_str(&input, "%Y-%m-%d") {
+        date.and_hms(0, 0, 0)
+    } else {
+        now.naive_local()
+            .date()
+            .and_time(parse_time_of_day(&input).ok_or_else(invalid)?)
+    };
+
+    Local
+        .from_local_datetime(&local)
+        .earliest()
+        .map(to_system_time)
+        .ok_or_else(|| invalid().into())
+}
+
+/// the versions between `since` and `now`
+fn since_time_frame(since: &str, now: DateTime<Local>) -> Result<TimeFrame, Box<dyn error::Error>> {
+    let seconds = to_system_time(now)
+        .duration_since(parse_time(since, now)?)
+        .map_err(|_| format!("{} lies in the future", since))?
+        .as_secs();
+    Ok(TimeFrame::SECONDS(seconds.max(1) as i64))
+}
+
+fn parse_duration(input: &str) -> Option<Duration> {
+    let (amount, unit) = input.split_at(input.len().checked_sub(1)?);
+    let amount = amount.parse::<i64>().ok()?;
+    match unit {
+        "m" => Some(Duration::minutes(amount)),
+        "h" => Some(Duration::hours(amount)),
+        "d" => Some(Duration::days(amount)),
+        _ => None,
+    }
+}
+
+fn parse_time_of_day(input: &str) -> Option<NaiveTime> {
+    let (clock, offset) = if let Some(clock) = input.strip_suffix("am") {
+        (clock, 0)
+    } else if let Some(clock) = input.strip_suffix("pm") {
+        (clock, 12)
+    } else {
+        return NaiveTime::parse_from_str(input, "%H:%M").ok();
+    };
+
+    let (hour, minute) = match clock.trim().split_once(':') {
+        Some((hour, minute)) => (hour.parse::<u32>().ok()?, minute.parse::<u32>().ok()?),
+        None => (clock.trim().parse::<u32>().ok()?, 0),
+    };
+    if hour == 0 || hour > 12 {
+        return None;
+    }
+    NaiveTime::from_hms_opt(hour % 12 + offset, minute, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_times_relative_to_now() {
+        let now = Local.ymd(2021, 5, 3).and_hms(15, 0, 0);
+        let at = |day: u32, hour: u32, minute: u32| {
+            to_system_time(Local.ymd(2021, 5, day).and_hms(hour, minute, 0))
+        };
+
+        assert_eq!(parse_time("9am", now).unwrap(), at(3, 9, 0));
+        assert_eq!(parse_time("12am", now).unwrap(), at(3, 0, 0));
+        assert_eq!(parse_time("9:30 PM", now).unwrap(), at(3, 21, 30));
+        assert_eq!(parse_time("14:30", now).unwrap(), at(3, 14, 30));
+        assert_eq!(parse_time("2h", now).unwrap(), at(3, 13, 0));
+        assert_eq!(parse_time("1d", now).unwrap(), at(2, 15, 0));
+        assert_eq!(parse_time("2021-05-01", now).unwrap(), at(1, 0, 0));
+        assert_eq!(parse_time("2021-05-01 08:15", now).unwrap(), at(1, 8, 15));
+        assert!(parse_time("13pm", now).is_err());
+        assert!(parse_time("yesterday", now).is_err());
+    }
+
+    #[test]
+    fn since_becomes_a_time_frame_up_to_now() {
+        let now = Local.ymd(2021, 5, 3).and_hms(15, 0, 0);
+        assert_eq!(
+            since_time_frame("9am", now).unwrap(),
+            TimeFrame::SECONDS(6 * 60 * 60)
+        );
+        assert_eq!(
+            since_time_frame("1d", now).unwrap(),
+            TimeFrame::SECONDS(24 * 60 * 60)
+        );
+        assert!(since_time_frame("2021-05-04", now).is_err());
+    }
+
+    #[test]
+    fn output_implies_headless() {
+        let args = Args::from_args(&["auto_stash"], &["--output", "jsonl"]).unwrap();
+        assert_eq!(args.output, Some(OutputFormat::Jsonl));
+        assert!(args.is_headless());
+        assert!(!Args::from_args(&["auto_stash"], &[]).unwrap().is_headless());
+        assert!(Args::from_args(&["auto_stash"], &["--output", "csv"]).is_err());
+    }
+
+    #[test]
+    fn export_takes_a_known_format() {
+        let args = Args::from_args(&["auto_stash"], &["export", "--format", "csv"]).unwrap();
+        assert!(matches!(
+            args.command,
+            Some(Command::Export(Export {
+                format: ExportFormat::Csv
+            }))
+        ));
+        assert!(Args::from_args(&["auto_stash"], &["export", "--format", "xlsx"]).is_err());
+    }
+}
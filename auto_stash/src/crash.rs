@@ -0,0 +1,299 @@
+//!
+//! a local report of a fatal error or a panic to attach to a bug report,
+//! written with `--crash-report` or `AUTO_STASH_CRASH_REPORT` set. it
+//! holds the error chain, the latest recorded versions and watch events,
+//! the config file and the size of the stores. nothing leaves the machine.
+//!
+use crate::Config;
+use chrono::Local;
+use event_handle::event_handle::{Activity, Metrics};
+use std::collections::VecDeque;
+use std::error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+pub static CRASH_REPORT_VARIABLE: &str = "AUTO_STASH_CRASH_REPORT";
+/// the activity lines a report keeps
+static RECENT_LINES: usize = 100;
+/// the directory below the temp directory only the user can read the reports in
+static REPORT_DIR: &str = "auto-stash-crash-reports";
+
+/// whether the environment asks for crash reports, set to anything but `0`
+pub fn requested_by_env() -> bool {
+    std::env::var(CRASH_REPORT_VARIABLE).is_ok_and(|value| !value.is_empty() && value != "0")
+}
+
+#[derive(Clone)]
+pub struct CrashReport {
+    config_path: String,
+    config: Config,
+    recent: Arc<Mutex<VecDeque<String>>>,
+}
+
+impl CrashReport {
+    pub fn new(config_path: &str, config: &Config) -> CrashReport {
+        CrashReport {
+            config_path: config_path.to_string(),
+            config: config.clone(),
+            recent: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    /// keeps `activity` as one of the latest lines of the report
+    pub fn note(&self, activity: &Activity) {
+        let version = activity
+            .version
+            .map_or_else(String::new, |version| format!(" version {}", version));
+        let line = format!(
+            "{} {:?} {}{} +{} -{} ~{}",
+            activity.timestamp,
+            activity.kind,
+            activity.path,
+            version,
+            activity.added,
+            activity.removed,
+            activity.modified
+        );
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() == RECENT_LINES {
+            recent.pop_front();
+        }
+        recent.push_back(line);
+    }
+
+    ///
+    /// writes the report of `err` to a directory of the temp directory
+    /// only the user can read and returns its path. `metrics` are left out
+    /// if the stores weren't open.
+    ///
+    pub fn write(
+        &self,
+        err: &dyn error::Error,
+        metrics: Option<Metrics>,
+    ) -> Result<PathBuf, Box<dyn error::Error>> {
+        self.write_to(&std::env::temp_dir().join(REPORT_DIR), err, metrics)
+    }
+
+    fn write_to(
+        &self,
+        dir: &Path,
+        err: &dyn error::Error,
+        metrics: Option<Metrics>,
+    ) -> Result<PathBuf, Box<dyn error::Error>> {
+        private_dir(dir)?;
+        let stem = format!(
+            "auto-stash-crash-{}-{}",
+            Local::now().format("%Y%m%d-%H%M%S"),
+            std::process::id()
+        );
+        // a file somebody put there before, or a link, isn't written through
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        // a panic and the error it ends in are reported within the same second
+        let mut attempt = 0;
+        let (path, mut file) = loop {
+            let path = match attempt {
+                0 => dir.join(format!("{}.txt", stem)),
+                _ => dir.join(format!("{}-{}.txt", stem, attempt)),
+            };
+            match options.open(&path) {
+                Ok(file) => break (path, file),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists && attempt < 10 => {
+                    attempt += 1
+                }
+                Err(err) => return Err(err.into()),
+            }
+        };
+        self.render(err, metrics, &mut file)?;
+        Ok(path)
+    }
+
+    ///
+    /// writes a report of every panic after the default hook printed it,
+    /// the error the panic ends in is reported by `write` as usual
+    ///
+    pub fn install_panic_hook(&self) {
+        let report = self.clone();
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            default_hook(info);
+            let panicked = Panicked(info.to_string());
+            match report.write(&panicked, None) {
+                Ok(path) => eprintln!("Wrote a crash report to {}", path.display()),
+                Err(err) => eprintln!("Could not write a crash report: {}", err),
+            }
+        }));
+    }
+
+    fn render(
+        &self,
+        err: &dyn error::Error,
+        metrics: Option<Metrics>,
+        mut out: impl Write,
+    ) -> io::Result<()> {
+        writeln!(
+            out,
+            "auto-stash {} crash report, {}",
+            env!("CARGO_PKG_VERSION"),
+            Local::now().format("%Y-%m-%d %H:%M:%S")
+        )?;
+
+        writeln!(out, "\n# error\n{}", err)?;
+        let mut source = err.source();
+        while let Some(err) = source {
+            writeln!(out, "caused by: {}", err)?;
+            source = err.source();
+        }
+
+        writeln!(out, "\n# recorded versions and watch events")?;
+        let recent = self.recent.lock().unwrap();
+        if recent.is_empty() {
+            writeln!(out, "none")?;
+        }
+        recent
+            .iter()
+            .try_for_each(|line| writeln!(out, "{}", line))?;
+
+        writeln!(out, "\n# config {}", self.config_path)?;
+        match fs::read_to_string(&self.config_path) {
+            Ok(config) => write!(out, "{}", config)?,
+            Err(err) => writeln!(out, "could not be read: {}", err)?,
+        }
+
+        writeln!(out, "\n# stores")?;
+        if let Some(metrics) = metrics {
+            writeln!(out, "{}", metrics)?;
+        }
+        let store_paths = std::iter::once(&self.config.store_path)
+            .chain(self.config.stores.iter().map(|route| &route.store_path));
+        for store_path in store_paths {
+            match fs::metadata(store_path) {
+                Ok(metadata) => writeln!(out, "{}: {} bytes", store_path, metadata.len())?,
+                Err(err) => writeln!(out, "{}: {}", store_path, err)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// a panic as the error of a report
+#[derive(Debug)]
+struct Panicked(String);
+
+impl std::fmt::Display for Panicked {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl error::Error for Panicked {}
+
+///
+/// creates `dir` only the user can enter, an existing one must be a
+/// directory nobody else has access to
+///
+fn private_dir(dir: &Path) -> Result<(), Box<dyn error::Error>> {
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    match builder.create(dir) {
+        Ok(()) => return Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(err) => return Err(err.into()),
+    }
+    let metadata = fs::symlink_metadata(dir)?;
+    if !metadata.is_dir() {
+        return Err(format!("{} is no directory", dir.display()).into());
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if metadata.permissions().mode() & 0o077 != 0 {
+            return Err(format!("{} can be read by others", dir.display()).into());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use event_handle::event_handle::ActivityKind;
+
+    #[derive(Debug)]
+    struct Failed(io::Error);
+
+    impl std::fmt::Display for Failed {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "could not record")
+        }
+    }
+
+    impl error::Error for Failed {
+        fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+            Some(&self.0)
+        }
+    }
+
+    #[test]
+    fn a_report_has_the_error_chain_and_the_latest_activity() {
+        let dir = std::env::temp_dir().join(format!("auto_stash_crash_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let config_path = dir.join("config.toml");
+        let store_path = dir.join("store.db");
+        let config = format!(
+            "{}{}",
+            format_args!("store_path = {:?}\nwatch_path = {:?}\n", store_path, dir),
+            "debounce_time = 500\n[exclude]\nfiles = []\npaths = []\n"
+        );
+        fs::write(&config_path, &config).unwrap();
+        fs::write(&store_path, [0; 42]).unwrap();
+
+        let config = toml::from_str(&config).unwrap();
+        let report = CrashReport::new(config_path.to_str().unwrap(), &config);
+        (0..RECENT_LINES + 1).for_each(|version| {
+            report.note(&Activity {
+                kind: ActivityKind::Change,
+                path: String::from("/watch/a.txt"),
+                version: Some(version),
+                timestamp: 1620000000,
+                added: 1,
+                removed: 0,
+                modified: 0,
+            })
+        });
+        let err = Failed(io::Error::other("disk full"));
+        let mut out = Vec::new();
+        report.render(&err, None, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert!(out.contains("# error\ncould not record\ncaused by: disk full\n"));
+        assert!(!out.contains("version 0 "));
+        assert!(out.contains("1620000000 Change /watch/a.txt version 1 +1 -0 ~0\n"));
+        assert!(out.contains("version 100 "));
+        assert!(out.contains("debounce_time = 500\n"));
+        assert!(out.ends_with(&format!("{}: 42 bytes\n", store_path.display())));
+
+        let reports = dir.join("reports");
+        let first = report.write_to(&reports, &err, None).unwrap();
+        let second = report.write_to(&reports, &err, None).unwrap();
+        assert_ne!(first, second);
+        assert!(fs::read_to_string(&second)
+            .unwrap()
+            .contains("caused by: disk full"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+            assert_eq!((mode(&reports), mode(&first)), (0o700, 0o600));
+            fs::set_permissions(&reports, fs::Permissions::from_mode(0o755)).unwrap();
+            assert!(report.write_to(&reports, &err, None).is_err());
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
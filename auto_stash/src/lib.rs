@@ -1,32 +1,604 @@
+pub mod cli;
+pub mod crash;
+pub mod output;
+
 use serde::Deserialize;
 use std::error;
+use std::io::{BufRead, IsTerminal};
+use std::path::{Component, Path, PathBuf};
 use std::time::Duration;
 
+static PASSPHRASE_VARIABLE: &str = "AUTO_STASH_PASSPHRASE";
+/// debounces below this record every intermediate write of a save
+static MIN_DEBOUNCE_MILLIS: u64 = 50;
+/// debounces below this are allowed but likely record partial saves
+static LOW_DEBOUNCE_MILLIS: u64 = 200;
+
+fn default_danger_paths() -> Vec<String> {
+    vec![String::from("/"), String::from("$HOME"), String::from("C:\\")]
+}
+
+fn default_view_coalesce_time() -> u64 {
+    100
+}
+
+fn default_rename_similarity_threshold() -> f64 {
+    RenameDetection::default().similarity_threshold
+}
+
+fn default_rename_max_size_ratio() -> f64 {
+    RenameDetection::default().max_size_ratio
+}
+
+fn default_scan_warning() -> usize {
+    50_000
+}
+
+fn default_time_frames() -> Vec<TimeFrameTab> {
+    [("1h", "1h"), ("24h", "1d"), ("7 Tage", "1w")]
+        .iter()
+        .map(|(label, duration)| TimeFrameTab {
+            label: label.to_string(),
+            duration: duration.to_string(),
+        })
+        .collect()
+}
+
 #[derive(Clone, Deserialize)]
 pub struct Config {
     pub store_path: String,
     pub watch_path: String,
     pub debounce_time: u64,
     pub exclude: Exclude,
+    /// when set only files matching one of the patterns are tracked
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub encrypt: bool,
+    #[serde(default)]
+    pub line_edits: bool,
+    /// keeps a block of lines that moved as one move
+    #[serde(default)]
+    pub detect_moves: bool,
+    /// how the lines of a snapshot are matched up, `myers` by default, see `DiffAlgorithm`
+    #[serde(default)]
+    pub diff_algorithm: DiffAlgorithm,
+    /// files with a longer line are kept as whole snapshots instead of diffed
+    #[serde(default)]
+    pub max_line_length: Option<usize>,
+    /// hard cap of the store file, the oldest versions get evicted above it
+    #[serde(default)]
+    pub max_store_bytes: Option<u64>,
+    /// collects orphaned data on startup once the store is fragmented
+    #[serde(default)]
+    pub compact_on_open: bool,
+    /// `earliest` or `latest`, the version of identical ones `dedupe` keeps
+    #[serde(default)]
+    pub keep_duplicate: KeepDuplicate,
+    /// `capture` dates versions when they are recorded, `mtime` when the file was modified
+    #[serde(default)]
+    pub timestamp_source: TimestampSource,
+    /// files above `above_bytes` only keep their changed lines and `context_lines` around them
+    #[serde(default)]
+    pub context_only: Option<ContextOnly>,
+    /// the share of lines a file has to have in common with a gone one to take over its history
+    #[serde(default = "default_rename_similarity_threshold")]
+    pub rename_similarity_threshold: f64,
+    /// how many times the size of the gone file the one taking over its history may have
+    #[serde(default = "default_rename_max_size_ratio")]
+    pub rename_max_size_ratio: f64,
+    /// adapts the debounce of each file to how often it is saved
+    #[serde(default)]
+    pub adaptive_debounce: Option<AdaptiveDebounce>,
+    /// threads recording changed files, the number of CPUs by default
+    #[serde(default)]
+    pub diff_workers: Option<usize>,
+    /// flushes, and optionally compacts, the store once no events arrived
+    #[serde(default)]
+    pub idle_maintenance: Option<IdleMaintenance>,
+    /// `ignore` or `prewarm`, whether the notice of a starting write reads the file ahead
+    #[serde(default)]
+    pub notice_write: NoticeWrite,
+    /// seconds between passes recording the changes the events missed, off by default
+    #[serde(default)]
+    pub poll_interval: Option<u64>,
+    /// `head`, `middle` or `tail`, the part of a path the TUI keeps once it is too wide
+    #[serde(default)]
+    pub filename_truncation: Truncation,
+    /// milliseconds the TUI merges the views arriving in a burst before drawing them
+    #[serde(default = "default_view_coalesce_time")]
+    pub view_coalesce_time: u64,
+    /// seconds without an event after which a headless auto-stash exits
+    #[serde(default)]
+    pub idle_shutdown: Option<u64>,
+    /// watch paths at or above one of these are refused without `--force`,
+    /// a leading `$NAME` is replaced by the environment variable
+    #[serde(default = "default_danger_paths")]
+    pub danger_paths: Vec<String>,
+    /// warns once more files than this are tracked
+    #[serde(default = "default_scan_warning")]
+    pub scan_warning: usize,
+    /// the tabs of the TUI, the first one is selected on startup
+    #[serde(default = "default_time_frames")]
+    pub time_frames: Vec<TimeFrameTab>,
+    /// a tab in front of the configured ones, set by `--since`
+    #[serde(skip)]
+    pub launch_tab: Option<(String, TimeFrame)>,
+    /// opens stores of a newer schema read-only, set by `--read-newer`
+    #[serde(skip)]
+    pub read_newer: bool,
+    /// prunes the versions it doesn't keep on startup
+    #[serde(default)]
+    pub retention: Option<RetentionConfig>,
+    /// forgets files removed longer ago than this duration, like `30d`, on startup
+    #[serde(default)]
+    pub forget_deleted_after: Option<String>,
+    /// the directories whose files are kept in a store of their own
+    #[serde(default)]
+    pub stores: Vec<StoreRoute>,
+    /// `warn`, `rewrite` or `keep`, what happens to the history of a store watched below a new root
+    #[serde(default)]
+    pub watch_root_change: WatchRootChange,
+    /// globs of the files written back to their latest version on startup
+    #[serde(default)]
+    pub auto_restore: Vec<String>,
+}
+
+/// What a store does with the history recorded below another watch root than the current one.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchRootChange {
+    /// leaves the history where it is and says how to move it
+    #[default]
+    Warn,
+    /// moves the history below the current root, see `Store::rekey_watch_root`
+    Rewrite,
+    /// keeps the history below both roots
+    Keep,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct StoreRoute {
+    /// absolute or relative to the watch path
+    pub root: String,
+    pub store_path: String,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct RetentionConfig {
+    /// for the files no override matches
+    #[serde(flatten)]
+    pub default: RetentionRule,
+    #[serde(default)]
+    pub overrides: Vec<RetentionOverride>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct RetentionOverride {
+    pub pattern: String,
+    #[serde(flatten)]
+    pub rule: RetentionRule,
+}
+
+/// keeps everything unless one of the limits is set
+#[derive(Clone, Default, Deserialize)]
+pub struct RetentionRule {
+    pub keep_last: Option<usize>,
+    /// a duration like `30d`
+    pub older_than: Option<String>,
+}
+
+impl RetentionRule {
+    fn retention(&self) -> Result<Retention, Box<dyn error::Error>> {
+        match (self.keep_last, &self.older_than) {
+            (Some(_), Some(_)) => Err("a retention takes keep_last or older_than, not both".into()),
+            (Some(n), None) => Ok(Retention::KeepLast(n)),
+            (None, Some(older_than)) => Ok(Retention::OlderThan(
+                older_than.parse::<TimeFrame>()?.value(),
+            )),
+            (None, None) => Ok(Retention::KeepAll),
+        }
+    }
+}
+
+/// a tab showing the versions of the last `duration`, like `15m` or `all`
+#[derive(Clone, Deserialize)]
+pub struct TimeFrameTab {
+    pub label: String,
+    pub duration: String,
+}
+
+/// bounds of the adaptive debounce in milliseconds
+#[derive(Clone, Deserialize)]
+pub struct AdaptiveDebounce {
+    pub min: u64,
+    pub max: u64,
+}
+
+/// a maintenance pass after `after` seconds without events
+#[derive(Clone, Deserialize)]
+pub struct IdleMaintenance {
+    pub after: u64,
+    #[serde(default)]
+    pub compact: bool,
 }
 
 #[derive(Clone, Deserialize)]
 pub struct Exclude {
     pub paths: Vec<String>,
     pub files: Vec<String>,
+    /// skips dotfiles and dot-directories, see `--exclude-hidden`
+    #[serde(default)]
+    pub hidden: bool,
 }
 
 impl Config {
     pub fn new(config_path: String) -> Result<Config, Box<dyn error::Error>> {
         let config: Config = toml::from_str(&std::fs::read_to_string(&config_path)?)?;
+        config.check_debounce()?;
+        config.check_stores()?;
+        if config.poll_interval == Some(0) {
+            return Err("a poll_interval has to be at least one second".into());
+        }
+        if !(0.0..=1.0).contains(&config.rename_similarity_threshold) {
+            return Err("a rename_similarity_threshold has to be between 0 and 1".into());
+        }
+        let ratio = config.rename_max_size_ratio;
+        if !ratio.is_finite() || ratio < 1.0 {
+            return Err("a rename_max_size_ratio has to be a number of at least 1".into());
+        }
 
         Ok(config)
     }
+
+    ///
+    /// refuses a `debounce_time` or adaptive minimum below 50ms and warns
+    /// about ones below 200ms, notify fires for every write in between
+    ///
+    fn check_debounce(&self) -> Result<(), Box<dyn error::Error>> {
+        let adaptive_min = self.adaptive_debounce.as_ref().map(|bounds| bounds.min);
+        let debounces = std::iter::once(("debounce_time", self.debounce_time))
+            .chain(adaptive_min.map(|min| ("adaptive_debounce.min", min)));
+        for (name, millis) in debounces {
+            if millis < MIN_DEBOUNCE_MILLIS {
+                return Err(format!(
+                    "{} of {}ms is below the minimum of {}ms",
+                    name, millis, MIN_DEBOUNCE_MILLIS
+                )
+                .into());
+            }
+            if millis < LOW_DEBOUNCE_MILLIS {
+                eprintln!(
+                    "{} of {}ms is low, a save may be recorded as several versions",
+                    name, millis
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// refuses store roots outside of the watch path and stores kept in the same file
+    fn check_stores(&self) -> Result<(), Box<dyn error::Error>> {
+        let mut store_paths = vec![self.store_path.as_str()];
+        for (route, root) in self.stores.iter().zip(self.store_roots()) {
+            let below = root.strip_prefix(&self.watch_path).map(|below| {
+                below
+                    .components()
+                    .all(|component| component != Component::ParentDir)
+            });
+            if below != Ok(true) {
+                return Err(format!(
+                    "the store root {} is outside of the watch path {}",
+                    route.root, self.watch_path
+                )
+                .into());
+            }
+            if store_paths.contains(&route.store_path.as_str()) {
+                return Err(format!("{} is the store of two roots", route.store_path).into());
+            }
+            store_paths.push(route.store_path.as_str());
+        }
+        Ok(())
+    }
+
+    ///
+    /// the roots of `stores` as absolute paths, in the same order. their
+    /// `.` and `..` are resolved, like the paths of the events they are
+    /// matched against.
+    ///
+    pub fn store_roots(&self) -> Vec<PathBuf> {
+        self.stores
+            .iter()
+            .map(|route| Path::new(&self.watch_path).join(resolve_dots(Path::new(&route.root))))
+            .collect()
+    }
+
+    ///
+    /// the passphrase of an encrypted store is read from the
+    /// `AUTO_STASH_PASSPHRASE` environment variable or asked for on the
+    /// terminal, without showing what is typed. without a terminal to ask
+    /// on the variable has to be set.
+    ///
+    pub fn passphrase(&self, store_path: &str) -> Result<Option<String>, Box<dyn error::Error>> {
+        if !self.encrypt && !Store::is_encrypted(store_path) {
+            return Ok(None);
+        }
+        if let Ok(passphrase) = std::env::var(PASSPHRASE_VARIABLE) {
+            return Ok(Some(passphrase));
+        }
+        if !std::io::stdin().is_terminal() {
+            return Err(format!(
+                "{} is encrypted, set {} to open it without a terminal",
+                store_path, PASSPHRASE_VARIABLE
+            )
+            .into());
+        }
+
+        eprint!("Passphrase for {}: ", store_path);
+        crossterm::terminal::enable_raw_mode()?;
+        let passphrase = read_hidden_line(std::io::stdin().lock());
+        crossterm::terminal::disable_raw_mode()?;
+        eprintln!();
+        Ok(Some(passphrase?))
+    }
+
+    ///
+    /// refuses a watch path that contains one of the `danger_paths`,
+    /// like `/` or the home directory, its initial scan would track
+    /// an enormous tree
+    ///
+    pub fn check_watch_path(&self) -> Result<(), Box<dyn error::Error>> {
+        let watch_path = canonical(Path::new(&self.watch_path));
+        let danger = self
+            .danger_paths
+            .iter()
+            .filter_map(|danger_path| expand_variable(danger_path))
+            .map(|danger_path| canonical(Path::new(&danger_path)))
+            .find(|danger_path| danger_path.starts_with(&watch_path));
+
+        match danger {
+            Some(danger_path) => Err(format!(
+                "refusing to watch {} because it contains {}, pass --force to watch it anyway",
+                self.watch_path,
+                danger_path.display()
+            )
+            .into()),
+            None => Ok(()),
+        }
+    }
+
+    ///
+    /// `path` the way the stores know it, below the watch path as it is
+    /// configured. a path relative to the working directory, through `..`
+    /// or a symlink names the same file, a removed one too. paths outside
+    /// of the watch path stay as they are.
+    ///
+    pub fn tracked_path(&self, path: &str) -> String {
+        let given = Path::new(path);
+        let parent = match given.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let resolved = given.canonicalize().ok().or_else(|| {
+            Some(parent.canonicalize().ok()?.join(given.file_name()?))
+        });
+        let watch_path = canonical(Path::new(&self.watch_path));
+        match resolved.as_ref().map(|resolved| resolved.strip_prefix(&watch_path)) {
+            Some(Ok(relative)) => Path::new(&self.watch_path)
+                .join(relative)
+                .to_string_lossy()
+                .into_owned(),
+            _ => path.to_string(),
+        }
+    }
+
+    /// the tabs of the TUI, the launch tab first if there is one
+    pub fn time_frames(&self) -> Result<Vec<(String, TimeFrame)>, Box<dyn error::Error>> {
+        if self.time_frames.is_empty() {
+            return Err("time_frames needs at least one tab".into());
+        }
+        self.launch_tab
+            .iter()
+            .cloned()
+            .map(Ok)
+            .chain(
+                self.time_frames
+                    .iter()
+                    .map(|tab| Ok((tab.label.clone(), tab.duration.parse::<TimeFrame>()?))),
+            )
+            .collect()
+    }
+
+    pub fn retention_policy(&self) -> Result<Option<RetentionPolicy>, Box<dyn error::Error>> {
+        let retention = match &self.retention {
+            Some(retention) => retention,
+            None => return Ok(None),
+        };
+        let overrides = retention
+            .overrides
+            .iter()
+            .map(|o| Ok((o.pattern.clone(), o.rule.retention()?)))
+            .collect::<Result<Vec<_>, Box<dyn error::Error>>>()?;
+        RetentionPolicy::new(&self.watch_path, retention.default.retention()?, &overrides).map(Some)
+    }
+
+    pub fn forget_deleted_after(&self) -> Result<Option<Duration>, Box<dyn error::Error>> {
+        self.forget_deleted_after
+            .as_ref()
+            .map(|after| Ok(Duration::from_secs(after.parse::<TimeFrame>()?.value() as u64)))
+            .transpose()
+    }
+
+    pub fn filter(&self) -> Result<PathFilter, Box<dyn error::Error>> {
+        PathFilter::new(self.watch_path.as_str(), &self.include, self.exclude.hidden)
+    }
+
+    /// the default store, the one of the files below no root of `stores`
+    pub fn open_store(&self) -> Result<Store, Box<dyn error::Error>> {
+        self.open_store_at(&self.store_path, Path::new(&self.watch_path))
+    }
+
+    /// the store keeping `path`, the one of the deepest root it is below
+    pub fn open_store_for(&self, path: &str) -> Result<Store, Box<dyn error::Error>> {
+        let route = self
+            .stores
+            .iter()
+            .zip(self.store_roots())
+            .filter(|(_, root)| Path::new(path).starts_with(root))
+            .max_by_key(|(_, root)| root.components().count());
+        match route {
+            Some((route, root)) => self.open_store_at(&route.store_path, &root),
+            None => self.open_store(),
+        }
+    }
+
+    /// the default store with the one of every root of `stores`
+    pub fn open_stores(&self) -> Result<Stores, Box<dyn error::Error>> {
+        self.stores
+            .iter()
+            .zip(self.store_roots())
+            .try_fold(Stores::new(self.open_store()?), |stores, (route, root)| {
+                let store = self.open_store_at(&route.store_path, &root)?;
+                Ok(stores.with_route(root, store))
+            })
+    }
+
+    ///
+    /// the stores like `open_stores` opens them, read-only and without
+    /// taking their locks, so they can be read while auto-stash records.
+    /// see `Store::open_reader`. nothing gets created, a routed store that
+    /// isn't there yet is left out.
+    ///
+    pub fn read_stores(&self) -> Result<Stores, Box<dyn error::Error>> {
+        let read = |store_path: &str, root: &Path| -> Result<Store, Box<dyn error::Error>> {
+            if !Path::new(store_path).exists() {
+                return Err(format!("{}: no store yet", store_path).into());
+            }
+            let root_path = root.to_str().ok_or("the store root isn't valid unicode")?;
+            Store::open_reader(store_path, root_path, self.store_options(store_path, root)?)
+                .map_err(|err| format!("Could not read {}: {}", store_path, err).into())
+        };
+        self.stores
+            .iter()
+            .zip(self.store_roots())
+            .try_fold(
+                Stores::new(read(&self.store_path, Path::new(&self.watch_path))?),
+                |stores, (route, root)| {
+                    if !Path::new(&route.store_path).exists() {
+                        eprintln!("{}: no store yet", route.store_path);
+                        return Ok(stores);
+                    }
+                    let store = read(&route.store_path, &root)?;
+                    Ok(stores.with_route(root, store))
+                },
+            )
+    }
+
+    fn store_options(
+        &self,
+        store_path: &str,
+        root: &Path,
+    ) -> Result<StoreOptions, Box<dyn error::Error>> {
+        let nested = stores::nested_roots(root, &self.store_roots());
+        Ok(StoreOptions {
+            excluded_files: self.exclude.files.clone(),
+            excluded_paths: self.exclude.paths.clone(),
+            filter: self.filter()?.without_roots(&nested),
+            passphrase: self.passphrase(store_path)?,
+            line_edits: self.line_edits,
+            detect_moves: self.detect_moves,
+            diff_algorithm: self.diff_algorithm,
+            max_line_length: self.max_line_length,
+            max_store_bytes: self.max_store_bytes,
+            compact_on_open: self.compact_on_open,
+            keep_duplicate: self.keep_duplicate,
+            timestamp_source: self.timestamp_source,
+            context_only: self.context_only,
+            rename_detection: RenameDetection {
+                similarity_threshold: self.rename_similarity_threshold,
+                max_size_ratio: self.rename_max_size_ratio,
+            },
+            read_newer: self.read_newer,
+        })
+    }
+
+    /// a new store scans the files below `root` the stores of deeper roots don't keep
+    fn open_store_at(&self, store_path: &str, root: &Path) -> Result<Store, Box<dyn error::Error>> {
+        let mut store = Store::open(
+            store_path,
+            root.to_str().ok_or("the store root isn't valid unicode")?,
+            self.store_options(store_path, root)?,
+        )
+        .map_err(|err| match err.downcast_ref::<UnsupportedSchema>() {
+            Some(_) => format!("{}, --read-newer opens it read-only", err).into(),
+            None => err,
+        })?;
+        if store.is_read_only() {
+            eprintln!("{} is of a newer schema, nothing gets written to it", store_path);
+        }
+        if let Some(previous) = store.previous_watch_roots() {
+            self.on_watch_root_change(&mut store, store_path, root, &previous)?;
+        }
+        Ok(store)
+    }
+
+    fn on_watch_root_change(
+        &self,
+        store: &mut Store,
+        store_path: &str,
+        root: &Path,
+        previous: &[String],
+    ) -> Result<(), Box<dyn error::Error>> {
+        match self.watch_root_change {
+            WatchRootChange::Rewrite if !store.is_read_only() => {
+                let moved = store.rekey_watch_root()?;
+                eprintln!(
+                    "Moved the history of {} files from {} to {}",
+                    moved,
+                    previous.join(", "),
+                    root.display()
+                );
+            }
+            WatchRootChange::Keep if !store.is_read_only() => store.keep_watch_roots()?,
+            _ => eprintln!(
+                "{} holds the history of {}, not of {}. `relocate` moves it, \
+                 watch_root_change = \"rewrite\" does it on startup, \"keep\" keeps both",
+                store_path,
+                previous.join(", "),
+                root.display()
+            ),
+        }
+        Ok(())
+    }
+}
+
+/// `$NAME` at the start becomes the value of `NAME`, `None` if it isn't set
+fn expand_variable(path: &str) -> Option<String> {
+    let variable = match path.strip_prefix('$') {
+        Some(variable) => variable,
+        None => return Some(path.to_string()),
+    };
+    let (name, rest) = variable.split_at(variable.find(['/', '\\']).unwrap_or(variable.len()));
+    std::env::var(name).ok().map(|value| format!("{}{}", value, rest))
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
 }
 
-use event_handle::event_handle::{EventHandle, EventHandleCommunication};
-use filewatch::FileWatch;
-use store::store::Store;
+use diff::DiffAlgorithm;
+use event_handle::event_handle::{EventHandle, EventHandleCommunication, Metrics, ViewUpdate};
+use event_handle::stores::{self, Stores};
+use filewatch::adaptive::DebounceBounds;
+use filewatch::{FileWatch, Maintenance, NoticeWrite, Stopped};
+use store::excerpt::ContextOnly;
+use store::filter::PathFilter;
+use store::retention::{Retention, RetentionPolicy};
+use store::store::{
+    KeepDuplicate, RenameDetection, Store, StoreOptions, TimeFrame, TimestampSource,
+    UnsupportedSchema,
+};
+use ui::ui::Truncation;
 
 pub struct AutoStash {
     pub watch_path: String,
@@ -39,32 +611,317 @@ impl AutoStash {
         communication: EventHandleCommunication,
         on_quit: flume::Receiver<()>,
     ) -> Result<AutoStash, Box<dyn std::error::Error>> {
-        let store = Store::new(
-            config.store_path.as_str(),
-            config.watch_path.as_str(),
-            config.exclude.files.clone(),
-            config.exclude.paths.clone(),
-        )?;
+        let filter = config.filter()?;
+        let stores = config.open_stores()?;
+        for store in stores.all() {
+            prepare_store(config, &mut store.lock().unwrap())?;
+        }
 
-        let mut event_handle = EventHandle::new(store, communication);
+        let tracked = stores.tracked_paths().len();
+        if tracked > config.scan_warning {
+            eprintln!(
+                "Tracking {} files below {}, consider include or exclude patterns",
+                tracked, config.watch_path
+            );
+        }
+
+        let mut event_handle = EventHandle::with_stores(stores, communication);
+        let caught_up = event_handle.catch_up();
+        if caught_up > 0 {
+            eprintln!(
+                "Caught up {} files changed while auto-stash wasn't running",
+                caught_up
+            );
+        }
         event_handle.init_file_versions();
         event_handle.on_redo();
         event_handle.on_undo();
         event_handle.on_time_frame_change();
+        event_handle.on_purge();
+        event_handle.on_checkpoint();
+        event_handle.on_toggle_disabled();
+        event_handle.on_set_debounce();
+        event_handle.on_toggle_pinned();
+        event_handle.on_copy_version();
+        event_handle.on_copy_diff();
+        event_handle.on_show_version();
+        event_handle.on_list_versions();
+        event_handle.on_visible_page();
+        event_handle.on_attach_ui();
+        event_handle.spawn_workers(config.diff_workers.unwrap_or_else(|| {
+            std::thread::available_parallelism().map_or(1, |count| count.get())
+        }));
         let watch = FileWatch::new(
             Duration::from_millis(config.debounce_time),
             event_handle,
             on_quit,
             config.exclude.files.clone(),
             config.exclude.paths.clone(),
-        )?;
+            filter,
+            config
+                .adaptive_debounce
+                .as_ref()
+                .map(|bounds| DebounceBounds {
+                    min: Duration::from_millis(bounds.min),
+                    max: Duration::from_millis(bounds.max),
+                }),
+        )?
+        .with_maintenance(
+            config
+                .idle_maintenance
+                .as_ref()
+                .map(|maintenance| Maintenance {
+                    idle: Duration::from_secs(maintenance.after),
+                    compact: maintenance.compact,
+                }),
+        )
+        .with_poll(config.poll_interval.map(Duration::from_secs))
+        .with_notice_write(config.notice_write)
+        .with_idle_shutdown(config.idle_shutdown.map(Duration::from_secs));
 
         Ok(AutoStash {
             watch,
             watch_path: config.watch_path.clone(),
         })
     }
-    pub fn run(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn run(&mut self) -> Result<Stopped, Box<dyn std::error::Error>> {
         self.watch.start_watching(self.watch_path.as_str())
     }
+
+    pub fn metrics(&self) -> Metrics {
+        self.watch.metrics()
+    }
+
+    /// every view of the stores from now on, next to the TUI or without one
+    pub fn subscribe(&self) -> flume::Receiver<ViewUpdate> {
+        self.watch.subscribe()
+    }
+}
+
+///
+/// a line typed while the terminal is in raw mode, it echoes nothing.
+/// backspace takes back the last character, ctrl-c gives up.
+///
+fn read_hidden_line(input: impl BufRead) -> Result<String, Box<dyn error::Error>> {
+    let mut line = Vec::new();
+    for byte in input.bytes() {
+        match byte? {
+            b'\r' | b'\n' => break,
+            3 => return Err("asking for the passphrase was interrupted".into()),
+            8 | 127 => {
+                // the continuation bytes of a multibyte character first
+                while line.last().is_some_and(|byte| byte & 0xc0 == 0x80) {
+                    line.pop();
+                }
+                line.pop();
+            }
+            byte => line.push(byte),
+        }
+    }
+    String::from_utf8(line).map_err(|_| "the passphrase isn't valid unicode".into())
+}
+
+/// `path` with its `.` and `..` resolved without looking at the disk, a `..` at its start stays
+fn resolve_dots(path: &Path) -> PathBuf {
+    let mut resolved = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(resolved.components().next_back(), Some(Component::Normal(_))) =>
+            {
+                resolved.pop();
+            }
+            component => resolved.push(component),
+        }
+    }
+    resolved
+}
+
+/// the maintenance every store gets on startup, reported on stderr
+fn prepare_store(config: &Config, store: &mut Store) -> Result<(), Box<dyn error::Error>> {
+    if let Some((_, time_frame)) = config.time_frames()?.into_iter().next() {
+        store.change_time_frame(time_frame);
+    }
+    if let Some(report) = store.take_gc_report() {
+        eprintln!(
+            "Compacted store: removed {} orphaned lists, {} records and {} markers, reclaimed {} bytes",
+            report.orphaned_lists,
+            report.orphaned_records,
+            report.orphaned_markers,
+            report.reclaimed_bytes
+        );
+    }
+
+    if let Some(policy) = config.retention_policy()? {
+        let pruned = store.prune(&policy)?;
+        if pruned > 0 {
+            eprintln!("Pruned {} versions the retention doesn't keep", pruned);
+        }
+    }
+    if let Some(after) = config.forget_deleted_after()? {
+        let forgotten = store.prune_deleted(after)?;
+        if forgotten > 0 {
+            eprintln!("Forgot {} long removed files", forgotten);
+        }
+    }
+    // nothing watches the files yet, the restores don't become versions
+    if !config.auto_restore.is_empty() {
+        let report = store.restore_latest_matching(&config.watch_path, &config.auto_restore)?;
+        report
+            .restored
+            .iter()
+            .for_each(|path| eprintln!("Restored {} to its latest version", path));
+        report
+            .failed
+            .iter()
+            .for_each(|(path, err)| eprintln!("Could not restore {}: {}", path, err));
+    }
+    store.start_session()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(watch_path: &str, danger_paths: &[&str]) -> Config {
+        let mut config: Config = toml::from_str(&format!(
+            "store_path = \"store.db\"\nwatch_path = {:?}\ndebounce_time = 100\n\
+             [exclude]\npaths = []\nfiles = []\n",
+            watch_path
+        ))
+        .unwrap();
+        if !danger_paths.is_empty() {
+            config.danger_paths = danger_paths.iter().map(|path| path.to_string()).collect();
+        }
+        config
+    }
+
+    #[test]
+    fn retention_overrides_are_read_per_pattern() {
+        let mut config = config("/watch", &[]);
+        config.retention = toml::from_str(
+            "keep_last = 20\n\
+             [[overrides]]\npattern = \"src/**\"\n\
+             [[overrides]]\npattern = \"build/**\"\nolder_than = \"2d\"\n",
+        )
+        .ok();
+        let policy = config.retention_policy().unwrap().unwrap();
+
+        let retention = |path: &str| policy.for_path(Path::new(path));
+        assert_eq!(retention("/watch/src/lib.rs"), Retention::KeepAll);
+        assert_eq!(retention("/watch/build/a.o"), Retention::OlderThan(2 * 24 * 60 * 60));
+        assert_eq!(retention("/watch/notes.md"), Retention::KeepLast(20));
+
+        config.retention = toml::from_str("keep_last = 1\nolder_than = \"1d\"\n").ok();
+        assert!(config.retention_policy().is_err());
+    }
+
+    #[test]
+    fn diff_algorithms_are_read_by_name() {
+        assert_eq!(config("/watch", &[]).diff_algorithm, DiffAlgorithm::Myers);
+        let with = |algorithm: &str| {
+            toml::from_str::<Config>(&format!(
+                "store_path = \"store.db\"\nwatch_path = \"/watch\"\ndebounce_time = 100\n\
+                 diff_algorithm = {:?}\n[exclude]\npaths = []\nfiles = []\n",
+                algorithm
+            ))
+            .map(|config| config.diff_algorithm)
+        };
+        assert_eq!(with("histogram").unwrap(), DiffAlgorithm::Histogram);
+        assert_eq!(with("lines").unwrap(), DiffAlgorithm::Lines);
+        assert!(with("minimal").is_err());
+    }
+
+    #[test]
+    fn debounces_below_the_minimum_are_refused() {
+        let mut config = config("/watch", &[]);
+        assert!(config.check_debounce().is_ok());
+        config.debounce_time = 0;
+        assert!(config.check_debounce().is_err());
+
+        config.debounce_time = 300;
+        config.adaptive_debounce = Some(AdaptiveDebounce { min: 10, max: 5000 });
+        assert!(config.check_debounce().is_err());
+    }
+
+    #[test]
+    fn store_roots_have_to_be_below_the_watch_path() {
+        let mut config = config("/watch", &[]);
+        let route = |root: &str, store_path: &str| StoreRoute {
+            root: root.to_string(),
+            store_path: store_path.to_string(),
+        };
+        config.stores = vec![route("web", "web.db"), route("/watch/api", "api.db")];
+        let roots = config.store_roots();
+        assert_eq!(roots, vec![PathBuf::from("/watch/web"), PathBuf::from("/watch/api")]);
+        assert!(config.check_stores().is_ok());
+
+        config.stores[1].store_path = String::from("web.db");
+        assert!(config.check_stores().is_err());
+        config.stores[1].store_path = String::from("store.db");
+        assert!(config.check_stores().is_err());
+        config.stores[1].store_path = String::from("api.db");
+        config.stores[1].root = String::from("/elsewhere");
+        assert!(config.check_stores().is_err());
+
+        config.stores[1].root = String::from("./api/../docs");
+        assert_eq!(config.store_roots()[1], PathBuf::from("/watch/docs"));
+        assert!(config.check_stores().is_ok());
+        config.stores[1].root = String::from("api/../../elsewhere");
+        assert!(config.check_stores().is_err());
+        config.stores[1].root = String::from("/watch/../elsewhere");
+        assert!(config.check_stores().is_err());
+    }
+
+    #[test]
+    fn watch_paths_containing_a_danger_path_are_refused() {
+        let home = std::env::temp_dir().join("auto_stash_home");
+        std::env::set_var("AUTO_STASH_TEST_HOME", &home);
+        let danger_paths = ["/", "$AUTO_STASH_TEST_HOME", "$AUTO_STASH_UNSET/x"];
+
+        assert!(config("/", &[]).check_watch_path().is_err());
+        assert!(config("/", &danger_paths).check_watch_path().is_err());
+        let home = home.to_str().unwrap();
+        assert!(config(home, &danger_paths).check_watch_path().is_err());
+        let parent = Path::new(home).parent().unwrap().to_str().unwrap();
+        assert!(config(parent, &danger_paths).check_watch_path().is_err());
+        let project = format!("{}/project", home);
+        assert!(config(&project, &danger_paths).check_watch_path().is_ok());
+    }
+
+    #[test]
+    fn reading_creates_no_store() {
+        let dir = std::env::temp_dir().join(format!("auto_stash_read_{}", std::process::id()));
+        let mut config = config(dir.to_str().unwrap(), &[]);
+        config.store_path = dir.join("store.db").to_str().unwrap().to_string();
+        let err = config.read_stores().err().unwrap();
+        assert!(err.to_string().contains("no store yet"));
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn a_hidden_line_takes_backspaces_and_stops_at_the_return() {
+        let typed = "se\u{e4}x\u{7f}\u{7f}cret\rrest";
+        assert_eq!(read_hidden_line(typed.as_bytes()).unwrap(), "secret");
+        assert!(read_hidden_line(&b"sec\x03ret\r"[..]).is_err());
+    }
+
+    #[test]
+    fn paths_given_on_the_command_line_name_the_tracked_file() {
+        let dir = std::env::temp_dir().join(format!("auto_stash_tracked_{}", std::process::id()));
+        let watch = dir.join("watch");
+        std::fs::create_dir_all(watch.join("sub")).unwrap();
+        std::fs::write(watch.join("a.txt"), "a\n").unwrap();
+        let config = config(watch.to_str().unwrap(), &[]);
+        let tracked = |path: PathBuf| config.tracked_path(path.to_str().unwrap());
+
+        let a = watch.join("a.txt");
+        assert_eq!(tracked(watch.join("sub/../a.txt")), a.to_str().unwrap());
+        // removed, only the directory it was in is left
+        let gone = watch.join("sub/gone.txt");
+        assert_eq!(tracked(watch.join("sub/./gone.txt")), gone.to_str().unwrap());
+        assert_eq!(config.tracked_path("/elsewhere/a.txt"), "/elsewhere/a.txt");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -1,62 +1,278 @@
-use auto_stash::{AutoStash, Config};
-use event_handle::event_handle::EventHandleCommunication;
-use flume::unbounded;
-use std::{process, thread};
+use auto_stash::cli::{Args, OutputFormat};
+use auto_stash::crash::CrashReport;
+use auto_stash::{output, AutoStash, Config};
+use event_handle::event_handle::{Activity, EventHandleCommunication, Metrics};
+use filewatch::Stopped;
+use flume::{unbounded, Receiver};
+use std::time::Duration;
+use std::{error, io, process, thread};
 use ui::ui::{UICommunication, UI};
 
+/// the exit code once `idle_shutdown` ran out, telling it apart from quitting or failing
+static IDLE_EXIT_CODE: i32 = 3;
+
 fn main() {
+    let args: Args = argh::from_env();
+    let headless = args.is_headless();
+    let mut config = Config::new(args.config.clone()).unwrap_or_else(|err| {
+        eprintln!("Problem parsing arguments: {}", err);
+        process::exit(1);
+    });
+    config.exclude.hidden |= args.exclude_hidden;
+    config.read_newer = args.read_newer;
+    config.launch_tab = args.launch_tab().unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+    if !args.force {
+        config.check_watch_path().unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(1);
+        });
+    }
+    if let Some(command) = args.command {
+        command.run(&config).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(1);
+        });
+        return;
+    }
+    if !headless {
+        // someone may come back to the TUI any time
+        config.idle_shutdown = None;
+    }
+    let crash = args
+        .wants_crash_report()
+        .then(|| CrashReport::new(&args.config, &config));
+    if let Some(crash) = &crash {
+        crash.install_panic_hook();
+    }
+
     let (file_versions_to_ui, on_file_versions) = unbounded();
+    let (visible_page_to_handle, on_visible_page) = unbounded();
+    let (attach_to_handle, on_attach_ui) = unbounded();
     let (undo_to_handle, on_undo) = unbounded();
     let (redo_to_handle, on_redo) = unbounded();
     let (time_frame_change_to_handle, on_time_frame_change) = unbounded();
+    let (purge_to_handle, on_purge) = unbounded();
+    let (checkpoint_to_handle, on_checkpoint) = unbounded();
+    let (toggle_disabled_to_handle, on_toggle_disabled) = unbounded();
+    let (set_debounce_to_handle, on_set_debounce) = unbounded();
+    let (toggle_pinned_to_handle, on_toggle_pinned) = unbounded();
+    let (record_now_to_handle, on_record_now) = unbounded();
+    let (watch_root_to_ui, on_watch_root) = unbounded();
+    let (copy_version_to_handle, on_copy_version) = unbounded();
+    let (copy_diff_to_handle, on_copy_diff) = unbounded();
+    let (show_version_to_handle, on_show_version) = unbounded();
+    let (version_content_to_ui, on_version_content) = unbounded();
+    let (list_versions_to_handle, on_list_versions) = unbounded();
+    let (version_list_to_ui, on_version_list) = unbounded();
+    let (clipboard_to_ui, on_clipboard) = unbounded();
+    let (activity_to_output, on_activity) = unbounded();
+    let (debounce_to_ui, on_debounce) = unbounded();
+    let (maintenance_to_ui, on_maintenance) = unbounded();
+    let (progress_to_ui, on_progress) = unbounded();
+    let (metrics_to_ui, on_metrics) = unbounded();
     let (key_to_ui, on_key) = unbounded();
     let (quit_to_ui, on_quit) = unbounded();
     let (quit_to_handle, on_handle_quit) = unbounded();
 
+    let time_frames = config.time_frames().unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
     let ui = UI::new(
         "".to_string(),
         UICommunication {
             on_file_versions,
+            on_watch_root,
+            on_clipboard,
+            on_debounce,
+            on_maintenance,
+            on_progress,
+            on_metrics,
+            on_version_content,
+            on_version_list,
             on_key,
             on_quit,
             undo_to_handle,
             redo_to_handle,
             time_frame_change_to_handle,
+            purge_to_handle,
+            checkpoint_to_handle,
+            toggle_disabled_to_handle,
+            set_debounce_to_handle,
+            toggle_pinned_to_handle,
+            record_now_to_handle,
+            copy_version_to_handle,
+            copy_diff_to_handle,
+            show_version_to_handle,
+            list_versions_to_handle,
+            visible_page_to_handle,
+            attach_to_handle,
             key_to_ui,
             quit_to_ui,
             quit_to_handle,
         },
-    );
-
-    thread::spawn(|| {
-        ui::run(ui).unwrap_or_else(|err| {
-            eprintln!("Could not run ui: {:?}", err);
-            process::exit(1);
-        });
-    });
-
-    let config = Config::new("config.toml".to_string()).unwrap_or_else(|err| {
-        eprintln!("Problem parsing arguments: {}", err);
-        process::exit(1);
-    });
+        time_frames,
+    )
+    .with_view_coalesce(Duration::from_millis(config.view_coalesce_time))
+    .with_filename_truncation(config.filename_truncation)
+    .with_global_debounce(Duration::from_millis(
+        // the watcher waits for the shortest adaptive debounce instead
+        config
+            .adaptive_debounce
+            .as_ref()
+            .map_or(config.debounce_time, |bounds| bounds.min),
+    ));
 
     let mut auto_stash = AutoStash::new(
         &config,
         EventHandleCommunication {
             file_versions_to_ui,
+            on_visible_page,
+            on_attach_ui,
             on_undo,
             on_redo,
             on_time_frame_change,
+            on_purge,
+            on_checkpoint,
+            on_toggle_disabled,
+            on_set_debounce,
+            on_toggle_pinned,
+            on_record_now,
+            watch_root_to_ui,
+            on_copy_version,
+            on_copy_diff,
+            on_show_version,
+            version_content_to_ui,
+            on_list_versions,
+            version_list_to_ui,
+            clipboard_to_ui,
+            activity_to_output,
+            debounce_to_ui,
+            maintenance_to_ui,
+            progress_to_ui,
+            metrics_to_ui,
         },
         on_handle_quit,
     )
-    .unwrap_or_else(|err| {
-        eprintln!("Problem creating auto stash: {:?}", err);
-        process::exit(1);
+    .unwrap_or_else(|err| exit_with("Problem creating auto stash", &*err, crash.as_ref(), None));
+
+    let on_activity = match &crash {
+        Some(crash) => noted(on_activity, crash.clone()),
+        None => on_activity,
+    };
+    let writer = match args.output {
+        Some(OutputFormat::Jsonl) => {
+            let crash = crash.clone();
+            Some(thread::spawn(move || {
+                output::write_jsonl(on_activity, io::stdout()).unwrap_or_else(|err| {
+                    exit_with("Could not write output", &*err, crash.as_ref(), None)
+                });
+            }))
+        }
+        // the activity would pile up in the channel otherwise
+        None => {
+            drain(on_activity);
+            None
+        }
+    };
+
+    // keeps the ui alive to hold the other channel ends, nobody reads them
+    let held_ui = if headless {
+        let communication = &ui.communication;
+        // quits like the ui does, after the pending saves got recorded
+        let quit_to_handle = communication.quit_to_handle.clone();
+        ctrlc::set_handler(move || {
+            let _ = quit_to_handle.send(());
+        })
+        .unwrap_or_else(|err| {
+            eprintln!("Could not handle termination signals: {:?}", err);
+        });
+        #[cfg(unix)]
+        record_now_on_signal(communication.record_now_to_handle.clone());
+        drain(communication.on_file_versions.clone());
+        drain(communication.on_watch_root.clone());
+        drain(communication.on_clipboard.clone());
+        drain(communication.on_debounce.clone());
+        drain(communication.on_maintenance.clone());
+        drain(communication.on_progress.clone());
+        drain(communication.on_metrics.clone());
+        drain(communication.on_version_content.clone());
+        drain(communication.on_version_list.clone());
+        Some(ui)
+    } else {
+        // the ui takes over the terminal, so it starts once the store is open
+        let crash = crash.clone();
+        thread::spawn(move || {
+            ui::run(ui)
+                .unwrap_or_else(|err| exit_with("Could not run ui", &*err, crash.as_ref(), None));
+        });
+        None
+    };
+
+    match auto_stash.run() {
+        Ok(Stopped::Quit) => {}
+        Ok(Stopped::Idle) => {
+            // without the watcher and the ui ends the channels close, the
+            // writer gets to the end of the activity before exiting
+            drop(auto_stash);
+            drop(held_ui);
+            if let Some(writer) = writer {
+                let _ = writer.join();
+            }
+            process::exit(IDLE_EXIT_CODE)
+        }
+        Err(err) => {
+            let metrics = crash.as_ref().map(|_| auto_stash.metrics());
+            exit_with("Could not run auto stash", &*err, crash.as_ref(), metrics);
+        }
+    }
+}
+
+/// prints `message` with `err` and exits, writing a crash report if asked for
+fn exit_with(
+    message: &str,
+    err: &dyn error::Error,
+    crash: Option<&CrashReport>,
+    metrics: Option<Metrics>,
+) -> ! {
+    eprintln!("{}: {:?}", message, err);
+    match crash.map(|crash| crash.write(err, metrics)) {
+        Some(Ok(path)) => eprintln!("Wrote a crash report to {}", path.display()),
+        Some(Err(err)) => eprintln!("Could not write a crash report: {}", err),
+        None => {}
+    }
+    process::exit(1);
+}
+
+/// passes on every activity after keeping it for the crash report
+fn noted(on_activity: Receiver<Activity>, crash: CrashReport) -> Receiver<Activity> {
+    let (activity_to_output, on_noted) = unbounded();
+    thread::spawn(move || {
+        for activity in on_activity.iter() {
+            crash.note(&activity);
+            let _ = activity_to_output.send(activity);
+        }
     });
+    on_noted
+}
 
-    auto_stash.run().unwrap_or_else(|err| {
-        eprintln!("Could not run auto stash: {:?}", err);
-        process::exit(1);
+/// `SIGUSR1` records every changed file right away, like the TUI does for one
+#[cfg(unix)]
+fn record_now_on_signal(record_now_to_handle: flume::Sender<Option<String>>) {
+    let signals = match signal_hook::iterator::Signals::new([signal_hook::SIGUSR1]) {
+        Ok(signals) => signals,
+        Err(err) => return eprintln!("Could not handle SIGUSR1: {:?}", err),
+    };
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            let _ = record_now_to_handle.send(None);
+        }
     });
 }
+
+fn drain<T: Send + 'static>(receiver: Receiver<T>) {
+    thread::spawn(move || receiver.iter().for_each(drop));
+}
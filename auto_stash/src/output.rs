@@ -0,0 +1,65 @@
+//!
+//! machine readable activity for headless runs
+//!
+use event_handle::event_handle::Activity;
+use flume::Receiver;
+use std::error;
+use std::io::Write;
+
+///
+/// writes every activity as one JSON object per line until the event
+/// handle goes away, each line is flushed right away so it can be piped
+///
+pub fn write_jsonl(
+    on_activity: Receiver<Activity>,
+    mut out: impl Write,
+) -> Result<(), Box<dyn error::Error>> {
+    for activity in on_activity.iter() {
+        serde_json::to_writer(&mut out, &activity)?;
+        out.write_all(b"\n")?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use event_handle::event_handle::ActivityKind;
+
+    #[test]
+    fn one_json_object_per_line() {
+        let (activity_to_output, on_activity) = flume::unbounded();
+        let activity = Activity {
+            kind: ActivityKind::Change,
+            path: String::from("/watch/a.txt"),
+            version: Some(2),
+            timestamp: 1620000000,
+            added: 1,
+            removed: 0,
+            modified: 3,
+        };
+        activity_to_output.send(activity.clone()).unwrap();
+        activity_to_output
+            .send(Activity {
+                kind: ActivityKind::RootUnavailable,
+                path: String::from("/watch"),
+                version: None,
+                ..activity
+            })
+            .unwrap();
+        drop(activity_to_output);
+
+        let mut out = Vec::new();
+        write_jsonl(on_activity, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            concat!(
+                r#"{"kind":"change","path":"/watch/a.txt","version":2,"timestamp":1620000000,"added":1,"removed":0,"modified":3}"#,
+                "\n",
+                r#"{"kind":"root_unavailable","path":"/watch","timestamp":1620000000,"added":1,"removed":0,"modified":3}"#,
+                "\n"
+            )
+        );
+    }
+}
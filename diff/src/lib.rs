@@ -1,8 +1,9 @@
 use chrono::{NaiveDateTime, Utc};
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
-use std::fs::File;
-use std::io::{self, BufRead};
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::ops::Range;
 
 pub static RFC3339: &str = "%Y-%m-%dT%H:%M:%S%.9f%:z";
 
@@ -13,6 +14,98 @@ pub struct LineDifference {
     pub line: String,
     pub changed_line: String,
     pub date_time: String,
+    /// set instead of `line` and `changed_line` when only the edited
+    /// range of the line is kept
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edit: Option<LineEdit>,
+    /// set for a block of lines that moved, `line_number` is where it went
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub moved: Option<Moved>,
+}
+
+///
+/// the part of a line that changed, `removed` bytes starting at `offset`
+/// got replaced by `inserted`
+///
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LineEdit {
+    pub offset: usize,
+    pub removed: usize,
+    pub inserted: String,
+}
+
+impl LineEdit {
+    pub fn between(line: &str, changed_line: &str) -> LineEdit {
+        let prefix = line
+            .char_indices()
+            .zip(changed_line.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(line.len().min(changed_line.len()), |((index, _), _)| index);
+        let suffix = line[prefix..]
+            .chars()
+            .rev()
+            .zip(changed_line[prefix..].chars().rev())
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| a.len_utf8())
+            .sum::<usize>();
+
+        LineEdit {
+            offset: prefix,
+            removed: line.len() - prefix - suffix,
+            inserted: changed_line[prefix..changed_line.len() - suffix].to_string(),
+        }
+    }
+
+    pub fn apply(&self, line: &str) -> String {
+        let end = (self.offset + self.removed).min(line.len());
+        [
+            line.get(..self.offset).unwrap_or(line),
+            self.inserted.as_str(),
+            line.get(end..).unwrap_or(""),
+        ]
+        .concat()
+    }
+}
+
+///
+/// a block of `len` lines cut out at `from_line` and put back so that it
+/// starts at `to_line`, the lines in between close up behind it. the
+/// number of lines stays the same.
+///
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Moved {
+    pub from_line: usize,
+    pub to_line: usize,
+    pub len: usize,
+}
+
+impl Moved {
+    pub fn apply<T: Clone + Default>(&self, lines: &mut Vec<T>) {
+        let end = self.from_line + self.len;
+        let needed = end.max(self.to_line + self.len);
+        if lines.len() < needed {
+            lines.resize(needed, T::default());
+        }
+        let block = lines.drain(self.from_line..end).collect_vec();
+        lines.splice(self.to_line..self.to_line, block);
+    }
+
+    /// where line `line` was before the move
+    pub fn source_of(&self, line: usize) -> usize {
+        if (self.to_line..self.to_line + self.len).contains(&line) {
+            return self.from_line + line - self.to_line;
+        }
+        let closed_up = if line < self.to_line {
+            line
+        } else {
+            line - self.len
+        };
+        if closed_up < self.from_line {
+            closed_up
+        } else {
+            closed_up + self.len
+        }
+    }
 }
 
 impl LineDifference {
@@ -28,12 +121,94 @@ impl LineDifference {
             line,
             changed_line,
             date_time: Utc::now().to_rfc3339(),
+            edit: None,
+            moved: None,
         }
     }
 
     pub fn token() -> LineDifference {
         LineDifference::new("".to_string(), 0, "".to_string(), "".to_string())
     }
+
+    pub fn moved(path: String, moved: Moved) -> LineDifference {
+        LineDifference {
+            moved: Some(moved.clone()),
+            ..LineDifference::new(path, moved.to_line, String::new(), String::new())
+        }
+    }
+
+    /// overwrites the line of the change in `lines`, or moves the block
+    pub fn replay_onto(&self, lines: &mut Vec<String>) {
+        if let Some(moved) = &self.moved {
+            return moved.apply(lines);
+        }
+        if self.line_number >= lines.len() {
+            lines.resize(self.line_number + 1, String::new());
+        }
+        lines[self.line_number] = self.changed_line.clone();
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+impl LineDifference {
+    /// `None` for differences that leave the line as it was, and for moves
+    pub fn kind(&self) -> Option<ChangeKind> {
+        match (self.line.is_empty(), self.changed_line.is_empty()) {
+            (true, false) => Some(ChangeKind::Added),
+            (false, true) => Some(ChangeKind::Removed),
+            (false, false) if self.line.ne(&self.changed_line) => Some(ChangeKind::Modified),
+            _ => None,
+        }
+    }
+}
+
+/// Counts of the line differences of a version by their `ChangeKind`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DiffSummary {
+    pub added: usize,
+    pub removed: usize,
+    pub modified: usize,
+}
+
+impl DiffSummary {
+    pub fn of(changes: &[LineDifference]) -> DiffSummary {
+        changes
+            .iter()
+            .fold(DiffSummary::default(), |mut summary, change| {
+                match change.kind() {
+                    Some(ChangeKind::Added) => summary.added += 1,
+                    Some(ChangeKind::Removed) => summary.removed += 1,
+                    Some(ChangeKind::Modified) => summary.modified += 1,
+                    None => {}
+                }
+                summary
+            })
+    }
+
+    /// every changed line regardless of its kind
+    pub fn churn(&self) -> usize {
+        self.added + self.removed + self.modified
+    }
+}
+
+impl std::ops::AddAssign for DiffSummary {
+    fn add_assign(&mut self, other: DiffSummary) {
+        self.added += other.added;
+        self.removed += other.removed;
+        self.modified += other.modified;
+    }
+}
+
+impl std::fmt::Display for DiffSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "+{} -{} ~{}", self.added, self.removed, self.modified)
+    }
 }
 
 impl PartialEq for LineDifference {
@@ -42,16 +217,427 @@ impl PartialEq for LineDifference {
             && self.line_number.eq(&other.line_number)
             && self.line.eq(&other.line)
             && self.changed_line.eq(&other.changed_line)
+            && self.moved.eq(&other.moved)
     }
 }
 
+///
+/// the changes of the file at `path` against the lines `prev_changes`
+/// replay to, matched up by `algorithm`, see `find_in_aligned`
+///
 pub fn find(
     path: &str,
     prev_changes: &[LineDifference],
+    algorithm: DiffAlgorithm,
+) -> Result<Vec<LineDifference>, Box<dyn std::error::Error>> {
+    find_in_aligned(path, &std::fs::read(path)?, prev_changes, algorithm)
+}
+
+///
+/// like `find`, but diffs `content` instead of reading `path`, so the
+/// changes belong to exactly that snapshot of the file
+///
+pub fn find_in(
+    path: &str,
+    content: &[u8],
+    prev_changes: &[LineDifference],
+) -> Result<Vec<LineDifference>, Box<dyn std::error::Error>> {
+    Ok(find_against(
+        path,
+        content,
+        &unique_prev_changes(prev_changes),
+    ))
+}
+
+///
+/// every line of `content` as a change, without the line it replaced.
+/// coarser than `find_in` but it replays to `content` all the same, for
+/// lines too long to be worth diffing.
+///
+pub fn snapshot(path: &str, content: &[u8]) -> Vec<LineDifference> {
+    content
+        .lines()
+        .enumerate()
+        .map(|(index, line)| {
+            LineDifference::new(
+                path.to_string(),
+                index,
+                String::new(),
+                line.unwrap_or_default(),
+            )
+        })
+        .collect()
+}
+
+///
+/// how the lines of a snapshot are matched up with the lines before it.
+/// `lines` compares the lines with the same number, so a line inserted at
+/// the top changes every line below it. the others, `myers` by default,
+/// align the lines first and keep the shift as moves, see `find_in_aligned`.
+///
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffAlgorithm {
+    Lines,
+    #[default]
+    Myers,
+    Patience,
+    /// like git's, see `histogram_ops`
+    Histogram,
+}
+
+impl DiffAlgorithm {
+    /// how `wanted` is reached from `lines`, `None` for `Lines`
+    fn ops(self, lines: &[String], wanted: &[String]) -> Option<Vec<similar::DiffOp>> {
+        let algorithm = match self {
+            DiffAlgorithm::Lines => return None,
+            DiffAlgorithm::Myers => similar::Algorithm::Myers,
+            DiffAlgorithm::Patience => similar::Algorithm::Patience,
+            DiffAlgorithm::Histogram => return Some(histogram_ops(lines, wanted)),
+        };
+        Some(similar::capture_diff_slices(algorithm, lines, wanted))
+    }
+}
+
+/// lines more often on the old side than this aren't anchors of `histogram_ops`
+const MAX_CHAIN_LENGTH: usize = 64;
+
+/// What is left to do for `histogram_ops`, in the order of the ops.
+enum Histogram {
+    Ranges(Range<usize>, Range<usize>),
+    Op(similar::DiffOp),
+}
+
+///
+/// the histogram diff git uses. the lines both sides start and end with
+/// are kept, then the line the old side has the fewest times anchors the
+/// longest run of lines both have, and the lines before and after it are
+/// diffed the same way. without any anchor it falls back to myers.
+///
+fn histogram_ops(old: &[String], new: &[String]) -> Vec<similar::DiffOp> {
+    let mut ops = vec![];
+    // the later ranges wait below the earlier ones, a long file doesn't recurse deeply
+    let mut todo = vec![Histogram::Ranges(0..old.len(), 0..new.len())];
+    while let Some(next) = todo.pop() {
+        let (mut o, mut n) = match next {
+            Histogram::Op(op) => {
+                ops.push(op);
+                continue;
+            }
+            Histogram::Ranges(o, n) => (o, n),
+        };
+        let same = |(i, j): &(usize, usize)| old[*i] == new[*j];
+        let prefix = o.clone().zip(n.clone()).take_while(same).count();
+        if prefix > 0 {
+            ops.push(similar::DiffOp::Equal {
+                old_index: o.start,
+                new_index: n.start,
+                len: prefix,
+            });
+            o.start += prefix;
+            n.start += prefix;
+        }
+        let suffix = o
+            .clone()
+            .rev()
+            .zip(n.clone().rev())
+            .take_while(same)
+            .count();
+        if suffix > 0 {
+            o.end -= suffix;
+            n.end -= suffix;
+            todo.push(Histogram::Op(similar::DiffOp::Equal {
+                old_index: o.end,
+                new_index: n.end,
+                len: suffix,
+            }));
+        }
+        if o.is_empty() && n.is_empty() {
+            continue;
+        }
+        if o.is_empty() {
+            ops.push(similar::DiffOp::Insert {
+                old_index: o.start,
+                new_index: n.start,
+                new_len: n.len(),
+            });
+            continue;
+        }
+        if n.is_empty() {
+            ops.push(similar::DiffOp::Delete {
+                old_index: o.start,
+                old_len: o.len(),
+                new_index: n.start,
+            });
+            continue;
+        }
+        match histogram_anchor(old, new, &o, &n) {
+            Some((i, j, len)) => {
+                todo.push(Histogram::Ranges(i + len..o.end, j + len..n.end));
+                todo.push(Histogram::Op(similar::DiffOp::Equal {
+                    old_index: i,
+                    new_index: j,
+                    len,
+                }));
+                todo.push(Histogram::Ranges(o.start..i, n.start..j));
+            }
+            None => ops.extend(similar::capture_diff(
+                similar::Algorithm::Myers,
+                old,
+                o,
+                new,
+                n,
+            )),
+        }
+    }
+    ops
+}
+
+///
+/// where the run of lines anchored by the line of `o` occurring the fewest
+/// times starts on both sides and how long it is, the longest run of those
+///
+fn histogram_anchor(
+    old: &[String],
+    new: &[String],
+    o: &Range<usize>,
+    n: &Range<usize>,
+) -> Option<(usize, usize, usize)> {
+    let mut occurrences: HashMap<&str, Vec<usize>> = HashMap::new();
+    for i in o.clone() {
+        occurrences.entry(old[i].as_str()).or_default().push(i);
+    }
+    // the fewest occurrences, then the longest run
+    let mut best: Option<(usize, usize, (usize, usize, usize))> = None;
+    for j in n.clone() {
+        let at = match occurrences.get(new[j].as_str()) {
+            Some(at) if at.len() <= MAX_CHAIN_LENGTH => at,
+            _ => continue,
+        };
+        for &i in at {
+            let len = (i..o.end)
+                .zip(j..n.end)
+                .take_while(|(i, j)| old[*i] == new[*j])
+                .count();
+            let better = best.is_none_or(|(count, longest, _)| {
+                at.len() < count || (at.len() == count && len > longest)
+            });
+            if better {
+                best = Some((at.len(), len, (i, j, len)));
+            }
+        }
+    }
+    best.map(|(_, _, anchor)| anchor)
+}
+
+///
+/// like `find_in`, but the lines are aligned by `algorithm` first. the
+/// inserted and deleted blocks become moves that shift the lines after
+/// them, so only the lines that really changed are left. the moves come
+/// first like with `find_in_with_moves`.
+///
+pub fn find_in_aligned(
+    path: &str,
+    content: &[u8],
+    prev_changes: &[LineDifference],
+    algorithm: DiffAlgorithm,
+) -> Result<Vec<LineDifference>, Box<dyn std::error::Error>> {
+    let mut lines = replay(prev_changes);
+    let wanted = lines_of(content);
+    let ops = match algorithm.ops(&lines, &wanted) {
+        Some(ops) => ops,
+        None => return find_in(path, content, prev_changes),
+    };
+    let moves = align_by(&mut lines, &ops);
+    Ok(find_after_moves(path, content, lines, moves))
+}
+
+///
+/// what changed from `before` to `after`, line by line the way `find_in`
+/// compares them, for versions that don't keep what they replaced
+///
+pub fn summary_between(before: &[String], after: &[String]) -> DiffSummary {
+    (0..before.len().max(after.len())).fold(DiffSummary::default(), |mut summary, index| {
+        let before = before.get(index).map_or("", String::as_str);
+        let after = after.get(index).map_or("", String::as_str);
+        match (before.is_empty(), after.is_empty()) {
+            (true, false) => summary.added += 1,
+            (false, true) => summary.removed += 1,
+            (false, false) if before != after => summary.modified += 1,
+            _ => {}
+        }
+        summary
+    })
+}
+
+/// a moved block has to be at least this long to be kept as one
+pub const MIN_MOVED_LINES: usize = 2;
+
+/// how many moves one snapshot is searched for
+const MAX_MOVES: usize = 16;
+
+///
+/// like `find_in`, but a block of lines that reappears elsewhere becomes
+/// one move instead of every line of it changing. the moves come first,
+/// the other changes are relative to the lines after the moves. searching
+/// for blocks takes a lot longer than the plain diff.
+///
+pub fn find_in_with_moves(
+    path: &str,
+    content: &[u8],
+    prev_changes: &[LineDifference],
 ) -> Result<Vec<LineDifference>, Box<dyn std::error::Error>> {
-    let prev_changes = &unique_prev_changes(prev_changes);
-    let mut changed_or_added_lines = find_changed_or_added_lines(path, prev_changes)?;
-    let line_count = line_count(path)?;
+    let mut lines = replay(prev_changes);
+    let wanted = lines_of(content);
+    let moves = find_moves(&mut lines, &wanted);
+    Ok(find_after_moves(path, content, lines, moves))
+}
+
+fn lines_of(content: &[u8]) -> Vec<String> {
+    content
+        .lines()
+        .map(|line| line.unwrap_or_default())
+        .collect_vec()
+}
+
+/// the `moves` that turned the previous lines into `lines`, then the changes of `content`
+fn find_after_moves(
+    path: &str,
+    content: &[u8],
+    lines: Vec<String>,
+    moves: Vec<Moved>,
+) -> Vec<LineDifference> {
+    let moved_lines = lines
+        .into_iter()
+        .enumerate()
+        .map(|(index, line)| LineDifference::new(path.to_string(), index, String::new(), line))
+        .collect_vec();
+
+    moves
+        .into_iter()
+        .map(|moved| LineDifference::moved(path.to_string(), moved))
+        .chain(find_against(path, content, &moved_lines))
+        .collect()
+}
+
+///
+/// shifts `lines` so that the lines the `ops` of a diff match up end up
+/// in place. a deleted block goes to the end, an inserted one starts out
+/// as empty lines. returns the moves in the order they were applied.
+///
+pub fn align_by<T: Clone + Default>(lines: &mut Vec<T>, ops: &[similar::DiffOp]) -> Vec<Moved> {
+    let mut moves = vec![];
+    for op in ops {
+        let (_, old, new) = op.as_tag_tuple();
+        // the lines both sides have are changed in place
+        let kept = old.len().min(new.len());
+        let deleted = Moved {
+            from_line: new.start + kept,
+            to_line: lines.len() - (old.len() - kept),
+            len: old.len() - kept,
+        };
+        let inserted = Moved {
+            from_line: lines.len(),
+            to_line: new.start + kept,
+            len: new.len() - kept,
+        };
+        for moved in [deleted, inserted] {
+            if moved.len > 0 && moved.from_line != moved.to_line {
+                moved.apply(lines);
+                moves.push(moved);
+            }
+        }
+    }
+    moves
+}
+
+/// Applies `changes` in order, see `LineDifference::replay_onto`.
+pub fn replay(changes: &[LineDifference]) -> Vec<String> {
+    let mut lines = vec![];
+    changes
+        .iter()
+        .for_each(|change| change.replay_onto(&mut lines));
+    lines
+}
+
+///
+/// moves blocks of `lines` to where `wanted` has them as long as that
+/// puts more lines into place, returns the moves in the order they were
+/// applied
+///
+fn find_moves(lines: &mut Vec<String>, wanted: &[String]) -> Vec<Moved> {
+    let mut moves = vec![];
+    let mut in_place = lines_in_place(lines, wanted);
+    while moves.len() < MAX_MOVES {
+        let moved = match longest_misplaced_block(lines, wanted) {
+            Some(moved) => moved,
+            None => break,
+        };
+        let mut after = lines.clone();
+        moved.apply(&mut after);
+        let after_in_place = lines_in_place(&after, wanted);
+        if after_in_place <= in_place {
+            break;
+        }
+        *lines = after;
+        in_place = after_in_place;
+        moves.push(moved);
+    }
+    moves
+}
+
+fn lines_in_place(lines: &[String], wanted: &[String]) -> usize {
+    lines
+        .iter()
+        .zip(wanted)
+        .filter(|(line, wanted)| line == wanted)
+        .count()
+}
+
+///
+/// the longest block of `lines` that `wanted` has at a position where it
+/// isn't in place yet. a block starts with a line that isn't blank.
+///
+fn longest_misplaced_block(lines: &[String], wanted: &[String]) -> Option<Moved> {
+    let mut positions: HashMap<&str, Vec<usize>> = HashMap::new();
+    lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .for_each(|(index, line)| positions.entry(line.as_str()).or_default().push(index));
+
+    let mut longest: Option<Moved> = None;
+    for (to_line, line) in wanted.iter().enumerate().take(lines.len()) {
+        if lines[to_line] == *line {
+            continue;
+        }
+        for &from_line in positions.get(line.as_str()).into_iter().flatten() {
+            let len = lines[from_line..]
+                .iter()
+                .zip(&wanted[to_line..])
+                .take_while(|(line, wanted)| line == wanted)
+                .count()
+                .min(lines.len() - to_line);
+            if len >= MIN_MOVED_LINES && longest.as_ref().is_none_or(|longest| len > longest.len) {
+                longest = Some(Moved {
+                    from_line,
+                    to_line,
+                    len,
+                });
+            }
+        }
+    }
+    longest
+}
+
+/// the changes of `content` against `prev_changes`, one change per line
+fn find_against(
+    path: &str,
+    content: &[u8],
+    prev_changes: &[LineDifference],
+) -> Vec<LineDifference> {
+    let mut changed_or_added_lines = find_changed_or_added_lines(path, content, prev_changes);
+    let line_count = content.lines().count();
 
     if has_removed_lines(prev_changes, line_count) {
         changed_or_added_lines = [
@@ -61,23 +647,30 @@ pub fn find(
         .concat();
     }
 
-    Ok(changed_or_added_lines)
-}
-
-fn line_count(path: &str) -> Result<usize, Box<dyn std::error::Error>> {
-    let file = File::open(path)?;
-    Ok(io::BufReader::new(&file).lines().count())
+    changed_or_added_lines
 }
 
 fn has_removed_lines(prev_changes: &[LineDifference], line_count: usize) -> bool {
     prev_changes.len() > line_count
 }
 
+///
+/// the latest change of every line. once lines moved a line number no
+/// longer stays with its line, the changes get replayed instead.
+///
 fn unique_prev_changes(prev_changes: &[LineDifference]) -> Vec<LineDifference> {
+    if prev_changes.iter().any(|change| change.moved.is_some()) {
+        let path = prev_changes[0].path.clone();
+        return replay(prev_changes)
+            .into_iter()
+            .enumerate()
+            .map(|(index, line)| LineDifference::new(path.clone(), index, String::new(), line))
+            .collect();
+    }
     prev_changes
         .iter()
         .sorted_by(|a, b| sort(b.date_time.as_str(), a.date_time.as_str()))
-        .dedup_by(|a, b| a.line_number.eq(&b.line_number))
+        .unique_by(|change| change.line_number)
         .sorted_by(|a, b| sort(a.date_time.as_str(), b.date_time.as_str()))
         .cloned()
         .collect_vec()
@@ -95,9 +688,8 @@ fn find_removed_lines(
     line_count: usize,
 ) -> Vec<LineDifference> {
     prev_changes
-        .split_at(line_count)
-        .1
         .iter()
+        .filter(|line| line.line_number >= line_count)
         .map(|line| {
             LineDifference::new(
                 line.path.to_string(),
@@ -111,12 +703,12 @@ fn find_removed_lines(
 
 fn find_changed_or_added_lines(
     path: &str,
+    content: &[u8],
     prev_changes: &[LineDifference],
-) -> Result<Vec<LineDifference>, Box<dyn std::error::Error>> {
-    let file = File::open(path)?;
+) -> Vec<LineDifference> {
     let token = LineDifference::token();
 
-    Ok(io::BufReader::new(file)
+    content
         .lines()
         .enumerate()
         .map(|(index, line)| {
@@ -128,7 +720,7 @@ fn find_changed_or_added_lines(
             find_changed_or_added_line(prev_changes, index, path, line.unwrap(), &token)
         })
         .filter(|e| e.ne(&token))
-        .collect())
+        .collect()
 }
 
 fn find_changed_or_added_line(
@@ -164,8 +756,8 @@ fn find_changed_or_added_line(
 mod tests {
     use super::*;
     use std::{
-        fs::{remove_file, OpenOptions},
-        io::Write,
+        fs::{remove_file, File, OpenOptions},
+        io::{self, Write},
     };
 
     fn init(path: &str) -> Result<(), Box<dyn std::error::Error>> {
@@ -216,7 +808,7 @@ mod tests {
         init(path).unwrap();
         let changes = read(path).unwrap();
 
-        let new_changes: Vec<LineDifference> = find(path, &changes).unwrap();
+        let new_changes: Vec<LineDifference> = find(path, &changes, DiffAlgorithm::Lines).unwrap();
         remove(path).unwrap();
 
         assert_eq!(new_changes, []);
@@ -240,7 +832,7 @@ mod tests {
                 .expect("Couldn't write to file.");
         });
 
-        let new_changes: Vec<LineDifference> = find(path, &changes).unwrap();
+        let new_changes: Vec<LineDifference> = find(path, &changes, DiffAlgorithm::Lines).unwrap();
         remove(path).unwrap();
 
         assert_eq!(
@@ -275,7 +867,7 @@ mod tests {
                 .expect("Couldn't write to file.");
         });
 
-        let new_changes: Vec<LineDifference> = find(path, &changes).unwrap();
+        let new_changes: Vec<LineDifference> = find(path, &changes, DiffAlgorithm::Lines).unwrap();
         remove(path).unwrap();
 
         assert_eq!(
@@ -305,7 +897,7 @@ mod tests {
         ]
         .concat();
 
-        let new_changes: Vec<LineDifference> = find(path, &changes).unwrap();
+        let new_changes: Vec<LineDifference> = find(path, &changes, DiffAlgorithm::Lines).unwrap();
         remove(path).unwrap();
 
         assert_eq!(
@@ -342,7 +934,7 @@ mod tests {
         file.write_all("Hello World2\nNew Change\n".as_bytes())
             .unwrap();
 
-        let new_changes: Vec<LineDifference> = find(path, &changes).unwrap();
+        let new_changes: Vec<LineDifference> = find(path, &changes, DiffAlgorithm::Lines).unwrap();
         remove(path).unwrap();
 
         assert_eq!(
@@ -356,6 +948,196 @@ mod tests {
         );
     }
 
+    #[test]
+    fn line_edit_keeps_only_the_changed_range() {
+        let line = "let value = compute(first, second);";
+        let changed_line = "let value = compute(first, third);";
+        let edit = LineEdit::between(line, changed_line);
+
+        assert_eq!(
+            edit,
+            LineEdit {
+                offset: 27,
+                removed: 5,
+                inserted: "thir".to_string()
+            }
+        );
+        assert_eq!(edit.apply(line), changed_line);
+    }
+
+    #[test]
+    fn line_edit_round_trips() {
+        [
+            ("", "added"),
+            ("removed", ""),
+            ("same", "same"),
+            ("aaa", "aaaa"),
+            ("größe", "grüße"),
+            ("prefix only", "prefix"),
+        ]
+        .iter()
+        .for_each(|(line, changed_line)| {
+            assert_eq!(LineEdit::between(line, changed_line).apply(line), *changed_line);
+        });
+    }
+
+    #[test]
+    fn summary_counts_change_kinds() {
+        let path = "summary.txt".to_string();
+        let changes = vec![
+            LineDifference::new(path.clone(), 0, "".to_string(), "added".to_string()),
+            LineDifference::new(path.clone(), 1, "old".to_string(), "new".to_string()),
+            LineDifference::new(path.clone(), 2, "gone".to_string(), "".to_string()),
+            LineDifference::new(path, 3, "".to_string(), "".to_string()),
+        ];
+
+        let summary = DiffSummary::of(&changes);
+        assert_eq!(
+            summary,
+            DiffSummary {
+                added: 1,
+                removed: 1,
+                modified: 1
+            }
+        );
+        assert_eq!(summary.to_string(), "+1 -1 ~1");
+    }
+
+    #[test]
+    fn moved_block_replays_to_the_content() {
+        let path = "moved.txt";
+        let lines = |content: &str| {
+            content
+                .lines()
+                .enumerate()
+                .map(|(index, line)| {
+                    LineDifference::new(path.to_string(), index, "".to_string(), line.to_string())
+                })
+                .collect_vec()
+        };
+        let prev_changes = lines("a\nb\nc\nd\ne\n");
+        let content = "d\ne\na\nb\nc\nf\n";
+
+        let changes = find_in_with_moves(path, content.as_bytes(), &prev_changes).unwrap();
+        // the longer block is the one that moves
+        let moved = Moved {
+            from_line: 0,
+            to_line: 2,
+            len: 3,
+        };
+        assert_eq!(changes[0].moved, Some(moved.clone()));
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[1].kind(), Some(ChangeKind::Added));
+        let replayed = replay(&[prev_changes.clone(), changes.clone()].concat());
+        assert_eq!(replayed.join("\n") + "\n", content);
+
+        // every line is traced back to where it was before the move
+        let previous = replay(&prev_changes);
+        (0..5).for_each(|line| assert_eq!(replayed[line], previous[moved.source_of(line)]));
+
+        // the plain diff keeps working on top of the moves
+        let history = [prev_changes, changes].concat();
+        assert_eq!(find_in(path, content.as_bytes(), &history).unwrap(), []);
+        assert_eq!(find_in(path, b"d\ne\na\n", &history).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn aligned_diffs_only_keep_the_lines_that_changed() {
+        let path = "aligned.txt";
+        let prev_changes = snapshot(path, b"a\nb\nc\nd\ne\n");
+        let content = "x\na\nb\nd\nE\nf\n";
+
+        // by line number only `d` is still in place
+        let by_line = find_in(path, content.as_bytes(), &prev_changes).unwrap();
+        assert_eq!(DiffSummary::of(&by_line).churn(), 5);
+        for algorithm in [DiffAlgorithm::Myers, DiffAlgorithm::Patience] {
+            let changes = find_in_aligned(path, content.as_bytes(), &prev_changes, algorithm);
+            let changes = changes.unwrap();
+            assert_eq!(
+                changes
+                    .iter()
+                    .filter(|change| change.moved.is_some())
+                    .count(),
+                3
+            );
+            assert_eq!(
+                DiffSummary::of(&changes),
+                DiffSummary {
+                    added: 2,
+                    removed: 1,
+                    modified: 1
+                }
+            );
+            let replayed = replay(&[prev_changes.clone(), changes].concat());
+            assert_eq!(replayed[..6].join("\n") + "\n", content);
+        }
+        let lines = find_in_aligned(
+            path,
+            content.as_bytes(),
+            &prev_changes,
+            DiffAlgorithm::Lines,
+        );
+        assert_eq!(lines.unwrap(), by_line);
+    }
+
+    #[test]
+    fn every_algorithm_replays_to_the_snapshot() {
+        let path = "round_trip.txt";
+        let versions = [
+            "a\nb\nc\nd\ne\n",
+            "x\na\nb\nd\nE\nf\n",
+            "}\nf\n}\nb\nb\n}\nx\n",
+            "",
+            "a\na\na\nb\n",
+            "b\na\nc\na\nlast",
+            "a\nb\nc\nd\ne\n",
+        ];
+        let algorithms = [
+            DiffAlgorithm::Lines,
+            DiffAlgorithm::Myers,
+            DiffAlgorithm::Patience,
+            DiffAlgorithm::Histogram,
+        ];
+        for algorithm in algorithms {
+            let mut history = vec![];
+            for content in versions {
+                let changes = find_in_aligned(path, content.as_bytes(), &history, algorithm);
+                history.extend(changes.unwrap());
+                let replayed = replay(&history);
+                let wanted = lines_of(content.as_bytes());
+                assert_eq!(replayed[..wanted.len()], wanted, "{:?}", algorithm);
+                // the lines past the end of the file are left empty
+                assert!(replayed[wanted.len()..].iter().all(String::is_empty));
+            }
+        }
+        assert_eq!(DiffAlgorithm::default(), DiffAlgorithm::Myers);
+    }
+
+    #[test]
+    fn histogram_ops_cover_both_sides_in_order() {
+        let lines = |text: &str| text.split(' ').map(String::from).collect::<Vec<_>>();
+        let old = lines("} a } b } c } d");
+        let new = lines("} x } b } y } d e");
+        let ops = histogram_ops(&old, &new);
+        let (mut o, mut n) = (0, 0);
+        for op in &ops {
+            let (tag, old_range, new_range) = op.as_tag_tuple();
+            assert_eq!((old_range.start, new_range.start), (o, n));
+            if tag == similar::DiffTag::Equal {
+                assert_eq!(old[old_range.clone()], new[new_range.clone()]);
+            }
+            o = old_range.end;
+            n = new_range.end;
+        }
+        assert_eq!((o, n), (old.len(), new.len()));
+        // the rare lines anchor the diff, not the braces
+        assert!(ops.contains(&similar::DiffOp::Equal {
+            old_index: 3,
+            new_index: 3,
+            len: 2
+        }));
+    }
+
     #[ignore]
     #[test]
     fn a_lot_of_lines() {
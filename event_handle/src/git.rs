@@ -0,0 +1,277 @@
+//!
+//! the git commit a file was changed on top of. the enclosing repository is
+//! found by walking up from the file to a `.git` directory, or a `.git` file
+//! pointing to it for worktrees and submodules. HEAD is resolved from the
+//! files in there, only whether the work tree is dirty needs the git binary.
+//! its answer is kept for a moment, a burst of changes asks it once. the
+//! repository a directory is in is kept a while longer, outside of one it
+//! is looked for every time, so a `git init` is seen right away.
+//!
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// the version metadata key of the HEAD commit
+pub static HEAD_KEY: &str = "git_head";
+/// the version metadata key of whether the work tree had uncommitted changes
+pub static DIRTY_KEY: &str = "git_dirty";
+
+/// how long the dirty state of a work tree is taken as it is
+const DIRTY_TTL: Duration = Duration::from_secs(2);
+
+/// how long the repository found for a directory is taken as it is, e.g. for a nested one
+const REPO_TTL: Duration = Duration::from_secs(30);
+
+/// the dirty state last asked for of each work tree
+static DIRTY: Mutex<Vec<Dirty>> = Mutex::new(Vec::new());
+
+/// the repositories of the directories files changed in lately
+static REPOS: Mutex<Vec<Repo>> = Mutex::new(Vec::new());
+
+/// The work tree and the git directory a directory is in.
+struct Repo {
+    dir: PathBuf,
+    found: Instant,
+    work_tree: PathBuf,
+    git_dir: PathBuf,
+}
+
+/// What git said about a work tree on the HEAD it was on.
+struct Dirty {
+    work_tree: PathBuf,
+    head: String,
+    asked: Instant,
+    dirty: Option<bool>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GitState {
+    pub head: String,
+    /// `None` if git isn't installed or failed
+    pub dirty: Option<bool>,
+}
+
+impl GitState {
+    /// the state of the repository `path` is in, `None` outside of one
+    pub fn of(path: &Path) -> Option<GitState> {
+        let (work_tree, git_dir) = cached_repo(path)?;
+        let head = resolve_head(&git_dir);
+        if head.is_none() {
+            // removed, or a branch without commits yet
+            forget_repo(&git_dir);
+        }
+        let head = head?;
+        let dirty = cached_dirty(&work_tree, &head);
+        Some(GitState { head, dirty })
+    }
+
+    /// the metadata stamped into a version
+    pub fn meta(&self) -> Vec<(&'static str, String)> {
+        let head = (HEAD_KEY, self.head.clone());
+        let dirty = self.dirty.map(|dirty| (DIRTY_KEY, dirty.to_string()));
+        std::iter::once(head).chain(dirty).collect()
+    }
+}
+
+/// `find_repo` of `path`, a repository found is looked up again for its directory after `REPO_TTL`
+fn cached_repo(path: &Path) -> Option<(PathBuf, PathBuf)> {
+    let dir = path.parent()?;
+    let mut repos = REPOS.lock().unwrap();
+    let now = Instant::now();
+    repos.retain(|cached| now.duration_since(cached.found) < REPO_TTL);
+    if let Some(cached) = repos.iter().find(|cached| cached.dir == dir) {
+        return Some((cached.work_tree.clone(), cached.git_dir.clone()));
+    }
+    let (work_tree, git_dir) = find_repo(path)?;
+    repos.push(Repo {
+        dir: dir.to_path_buf(),
+        found: now,
+        work_tree: work_tree.clone(),
+        git_dir: git_dir.clone(),
+    });
+    Some((work_tree, git_dir))
+}
+
+fn forget_repo(git_dir: &Path) {
+    REPOS
+        .lock()
+        .unwrap()
+        .retain(|cached| cached.git_dir != git_dir);
+}
+
+/// the work tree and the git directory of the closest repository above `path`
+fn find_repo(path: &Path) -> Option<(PathBuf, PathBuf)> {
+    path.ancestors().skip(1).find_map(|dir| {
+        let dot_git = dir.join(".git");
+        if dot_git.is_dir() {
+            return Some((dir.to_path_buf(), dot_git));
+        }
+        let gitdir = fs::read_to_string(&dot_git).ok()?;
+        let gitdir = gitdir.trim().strip_prefix("gitdir:")?.trim();
+        Some((dir.to_path_buf(), dir.join(gitdir)))
+    })
+}
+
+///
+/// the commit HEAD points to, either directly when it is detached or through
+/// a loose or packed branch ref. worktrees keep their refs in the common dir.
+///
+fn resolve_head(git_dir: &Path) -> Option<String> {
+    let head = fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    let head = head.trim();
+    let reference = match head.strip_prefix("ref:") {
+        Some(reference) => reference.trim(),
+        None => return is_sha(head).then(|| head.to_string()),
+    };
+
+    let common_dir = fs::read_to_string(git_dir.join("commondir"))
+        .map(|common_dir| git_dir.join(common_dir.trim()))
+        .unwrap_or_else(|_| git_dir.to_path_buf());
+    [git_dir, common_dir.as_path()].iter().find_map(|dir| {
+        let loose = fs::read_to_string(dir.join(reference)).ok();
+        let sha = loose.or_else(|| packed_ref(dir, reference))?;
+        let sha = sha.trim();
+        is_sha(sha).then(|| sha.to_string())
+    })
+}
+
+fn packed_ref(git_dir: &Path, reference: &str) -> Option<String> {
+    fs::read_to_string(git_dir.join("packed-refs"))
+        .ok()?
+        .lines()
+        .filter(|line| !line.starts_with('#') && !line.starts_with('^'))
+        .find_map(|line| {
+            let (sha, name) = line.split_once(' ')?;
+            (name == reference).then(|| sha.to_string())
+        })
+}
+
+fn is_sha(sha: &str) -> bool {
+    sha.len() >= 40 && sha.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+///
+/// `is_dirty` of `work_tree`, asked again once HEAD moved or `DIRTY_TTL`
+/// passed since. the workers asking at the same time wait for one answer.
+///
+fn cached_dirty(work_tree: &Path, head: &str) -> Option<bool> {
+    let mut cache = DIRTY.lock().unwrap();
+    let now = Instant::now();
+    cache.retain(|cached| now.duration_since(cached.asked) < DIRTY_TTL);
+    let cached = cache
+        .iter()
+        .find(|cached| cached.work_tree == work_tree && cached.head == head);
+    if let Some(cached) = cached {
+        return cached.dirty;
+    }
+    let dirty = is_dirty(work_tree);
+    cache.retain(|cached| cached.work_tree != work_tree);
+    cache.push(Dirty {
+        work_tree: work_tree.to_path_buf(),
+        head: head.to_string(),
+        asked: now,
+        dirty,
+    });
+    dirty
+}
+
+///
+/// untracked files don't count, a new file isn't on top of anything yet.
+/// git leaves the index alone, refreshing it would be a change below the
+/// watch root to record again.
+///
+fn is_dirty(work_tree: &Path) -> Option<bool> {
+    let output = Command::new("git")
+        .arg("--no-optional-locks")
+        .arg("-C")
+        .arg(work_tree)
+        .args(["status", "--porcelain", "--untracked-files=no"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    output.status.success().then_some(!output.stdout.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static SHA: &str = "3f786850e387550fdab836ed7e6dc881de23001b";
+    static OTHER_SHA: &str = "89e6c98d92887913cadf06b2adb97f26cde4849b";
+
+    #[test]
+    fn head_is_resolved_through_loose_and_packed_refs() {
+        let dir = std::env::temp_dir().join(format!("auto_stash_git_{}", std::process::id()));
+        let git_dir = dir.join("repo/.git");
+        fs::create_dir_all(git_dir.join("refs/heads")).unwrap();
+        fs::create_dir_all(dir.join("repo/src")).unwrap();
+        fs::write(git_dir.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        let file = dir.join("repo/src/lib.rs");
+
+        let packed = format!("# pack-refs with: peeled\n{} refs/heads/main\n", SHA);
+        fs::write(git_dir.join("packed-refs"), packed).unwrap();
+        assert_eq!(resolve_head(&git_dir), Some(SHA.to_string()));
+        // a loose ref is newer than the packed one
+        fs::write(git_dir.join("refs/heads/main"), format!("{}\n", OTHER_SHA)).unwrap();
+        assert_eq!(GitState::of(&file).unwrap().head, OTHER_SHA);
+
+        // detached
+        fs::write(git_dir.join("HEAD"), SHA).unwrap();
+        assert_eq!(GitState::of(&file).unwrap().head, SHA);
+        // a branch without commits has no HEAD yet
+        fs::write(git_dir.join("HEAD"), "ref: refs/heads/new\n").unwrap();
+        assert_eq!(GitState::of(&file), None);
+
+        // a worktree points to its git dir, which shares the refs of the repository
+        let worktree_git_dir = git_dir.join("worktrees/feature");
+        fs::create_dir_all(&worktree_git_dir).unwrap();
+        fs::write(worktree_git_dir.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        fs::write(worktree_git_dir.join("commondir"), "../..\n").unwrap();
+        fs::create_dir_all(dir.join("feature")).unwrap();
+        let gitdir = format!("gitdir: {}\n", worktree_git_dir.display());
+        fs::write(dir.join("feature/.git"), gitdir).unwrap();
+        let worktree_file = dir.join("feature/a.txt");
+        assert_eq!(GitState::of(&worktree_file).unwrap().head, OTHER_SHA);
+
+        assert_eq!(GitState::of(&dir.join("a.txt")), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn asking_whether_the_work_tree_is_dirty_leaves_the_index_alone() {
+        let dir = std::env::temp_dir().join(format!("auto_stash_index_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let git = |args: &[&str]| {
+            Command::new("git")
+                .arg("-C")
+                .arg(&dir)
+                .args(["-c", "user.name=a", "-c", "user.email=a@a"])
+                .args(args)
+                .output()
+                .is_ok_and(|output| output.status.success())
+        };
+        // nothing to check without git
+        if !git(&["init", "-q"]) {
+            return fs::remove_dir_all(&dir).unwrap();
+        }
+        fs::write(dir.join("a.txt"), "a\n").unwrap();
+        assert!(git(&["add", "a.txt"]) && git(&["commit", "-qm", "a"]));
+        let index = || fs::read(dir.join(".git/index")).unwrap();
+        let before = index();
+
+        // the same content written again, a plain status would refresh its stat data
+        std::thread::sleep(Duration::from_millis(1100));
+        fs::write(dir.join("a.txt"), "a\n").unwrap();
+        assert_eq!(is_dirty(&dir), Some(false));
+        assert_eq!(index(), before);
+        assert_eq!(
+            cached_repo(&dir.join("a.txt")),
+            cached_repo(&dir.join("b.txt"))
+        );
+        assert_eq!(cached_repo(&dir.join("a.txt")).unwrap().0, dir);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
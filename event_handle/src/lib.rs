@@ -1,119 +1,1462 @@
+pub mod git;
+pub mod stores;
+
 pub mod event_handle {
-    use diff::LineDifference;
+    use crate::git::GitState;
+    use crate::stores::Stores;
+    use diff::{DiffSummary, LineDifference};
     use flume::{Receiver, Sender};
     use notify::DebouncedEvent;
+    use serde::Serialize;
     use std::path::Path;
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::HashMap;
+    use std::fmt;
+    use std::hash::{Hash, Hasher};
+    use std::io;
     use std::path::PathBuf;
-    use std::process;
-    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Condvar, Mutex};
     use std::thread;
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+    use store::retention::Retention;
     use store::store::FileVersions;
     use store::store::Store;
     use store::store::TimeFrame;
+    use store::store::VersionSource;
+    use store::store::VersionSummary;
+
+    static RETRY_DELAY: Duration = Duration::from_millis(50);
+    /// reads of a file that keeps changing before its diff is given up
+    static MAX_READ_ATTEMPTS: usize = 3;
+    /// queued paths per worker before the watcher has to wait for them
+    static QUEUE_PER_WORKER: usize = 64;
+    /// progress updates per operation at most, so a large store doesn't flood the TUI
+    static PROGRESS_STEPS: usize = 100;
+    /// how long a restore or removal waits for the event it expects, see `Expiring`
+    static EXPECTED_EVENT_TTL: Duration = Duration::from_secs(60);
+
+    /// the versions of every file, like `Stores::view`
+    pub type ViewUpdate = Vec<Option<FileVersions>>;
+    type ViewSender = Sender<ViewUpdate>;
+
+    /// What the TUI gets, the full view or the files of it that changed for it.
+    #[derive(Clone, Debug)]
+    pub enum UiView {
+        Full(ViewUpdate),
+        /// the page the TUI shows, see `EventHandle::show_page`, and the file recorded
+        Page(Vec<FileVersions>),
+    }
+
+    impl UiView {
+        /// the files of `next` replace those with their path, a full view replaces everything
+        pub fn then(self, next: UiView) -> UiView {
+            match (self, next) {
+                (_, UiView::Full(view)) => UiView::Full(view),
+                (UiView::Full(mut view), UiView::Page(files)) => {
+                    merge_page(&mut view, files);
+                    UiView::Full(view)
+                }
+                (UiView::Page(files), UiView::Page(next)) => {
+                    let mut page: ViewUpdate = files.into_iter().map(Some).collect();
+                    merge_page(&mut page, next);
+                    UiView::Page(page.into_iter().flatten().collect())
+                }
+            }
+        }
+    }
+
+    /// puts the files of a page into `view` in place of those with the same path
+    pub fn merge_page(view: &mut ViewUpdate, files: Vec<FileVersions>) {
+        for file_versions in files {
+            let known = view.iter().position(|known| {
+                known
+                    .as_ref()
+                    .is_some_and(|known| known.path == file_versions.path)
+            });
+            match known {
+                Some(position) => view[position] = Some(file_versions),
+                None => view.push(Some(file_versions)),
+            }
+        }
+    }
 
     pub struct EventHandle {
-        store: Arc<Mutex<Store>>,
+        stores: Arc<Stores>,
         communication: Arc<EventHandleCommunication>,
+        /// paths an undo or redo is writing to, their next change is a restore
+        restoring: Arc<Mutex<Expiring<()>>>,
+        /// last known content of files about to be removed, read on `NoticeRemove`
+        removing: Arc<Mutex<Expiring<Vec<u8>>>>,
+        /// records the changed files instead of the watcher thread, see `spawn_workers`
+        workers: Option<Arc<Workers>>,
+        /// where the file versions go, see `attach_ui` and `subscribe`
+        viewers: Arc<Mutex<Viewers>>,
+    }
+
+    ///
+    /// paths waiting for an event, like the write of a restore or the
+    /// removal a `NoticeRemove` announced. the event may never come, e.g.
+    /// an undo writing the content the file already has or a removal that
+    /// turned out to be a rename. after `EXPECTED_EVENT_TTL` the path is
+    /// forgotten, so neither a later change is taken for a restore nor
+    /// the content stays around.
+    ///
+    #[derive(Default)]
+    struct Expiring<T> {
+        entries: HashMap<String, (Instant, T)>,
+    }
+
+    impl<T> Expiring<T> {
+        fn insert(&mut self, path: String, value: T) {
+            self.entries
+                .retain(|_, (since, _)| since.elapsed() < EXPECTED_EVENT_TTL);
+            self.entries.insert(path, (Instant::now(), value));
+        }
+
+        fn remove(&mut self, path: &str) -> Option<T> {
+            self.entries
+                .remove(path)
+                .filter(|(since, _)| since.elapsed() < EXPECTED_EVENT_TTL)
+                .map(|(_, value)| value)
+        }
+
+        fn contains(&self, path: &str) -> bool {
+            self.entries
+                .get(path)
+                .is_some_and(|(since, _)| since.elapsed() < EXPECTED_EVENT_TTL)
+        }
+    }
+
+    /// The TUI, while one is attached, and the other subscribers of the views.
+    struct Viewers {
+        ui: Option<Sender<UiView>>,
+        subscribers: Vec<ViewSender>,
+        /// the files the TUI shows, it gets the full view until it tells
+        page: Option<(usize, usize)>,
+        /// how many files the last full view for the TUI held and its epoch
+        shown: Option<(usize, u64)>,
+    }
+
+    /// How far a path handed to the workers is.
+    #[derive(Clone, Copy, PartialEq)]
+    enum Pending {
+        Queued,
+        Recording,
+        /// it gets recorded once more afterwards
+        ChangedWhileRecording,
+    }
+
+    ///
+    /// a bounded queue of changed paths the worker threads record. a path
+    /// is queued once however often it changes until it got recorded, a
+    /// full queue blocks the watcher until the workers caught up.
+    ///
+    struct Workers {
+        queue: Sender<PathBuf>,
+        pending: Mutex<HashMap<PathBuf, Pending>>,
+        settled: Condvar,
+    }
+
+    impl Workers {
+        fn submit(&self, path: &Path) {
+            {
+                let mut pending = self.pending.lock().unwrap();
+                match pending.get(path) {
+                    None => pending.insert(path.to_path_buf(), Pending::Queued),
+                    Some(Pending::Recording) => {
+                        pending.insert(path.to_path_buf(), Pending::ChangedWhileRecording);
+                        return;
+                    }
+                    Some(_) => return,
+                };
+            }
+            // the workers stop only once the handle with them is gone
+            let _ = self.queue.send(path.to_path_buf());
+        }
+
+        /// whether `path` has to be recorded (again), it is done otherwise
+        fn take(&self, path: &Path) -> bool {
+            let mut pending = self.pending.lock().unwrap();
+            match pending.get(path) {
+                Some(Pending::Queued) | Some(Pending::ChangedWhileRecording) => {
+                    pending.insert(path.to_path_buf(), Pending::Recording);
+                    true
+                }
+                _ => {
+                    pending.remove(path);
+                    if pending.is_empty() {
+                        self.settled.notify_all();
+                    }
+                    false
+                }
+            }
+        }
+
+        fn wait_until_settled(&self) {
+            let pending = self.pending.lock().unwrap();
+            let _settled = self
+                .settled
+                .wait_while(pending, |pending| !pending.is_empty())
+                .unwrap();
+        }
     }
 
     pub struct EventHandleCommunication {
-        pub file_versions_to_ui: Sender<Vec<Option<FileVersions>>>,
+        pub file_versions_to_ui: Sender<UiView>,
+        /// the files the TUI shows as an offset and a count, see `EventHandle::show_page`
+        pub on_visible_page: Receiver<(usize, usize)>,
+        /// where a restarted TUI wants the views, see `EventHandle::attach_ui`
+        pub on_attach_ui: Receiver<Sender<UiView>>,
         pub on_undo: Receiver<(String, usize)>,
         pub on_redo: Receiver<(String, usize)>,
         pub on_time_frame_change: Receiver<TimeFrame>,
+        pub on_purge: Receiver<String>,
+        /// a path to checkpoint with an optional label
+        pub on_checkpoint: Receiver<(String, Option<String>)>,
+        /// a path to disable, or to enable again if it is disabled
+        pub on_toggle_disabled: Receiver<String>,
+        /// a path and the debounce its saves wait for, `None` for the global one
+        pub on_set_debounce: Receiver<(String, Option<Duration>)>,
+        /// a path to keep every version of, or to leave to the retention policy again
+        pub on_toggle_pinned: Receiver<String>,
+        /// a path to record right away whatever its debounce, `None` for every tracked file
+        pub on_record_now: Receiver<Option<String>>,
+        pub watch_root_to_ui: Sender<bool>,
+        pub on_copy_version: Receiver<(String, usize)>,
+        /// a version and the one to diff it from, `None` for the version before it
+        pub on_copy_diff: Receiver<(String, Option<usize>, usize)>,
+        pub on_show_version: Receiver<(String, usize)>,
+        /// the content of a version to show, or why it couldn't be rebuilt
+        pub version_content_to_ui: Sender<(String, usize, Result<String, String>)>,
+        /// a path and how many of its latest versions to list
+        pub on_list_versions: Receiver<(String, usize)>,
+        /// the latest versions of a path asked for, with the store epoch they are from
+        pub version_list_to_ui: Sender<(String, u64, Vec<VersionSummary>)>,
+        /// text to put on the clipboard or why it couldn't be produced
+        pub clipboard_to_ui: Sender<Result<String, String>>,
+        /// every recorded version and watch event, drained unless an output
+        /// or a crash report reads it
+        pub activity_to_output: Sender<Activity>,
+        /// the adaptive debounce of a file whenever it changes
+        pub debounce_to_ui: Sender<(String, Duration)>,
+        /// whether an idle maintenance pass is running
+        pub maintenance_to_ui: Sender<bool>,
+        /// how far a long running operation is, `None` once it finished
+        pub progress_to_ui: Sender<Option<Progress>>,
+        /// what auto-stash is doing, sent every second while a TUI is attached
+        pub metrics_to_ui: Sender<Metrics>,
+    }
+
+    /// How far a long running operation like rebuilding the view is.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Progress {
+        pub operation: String,
+        pub done: usize,
+        pub total: usize,
+    }
+
+    impl fmt::Display for Progress {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{} {}/{}", self.operation, self.done, self.total)
+        }
+    }
+
+    /// the versions of the last minute are counted for `Metrics`
+    pub static METRICS_WINDOW: Duration = Duration::from_secs(60);
+
+    /// What auto-stash is doing right now, shown in the status bar of the TUI.
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct Metrics {
+        /// versions of all files recorded within `METRICS_WINDOW`
+        pub recent_versions: usize,
+        pub tracked_files: usize,
+        /// the store files together
+        pub store_bytes: u64,
+    }
+
+    impl fmt::Display for Metrics {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            let units = ["B", "KiB", "MiB", "GiB"];
+            let mut size = self.store_bytes as f64;
+            let mut unit = 0;
+            while size >= 1024.0 && unit < units.len() - 1 {
+                size /= 1024.0;
+                unit += 1;
+            }
+            write!(
+                f,
+                "{} versions/min · {} files · {:.1} {}",
+                self.recent_versions, self.tracked_files, size, units[unit]
+            )
+        }
+    }
+
+    /// A recorded version or watch event, as streamed by `--output jsonl`.
+    #[derive(Clone, Debug, PartialEq, Serialize)]
+    pub struct Activity {
+        pub kind: ActivityKind,
+        pub path: String,
+        /// the recorded version, `None` for watch events
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub version: Option<usize>,
+        /// seconds since the unix epoch
+        pub timestamp: u64,
+        pub added: usize,
+        pub removed: usize,
+        pub modified: usize,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum ActivityKind {
+        Change,
+        /// every line got removed but the file is still there, unlike `Remove`
+        Truncate,
+        Remove,
+        Restore,
+        Scan,
+        Checkpoint,
+        /// the history of a file went along with it to `path`, see `Store::find_moved_files`
+        Move,
+        RootUnavailable,
+        RootAvailable,
+    }
+
+    impl Activity {
+        fn new(
+            kind: ActivityKind,
+            path: &str,
+            version: Option<usize>,
+            changes: &[LineDifference],
+        ) -> Activity {
+            let summary = DiffSummary::of(changes);
+            Activity {
+                kind,
+                path: path.to_string(),
+                version,
+                timestamp: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |duration| duration.as_secs()),
+                added: summary.added,
+                removed: summary.removed,
+                modified: summary.modified,
+            }
+        }
+    }
+
+    /// a TUI that went away is detached, recording goes on without it
+    fn send_to_ui(viewers: &mut Viewers, view: UiView) {
+        let detached = viewers.ui.as_ref().is_some_and(|sender| sender.send(view).is_err());
+        if detached {
+            eprintln!("The TUI went away, recording goes on without it");
+            viewers.ui = None;
+        }
     }
 
     fn transmit_file_versions(event_handle: &EventHandle) {
-        let view = event_handle.store.lock().unwrap().view().unwrap();
+        // nobody would see the view
+        if !event_handle.is_viewed() {
+            return;
+        }
+        let communication = &event_handle.communication;
+        let step = |total: usize| (total / PROGRESS_STEPS).max(1);
+        let view = event_handle
+            .stores
+            .view_with_progress(|done, total| {
+                if done % step(total) == 0 || done == total {
+                    report_progress(communication, "Loading file versions", done, total);
+                }
+            })
+            .unwrap();
+
+        event_handle.send_file_versions(view);
+        report_progress_finished(communication);
+    }
+
+    fn report_progress(
+        communication: &EventHandleCommunication,
+        operation: &str,
+        done: usize,
+        total: usize,
+    ) {
+        let progress = Progress {
+            operation: operation.to_string(),
+            done,
+            total,
+        };
+        communication
+            .progress_to_ui
+            .send(Some(progress))
+            .unwrap_or_else(|err| {
+                eprintln!("Could not transmit progress to TUI {:?}", err);
+            });
+    }
+
+    fn report_progress_finished(communication: &EventHandleCommunication) {
+        communication
+            .progress_to_ui
+            .send(None)
+            .unwrap_or_else(|err| {
+                eprintln!("Could not transmit progress to TUI {:?}", err);
+            });
+    }
 
-        event_handle
-            .communication
-            .file_versions_to_ui
-            .send(view)
+    fn transmit_clipboard(
+        communication: &EventHandleCommunication,
+        text: Result<String, Box<dyn std::error::Error>>,
+    ) {
+        communication
+            .clipboard_to_ui
+            .send(text.map_err(|err| err.to_string()))
             .unwrap_or_else(|err| {
-                eprintln!("Could not transmit data to TUI {:?}", err);
-                process::exit(1);
+                eprintln!("Could not transmit clipboard content to TUI {:?}", err);
             });
     }
 
+    fn content_hash(content: &[u8]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        content.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    ///
+    /// diffs one snapshot of a file and reads it again afterwards, if it
+    /// changed in the meantime the new content gets diffed instead. returns
+    /// the snapshot together with its changes.
+    ///
+    fn find_consistent(
+        path: &str,
+        prev_changes: &[LineDifference],
+        read: impl FnMut() -> io::Result<Vec<u8>>,
+    ) -> Result<(Vec<u8>, Vec<LineDifference>), Box<dyn std::error::Error>> {
+        find_consistent_with(
+            path,
+            |content| diff::find_in(path, content, prev_changes),
+            read,
+        )
+    }
+
+    /// like `find_consistent` with the changes of a snapshot coming from `diff`
+    fn find_consistent_with(
+        path: &str,
+        mut diff: impl FnMut(&[u8]) -> Result<Vec<LineDifference>, Box<dyn std::error::Error>>,
+        mut read: impl FnMut() -> io::Result<Vec<u8>>,
+    ) -> Result<(Vec<u8>, Vec<LineDifference>), Box<dyn std::error::Error>> {
+        let mut content = read()?;
+        for _ in 0..MAX_READ_ATTEMPTS {
+            let changes = diff(&content)?;
+            let current = read()?;
+            if content_hash(&current) == content_hash(&content) {
+                return Ok((content, changes));
+            }
+            content = current;
+        }
+        Err(format!("{} kept changing while it was read", path).into())
+    }
+
+    fn report_version(
+        communication: &EventHandleCommunication,
+        store: &Store,
+        path: &str,
+        changes: &[LineDifference],
+        kind: ActivityKind,
+    ) {
+        let version = store
+            .version_count(path)
+            .ok()
+            .and_then(|count| count.checked_sub(1));
+        // the receiver is gone once the process is shutting down
+        let _ = communication
+            .activity_to_output
+            .send(Activity::new(kind, path, version, changes));
+    }
+
+    /// notes the commit the latest version of `path` was recorded on top of
+    fn stamp_git_state(store: &mut Store, path: &str, git: &Option<GitState>) {
+        let version = store
+            .version_count(path)
+            .ok()
+            .and_then(|count| count.checked_sub(1));
+        if let (Some(git), Some(version)) = (git, version) {
+            if let Err(err) = store.set_version_meta(path, version, &git.meta()) {
+                eprintln!("Could not note the git HEAD of {}: {}", path, err);
+            }
+        }
+    }
+
+    fn is_permission_denied(err: &(dyn std::error::Error + 'static)) -> bool {
+        err.downcast_ref::<io::Error>()
+            .is_some_and(|err| err.kind() == io::ErrorKind::PermissionDenied)
+    }
+
+    fn report_maintenance(communication: &EventHandleCommunication, running: bool) {
+        communication
+            .maintenance_to_ui
+            .send(running)
+            .unwrap_or_else(|err| {
+                eprintln!("Could not transmit maintenance state to TUI {:?}", err);
+            });
+    }
+
+    fn report_evictions(store: &mut Store) {
+        store.take_evictions().iter().for_each(|eviction| {
+            eprintln!(
+                "Store size limit reached, evicted the version of {} from {}",
+                eviction.path, eviction.datetime
+            );
+        });
+    }
+
     impl EventHandle {
         pub fn new(store: Store, communication: EventHandleCommunication) -> EventHandle {
+            EventHandle::with_stores(Stores::new(store), communication)
+        }
+
+        /// like `new` with every file recorded in the store it is routed to
+        pub fn with_stores(stores: Stores, communication: EventHandleCommunication) -> EventHandle {
             EventHandle {
-                store: Arc::new(Mutex::new(store)),
+                stores: Arc::new(stores),
+                restoring: Arc::new(Mutex::new(Expiring::default())),
+                removing: Arc::new(Mutex::new(Expiring::default())),
+                workers: None,
+                viewers: Arc::new(Mutex::new(Viewers {
+                    ui: Some(communication.file_versions_to_ui.clone()),
+                    subscribers: Vec::new(),
+                    page: None,
+                    shown: None,
+                })),
                 communication: Arc::new(communication),
             }
         }
 
+        ///
+        /// records changed files on `count` threads from now on, events
+        /// are handled without waiting for their diff
+        ///
+        pub fn spawn_workers(&mut self, count: usize) {
+            let count = count.max(1);
+            let (queue, on_queue) = flume::bounded::<PathBuf>(count * QUEUE_PER_WORKER);
+            let workers = Arc::new(Workers {
+                queue,
+                pending: Mutex::new(HashMap::new()),
+                settled: Condvar::new(),
+            });
+            (0..count).for_each(|_| {
+                let on_queue = on_queue.clone();
+                // the queue closes with the handle, the workers don't keep it open
+                let workers = Arc::downgrade(&workers);
+                let mut event_handle = EventHandle {
+                    communication: self.communication.clone(),
+                    stores: self.stores.clone(),
+                    restoring: self.restoring.clone(),
+                    removing: self.removing.clone(),
+                    workers: None,
+                    viewers: self.viewers.clone(),
+                };
+                thread::spawn(move || {
+                    for path in on_queue.iter() {
+                        let workers = match workers.upgrade() {
+                            Some(workers) => workers,
+                            None => return,
+                        };
+                        while workers.take(&path) {
+                            // removed in the meantime
+                            if path.is_file() {
+                                let _ = event_handle.on_file_change_or_skip(&path);
+                            }
+                        }
+                    }
+                });
+            });
+            self.workers = Some(workers);
+        }
+
+        ///
+        /// sends the views to `file_versions_to_ui` from now on, starting
+        /// with the full view. a restarted TUI takes over from the one that
+        /// went away this way, see `on_attach_ui`.
+        ///
+        pub fn attach_ui(&self, file_versions_to_ui: Sender<UiView>) {
+            {
+                let mut viewers = self.viewers.lock().unwrap();
+                viewers.ui = Some(file_versions_to_ui);
+                // the new TUI tells which files it shows
+                viewers.page = None;
+                viewers.shown = None;
+            }
+            match self.stores.view() {
+                Ok(view) => {
+                    let shown = (view.iter().flatten().count(), self.stores.epoch());
+                    let mut viewers = self.viewers.lock().unwrap();
+                    viewers.shown = Some(shown);
+                    send_to_ui(&mut viewers, UiView::Full(view));
+                }
+                Err(err) => eprintln!("Could not load the view for the TUI: {}", err),
+            }
+        }
+
+        /// attaches the TUIs sent to `on_attach_ui`, the handle itself is out of their reach
+        pub fn on_attach_ui(&mut self) {
+            let event_handle = EventHandle {
+                communication: self.communication.clone(),
+                stores: self.stores.clone(),
+                restoring: self.restoring.clone(),
+                removing: self.removing.clone(),
+                workers: None,
+                viewers: self.viewers.clone(),
+            };
+            thread::spawn(move || {
+                for file_versions_to_ui in event_handle.communication.on_attach_ui.iter() {
+                    event_handle.attach_ui(file_versions_to_ui);
+                }
+            });
+        }
+
+        ///
+        /// a receiver of every view from now on, next to the TUI or without
+        /// one. each subscriber gets its own copy, starting with the full
+        /// view. dropping the receiver unsubscribes.
+        ///
+        pub fn subscribe(&self) -> Receiver<ViewUpdate> {
+            let (view_to_subscriber, on_view) = flume::unbounded();
+            if let Ok(view) = self.stores.view() {
+                let _ = view_to_subscriber.send(view);
+            }
+            self.viewers.lock().unwrap().subscribers.push(view_to_subscriber);
+            on_view
+        }
+
+        fn is_ui_attached(&self) -> bool {
+            self.viewers.lock().unwrap().ui.is_some()
+        }
+
+        /// whether anybody gets the views, building one is wasted otherwise
+        fn is_viewed(&self) -> bool {
+            let viewers = self.viewers.lock().unwrap();
+            viewers.ui.is_some() || !viewers.subscribers.is_empty()
+        }
+
+        ///
+        /// a TUI that went away is detached, recording goes on without it.
+        /// subscribers whose receiver is gone are dropped.
+        ///
+        fn send_file_versions(&self, view: ViewUpdate) {
+            let shown = (view.iter().flatten().count(), self.stores.epoch());
+            let mut viewers = self.viewers.lock().unwrap();
+            viewers
+                .subscribers
+                .retain(|subscriber| subscriber.send(view.clone()).is_ok());
+            viewers.shown = Some(shown);
+            send_to_ui(&mut viewers, UiView::Full(view));
+        }
+
+        ///
+        /// after `path` changed. the subscribers get the full view, the TUI
+        /// only the page it shows and `path`. once files came or went or
+        /// the versions got renumbered it gets the full view again.
+        ///
+        fn send_changes(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+            let (page, shown, subscribed) = {
+                let viewers = self.viewers.lock().unwrap();
+                if viewers.ui.is_none() && viewers.subscribers.is_empty() {
+                    return Ok(());
+                }
+                (viewers.page, viewers.shown, !viewers.subscribers.is_empty())
+            };
+            let current = || (self.stores.visible_count(), self.stores.epoch());
+            match page {
+                Some((offset, limit)) if !subscribed && shown == Some(current()) => {
+                    let mut files = self.stores.view_page(offset, limit);
+                    if !files.iter().any(|file_versions| file_versions.path == path) {
+                        files.extend(self.stores.file_view(path));
+                    }
+                    send_to_ui(&mut self.viewers.lock().unwrap(), UiView::Page(files));
+                }
+                _ => self.send_file_versions(self.stores.view()?),
+            }
+            Ok(())
+        }
+
+        ///
+        /// the TUI shows `limit` files of the view ordered by path after
+        /// skipping `offset`, from now on a change only sends it those
+        ///
+        fn show_page(&self, offset: usize, limit: usize) {
+            let shown = {
+                let mut viewers = self.viewers.lock().unwrap();
+                viewers.page = Some((offset, limit));
+                viewers.shown
+            };
+            // the files scrolled to may be older than the rest of the view
+            if shown.is_some() {
+                let page = UiView::Page(self.stores.view_page(offset, limit));
+                send_to_ui(&mut self.viewers.lock().unwrap(), page);
+            }
+        }
+
+        pub fn on_visible_page(&mut self) {
+            let event_handle = EventHandle {
+                communication: self.communication.clone(),
+                stores: self.stores.clone(),
+                restoring: self.restoring.clone(),
+                removing: self.removing.clone(),
+                workers: None,
+                viewers: self.viewers.clone(),
+            };
+            thread::spawn(move || {
+                for (offset, limit) in event_handle.communication.on_visible_page.iter() {
+                    event_handle.show_page(offset, limit);
+                }
+            });
+        }
+
+        /// on the workers if there are any, right away otherwise
+        fn record(&mut self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+            match &self.workers {
+                Some(workers) => {
+                    workers.submit(path);
+                    Ok(())
+                }
+                None => self.on_file_change_or_skip(path),
+            }
+        }
+
+        fn wait_for_workers(&self) {
+            if let Some(workers) = &self.workers {
+                workers.wait_until_settled();
+            }
+        }
+
+        pub fn on_show_version(&mut self) {
+            let communication = self.communication.clone();
+            let stores = self.stores.clone();
+            thread::spawn(move || {
+                for (path, version) in communication.on_show_version.iter() {
+                    let content = stores
+                        .for_path(&path)
+                        .lock()
+                        .unwrap()
+                        .reconstruct(&path, version);
+                    communication
+                        .version_content_to_ui
+                        .send((path, version, content.map_err(|err| err.to_string())))
+                        .unwrap_or_else(|err| {
+                            eprintln!("Could not transmit version content to TUI {:?}", err);
+                        });
+                }
+            });
+        }
+
+        pub fn on_list_versions(&mut self) {
+            let communication = self.communication.clone();
+            let stores = self.stores.clone();
+            thread::spawn(move || {
+                for (path, count) in communication.on_list_versions.iter() {
+                    let (epoch, versions) = {
+                        let store = stores.for_path(&path).lock().unwrap();
+                        (store.epoch(), store.latest_n_versions(&path, count))
+                    };
+                    communication
+                        .version_list_to_ui
+                        .send((path, epoch, versions))
+                        .unwrap_or_else(|err| {
+                            eprintln!("Could not transmit versions to TUI {:?}", err);
+                        });
+                }
+            });
+        }
+
+        ///
+        /// flushes the store and, with `compact`, collects its orphaned data
+        /// if it is fragmented, in the background. the pass stops between
+        /// the steps once `interrupted` is set, e.g. by a new event.
+        ///
+        pub fn maintain(&self, compact: bool, interrupted: Arc<AtomicBool>) {
+            let communication = self.communication.clone();
+            let stores = self.stores.clone();
+            thread::spawn(move || {
+                report_maintenance(&communication, true);
+                let maintain = || -> Result<(), Box<dyn std::error::Error>> {
+                    for store in stores.all() {
+                        store.lock().unwrap().flush()?;
+                        let mut store = store.lock().unwrap();
+                        let compact = compact && !interrupted.load(Ordering::SeqCst);
+                        if compact && store.needs_compaction() {
+                            store.gc()?;
+                        }
+                    }
+                    Ok(())
+                };
+                maintain().unwrap_or_else(|err| {
+                    eprintln!("Idle maintenance failed: {}", err);
+                });
+                report_maintenance(&communication, false);
+            });
+        }
+
+        ///
+        /// sends the current `Metrics` to the TUI in the background, the
+        /// store files are only looked at while one is attached. adding them
+        /// up reads every version stack, it doesn't hold up the watcher.
+        ///
+        pub fn report_metrics(&self) -> Option<thread::JoinHandle<()>> {
+            if !self.is_ui_attached() {
+                return None;
+            }
+            let event_handle = EventHandle {
+                communication: self.communication.clone(),
+                stores: self.stores.clone(),
+                restoring: self.restoring.clone(),
+                removing: self.removing.clone(),
+                workers: None,
+                viewers: self.viewers.clone(),
+            };
+            Some(thread::spawn(move || {
+                event_handle
+                    .communication
+                    .metrics_to_ui
+                    .send(event_handle.metrics())
+                    .unwrap_or_else(|err| {
+                        eprintln!("Could not transmit metrics to TUI {:?}", err);
+                    });
+            }))
+        }
+
+        /// the `Metrics` of every store added up
+        pub fn metrics(&self) -> Metrics {
+            let since = SystemTime::now() - METRICS_WINDOW;
+            self.stores.all().fold(Metrics::default(), |metrics, store| {
+                let store = store.lock().unwrap();
+                Metrics {
+                    recent_versions: metrics.recent_versions + store.versions_since(since),
+                    tracked_files: metrics.tracked_files + store.tracked_paths_iter().count(),
+                    store_bytes: metrics.store_bytes + store.store_size(),
+                }
+            })
+        }
+
+        ///
+        /// flushes the store once the workers recorded the queued files and
+        /// the undo, redo and checkpoint threads let go of it
+        ///
+        pub fn flush(&self) -> Result<(), Box<dyn std::error::Error>> {
+            self.wait_for_workers();
+            self.stores
+                .all()
+                .try_for_each(|store| store.lock().unwrap().flush())
+        }
+
+        ///
+        /// sends the first view in the background, the TUI shows how far
+        /// it is in the meantime
+        ///
         pub fn init_file_versions(&self) {
-            transmit_file_versions(self);
+            let event_handle = EventHandle {
+                communication: self.communication.clone(),
+                stores: self.stores.clone(),
+                restoring: self.restoring.clone(),
+                removing: self.removing.clone(),
+                workers: None,
+                viewers: self.viewers.clone(),
+            };
+            thread::spawn(move || transmit_file_versions(&event_handle));
         }
 
         pub fn on_time_frame_change(&mut self) {
             let communication = self.communication.clone();
-            let store = self.store.clone();
-            thread::spawn(move || loop {
-                let time_frame = communication.on_time_frame_change.recv().unwrap();
-                store.lock().unwrap().change_time_frame(time_frame);
-                transmit_file_versions(&EventHandle {
-                    communication: communication.clone(),
-                    store: store.clone(),
-                });
+            let stores = self.stores.clone();
+            let restoring = self.restoring.clone();
+            let removing = self.removing.clone();
+            let viewers = self.viewers.clone();
+            thread::spawn(move || {
+                for time_frame in communication.on_time_frame_change.iter() {
+                    for store in stores.all() {
+                        store.lock().unwrap().change_time_frame(time_frame.clone());
+                    }
+                    transmit_file_versions(&EventHandle {
+                        communication: communication.clone(),
+                        stores: stores.clone(),
+                        restoring: restoring.clone(),
+                        removing: removing.clone(),
+                        workers: None,
+                        viewers: viewers.clone(),
+                    });
+                }
             });
         }
 
         pub fn on_undo(&mut self) {
             let communication = self.communication.clone();
-            let store = self.store.clone();
-            thread::spawn(move || loop {
-                let (path, count) = communication.on_undo.recv().unwrap();
-                store.lock().unwrap().undo_by(path, count).unwrap();
-                transmit_file_versions(&EventHandle {
-                    communication: communication.clone(),
-                    store: store.clone(),
-                });
+            let stores = self.stores.clone();
+            let restoring = self.restoring.clone();
+            let removing = self.removing.clone();
+            let viewers = self.viewers.clone();
+            thread::spawn(move || {
+                for (path, count) in communication.on_undo.iter() {
+                    restoring.lock().unwrap().insert(path.clone(), ());
+                    let done = stores.for_path(&path).lock().unwrap().undo_by(path.clone(), count);
+                    if let Err(err) = done {
+                        eprintln!("Could not undo {}: {}", path, err);
+                        restoring.lock().unwrap().remove(&path);
+                    }
+                    transmit_file_versions(&EventHandle {
+                        communication: communication.clone(),
+                        stores: stores.clone(),
+                        restoring: restoring.clone(),
+                        removing: removing.clone(),
+                        workers: None,
+                        viewers: viewers.clone(),
+                    });
+                }
             });
         }
 
         pub fn on_redo(&mut self) {
             let communication = self.communication.clone();
-            let store = self.store.clone();
-            thread::spawn(move || loop {
-                let (path, count) = communication.on_redo.recv().unwrap();
-                store.lock().unwrap().redo_by(path, count).unwrap();
-                transmit_file_versions(&EventHandle {
-                    communication: communication.clone(),
-                    store: store.clone(),
-                });
+            let stores = self.stores.clone();
+            let restoring = self.restoring.clone();
+            let removing = self.removing.clone();
+            let viewers = self.viewers.clone();
+            thread::spawn(move || {
+                for (path, count) in communication.on_redo.iter() {
+                    restoring.lock().unwrap().insert(path.clone(), ());
+                    let done = stores.for_path(&path).lock().unwrap().redo_by(path.clone(), count);
+                    if let Err(err) = done {
+                        eprintln!("Could not redo {}: {}", path, err);
+                        restoring.lock().unwrap().remove(&path);
+                    }
+                    transmit_file_versions(&EventHandle {
+                        communication: communication.clone(),
+                        stores: stores.clone(),
+                        restoring: restoring.clone(),
+                        removing: removing.clone(),
+                        workers: None,
+                        viewers: viewers.clone(),
+                    });
+                }
+            });
+        }
+
+        pub fn on_purge(&mut self) {
+            let communication = self.communication.clone();
+            let stores = self.stores.clone();
+            let restoring = self.restoring.clone();
+            let removing = self.removing.clone();
+            let viewers = self.viewers.clone();
+            thread::spawn(move || {
+                for path in communication.on_purge.iter() {
+                    stores.for_path(&path).lock().unwrap().purge(&path).unwrap_or_else(|err| {
+                        eprintln!("Could not purge {}: {}", path, err);
+                    });
+                    transmit_file_versions(&EventHandle {
+                        communication: communication.clone(),
+                        stores: stores.clone(),
+                        restoring: restoring.clone(),
+                        removing: removing.clone(),
+                        workers: None,
+                        viewers: viewers.clone(),
+                    });
+                }
+            });
+        }
+
+        pub fn on_checkpoint(&mut self) {
+            let communication = self.communication.clone();
+            let stores = self.stores.clone();
+            let restoring = self.restoring.clone();
+            let removing = self.removing.clone();
+            let viewers = self.viewers.clone();
+            thread::spawn(move || {
+                for (path, label) in communication.on_checkpoint.iter() {
+                    {
+                        let mut store = stores.for_path(&path).lock().unwrap();
+                        match store.touch(&path, label) {
+                            Ok(()) => report_version(
+                                &communication,
+                                &store,
+                                &path,
+                                &[],
+                                ActivityKind::Checkpoint,
+                            ),
+                            Err(err) => eprintln!("Could not checkpoint {}: {}", path, err),
+                        }
+                    }
+                    transmit_file_versions(&EventHandle {
+                        communication: communication.clone(),
+                        stores: stores.clone(),
+                        restoring: restoring.clone(),
+                        removing: removing.clone(),
+                        workers: None,
+                        viewers: viewers.clone(),
+                    });
+                }
+            });
+        }
+
+        pub fn on_toggle_disabled(&mut self) {
+            let communication = self.communication.clone();
+            let stores = self.stores.clone();
+            let restoring = self.restoring.clone();
+            let removing = self.removing.clone();
+            let viewers = self.viewers.clone();
+            thread::spawn(move || {
+                for path in communication.on_toggle_disabled.iter() {
+                    {
+                        let mut store = stores.for_path(&path).lock().unwrap();
+                        let toggled = match store.is_disabled(&path) {
+                            true => store.enable(&path),
+                            false => store.disable(&path).map(|_| false),
+                        };
+                        match toggled {
+                            Ok(true) => {
+                                let kind = ActivityKind::Change;
+                                report_version(&communication, &store, &path, &[], kind)
+                            }
+                            Ok(false) => {}
+                            Err(err) => {
+                                eprintln!("Could not toggle recording of {}: {}", path, err)
+                            }
+                        }
+                    }
+                    transmit_file_versions(&EventHandle {
+                        communication: communication.clone(),
+                        stores: stores.clone(),
+                        restoring: restoring.clone(),
+                        removing: removing.clone(),
+                        workers: None,
+                        viewers: viewers.clone(),
+                    });
+                }
+            });
+        }
+
+        pub fn on_toggle_pinned(&mut self) {
+            let communication = self.communication.clone();
+            let stores = self.stores.clone();
+            let restoring = self.restoring.clone();
+            let removing = self.removing.clone();
+            let viewers = self.viewers.clone();
+            thread::spawn(move || {
+                for path in communication.on_toggle_pinned.iter() {
+                    {
+                        let mut store = stores.for_path(&path).lock().unwrap();
+                        let toggled = match store.is_pinned(&path) {
+                            true => store.clear_retention(&path),
+                            false => store.set_retention(&path, Retention::Forever),
+                        };
+                        if let Err(err) = toggled {
+                            eprintln!("Could not toggle pinning of {}: {}", path, err);
+                        }
+                    }
+                    transmit_file_versions(&EventHandle {
+                        communication: communication.clone(),
+                        stores: stores.clone(),
+                        restoring: restoring.clone(),
+                        removing: removing.clone(),
+                        workers: None,
+                        viewers: viewers.clone(),
+                    });
+                }
+            });
+        }
+
+        pub fn on_set_debounce(&mut self) {
+            let communication = self.communication.clone();
+            let stores = self.stores.clone();
+            let restoring = self.restoring.clone();
+            let removing = self.removing.clone();
+            let viewers = self.viewers.clone();
+            thread::spawn(move || {
+                for (path, debounce) in communication.on_set_debounce.iter() {
+                    let set = stores.for_path(&path).lock().unwrap().set_debounce(&path, debounce);
+                    if let Err(err) = set {
+                        eprintln!("Could not set the debounce of {}: {}", path, err);
+                    }
+                    transmit_file_versions(&EventHandle {
+                        communication: communication.clone(),
+                        stores: stores.clone(),
+                        restoring: restoring.clone(),
+                        removing: removing.clone(),
+                        workers: None,
+                        viewers: viewers.clone(),
+                    });
+                }
+            });
+        }
+
+        pub fn on_copy_version(&mut self) {
+            let communication = self.communication.clone();
+            let stores = self.stores.clone();
+            thread::spawn(move || {
+                for (path, version) in communication.on_copy_version.iter() {
+                    let content = stores
+                        .for_path(&path)
+                        .lock()
+                        .unwrap()
+                        .reconstruct(&path, version);
+                    transmit_clipboard(&communication, content);
+                }
+            });
+        }
+
+        pub fn on_copy_diff(&mut self) {
+            let communication = self.communication.clone();
+            let stores = self.stores.clone();
+            thread::spawn(move || {
+                for (path, from, version) in communication.on_copy_diff.iter() {
+                    let from = from.or_else(|| version.checked_sub(1));
+                    let diff = stores
+                        .for_path(&path)
+                        .lock()
+                        .unwrap()
+                        .unified_diff_between(&path, from, version);
+                    transmit_clipboard(&communication, diff);
+                }
             });
         }
 
+        pub fn report_debounce(&self, path: &Path, debounce: Duration) {
+            self.communication
+                .debounce_to_ui
+                .send((path.to_string_lossy().to_string(), debounce))
+                .unwrap_or_else(|err| {
+                    eprintln!("Could not transmit debounce to TUI {:?}", err);
+                });
+        }
+
+        /// tells the ui whether the watch root is reachable
+        pub fn report_watch_root(&self, root: &str, available: bool) {
+            self.communication
+                .watch_root_to_ui
+                .send(available)
+                .unwrap_or_else(|err| {
+                    eprintln!("Could not transmit watch root state to TUI {:?}", err);
+                });
+            let kind = if available {
+                ActivityKind::RootAvailable
+            } else {
+                ActivityKind::RootUnavailable
+            };
+            let activity = Activity::new(kind, root, None, &[]);
+            let _ = self.communication.activity_to_output.send(activity);
+        }
+
+        ///
+        /// records what happened to the tracked files below `root` while no
+        /// events arrived, e.g. while the root was unmounted. files created
+        /// in the meantime are picked up on their next change.
+        ///
+        pub fn resync(&mut self, root: &str) {
+            self.carry_over_moves();
+            let tracked = self.stores.tracked_paths();
+            tracked
+                .iter()
+                .filter(|tracked| Path::new(&tracked.path).starts_with(root))
+                .for_each(|tracked| {
+                    let path = Path::new(&tracked.path);
+                    let synced = if path.is_file() {
+                        self.on_file_sync(path).map(|_| ())
+                    } else if !tracked.deleted {
+                        self.on_file_remove(path, VersionSource::Scan)
+                    } else {
+                        Ok(())
+                    };
+                    synced.unwrap_or_else(|err| {
+                        eprintln!("Could not resync {:?}: {}", path, err);
+                    });
+                });
+            transmit_file_versions(self);
+        }
+
+        ///
+        /// records the files changed while auto-stash wasn't running, see
+        /// `Store::changed_while_unwatched`. returns how many got recorded.
+        ///
+        pub fn catch_up(&mut self) -> usize {
+            self.carry_over_moves();
+            let changed = self
+                .stores
+                .all()
+                .flat_map(|store| store.lock().unwrap().changed_while_unwatched())
+                .collect::<Vec<String>>();
+            changed
+                .iter()
+                .filter(|path| {
+                    self.on_file_sync(Path::new(path))
+                        .map_err(|err| eprintln!("Could not catch up {:?}: {}", path, err))
+                        .unwrap_or(false)
+                })
+                .count()
+        }
+
+        ///
+        /// moves the histories of the files that turned up elsewhere with the
+        /// same content while nothing watched them, instead of recording a
+        /// removal and a new file. a file moved between stores goes once the
+        /// moves within them are done.
+        ///
+        fn carry_over_moves(&self) {
+            for store in self.stores.all() {
+                let mut store = store.lock().unwrap();
+                let moves = store.find_moved_files();
+                if moves.is_empty() {
+                    continue;
+                }
+                // the histories move together or stay where they are
+                let carried = store.begin().and_then(|mut txn| {
+                    for (from, to) in &moves {
+                        txn.move_history(from, to)
+                            .map_err(|err| format!("{} to {}: {}", from, to, err))?;
+                    }
+                    txn.commit()
+                });
+                if let Err(err) = carried {
+                    eprintln!("Could not move the histories of the moved files: {}", err);
+                    continue;
+                }
+                moves.iter().for_each(|(from, to)| self.report_move(from, to));
+            }
+            // moved below the root of another store, it takes the history there
+            for (from, to) in self.stores.find_moves_between() {
+                match self.stores.move_history(&from, &to) {
+                    Ok(()) => self.report_move(&from, &to),
+                    Err(err) => {
+                        eprintln!("Could not move the history of {} to {}: {}", from, to, err)
+                    }
+                }
+            }
+        }
+
+        fn report_move(&self, from: &str, to: &str) {
+            eprintln!("{} was moved to {}, its history went along", from, to);
+            let activity = Activity::new(ActivityKind::Move, to, None, &[]);
+            let _ = self.communication.activity_to_output.send(activity);
+        }
+
+        ///
+        /// records the changes of the tracked files modified since `since`
+        /// that no event brought in, in the background. a change an event
+        /// already recorded leaves nothing to diff, so it isn't recorded
+        /// twice. files an undo or redo is writing to are left to their event.
+        ///
+        pub fn poll(&self, since: SystemTime) -> thread::JoinHandle<()> {
+            let mut event_handle = EventHandle {
+                communication: self.communication.clone(),
+                stores: self.stores.clone(),
+                restoring: self.restoring.clone(),
+                removing: self.removing.clone(),
+                workers: None,
+                viewers: self.viewers.clone(),
+            };
+            let restoring = self.restoring.clone();
+            thread::spawn(move || {
+                let tracked = event_handle.stores.tracked_paths();
+                let recorded = tracked
+                    .iter()
+                    .filter(|tracked| !tracked.deleted)
+                    .filter(|tracked| !restoring.lock().unwrap().contains(&tracked.path))
+                    .filter(|tracked| {
+                        std::fs::metadata(&tracked.path)
+                            .and_then(|metadata| metadata.modified())
+                            .is_ok_and(|modified| modified >= since)
+                    })
+                    .filter(|tracked| {
+                        event_handle
+                            .on_file_sync(Path::new(&tracked.path))
+                            .map_err(|err| eprintln!("Could not poll {:?}: {}", tracked.path, err))
+                            .unwrap_or(false)
+                    })
+                    .count();
+                if recorded > 0 {
+                    transmit_file_versions(&event_handle);
+                }
+            })
+        }
+
+        /// remembers in every store that this run stops watching it
+        pub fn end_sessions(&self) -> Result<(), Box<dyn std::error::Error>> {
+            self.stores
+                .all()
+                .try_for_each(|store| store.lock().unwrap().end_session())
+        }
+
+        /// whether `path` differed from its latest version and got recorded
+        fn on_file_sync(&mut self, path: &Path) -> Result<bool, Box<dyn std::error::Error>> {
+            let path = path.to_str().unwrap();
+            self.record_if_changed(path, VersionSource::Scan, ActivityKind::Scan)
+        }
+
+        /// the files asked to be recorded right away since the last call, see `record_now`
+        pub fn take_record_now(&self) -> Vec<Option<String>> {
+            self.communication.on_record_now.try_iter().collect()
+        }
+
+        ///
+        /// records `path`, or every tracked file with `None`, right away if it
+        /// differs from its latest version, whatever its debounce. files an undo
+        /// or redo is writing to are left to their event, like in `poll`.
+        /// returns the files that got diffed, a write still held back for them
+        /// has nothing left to record.
+        ///
+        pub fn record_now(&self, path: Option<&str>) -> Vec<String> {
+            let paths = match path {
+                Some(path) => vec![path.to_string()],
+                None => self
+                    .stores
+                    .tracked_paths()
+                    .into_iter()
+                    .filter(|tracked| !tracked.deleted)
+                    .map(|tracked| tracked.path)
+                    .collect(),
+            };
+            let diffed = paths
+                .into_iter()
+                .filter(|path| !self.restoring.lock().unwrap().contains(path))
+                .filter(|path| Path::new(path).is_file())
+                .filter_map(|path| {
+                    let recorded =
+                        self.record_if_changed(&path, VersionSource::Watcher, ActivityKind::Change);
+                    match recorded {
+                        Ok(recorded) => Some((path, recorded)),
+                        Err(err) => {
+                            eprintln!("Could not record {}: {}", path, err);
+                            None
+                        }
+                    }
+                })
+                .collect::<Vec<_>>();
+            if diffed.iter().any(|(_, recorded)| *recorded) {
+                transmit_file_versions(self);
+            }
+            diffed.into_iter().map(|(path, _)| path).collect()
+        }
+
+        /// records `path` as coming from `source` unless it is unchanged, see `on_file_sync`
+        fn record_if_changed(
+            &self,
+            path: &str,
+            source: VersionSource,
+            kind: ActivityKind,
+        ) -> Result<bool, Box<dyn std::error::Error>> {
+            let git = GitState::of(Path::new(path));
+
+            let mut store = self.stores.for_path(path).lock().unwrap();
+            if store.is_disabled(path) {
+                return Ok(false);
+            }
+            let (content, changes) = find_consistent_with(
+                path,
+                |content| store.diff_snapshot(path, content),
+                || std::fs::read(path),
+            )?;
+            if changes.is_empty() && !store.mode_changed(path) {
+                return Ok(false);
+            }
+            let stored = store.store_snapshot(path, &content, &changes, source);
+            if stored.is_ok() {
+                stamp_git_state(&mut store, path, &git);
+                report_version(&self.communication, &store, path, &changes, kind);
+            }
+            report_evictions(&mut store);
+            stored.map(|_| true)
+        }
+
         pub fn handle(&mut self, event: DebouncedEvent) -> Result<(), Box<dyn std::error::Error>> {
             let path = self.to_path(&event)?;
             if path.is_none() {
                 return Ok(());
             }
             let path = path.unwrap();
-            if path.is_file() {
+            // its history stays, the changes are recorded once it is enabled
+            if self.is_disabled(&path) {
+                return Ok(());
+            }
+            if self.is_notice_removal(&event) {
+                self.stage_removal(&path);
+            } else if path.is_file() {
                 self.on_modification(&event, &path)?;
+            } else {
                 self.on_removal(&event, &path)?;
             }
             Ok(())
         }
 
+        fn is_disabled(&self, path: &Path) -> bool {
+            path.to_str()
+                .is_some_and(|path| self.stores.for_path(path).lock().unwrap().is_disabled(path))
+        }
+
+        /// the debounce set for `path` in place of the global one, see `Store::set_debounce`
+        pub fn debounce_of(&self, path: &Path) -> Option<Duration> {
+            let path = path.to_str()?;
+            self.stores.for_path(path).lock().unwrap().debounce_of(path)
+        }
+
+        ///
+        /// keeps the content of a file that is being removed, the removal is
+        /// confirmed once the file is gone and then it can't be read anymore
+        ///
+        fn stage_removal(&self, path: &Path) {
+            if let (Some(key), Ok(content)) = (path.to_str(), std::fs::read(path)) {
+                self.removing
+                    .lock()
+                    .unwrap()
+                    .insert(key.to_string(), content);
+            }
+        }
+
         fn on_modification(
             &mut self,
             event: &DebouncedEvent,
             path: &Path,
         ) -> Result<(), Box<dyn std::error::Error>> {
             if self.is_modification(event) {
-                return self.on_file_change(path);
+                return self.record(path);
+            }
+            if self.is_mode_change(event) && self.is_mode_changed(path) {
+                return self.record(path);
             }
             Ok(())
         }
 
+        ///
+        /// a file that can't be diffed (e.g. a transient read error) is retried
+        /// once, if it still fails it gets skipped so the remaining files are
+        /// still tracked. an unreadable file is marked as inaccessible right
+        /// away and tried again on its next change.
+        ///
+        fn on_file_change_or_skip(&mut self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+            if let Err(err) = self.on_file_change(path) {
+                if is_permission_denied(err.as_ref()) {
+                    self.on_inaccessible(path);
+                    return Ok(());
+                }
+                eprintln!("Could not record {:?}, retrying: {}", path, err);
+                thread::sleep(RETRY_DELAY);
+                if let Err(err) = self.on_file_change(path) {
+                    eprintln!("Skipping {:?}: {}", path, err);
+                }
+            }
+            Ok(())
+        }
+
+        fn on_inaccessible(&self, path: &Path) {
+            eprintln!(
+                "Skipping {:?} until it changes again, it is not readable",
+                path
+            );
+            if let Some(path) = path.to_str() {
+                self.stores.for_path(path).lock().unwrap().mark_inaccessible(path);
+            }
+            transmit_file_versions(self);
+        }
+
         fn on_removal(&self, event: &DebouncedEvent, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
             if self.is_removed(event) {
-                self.on_file_remove(path)?;
+                // a change recorded after the removal would bring the file back
+                self.wait_for_workers();
+                self.on_file_remove(path, VersionSource::Watcher)?;
             }
 
             Ok(())
@@ -123,7 +1466,9 @@ pub mod event_handle {
             match event {
                 DebouncedEvent::Write(p) => Ok(Some(p.clone())),
                 DebouncedEvent::Remove(p) => Ok(Some(p.clone())),
+                DebouncedEvent::NoticeRemove(p) => Ok(Some(p.clone())),
                 DebouncedEvent::NoticeWrite(p) => Ok(Some(p.clone())),
+                DebouncedEvent::Chmod(p) => Ok(Some(p.clone())),
                 DebouncedEvent::Error(e, _) => Err(e.to_string().into()),
                 _ => Ok(None),
             }
@@ -134,25 +1479,97 @@ pub mod event_handle {
             path: &Path,
         ) -> Result<(), Box<dyn std::error::Error>> {
             let path = path.to_str().unwrap();
+            // a hard link is recorded under the path its file is tracked by
+            let canonical = self.stores.for_path(path).lock().unwrap().resolve_hard_link(path)?;
+            let path = canonical.as_str();
+            let source = if self.restoring.lock().unwrap().remove(path).is_some() {
+                VersionSource::Restore
+            } else {
+                VersionSource::Watcher
+            };
+            let git = GitState::of(Path::new(path));
 
-            let mut store = self.store.lock().unwrap();
-            store.create_new_file_entry(path)?;
-            let changes = store.get_file_changes::<LineDifference>(path);
-            let changes = diff::find(path, &changes)?;
-            let stored = store.store_changes(path, &changes);
-            let _view = store.view()?;
-            self.communication.file_versions_to_ui.send(_view)?;
+            let read = || std::fs::read(path);
+            let (previous, context_only) = {
+                let mut store = self.stores.for_path(path).lock().unwrap();
+                store.create_new_file_entry(path)?;
+                (store.get_file_changes(path)?, store.is_context_only(path))
+            };
+            // diffed without the store, other files get recorded meanwhile
+            let (content, changes) = match context_only {
+                // its fingerprints are only read with the store
+                true => (Vec::new(), Vec::new()),
+                false => find_consistent(path, &previous, read)?,
+            };
+            let mut store = self.stores.for_path(path).lock().unwrap();
+            let current = store.get_file_changes(path)?;
+            let (content, changes) = if context_only {
+                find_consistent_with(path, |content| store.diff_snapshot(path, content), read)?
+            } else if current == previous {
+                (content, changes)
+            } else {
+                find_consistent(path, &current, read)?
+            };
+            let stored = store.store_snapshot(path, &content, &changes, source);
+            // an emptied file stays present, its version isn't a deletion
+            let kind = match source {
+                VersionSource::Watcher if content.is_empty() && !changes.is_empty() => {
+                    ActivityKind::Truncate
+                }
+                VersionSource::Watcher | VersionSource::Patch => ActivityKind::Change,
+                VersionSource::Scan => ActivityKind::Scan,
+                VersionSource::Restore => ActivityKind::Restore,
+                VersionSource::Checkpoint => ActivityKind::Checkpoint,
+            };
+            if stored.is_ok() {
+                stamp_git_state(&mut store, path, &git);
+                report_version(&self.communication, &store, path, &changes, kind);
+            }
+            report_evictions(&mut store);
+            // the view locks every store, this one included
+            drop(store);
+            self.send_changes(path)?;
 
             stored
         }
         
 
-        fn on_file_remove(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        fn on_file_remove(
+            &self,
+            path: &Path,
+            source: VersionSource,
+        ) -> Result<(), Box<dyn std::error::Error>> {
             let path = path.to_str().unwrap();
+            let staged = self.removing.lock().unwrap().remove(path);
+            let git = GitState::of(Path::new(path));
 
-            let mut store = self.store.lock().unwrap();
+            let mut store = self.stores.for_path(path).lock().unwrap();
+            // the file is still there under another of its hard links
+            if store.unlink(path)? {
+                drop(store);
+                transmit_file_versions(self);
+                return Ok(());
+            }
+            if !store.is_tracked(path) {
+                return Ok(());
+            }
+            // the last save may not have been recorded before the file vanished
+            if let Some(content) = staged {
+                let changes = store.diff_snapshot(path, &content)?;
+                if !changes.is_empty() {
+                    store.store_snapshot(path, &content, &changes, source)?;
+                    stamp_git_state(&mut store, path, &git);
+                    report_version(
+                        &self.communication,
+                        &store,
+                        path,
+                        &changes,
+                        ActivityKind::Change,
+                    );
+                }
+            }
 
-            let changes = store.get_file_changes::<LineDifference>(path);
+            let changes = store.get_file_changes(path)?;
             let changes: Vec<LineDifference> = changes.iter().map(|change| {
                 LineDifference::new(
                     path.to_string(),
@@ -161,9 +1578,21 @@ pub mod event_handle {
                     "".to_string(),
                 )
             }).collect();
-            let stored = store.store_changes(path, &changes);
-            let _view = store.view()?;
-            self.communication.file_versions_to_ui.send(_view)?;
+            let stored = store.store_changes_from(path, &changes, source);
+            if stored.is_ok() {
+                stamp_git_state(&mut store, path, &git);
+                report_version(
+                    &self.communication,
+                    &store,
+                    path,
+                    &changes,
+                    ActivityKind::Remove,
+                );
+            }
+            report_evictions(&mut store);
+            // the view locks every store, this one included
+            drop(store);
+            self.send_changes(path)?;
 
             stored
         }
@@ -174,6 +1603,18 @@ pub mod event_handle {
             }
             false
         }
+        fn is_mode_change(&self, event: &DebouncedEvent) -> bool {
+            matches!(event, DebouncedEvent::Chmod(_))
+        }
+        /// chmod events also fire for ownership and timestamp changes
+        fn is_mode_changed(&self, path: &Path) -> bool {
+            path.to_str().is_some_and(|path| {
+                self.stores.for_path(path).lock().unwrap().mode_changed(path)
+            })
+        }
+        fn is_notice_removal(&self, event: &DebouncedEvent) -> bool {
+            matches!(event, DebouncedEvent::NoticeRemove(_))
+        }
         fn is_removed(&self, event: &DebouncedEvent) -> bool {
             if let DebouncedEvent::Remove(_) = event {
                 return true;
@@ -181,4 +1622,788 @@ pub mod event_handle {
             false
         }
     }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::{git, stores};
+        use std::fs;
+        use store::filter::PathFilter;
+        use store::store::StoreOptions;
+
+        struct Fixture {
+            dir: PathBuf,
+            event_handle: EventHandle,
+            on_activity: Receiver<Activity>,
+            on_maintenance: Receiver<bool>,
+            on_file_versions: Receiver<UiView>,
+            attach_ui_to_handle: Sender<Sender<UiView>>,
+            on_progress: Receiver<Option<Progress>>,
+            on_metrics: Receiver<Metrics>,
+            // the other channel ends have to outlive the event handle
+            _channels: Vec<Box<dyn std::any::Any>>,
+        }
+
+        impl Fixture {
+            fn new(name: &str, files: &[(&str, &str)]) -> Fixture {
+                Fixture::routed(name, files, &[])
+            }
+
+            /// the files below each of `roots` get a store of their own
+            fn routed(name: &str, files: &[(&str, &str)], roots: &[&str]) -> Fixture {
+                let dir = std::env::temp_dir().join(format!(
+                    "auto_stash_event_handle_{}_{}",
+                    name,
+                    std::process::id()
+                ));
+                let _ = fs::remove_dir_all(&dir);
+                fs::create_dir_all(dir.join("watch")).unwrap();
+                files.iter().for_each(|(file, content)| {
+                    let path = dir.join("watch").join(file);
+                    fs::create_dir_all(path.parent().unwrap()).unwrap();
+                    fs::write(path, content).unwrap();
+                });
+                let open = |store: &str, watch: &Path, filter: PathFilter| {
+                    let store = dir.join(store);
+                    let options = StoreOptions {
+                        filter,
+                        ..StoreOptions::default()
+                    };
+                    Store::open(store.to_str().unwrap(), watch.to_str().unwrap(), options).unwrap()
+                };
+                let roots = roots
+                    .iter()
+                    .map(|root| dir.join("watch").join(root))
+                    .collect::<Vec<PathBuf>>();
+                let filter = |root: &Path| {
+                    PathFilter::default().without_roots(&stores::nested_roots(root, &roots))
+                };
+                let watch = dir.join("watch");
+                let stores = roots.iter().fold(
+                    Stores::new(open("store.db", &watch, filter(&watch))),
+                    |stores, root| {
+                        let name = format!("{}.db", root.file_name().unwrap().to_string_lossy());
+                        stores.with_route(root, open(&name, root, filter(root)))
+                    },
+                );
+
+                let (file_versions_to_ui, on_file_versions) = flume::unbounded();
+                let (visible_page_to_handle, on_visible_page) = flume::unbounded();
+                let (attach_ui_to_handle, on_attach_ui) = flume::unbounded();
+                let (undo_to_handle, on_undo) = flume::unbounded();
+                let (redo_to_handle, on_redo) = flume::unbounded();
+                let (time_frame_change_to_handle, on_time_frame_change) = flume::unbounded();
+                let (purge_to_handle, on_purge) = flume::unbounded();
+                let (checkpoint_to_handle, on_checkpoint) = flume::unbounded();
+                let (toggle_disabled_to_handle, on_toggle_disabled) = flume::unbounded();
+                let (set_debounce_to_handle, on_set_debounce) = flume::unbounded();
+                let (toggle_pinned_to_handle, on_toggle_pinned) = flume::unbounded();
+                let (record_now_to_handle, on_record_now) = flume::unbounded();
+                let (watch_root_to_ui, on_watch_root) = flume::unbounded();
+                let (copy_version_to_handle, on_copy_version) = flume::unbounded();
+                let (copy_diff_to_handle, on_copy_diff) = flume::unbounded();
+                let (show_version_to_handle, on_show_version) = flume::unbounded();
+                let (version_content_to_ui, on_version_content) = flume::unbounded();
+                let (list_versions_to_handle, on_list_versions) = flume::unbounded();
+                let (version_list_to_ui, on_version_list) = flume::unbounded();
+                let (clipboard_to_ui, on_clipboard) = flume::unbounded();
+                let (activity_to_output, on_activity) = flume::unbounded();
+                let (debounce_to_ui, on_debounce) = flume::unbounded();
+                let (maintenance_to_ui, on_maintenance) = flume::unbounded();
+                let (progress_to_ui, on_progress) = flume::unbounded();
+                let (metrics_to_ui, on_metrics) = flume::unbounded();
+                let event_handle = EventHandle::with_stores(
+                    stores,
+                    EventHandleCommunication {
+                        file_versions_to_ui,
+                        on_visible_page,
+                        on_attach_ui,
+                        on_undo,
+                        on_redo,
+                        on_time_frame_change,
+                        on_purge,
+                        on_checkpoint,
+                        on_toggle_disabled,
+                        on_set_debounce,
+                        on_toggle_pinned,
+                        on_record_now,
+                        watch_root_to_ui,
+                        on_copy_version,
+                        on_copy_diff,
+                        on_show_version,
+                        version_content_to_ui,
+                        on_list_versions,
+                        version_list_to_ui,
+                        clipboard_to_ui,
+                        activity_to_output,
+                        debounce_to_ui,
+                        maintenance_to_ui,
+                        progress_to_ui,
+                        metrics_to_ui,
+                    },
+                );
+
+                Fixture {
+                    dir,
+                    event_handle,
+                    on_activity,
+                    on_maintenance,
+                    on_file_versions,
+                    attach_ui_to_handle,
+                    on_progress,
+                    on_metrics,
+                    _channels: vec![
+                        Box::new(visible_page_to_handle),
+                        Box::new(undo_to_handle),
+                        Box::new(redo_to_handle),
+                        Box::new(time_frame_change_to_handle),
+                        Box::new(purge_to_handle),
+                        Box::new(checkpoint_to_handle),
+                        Box::new(toggle_disabled_to_handle),
+                        Box::new(set_debounce_to_handle),
+                        Box::new(toggle_pinned_to_handle),
+                        Box::new(record_now_to_handle),
+                        Box::new(on_watch_root),
+                        Box::new(copy_version_to_handle),
+                        Box::new(copy_diff_to_handle),
+                        Box::new(show_version_to_handle),
+                        Box::new(on_version_content),
+                        Box::new(list_versions_to_handle),
+                        Box::new(on_version_list),
+                        Box::new(on_clipboard),
+                        Box::new(on_debounce),
+                    ],
+                }
+            }
+
+            fn path(&self, file: &str) -> PathBuf {
+                self.dir.join("watch").join(file)
+            }
+
+            /// the default store
+            fn store(&self) -> std::sync::MutexGuard<'_, Store> {
+                self.event_handle.stores.all().next().unwrap().lock().unwrap()
+            }
+
+            fn version_count(&self, file: &str) -> usize {
+                let path = self.path(file);
+                let path = path.to_str().unwrap();
+                let store = self.event_handle.stores.for_path(path).lock().unwrap();
+                (0..)
+                    .take_while(|version| store.reconstruct(path, *version).is_ok())
+                    .count()
+            }
+        }
+
+        fn full_view(view: UiView) -> ViewUpdate {
+            match view {
+                UiView::Full(view) => view,
+                UiView::Page(_) => panic!("expected the full view"),
+            }
+        }
+
+        impl Drop for Fixture {
+            fn drop(&mut self) {
+                let _ = fs::remove_dir_all(&self.dir);
+            }
+        }
+
+        #[test]
+        fn resync_records_changes_missed_while_unavailable() {
+            let mut fixture = Fixture::new(
+                "resync",
+                &[("a.txt", "one\n"), ("b.txt", "two\n"), ("c.txt", "three\n")],
+            );
+            fs::write(fixture.path("a.txt"), "one\nmore\n").unwrap();
+            fs::remove_file(fixture.path("b.txt")).unwrap();
+
+            let root = fixture.dir.join("watch");
+            fixture.event_handle.resync(root.to_str().unwrap());
+            assert_eq!(fixture.version_count("a.txt"), 2);
+            assert_eq!(fixture.version_count("b.txt"), 2);
+            assert_eq!(fixture.version_count("c.txt"), 1);
+
+            let store = fixture.store();
+            let a = fixture.path("a.txt");
+            assert_eq!(store.reconstruct(a.to_str().unwrap(), 1).unwrap(), "one\nmore\n");
+            assert!(store
+                .tracked_paths()
+                .iter()
+                .any(|tracked| tracked.path.ends_with("b.txt") && tracked.deleted));
+            drop(store);
+
+            let view = fixture.store().view().unwrap();
+            view.iter().flatten().for_each(|file_versions| {
+                assert!(file_versions
+                    .versions
+                    .iter()
+                    .all(|version| version.source == VersionSource::Scan));
+            });
+        }
+
+        #[test]
+        fn resync_carries_the_history_of_moved_files() {
+            let files = [("a.txt", "one\n"), ("b.txt", "two\n")];
+            let mut fixture = Fixture::new("resync_moves", &files);
+            let root = fixture.dir.join("watch");
+            fs::write(fixture.path("a.txt"), "one\nmore\n").unwrap();
+            fixture.event_handle.resync(root.to_str().unwrap());
+
+            fs::create_dir(root.join("moved")).unwrap();
+            fs::rename(fixture.path("a.txt"), root.join("moved/a.txt")).unwrap();
+            fixture.event_handle.resync(root.to_str().unwrap());
+            assert_eq!(fixture.version_count("moved/a.txt"), 2);
+            assert_eq!(fixture.version_count("b.txt"), 1);
+            assert!(!fixture.store().is_tracked(fixture.path("a.txt").to_str().unwrap()));
+            let activity = fixture.on_activity.try_iter().last().unwrap();
+            assert_eq!(activity.kind, ActivityKind::Move);
+        }
+
+        #[test]
+        fn resync_carries_the_history_of_files_moved_to_another_store() {
+            let files = [("a.txt", "one\n"), ("web/b.txt", "two\n")];
+            let mut fixture = Fixture::routed("resync_moves_between", &files, &["web"]);
+            let root = fixture.dir.join("watch");
+            fs::write(fixture.path("a.txt"), "one\nmore\n").unwrap();
+            fixture.event_handle.resync(root.to_str().unwrap());
+
+            fs::rename(fixture.path("a.txt"), root.join("web/a.txt")).unwrap();
+            fixture.event_handle.resync(root.to_str().unwrap());
+            assert_eq!(fixture.version_count("web/a.txt"), 2);
+            assert!(!fixture.store().is_tracked(fixture.path("a.txt").to_str().unwrap()));
+            let activity = fixture.on_activity.try_iter().last().unwrap();
+            assert_eq!(activity.kind, ActivityKind::Move);
+        }
+
+        #[test]
+        fn poll_records_only_what_the_events_missed() {
+            let mut fixture = Fixture::new(
+                "poll",
+                &[("a.txt", "one\n"), ("b.txt", "two\n"), ("c.txt", "three\n")],
+            );
+            let since = SystemTime::now() - Duration::from_secs(60);
+            fs::write(fixture.path("a.txt"), "one\nmore\n").unwrap();
+            fixture
+                .event_handle
+                .handle(DebouncedEvent::Write(fixture.path("a.txt")))
+                .unwrap();
+            // no event arrives for this one
+            fs::write(fixture.path("b.txt"), "two\nmore\n").unwrap();
+
+            fixture.event_handle.poll(since).join().unwrap();
+            assert_eq!(fixture.version_count("a.txt"), 2);
+            assert_eq!(fixture.version_count("b.txt"), 2);
+            assert_eq!(fixture.version_count("c.txt"), 1);
+            assert!(fixture.on_file_versions.drain().count() > 0);
+
+            fixture.event_handle.poll(since).join().unwrap();
+            assert_eq!(fixture.version_count("b.txt"), 2);
+            assert!(fixture.on_file_versions.is_empty());
+        }
+
+        #[test]
+        fn restores_and_removals_nothing_came_for_are_forgotten() {
+            let expired = Instant::now() - EXPECTED_EVENT_TTL;
+            let mut restoring = Expiring::default();
+            restoring.insert(String::from("/watch/a.txt"), ());
+            restoring.insert(String::from("/watch/b.txt"), ());
+            restoring.entries.get_mut("/watch/b.txt").unwrap().0 = expired;
+            assert!(restoring.contains("/watch/a.txt") && !restoring.contains("/watch/b.txt"));
+            assert_eq!(restoring.remove("/watch/b.txt"), None);
+
+            let mut removing = Expiring::default();
+            removing.insert(String::from("/watch/a.txt"), b"one\n".to_vec());
+            removing.entries.get_mut("/watch/a.txt").unwrap().0 = expired;
+            // the next removal takes the stale content along
+            removing.insert(String::from("/watch/b.txt"), b"two\n".to_vec());
+            assert_eq!(removing.entries.len(), 1);
+            assert_eq!(removing.remove("/watch/b.txt"), Some(b"two\n".to_vec()));
+        }
+
+        #[test]
+        fn recording_now_leaves_unchanged_and_restored_files_alone() {
+            let fixture = Fixture::new(
+                "record_now",
+                &[("a.txt", "one\n"), ("b.txt", "two\n"), ("c.txt", "three\n")],
+            );
+            let path = |file: &str| fixture.path(file).to_str().unwrap().to_string();
+            let (a, b, c) = (path("a.txt"), path("b.txt"), path("c.txt"));
+            fs::write(&a, "one\nmore\n").unwrap();
+            // an undo is writing to this one
+            fs::write(&b, "two\nmore\n").unwrap();
+            fixture.event_handle.restoring.lock().unwrap().insert(b.clone(), ());
+
+            assert_eq!(fixture.event_handle.record_now(Some(&a)), vec![a.clone()]);
+            assert_eq!(fixture.version_count("a.txt"), 2);
+            assert!(fixture.on_file_versions.drain().count() > 0);
+            assert_eq!(fixture.event_handle.record_now(Some(&a)), vec![a.clone()]);
+            assert_eq!(fixture.version_count("a.txt"), 2);
+            assert!(fixture.on_file_versions.is_empty());
+
+            let mut diffed = fixture.event_handle.record_now(None);
+            diffed.sort();
+            assert_eq!(diffed, vec![a, c]);
+            assert_eq!(fixture.version_count("b.txt"), 1);
+            assert_eq!(fixture.version_count("c.txt"), 1);
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn chmod_records_a_version_only_if_the_mode_changed() {
+            use std::os::unix::fs::PermissionsExt;
+            let mut fixture = Fixture::new("chmod", &[("run.sh", "echo one\n")]);
+            let path = fixture.path("run.sh");
+            let chmod = || DebouncedEvent::Chmod(path.clone());
+
+            fixture.event_handle.handle(chmod()).unwrap();
+            assert_eq!(fixture.version_count("run.sh"), 1);
+
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o751)).unwrap();
+            fixture.event_handle.handle(chmod()).unwrap();
+            assert_eq!(fixture.version_count("run.sh"), 2);
+            let store = fixture.store();
+            assert!(!store.mode_changed(path.to_str().unwrap()));
+        }
+
+        #[test]
+        fn truncated_files_stay_tracked_as_present() {
+            let mut fixture = Fixture::new("truncated", &[("a.txt", "one\ntwo\n")]);
+            let path = fixture.path("a.txt");
+            let key = path.to_str().unwrap().to_string();
+            fs::write(&path, "").unwrap();
+            fixture
+                .event_handle
+                .handle(DebouncedEvent::Write(path.clone()))
+                .unwrap();
+
+            assert_eq!(fixture.version_count("a.txt"), 2);
+            let activity = fixture.on_activity.try_recv().unwrap();
+            assert_eq!(
+                (activity.kind, activity.removed),
+                (ActivityKind::Truncate, 2)
+            );
+            let mut store = fixture.store();
+            assert_eq!(store.reconstruct(&key, 1).unwrap(), "");
+            let tracked = store.tracked_paths();
+            assert!(tracked.iter().all(|tracked| !tracked.deleted));
+
+            store.undo_by(key.clone(), 1).unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), "one\ntwo\n");
+            store.redo_by(key, 1).unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), "");
+        }
+
+        #[test]
+        fn empty_files_get_their_first_lines() {
+            let mut fixture = Fixture::new("emptied", &[("a.txt", "")]);
+            let path = fixture.path("a.txt");
+            let key = path.to_str().unwrap().to_string();
+            fs::write(&path, "one\n").unwrap();
+            fixture
+                .event_handle
+                .handle(DebouncedEvent::Write(path.clone()))
+                .unwrap();
+
+            let activity = fixture.on_activity.try_recv().unwrap();
+            assert_eq!((activity.kind, activity.added), (ActivityKind::Change, 1));
+            let mut store = fixture.store();
+            assert_eq!(store.reconstruct(&key, 0).unwrap(), "");
+            assert_eq!(store.reconstruct(&key, 1).unwrap(), "one\n");
+
+            store.undo_by(key, 1).unwrap();
+            assert!(path.is_file());
+            assert_eq!(fs::read_to_string(&path).unwrap(), "");
+        }
+
+        #[test]
+        fn recorded_versions_are_reported_as_activity() {
+            let mut fixture = Fixture::new("activity", &[("a.txt", "one\ntwo\n")]);
+            let path = fixture.path("a.txt");
+            fs::write(&path, "one\n2\nthree\n").unwrap();
+            fixture
+                .event_handle
+                .handle(DebouncedEvent::Write(path.clone()))
+                .unwrap();
+            fixture.event_handle.report_watch_root("/watch", false);
+
+            let activity = fixture.on_activity.try_recv().unwrap();
+            assert_eq!(activity.kind, ActivityKind::Change);
+            assert_eq!(activity.path, path.to_str().unwrap());
+            assert_eq!(activity.version, Some(1));
+            assert_eq!(
+                (activity.added, activity.removed, activity.modified),
+                (1, 0, 1)
+            );
+            let activity = fixture.on_activity.try_recv().unwrap();
+            assert_eq!(activity.kind, ActivityKind::RootUnavailable);
+            assert_eq!(activity.version, None);
+        }
+
+        #[test]
+        fn versions_note_the_git_head_they_were_recorded_on() {
+            let mut fixture = Fixture::new("git", &[("a.txt", "one\n")]);
+            let path = fixture.path("a.txt");
+            fs::write(&path, "two\n").unwrap();
+            fixture
+                .event_handle
+                .handle(DebouncedEvent::Write(path.clone()))
+                .unwrap();
+            let key = path.to_str().unwrap().to_string();
+            let meta = |fixture: &Fixture, version| {
+                let store = fixture.store();
+                store.version_meta(&key, version).unwrap()
+            };
+            assert!(meta(&fixture, 1).is_empty());
+
+            let sha = "3f786850e387550fdab836ed7e6dc881de23001b";
+            fs::create_dir_all(fixture.dir.join(".git")).unwrap();
+            fs::write(fixture.dir.join(".git/HEAD"), sha).unwrap();
+            fs::write(&path, "three\n").unwrap();
+            fixture
+                .event_handle
+                .handle(DebouncedEvent::Write(path.clone()))
+                .unwrap();
+            let head = meta(&fixture, 2).get(git::HEAD_KEY).cloned();
+            assert_eq!(head.as_deref(), Some(sha));
+        }
+
+        #[test]
+        fn removal_records_the_content_read_before_the_file_vanished() {
+            let mut fixture = Fixture::new("notice_remove", &[("a.txt", "one\n")]);
+            let path = fixture.path("a.txt");
+            // saved and removed before the write was recorded
+            fs::write(&path, "one\ntwo\n").unwrap();
+            let notice = DebouncedEvent::NoticeRemove(path.clone());
+            fixture.event_handle.handle(notice).unwrap();
+            fs::remove_file(&path).unwrap();
+            fixture
+                .event_handle
+                .handle(DebouncedEvent::Remove(path.clone()))
+                .unwrap();
+
+            let store = fixture.store();
+            let path = path.to_str().unwrap();
+            assert_eq!(store.version_count(path).unwrap(), 3);
+            assert_eq!(store.reconstruct(path, 1).unwrap(), "one\ntwo\n");
+            assert_eq!(store.reconstruct(path, 2).unwrap(), "");
+            assert!(store
+                .tracked_paths()
+                .iter()
+                .any(|tracked| tracked.path == path && tracked.deleted));
+        }
+
+        #[test]
+        fn workers_record_every_queued_file_before_the_flush() {
+            let names = (0..20)
+                .map(|file| format!("{}.txt", file))
+                .collect::<Vec<String>>();
+            let files = names
+                .iter()
+                .map(|name| (name.as_str(), "one\n"))
+                .collect::<Vec<(&str, &str)>>();
+            let mut fixture = Fixture::new("workers", &files);
+            fixture.event_handle.spawn_workers(2);
+
+            names.iter().for_each(|name| {
+                let path = fixture.path(name);
+                fs::write(&path, "one\ntwo\n").unwrap();
+                let event = DebouncedEvent::Write(path);
+                fixture.event_handle.handle(event).unwrap();
+            });
+            fixture.event_handle.flush().unwrap();
+            names.iter().for_each(|name| {
+                assert_eq!(fixture.version_count(name), 2);
+            });
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn unreadable_files_are_marked_until_they_are_readable_again() {
+            use std::os::unix::fs::PermissionsExt;
+            let mut fixture = Fixture::new("inaccessible", &[("a.txt", "one\n")]);
+            let path = fixture.path("a.txt");
+            let write = || DebouncedEvent::Write(path.clone());
+            let inaccessible = |fixture: &Fixture| {
+                let view = fixture.store().view().unwrap();
+                view[0].as_ref().unwrap().inaccessible
+            };
+
+            fs::write(&path, "one\ntwo\n").unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o000)).unwrap();
+            // root reads it anyway
+            if fs::read(&path).is_err() {
+                fixture.event_handle.handle(write()).unwrap();
+                assert_eq!(fixture.version_count("a.txt"), 1);
+                assert!(inaccessible(&fixture));
+            }
+
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+            fixture.event_handle.handle(write()).unwrap();
+            assert_eq!(fixture.version_count("a.txt"), 2);
+            assert!(!inaccessible(&fixture));
+        }
+
+        #[test]
+        fn idle_maintenance_is_reported_while_it_runs() {
+            let fixture = Fixture::new("maintenance", &[("a.txt", "one\n")]);
+            let interrupted = Arc::new(AtomicBool::new(false));
+            fixture.event_handle.maintain(true, interrupted.clone());
+            let timeout = Duration::from_secs(5);
+            assert_eq!(fixture.on_maintenance.recv_timeout(timeout), Ok(true));
+            assert_eq!(fixture.on_maintenance.recv_timeout(timeout), Ok(false));
+
+            interrupted.store(true, Ordering::SeqCst);
+            fixture.event_handle.maintain(true, interrupted);
+            assert_eq!(fixture.on_maintenance.recv_timeout(timeout), Ok(true));
+            assert_eq!(fixture.on_maintenance.recv_timeout(timeout), Ok(false));
+        }
+
+        #[test]
+        fn progress_is_reported_until_the_view_is_sent() {
+            let fixture = Fixture::new("progress", &[("a.txt", "one\n"), ("b.txt", "two\n")]);
+            fixture.event_handle.init_file_versions();
+            let timeout = Duration::from_secs(5);
+
+            let progress = (0..2)
+                .map(|_| fixture.on_progress.recv_timeout(timeout).unwrap().unwrap())
+                .map(|progress| (progress.done, progress.total))
+                .collect::<Vec<_>>();
+            assert_eq!(progress, vec![(1, 2), (2, 2)]);
+            let view = full_view(fixture.on_file_versions.recv_timeout(timeout).unwrap());
+            assert_eq!(view.len(), 2);
+            assert_eq!(fixture.on_progress.recv_timeout(timeout), Ok(None));
+        }
+
+        #[test]
+        fn every_subscriber_gets_the_views() {
+            let mut fixture = Fixture::new("subscribe", &[("a.txt", "one\n")]);
+            let path = fixture.path("a.txt");
+            let first = fixture.event_handle.subscribe();
+            let second = fixture.event_handle.subscribe();
+            assert_eq!(first.try_recv().unwrap()[0].as_ref().unwrap().version_count, 1);
+            assert!(second.try_recv().is_ok());
+
+            drop(second);
+            fs::write(&path, "two\n").unwrap();
+            fixture
+                .event_handle
+                .handle(DebouncedEvent::Write(path.clone()))
+                .unwrap();
+            assert_eq!(first.try_recv().unwrap()[0].as_ref().unwrap().version_count, 2);
+            assert!(fixture.on_file_versions.try_recv().is_ok());
+            assert_eq!(fixture.event_handle.viewers.lock().unwrap().subscribers.len(), 1);
+        }
+
+        #[test]
+        fn the_ui_gets_the_page_it_shows_and_the_recorded_file() {
+            let mut fixture = Fixture::new(
+                "visible_page",
+                &[("a.txt", "a\n"), ("b.txt", "b\n"), ("c.txt", "c\n")],
+            );
+            let paths = |view: UiView| match view {
+                UiView::Page(files) => files
+                    .into_iter()
+                    .map(|file_versions| file_versions.path)
+                    .collect::<Vec<_>>(),
+                UiView::Full(_) => panic!("expected a page"),
+            };
+            let [a, c] = ["a.txt", "c.txt"]
+                .map(|file| fixture.path(file).to_string_lossy().into_owned());
+            transmit_file_versions(&fixture.event_handle);
+            assert_eq!(full_view(fixture.on_file_versions.try_recv().unwrap()).len(), 3);
+
+            fixture.event_handle.show_page(0, 1);
+            assert_eq!(paths(fixture.on_file_versions.try_recv().unwrap()), vec![a.clone()]);
+            fs::write(fixture.path("c.txt"), "c\nc\n").unwrap();
+            fixture
+                .event_handle
+                .handle(DebouncedEvent::Write(fixture.path("c.txt")))
+                .unwrap();
+            assert_eq!(
+                paths(fixture.on_file_versions.try_recv().unwrap()),
+                [a, c]
+            );
+
+            // a new file isn't on any page the ui knows about
+            fs::write(fixture.path("d.txt"), "d\n").unwrap();
+            fixture
+                .event_handle
+                .handle(DebouncedEvent::Write(fixture.path("d.txt")))
+                .unwrap();
+            let view = fixture.on_file_versions.try_iter().last().unwrap();
+            assert_eq!(full_view(view).len(), 4);
+        }
+
+        #[test]
+        fn a_restarted_ui_attaches_again_and_gets_the_full_view() {
+            let mut fixture = Fixture::new("attach_ui", &[("a.txt", "one\n"), ("b.txt", "b\n")]);
+            let path = fixture.path("a.txt");
+            fixture.event_handle.show_page(0, 1);
+            drop(std::mem::replace(
+                &mut fixture.on_file_versions,
+                flume::unbounded().1,
+            ));
+            fs::write(&path, "two\n").unwrap();
+            fixture
+                .event_handle
+                .handle(DebouncedEvent::Write(path.clone()))
+                .unwrap();
+            assert!(!fixture.event_handle.is_ui_attached());
+
+            fixture.event_handle.on_attach_ui();
+            let (file_versions_to_ui, on_file_versions) = flume::unbounded();
+            fixture.attach_ui_to_handle.send(file_versions_to_ui).unwrap();
+            let view = on_file_versions.recv_timeout(Duration::from_secs(5)).unwrap();
+            let view = full_view(view);
+            assert_eq!(view.len(), 2);
+            assert_eq!(view[0].as_ref().unwrap().version_count, 2);
+            assert!(fixture.event_handle.is_ui_attached());
+
+            // it didn't say which files it shows yet, the old page is gone with the old ui
+            fs::write(&path, "three\n").unwrap();
+            fixture
+                .event_handle
+                .handle(DebouncedEvent::Write(path.clone()))
+                .unwrap();
+            let view = full_view(on_file_versions.try_recv().unwrap());
+            assert_eq!(view[0].as_ref().unwrap().version_count, 3);
+        }
+
+        #[test]
+        fn recording_goes_on_while_the_ui_is_detached() {
+            let mut fixture = Fixture::new("detached_ui", &[("a.txt", "one\n")]);
+            let path = fixture.path("a.txt");
+            drop(std::mem::replace(
+                &mut fixture.on_file_versions,
+                flume::unbounded().1,
+            ));
+
+            fs::write(&path, "two\n").unwrap();
+            fixture
+                .event_handle
+                .handle(DebouncedEvent::Write(path.clone()))
+                .unwrap();
+            assert!(!fixture.event_handle.is_ui_attached());
+            fs::write(&path, "three\n").unwrap();
+            fixture
+                .event_handle
+                .handle(DebouncedEvent::Write(path.clone()))
+                .unwrap();
+            assert_eq!(fixture.version_count("a.txt"), 3);
+
+            // a viewer that comes later gets the full view right away
+            let on_file_versions = fixture.event_handle.subscribe();
+            let view = on_file_versions.try_recv().unwrap();
+            assert_eq!(view[0].as_ref().unwrap().version_count, 3);
+            fs::write(&path, "four\n").unwrap();
+            fixture
+                .event_handle
+                .handle(DebouncedEvent::Write(path.clone()))
+                .unwrap();
+            assert!(on_file_versions.try_recv().is_ok());
+        }
+
+        #[test]
+        fn disabled_files_are_left_alone() {
+            let mut fixture = Fixture::new("disabled", &[("a.txt", "one\n")]);
+            let path = fixture.path("a.txt");
+            fixture.store().disable(path.to_str().unwrap()).unwrap();
+            fs::write(&path, "two\n").unwrap();
+            fixture
+                .event_handle
+                .handle(DebouncedEvent::Write(path.clone()))
+                .unwrap();
+            fs::remove_file(&path).unwrap();
+            fixture
+                .event_handle
+                .handle(DebouncedEvent::Remove(path.clone()))
+                .unwrap();
+            assert!(!fixture.event_handle.on_file_sync(&path).unwrap());
+            fixture.event_handle.flush().unwrap();
+            assert_eq!(fixture.version_count("a.txt"), 1);
+        }
+
+        #[test]
+        fn metrics_add_up_every_store() {
+            let mut fixture =
+                Fixture::routed("metrics", &[("a.txt", "one\n"), ("web/a.txt", "1\n")], &["web"]);
+            let path = fixture.path("web/a.txt");
+            fs::write(&path, "2\n").unwrap();
+            fixture.event_handle.handle(DebouncedEvent::Write(path)).unwrap();
+            fixture.event_handle.flush().unwrap();
+
+            fixture.event_handle.report_metrics().unwrap().join().unwrap();
+            let metrics = fixture.on_metrics.try_recv().unwrap();
+            assert_eq!((metrics.recent_versions, metrics.tracked_files), (3, 2));
+            assert!(metrics.store_bytes > 0);
+            assert!(metrics.to_string().starts_with("3 versions/min · 2 files · "));
+        }
+
+        #[test]
+        fn files_are_recorded_in_the_store_of_their_root() {
+            let mut fixture = Fixture::routed(
+                "routed",
+                &[("a.txt", "one\n"), ("web/a.txt", "1\n"), ("web/api/a.txt", "i\n")],
+                &["web", "web/api"],
+            );
+            let tracked = |store: &Store| store.tracked_paths().len();
+            assert_eq!(tracked(&fixture.store()), 1);
+
+            let edits = [("a.txt", "two\n"), ("web/a.txt", "2\n"), ("web/api/a.txt", "ii\n")];
+            for (file, content) in edits {
+                fs::write(fixture.path(file), content).unwrap();
+                fixture
+                    .event_handle
+                    .handle(DebouncedEvent::Write(fixture.path(file)))
+                    .unwrap();
+                assert_eq!(fixture.version_count(file), 2);
+            }
+            let web = fixture.dir.join("web.db");
+            let web = Store::new(web.to_str().unwrap(), "", vec![], vec![]).unwrap();
+            assert_eq!(tracked(&web), 1);
+            assert_eq!(tracked(&fixture.store()), 1);
+
+            // the view holds the files of every store
+            let view = full_view(fixture.on_file_versions.try_iter().last().unwrap());
+            let paths = view.iter().flatten().map(|file_versions| file_versions.path.clone());
+            let files = ["a.txt", "web/a.txt", "web/api/a.txt"].map(|file| fixture.path(file));
+            assert_eq!(
+                paths.collect::<Vec<_>>(),
+                files.map(|path| path.to_string_lossy().into_owned()).to_vec()
+            );
+        }
+
+        #[test]
+        fn content_changing_while_read_is_diffed_again() {
+            let mut reads = vec!["one\n", "one\ntwo\n", "one\ntwo\n"].into_iter();
+            let (content, changes) = find_consistent("a.txt", &[], || {
+                Ok(reads.next().unwrap().as_bytes().to_vec())
+            })
+            .unwrap();
+            assert_eq!(content, b"one\ntwo\n");
+            assert_eq!(changes.len(), 2);
+
+            let mut count = 0;
+            let always_changing = find_consistent("a.txt", &[], || {
+                count += 1;
+                Ok(count.to_string().into_bytes())
+            });
+            assert!(always_changing.is_err());
+            assert_eq!(count, MAX_READ_ATTEMPTS + 1);
+        }
+
+        #[test]
+        fn failing_file_does_not_stop_other_files() {
+            let mut fixture = Fixture::new("failing_file", &[("a.txt", "one\n")]);
+            let missing = fixture.path("missing.txt");
+            let path = fixture.path("a.txt");
+
+            let event = DebouncedEvent::Write(missing.clone());
+            assert!(fixture.event_handle.on_modification(&event, &missing).is_ok());
+
+            fs::write(&path, "two\n").unwrap();
+            fixture.event_handle.handle(DebouncedEvent::Write(path)).unwrap();
+            assert_eq!(fixture.version_count("a.txt"), 2);
+        }
+    }
 }
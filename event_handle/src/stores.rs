@@ -0,0 +1,232 @@
+//!
+//! the stores the files below the watch path are kept in. a file goes to
+//! the store of the deepest root it is below, every other file to the
+//! default store. each store is locked on its own, recording a file of one
+//! project doesn't wait for the others.
+//!
+use std::collections::{HashMap, HashSet};
+use std::error;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use store::store::{FileVersions, Store, TrackedPath};
+
+///
+/// the `roots` below `root` whose files another store keeps, the initial
+/// scan of the store of `root` has to leave them out
+///
+pub fn nested_roots(root: &Path, roots: &[PathBuf]) -> Vec<PathBuf> {
+    roots
+        .iter()
+        .filter(|nested| nested.starts_with(root) && nested.as_path() != root)
+        .cloned()
+        .collect()
+}
+
+pub struct Stores {
+    default: Mutex<Store>,
+    /// the deepest root first
+    routes: Vec<(PathBuf, Mutex<Store>)>,
+}
+
+impl Stores {
+    pub fn new(default: Store) -> Stores {
+        Stores {
+            default: Mutex::new(default),
+            routes: Vec::new(),
+        }
+    }
+
+    /// keeps the files below `root` in `store`
+    pub fn with_route(mut self, root: impl Into<PathBuf>, store: Store) -> Stores {
+        self.routes.push((root.into(), Mutex::new(store)));
+        self.routes
+            .sort_by_key(|(root, _)| std::cmp::Reverse(root.components().count()));
+        self
+    }
+
+    /// the store `path` is kept in
+    pub fn for_path(&self, path: &str) -> &Mutex<Store> {
+        self.routes
+            .iter()
+            .find(|(root, _)| Path::new(path).starts_with(root))
+            .map_or(&self.default, |(_, store)| store)
+    }
+
+    /// the default store first
+    pub fn all(&self) -> impl Iterator<Item = &Mutex<Store>> {
+        std::iter::once(&self.default).chain(self.routes.iter().map(|(_, store)| store))
+    }
+
+    pub fn tracked_paths(&self) -> Vec<TrackedPath> {
+        self.all()
+            .flat_map(|store| store.lock().unwrap().tracked_paths())
+            .collect()
+    }
+
+    ///
+    /// the views of every store as one, ordered by path like `Store::view`.
+    /// `progress` counts the files of all stores. the epoch of the merged
+    /// view moves whenever the one of a store does.
+    ///
+    pub fn view_with_progress(
+        &self,
+        mut progress: impl FnMut(usize, usize),
+    ) -> Result<Vec<Option<FileVersions>>, Box<dyn error::Error>> {
+        let total = self
+            .all()
+            .map(|store| store.lock().unwrap().tracked_paths_iter().count())
+            .sum();
+        let mut done = 0;
+        let mut epoch = 0;
+        let mut view = Vec::new();
+        for store in self.all() {
+            let mut store = store.lock().unwrap();
+            let counted = done;
+            view.extend(store.view_with_progress(|store_done, _| {
+                done = counted + store_done;
+                progress(done, total);
+            })?);
+            epoch += store.epoch();
+        }
+
+        view.iter_mut()
+            .flatten()
+            .for_each(|file_versions| file_versions.epoch = epoch);
+        let path = |file_versions: &Option<FileVersions>| {
+            file_versions
+                .as_ref()
+                .map(|file_versions| file_versions.path.clone())
+        };
+        view.sort_by_cached_key(path);
+        Ok(view)
+    }
+
+    pub fn view(&self) -> Result<Vec<Option<FileVersions>>, Box<dyn error::Error>> {
+        self.view_with_progress(|_, _| {})
+    }
+
+    /// the epochs of the stores summed up, like the one of the merged view
+    pub fn epoch(&self) -> u64 {
+        self.all().map(|store| store.lock().unwrap().epoch()).sum()
+    }
+
+    /// how many files the merged view holds, see `Store::visible_paths`
+    pub fn visible_count(&self) -> usize {
+        self.all()
+            .map(|store| store.lock().unwrap().visible_paths().len())
+            .sum()
+    }
+
+    ///
+    /// `limit` files of the merged view after skipping `offset`, like
+    /// `Store::view_page`. a store only loads its files on the page.
+    ///
+    pub fn view_page(&self, offset: usize, limit: usize) -> Vec<FileVersions> {
+        let mut paths = self
+            .all()
+            .enumerate()
+            .flat_map(|(index, store)| {
+                let paths = store.lock().unwrap().visible_paths();
+                paths.into_iter().map(move |path| (path, index))
+            })
+            .collect::<Vec<_>>();
+        paths.sort();
+        let offset = offset.min(paths.len());
+        let page = &paths[offset..(offset.saturating_add(limit)).min(paths.len())];
+        let count = |paths: &[(String, usize)], index: usize| {
+            paths.iter().filter(|(_, store)| *store == index).count()
+        };
+        // the files of a store are ordered by path too, those on the page follow each other
+        let mut files = Vec::new();
+        for (index, store) in self.all().enumerate() {
+            let limit = count(page, index);
+            if limit > 0 {
+                let offset = count(&paths[..offset], index);
+                files.extend(store.lock().unwrap().view_page(offset, limit));
+            }
+        }
+        let epoch = self.epoch();
+        files
+            .iter_mut()
+            .for_each(|file_versions| file_versions.epoch = epoch);
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        files
+    }
+
+    /// the versions of `path` like the merged view holds them
+    pub fn file_view(&self, path: &str) -> Option<FileVersions> {
+        let mut file_versions = self.for_path(path).lock().unwrap().file_view(path)?;
+        file_versions.epoch = self.epoch();
+        Some(file_versions)
+    }
+
+    ///
+    /// the files gone from one store that turned up below the root of
+    /// another with the same content, e.g. moved to another project while
+    /// nothing watched them. like `Store::find_moved_files` a content more
+    /// than one file is gone with or turned up with is left alone.
+    ///
+    pub fn find_moves_between(&self) -> Vec<(String, String)> {
+        let by_content = |files: Vec<HashMap<Vec<u8>, Vec<String>>>| {
+            let mut merged: HashMap<Vec<u8>, Vec<String>> = HashMap::new();
+            for (content, paths) in files.into_iter().flatten() {
+                merged.entry(content).or_default().extend(paths);
+            }
+            merged
+        };
+        let gone = by_content(
+            self.all()
+                .map(|store| store.lock().unwrap().gone_files())
+                .collect(),
+        );
+        if gone.is_empty() {
+            return Vec::new();
+        }
+        let sizes = gone.keys().map(Vec::len).collect::<HashSet<_>>();
+        let appeared = by_content(
+            self.all()
+                .map(|store| store.lock().unwrap().untracked_files(&sizes))
+                .collect(),
+        );
+        let mut moves = gone
+            .iter()
+            .filter_map(|(content, from)| {
+                match (from.as_slice(), appeared.get(content).map(Vec::as_slice)) {
+                    ([from], Some([to]))
+                        if !std::ptr::eq(self.for_path(from), self.for_path(to)) =>
+                    {
+                        Some((from.clone(), to.clone()))
+                    }
+                    _ => None,
+                }
+            })
+            .collect::<Vec<_>>();
+        moves.sort();
+        moves
+    }
+
+    /// moves the history of `from` to `to`, into the store of `to` if that is another one
+    pub fn move_history(&self, from: &str, to: &str) -> Result<(), Box<dyn error::Error>> {
+        let (source, target) = (self.for_path(from), self.for_path(to));
+        if std::ptr::eq(source, target) {
+            return source.lock().unwrap().move_history(from, to);
+        }
+        let mut target = target.lock().unwrap();
+        source
+            .lock()
+            .unwrap()
+            .move_history_to(from, &mut target, to)
+    }
+
+    /// the `limit` latest versions across all stores, see `Store::recent`
+    pub fn recent(&self, limit: usize) -> Vec<(String, usize, SystemTime)> {
+        let mut recent = self
+            .all()
+            .flat_map(|store| store.lock().unwrap().recent(limit))
+            .collect::<Vec<_>>();
+        recent.sort_by_key(|(_, _, timestamp)| std::cmp::Reverse(*timestamp));
+        recent.truncate(limit);
+        recent
+    }
+}
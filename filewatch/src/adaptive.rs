@@ -0,0 +1,152 @@
+//!
+//! per file debounce that follows how often a file gets saved.
+//! a save that arrives while the previous one is still waiting means the
+//! file is churning, its debounce grows so only the settled state gets
+//! recorded. a save long after the previous one shrinks it again, so
+//! occasional edits are recorded quickly.
+//!
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// saves further apart than this many debounces count as occasional
+const QUIET_FACTOR: u32 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DebounceBounds {
+    pub min: Duration,
+    pub max: Duration,
+}
+
+impl DebounceBounds {
+    fn clamp(&self, debounce: Duration) -> Duration {
+        debounce.max(self.min).min(self.max)
+    }
+}
+
+struct FileDebounce {
+    debounce: Duration,
+    last_save: Instant,
+    due: Option<Instant>,
+}
+
+pub struct AdaptiveDebounce {
+    initial: Duration,
+    bounds: DebounceBounds,
+    files: HashMap<PathBuf, FileDebounce>,
+}
+
+impl AdaptiveDebounce {
+    /// every file starts with `initial`, kept within `bounds`
+    pub fn new(initial: Duration, bounds: DebounceBounds) -> AdaptiveDebounce {
+        AdaptiveDebounce {
+            initial: bounds.clamp(initial),
+            bounds,
+            files: HashMap::new(),
+        }
+    }
+
+    ///
+    /// registers a save of `path` and postpones its recording,
+    /// returns the debounce it waits for
+    ///
+    pub fn on_save(&mut self, path: &Path, now: Instant) -> Duration {
+        let (bounds, initial) = (self.bounds, self.initial);
+        let file = self
+            .files
+            .entry(path.to_path_buf())
+            .or_insert_with(|| FileDebounce {
+                debounce: initial,
+                last_save: now,
+                due: None,
+            });
+
+        let interval = now.saturating_duration_since(file.last_save);
+        if file.due.is_some() && interval < file.debounce {
+            file.debounce = bounds.clamp(file.debounce * 3 / 2);
+        } else if interval > file.debounce * QUIET_FACTOR {
+            file.debounce = bounds.clamp(file.debounce * 3 / 4);
+        }
+        file.last_save = now;
+        file.due = Some(now + file.debounce);
+        file.debounce
+    }
+
+    /// the files whose debounce ran out, they get recorded now
+    pub fn take_due(&mut self, now: Instant) -> Vec<PathBuf> {
+        self.take_where(|due| due <= now)
+    }
+
+    /// every held back file, whether its debounce ran out or not
+    pub fn take_pending(&mut self) -> Vec<PathBuf> {
+        self.take_where(|_| true)
+    }
+
+    /// forgets the waiting save of `path`, its debounce stays as it is
+    pub fn release(&mut self, path: &Path) {
+        if let Some(file) = self.files.get_mut(path) {
+            file.due = None;
+        }
+    }
+
+    fn take_where(&mut self, is_taken: impl Fn(Instant) -> bool) -> Vec<PathBuf> {
+        self.files
+            .iter_mut()
+            .filter(|(_, file)| file.due.is_some_and(&is_taken))
+            .map(|(path, file)| {
+                file.due = None;
+                path.clone()
+            })
+            .collect()
+    }
+
+    pub fn debounce_of(&self, path: &Path) -> Option<Duration> {
+        self.files.get(path).map(|file| file.debounce)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn churning_files_wait_longer_than_occasional_ones() {
+        let bounds = DebounceBounds {
+            min: ms(100),
+            max: ms(1000),
+        };
+        let mut adaptive = AdaptiveDebounce::new(ms(400), bounds);
+        let path = Path::new("/watch/a.txt");
+        let start = Instant::now();
+
+        assert_eq!(adaptive.on_save(path, start), ms(400));
+        assert!(adaptive.take_due(start + ms(399)).is_empty());
+        // saved again before the first save settled
+        assert_eq!(adaptive.on_save(path, start + ms(200)), ms(600));
+        assert_eq!(adaptive.on_save(path, start + ms(400)), ms(900));
+        assert_eq!(adaptive.on_save(path, start + ms(600)), ms(1000));
+        assert!(adaptive.take_due(start + ms(1599)).is_empty());
+        assert_eq!(
+            adaptive.take_due(start + ms(1600)),
+            vec![path.to_path_buf()]
+        );
+        assert!(adaptive.take_due(start + ms(1700)).is_empty());
+
+        // occasional saves bring it back down
+        let later = start + Duration::from_secs(60);
+        assert_eq!(adaptive.on_save(path, later), ms(750));
+        assert_eq!(adaptive.debounce_of(path), Some(ms(750)));
+        let debounce = (1..10).fold(ms(750), |_, minute| {
+            adaptive.on_save(path, later + Duration::from_secs(60 * minute))
+        });
+        assert_eq!(debounce, ms(100));
+
+        // quitting takes what is still held back
+        assert_eq!(adaptive.take_pending(), vec![path.to_path_buf()]);
+        assert!(adaptive.take_pending().is_empty());
+    }
+}
@@ -0,0 +1,159 @@
+//!
+//! collapses the events waiting for the event handle by path. while the
+//! handle is busy diffing, e.g. during a rebuild that rewrites thousands of
+//! files, the same file changes again and again before its first event got
+//! handled. only its latest event is handled, once.
+//!
+use notify::DebouncedEvent;
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+
+enum Pending {
+    /// the latest event of the path is in `Coalescer::events`
+    Path(PathBuf),
+    /// an event that isn't about a single path, like a rename
+    Event(DebouncedEvent),
+}
+
+#[derive(Default)]
+pub struct Coalescer {
+    /// in the order the first event of each path arrived
+    order: VecDeque<Pending>,
+    events: HashMap<PathBuf, DebouncedEvent>,
+}
+
+impl Coalescer {
+    ///
+    /// replaces an event of the same path that is still waiting. the events
+    /// of the paths of a rename are handled before it, events of them after
+    /// it wait behind it.
+    ///
+    pub fn push(&mut self, event: DebouncedEvent) {
+        let path = match path_of(&event) {
+            Some(path) => path.clone(),
+            None => {
+                if let DebouncedEvent::Rename(from, to) = &event {
+                    self.settle(from);
+                    self.settle(to);
+                }
+                return self.order.push_back(Pending::Event(event));
+            }
+        };
+        match self.events.get(&path) {
+            Some(pending) if keeps(pending, &event) => {}
+            Some(_) => {
+                self.events.insert(path, event);
+            }
+            None => {
+                self.events.insert(path.clone(), event);
+                self.order.push_back(Pending::Path(path));
+            }
+        }
+    }
+
+    /// keeps the waiting event of `path` in its place, later events of it don't replace it
+    fn settle(&mut self, path: &PathBuf) {
+        let event = match self.events.remove(path) {
+            Some(event) => event,
+            None => return,
+        };
+        if let Some(pending) = self
+            .order
+            .iter_mut()
+            .find(|pending| matches!(pending, Pending::Path(waiting) if waiting == path))
+        {
+            *pending = Pending::Event(event);
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<DebouncedEvent> {
+        match self.order.pop_front()? {
+            Pending::Path(path) => self.events.remove(&path),
+            Pending::Event(event) => Some(event),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+}
+
+fn path_of(event: &DebouncedEvent) -> Option<&PathBuf> {
+    match event {
+        DebouncedEvent::NoticeWrite(path)
+        | DebouncedEvent::NoticeRemove(path)
+        | DebouncedEvent::Create(path)
+        | DebouncedEvent::Write(path)
+        | DebouncedEvent::Chmod(path)
+        | DebouncedEvent::Remove(path) => Some(path),
+        _ => None,
+    }
+}
+
+/// recording a write picks up a later chmod too, a notice of another write changes nothing
+fn keeps(pending: &DebouncedEvent, next: &DebouncedEvent) -> bool {
+    matches!(pending, DebouncedEvent::Write(_))
+        && matches!(
+            next,
+            DebouncedEvent::Chmod(_) | DebouncedEvent::NoticeWrite(_)
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_latest_event_of_a_path_is_handled_once() {
+        let path = |name: &str| PathBuf::from(format!("/watch/{}", name));
+        let mut coalescer = Coalescer::default();
+        (0..100).for_each(|_| coalescer.push(DebouncedEvent::Write(path("a.txt"))));
+        coalescer.push(DebouncedEvent::Write(path("b.txt")));
+        coalescer.push(DebouncedEvent::Chmod(path("a.txt")));
+        coalescer.push(DebouncedEvent::Rename(path("c.txt"), path("d.txt")));
+        coalescer.push(DebouncedEvent::Write(path("b.txt")));
+        coalescer.push(DebouncedEvent::Remove(path("b.txt")));
+        assert_eq!(coalescer.len(), 3);
+
+        let events = std::iter::from_fn(|| coalescer.pop())
+            .map(|event| format!("{:?}", event))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                format!("{:?}", DebouncedEvent::Write(path("a.txt"))),
+                format!("{:?}", DebouncedEvent::Remove(path("b.txt"))),
+                format!("{:?}", DebouncedEvent::Rename(path("c.txt"), path("d.txt"))),
+            ]
+        );
+        assert!(coalescer.is_empty());
+    }
+
+    #[test]
+    fn writes_of_a_renamed_path_stay_on_their_side_of_the_rename() {
+        let path = |name: &str| PathBuf::from(format!("/watch/{}", name));
+        let mut coalescer = Coalescer::default();
+        coalescer.push(DebouncedEvent::Write(path("a.txt")));
+        coalescer.push(DebouncedEvent::Rename(path("a.txt"), path("b.txt")));
+        coalescer.push(DebouncedEvent::Write(path("a.txt")));
+        coalescer.push(DebouncedEvent::Write(path("b.txt")));
+        coalescer.push(DebouncedEvent::Write(path("a.txt")));
+
+        let events = std::iter::from_fn(|| coalescer.pop())
+            .map(|event| format!("{:?}", event))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                format!("{:?}", DebouncedEvent::Write(path("a.txt"))),
+                format!("{:?}", DebouncedEvent::Rename(path("a.txt"), path("b.txt"))),
+                format!("{:?}", DebouncedEvent::Write(path("a.txt"))),
+                format!("{:?}", DebouncedEvent::Write(path("b.txt"))),
+            ]
+        );
+    }
+}
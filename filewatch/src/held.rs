@@ -0,0 +1,72 @@
+//!
+//! writes of files with a debounce set in the TUI, held back until it ran
+//! out. a write that arrives meanwhile starts the wait over, like the
+//! watcher does with its own debounce.
+//!
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+#[derive(Default)]
+pub struct HeldWrites {
+    due: HashMap<PathBuf, Instant>,
+}
+
+impl HeldWrites {
+    /// records `path` once `due` passed, unless it gets held again
+    pub fn hold(&mut self, path: &Path, due: Instant) {
+        self.due.insert(path.to_path_buf(), due);
+    }
+
+    /// the files whose debounce ran out, they get recorded now
+    pub fn take_due(&mut self, now: Instant) -> Vec<PathBuf> {
+        let due = self
+            .due
+            .iter()
+            .filter(|(_, due)| **due <= now)
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+        due.iter().for_each(|path| {
+            self.due.remove(path);
+        });
+        due
+    }
+
+    /// forgets the held back write of `path`, it got recorded some other way
+    pub fn release(&mut self, path: &Path) {
+        self.due.remove(path);
+    }
+
+    /// every held back file, whether its debounce ran out or not
+    pub fn take_pending(&mut self) -> Vec<PathBuf> {
+        self.due.drain().map(|(path, _)| path).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn another_write_starts_the_wait_over() {
+        let (a, b) = (Path::new("/watch/a.txt"), Path::new("/watch/b.txt"));
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut held = HeldWrites::default();
+        held.hold(a, at(1000));
+        held.hold(b, at(1000));
+        held.hold(a, at(1500));
+
+        assert_eq!(held.take_due(at(1200)), vec![b.to_path_buf()]);
+        assert!(held.take_due(at(1200)).is_empty());
+        held.hold(b, at(5000));
+        held.hold(a, at(1500));
+        held.release(a);
+        assert!(held.take_due(at(1500)).is_empty());
+        held.hold(a, at(1500));
+        assert_eq!(held.take_due(at(1500)), vec![a.to_path_buf()]);
+        assert_eq!(held.take_pending(), vec![b.to_path_buf()]);
+        assert!(held.take_pending().is_empty());
+    }
+}
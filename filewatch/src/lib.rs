@@ -1,63 +1,345 @@
 extern crate notify;
 
-use std::path::Path;
+pub mod adaptive;
+pub mod coalesce;
+pub mod held;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{channel, Receiver};
-use std::time::Duration;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
 use notify::{watcher, DebouncedEvent, Error, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Deserialize;
+
+use adaptive::{AdaptiveDebounce, DebounceBounds};
+use coalesce::Coalescer;
+use held::HeldWrites;
+use event_handle::event_handle::{EventHandle, Metrics, ViewUpdate};
+use store::filter::PathFilter;
+
+static ROOT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// how much longer than its debounce the watcher may take to emit an event
+static EMIT_MARGIN: Duration = Duration::from_millis(100);
+/// how often the status bar of the TUI gets new metrics
+static METRICS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// a maintenance pass once no events arrived for `idle`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Maintenance {
+    pub idle: Duration,
+    /// collects orphaned data of a fragmented store as well
+    pub compact: bool,
+}
+
+/// why `FileWatch::start_watching` returned
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stopped {
+    /// by the TUI or a signal
+    Quit,
+    /// no event arrived for the `idle_shutdown`
+    Idle,
+}
+
+///
+/// what a `NoticeWrite` leads to. it fires as soon as a write starts, the
+/// content may not have landed yet, so only the `Write` after it is recorded.
+///
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NoticeWrite {
+    #[default]
+    Ignore,
+    /// reads the file ahead, so the diff of its `Write` finds it cached
+    Prewarm,
+}
 
-use event_handle::event_handle::EventHandle;
+///
+/// a background pass every `interval` recording the changes of tracked files
+/// the events missed, next to the watcher
+///
+struct Poll {
+    interval: Duration,
+    last: Instant,
+    /// files modified from here on get diffed by the next pass
+    since: SystemTime,
+    running: Option<thread::JoinHandle<()>>,
+}
 
 pub struct FileWatch {
     event_handle: EventHandle,
     on_event: Receiver<DebouncedEvent>,
+    /// the events that arrived while the last one was handled, see `coalesce`
+    pending: Coalescer,
     on_quit: flume::Receiver<()>,
     watch_dog: RecommendedWatcher,
+    debounce_time: Duration,
     excluded_files: Vec<String>,
     excluded_paths: Vec<String>,
+    filter: PathFilter,
+    /// holds back writes with a per file debounce, see `adaptive`
+    adaptive: Option<AdaptiveDebounce>,
+    /// holds back writes of files with their own debounce, see `held`
+    held: HeldWrites,
+    ///
+    /// files recorded ahead of their debounce until the watcher emitted the
+    /// write it was waiting with, that one is only recorded if it changed
+    /// something since
+    ///
+    recorded_now: HashMap<PathBuf, Instant>,
+    maintenance: Option<Maintenance>,
+    last_event: Instant,
+    /// whether a pass ran since the last event
+    maintained: bool,
+    /// set by the next event, the running pass stops early
+    interrupt_maintenance: Arc<AtomicBool>,
+    poll: Option<Poll>,
+    last_metrics: Instant,
+    /// the metrics being added up, the next report waits for them
+    metrics_running: Option<thread::JoinHandle<()>>,
+    notice_write: NoticeWrite,
+    /// stops watching once no event arrived for this long
+    idle_shutdown: Option<Duration>,
 }
 impl FileWatch {
+    ///
+    /// with `adaptive` bounds the watcher only debounces by their minimum,
+    /// writes are held back per file starting with `debounce_time`
+    ///
     pub fn new(
         debounce_time: Duration,
         event_handle: EventHandle,
         on_quit: flume::Receiver<()>,
         excluded_files: Vec<String>,
         excluded_paths: Vec<String>,
+        filter: PathFilter,
+        adaptive: Option<DebounceBounds>,
     ) -> Result<FileWatch, Error> {
+        let watcher_delay = adaptive.map_or(debounce_time, |bounds| bounds.min);
+        let adaptive = adaptive.map(|bounds| AdaptiveDebounce::new(debounce_time, bounds));
+        let debounce_time = watcher_delay;
         let (tx, on_event) = channel();
         let watch_dog = watcher(tx, debounce_time)?;
         Ok(FileWatch {
             event_handle,
             on_event,
+            pending: Coalescer::default(),
             on_quit,
             watch_dog,
+            debounce_time,
             excluded_files,
             excluded_paths,
+            filter,
+            adaptive,
+            held: HeldWrites::default(),
+            recorded_now: HashMap::new(),
+            maintenance: None,
+            last_event: Instant::now(),
+            maintained: false,
+            interrupt_maintenance: Arc::new(AtomicBool::new(false)),
+            poll: None,
+            last_metrics: Instant::now(),
+            metrics_running: None,
+            notice_write: NoticeWrite::default(),
+            idle_shutdown: None,
         })
     }
-    pub fn start_watching(&mut self, dir: &str) -> Result<(), Box<dyn std::error::Error>> {
+
+    pub fn with_maintenance(self, maintenance: Option<Maintenance>) -> FileWatch {
+        FileWatch {
+            maintenance,
+            ..self
+        }
+    }
+
+    /// polls the tracked files every `interval` on top of the events
+    pub fn with_poll(self, interval: Option<Duration>) -> FileWatch {
+        let poll = interval.map(|interval| Poll {
+            interval,
+            last: Instant::now(),
+            since: SystemTime::now(),
+            running: None,
+        });
+        FileWatch { poll, ..self }
+    }
+
+    pub fn with_notice_write(self, notice_write: NoticeWrite) -> FileWatch {
+        FileWatch {
+            notice_write,
+            ..self
+        }
+    }
+
+    /// quits like the TUI does once no event arrived for `idle`
+    pub fn with_idle_shutdown(self, idle_shutdown: Option<Duration>) -> FileWatch {
+        FileWatch {
+            idle_shutdown,
+            ..self
+        }
+    }
+    pub fn start_watching(&mut self, dir: &str) -> Result<Stopped, Box<dyn std::error::Error>> {
         self.watch(dir)?;
 
         loop {
-            self.listen()?;
+            if let Some(event) = self.listen() {
+                self.on_activity();
+                if self.lost_root(&event, dir) {
+                    if !self.wait_for_root(dir)? {
+                        break;
+                    }
+                } else {
+                    self.handle(event)?;
+                }
+            }
+            self.record_now_if_asked();
+            self.handle_settled()?;
+            self.maintain_if_idle();
+            self.poll_if_due();
+            self.report_metrics_if_due();
+
+            if self.on_quit.try_recv().is_ok() {
+                return self.finish().map(|_| Stopped::Quit);
+            }
+            let idle = self.idle_shutdown;
+            if let Some(idle) = idle.filter(|idle| self.last_event.elapsed() >= *idle) {
+                eprintln!("No activity for {}s, shutting down", idle.as_secs());
+                return self.finish().map(|_| Stopped::Idle);
+            }
+        }
+
+        Ok(Stopped::Quit)
+    }
+
+    fn on_activity(&mut self) {
+        self.last_event = Instant::now();
+        self.maintained = false;
+        self.interrupt_maintenance.store(true, Ordering::SeqCst);
+    }
+
+    fn maintain_if_idle(&mut self) {
+        let maintenance = match self.maintenance {
+            Some(maintenance) if !self.maintained => maintenance,
+            _ => return,
+        };
+        if self.last_event.elapsed() < maintenance.idle {
+            return;
+        }
+        self.maintained = true;
+        // the running pass keeps its own flag, a new one starts uninterrupted
+        self.interrupt_maintenance = Arc::new(AtomicBool::new(false));
+        self.event_handle
+            .maintain(maintenance.compact, self.interrupt_maintenance.clone());
+    }
+
+    /// starts the next pass once the interval is up and the last one is done
+    fn poll_if_due(&mut self) {
+        let poll = match &mut self.poll {
+            Some(poll) if poll.last.elapsed() >= poll.interval => poll,
+            _ => return,
+        };
+        if poll.running.as_ref().is_some_and(|running| !running.is_finished()) {
+            return;
+        }
+        // mtimes can be coarser than the clock
+        let since = poll.since - Duration::from_secs(1);
+        poll.last = Instant::now();
+        poll.since = SystemTime::now();
+        poll.running = Some(self.event_handle.poll(since));
+    }
+
+    pub fn metrics(&self) -> Metrics {
+        self.event_handle.metrics()
+    }
+
+    /// see `EventHandle::subscribe`
+    pub fn subscribe(&self) -> flume::Receiver<ViewUpdate> {
+        self.event_handle.subscribe()
+    }
+
+    fn report_metrics_if_due(&mut self) {
+        let running = self.metrics_running.as_ref();
+        if self.last_metrics.elapsed() < METRICS_INTERVAL
+            || running.is_some_and(|running| !running.is_finished())
+        {
+            return;
+        }
+        self.last_metrics = Instant::now();
+        self.metrics_running = self.event_handle.report_metrics();
+    }
 
+    ///
+    /// records the saves the debouncers still hold back, so quitting right
+    /// after a save doesn't lose it, and waits for the store to be flushed
+    ///
+    fn finish(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        thread::sleep(self.debounce_time + EMIT_MARGIN);
+        while let Some(event) = self.listen() {
+            self.handle(event)?;
+        }
+        let mut pending = self.held.take_pending();
+        if let Some(adaptive) = &mut self.adaptive {
+            pending.extend(adaptive.take_pending());
+        }
+        pending
+            .into_iter()
+            .try_for_each(|path| self.event_handle.handle(DebouncedEvent::Write(path)))?;
+        if let Some(running) = self.poll.as_mut().and_then(|poll| poll.running.take()) {
+            let _ = running.join();
+        }
+        self.event_handle.end_sessions()?;
+        self.event_handle.flush()
+    }
+
+    ///
+    /// an unmounted or removed watch root shows up as an error, a rescan
+    /// or the removal of the root itself
+    ///
+    fn lost_root(&self, event: &DebouncedEvent, dir: &str) -> bool {
+        let affects_root = match event {
+            DebouncedEvent::Error(_, _) | DebouncedEvent::Rescan => true,
+            DebouncedEvent::Remove(path) => path == Path::new(dir),
+            _ => false,
+        };
+        affects_root && !Path::new(dir).is_dir()
+    }
+
+    ///
+    /// pauses recording until the watch root is back, then watches it again
+    /// and records what changed in the meantime.
+    /// returns `false` if the app quit while waiting.
+    ///
+    fn wait_for_root(&mut self, dir: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        eprintln!("Watch root {} is unavailable, pausing", dir);
+        self.event_handle.report_watch_root(dir, false);
+        while !Path::new(dir).is_dir() {
             if self.on_quit.try_recv().is_ok() {
-                break;
+                return Ok(false);
             }
+            thread::sleep(ROOT_POLL_INTERVAL);
         }
 
-        Ok(())
+        let (tx, on_event) = channel();
+        self.watch_dog = watcher(tx, self.debounce_time)?;
+        self.on_event = on_event;
+        self.watch(dir)?;
+        self.event_handle.resync(dir);
+        self.event_handle.report_watch_root(dir, true);
+        Ok(true)
     }
 
     fn watch(&mut self, dir: &str) -> Result<(), notify::Error> {
         self.watch_dog.watch(dir, RecursiveMode::Recursive)
     }
 
-    fn listen(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        if let Ok(event) = self.on_event.try_recv() {
-            return self.handle(event);
+    /// a file that changed again while the handle was busy is handled once
+    fn listen(&mut self) -> Option<DebouncedEvent> {
+        while let Ok(event) = self.on_event.try_recv() {
+            self.pending.push(event);
         }
-        Ok(())
+        self.pending.pop()
     }
 
     fn handle(&mut self, event: DebouncedEvent) -> Result<(), Box<dyn std::error::Error>> {
@@ -66,17 +348,81 @@ impl FileWatch {
             return Ok(());
         }
         let path = path.unwrap();
-        if self.is_not_excluded(&path) {
-            return self.event_handle.handle(event);
+        // a removed file is gone by the time its removal arrives
+        let is_removal = matches!(event, DebouncedEvent::Remove(_));
+        if !(path.is_file() || is_removal) || !self.is_not_excluded(&path) {
+            return Ok(());
         }
+        if let DebouncedEvent::NoticeWrite(_) = event {
+            if self.notice_write == NoticeWrite::Prewarm {
+                let _ = std::fs::read(&path);
+            }
+            return Ok(());
+        }
+        if let DebouncedEvent::Write(_) = event {
+            let recorded_now = self.recorded_now.remove(&path);
+            if recorded_now.is_some_and(|until| until > Instant::now()) {
+                self.event_handle.record_now(path.to_str());
+                return Ok(());
+            }
+        }
+        let own_debounce = match event {
+            DebouncedEvent::Write(_) => self.event_handle.debounce_of(&path),
+            _ => None,
+        };
+        if let Some(debounce) = own_debounce {
+            // the watcher waited its own debounce already, a shorter one can't be had
+            let due = Instant::now() + debounce.saturating_sub(self.debounce_time);
+            self.held.hold(&path, due);
+            return Ok(());
+        }
+        if let (Some(adaptive), DebouncedEvent::Write(_)) = (&mut self.adaptive, &event) {
+            let previous = adaptive.debounce_of(&path);
+            let debounce = adaptive.on_save(&path, Instant::now());
+            if previous != Some(debounce) {
+                self.event_handle.report_debounce(&path, debounce);
+            }
+            return Ok(());
+        }
+        self.event_handle.handle(event)
+    }
+
+    /// records the held back writes whose debounce ran out
+    fn handle_settled(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let now = Instant::now();
+        let mut settled = self.held.take_due(now);
+        if let Some(adaptive) = &mut self.adaptive {
+            settled.extend(adaptive.take_due(now));
+        }
+        settled
+            .into_iter()
+            .try_for_each(|path| self.event_handle.handle(DebouncedEvent::Write(path)))
+    }
 
-        Ok(())
+    ///
+    /// records the files the TUI or a signal asked for without waiting for
+    /// their debounce, the writes held back for them are dropped
+    ///
+    fn record_now_if_asked(&mut self) {
+        let now = Instant::now();
+        self.recorded_now.retain(|_, until| *until > now);
+        for path in self.event_handle.take_record_now() {
+            let diffed = self.event_handle.record_now(path.as_deref());
+            for path in diffed.iter().map(Path::new) {
+                self.held.release(path);
+                if let Some(adaptive) = &mut self.adaptive {
+                    adaptive.release(path);
+                }
+                let until = now + self.debounce_time + EMIT_MARGIN;
+                self.recorded_now.insert(path.to_path_buf(), until);
+            }
+        }
     }
 
     fn is_not_excluded(&self, path: &Path) -> bool {
         let is_not_excluded_file = self.is_not_excluded_file(path);
         let is_not_excluded_path = self.is_not_excluded_path(path);
-        path.is_file() && is_not_excluded_file && is_not_excluded_path
+        is_not_excluded_file && is_not_excluded_path && self.filter.is_tracked(path)
     }
 
     fn is_not_excluded_file(&self, path: &Path) -> bool {
@@ -0,0 +1,56 @@
+//!
+//! checksums of the store file, a damaged store is told apart from a
+//! good one without opening it. a plaintext store auto-stash wrote by
+//! hand starts with a yaml comment holding the checksum of the rest,
+//! pickledb skips it. the dumps pickledb does on its own go without.
+//!
+
+static HEADER: &str = "# auto-stash checksum ";
+
+/// 64 bit FNV-1a, a single changed byte always changes it
+pub fn of(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash: u64, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// `payload` behind a header line with its checksum
+pub fn prepend(payload: &[u8]) -> Vec<u8> {
+    [
+        format!("{}{:016x}\n", HEADER, of(payload)).as_bytes(),
+        payload,
+    ]
+    .concat()
+}
+
+///
+/// what follows the header of `content`, all of it if there is no header.
+/// `None` if the checksum doesn't match.
+///
+pub fn verify(content: &[u8]) -> Option<&[u8]> {
+    let rest = match content.strip_prefix(HEADER.as_bytes()) {
+        Some(rest) => rest,
+        None => return Some(content),
+    };
+    let end = rest.iter().position(|byte| *byte == b'\n')?;
+    let checksum = std::str::from_utf8(&rest[..end]).ok()?;
+    let payload = &rest[end + 1..];
+    (u64::from_str_radix(checksum, 16).ok()? == of(payload)).then_some(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_flipped_byte_fails_the_checksum() {
+        let content = prepend(b"---\na: b\n");
+        assert_eq!(verify(&content), Some(&b"---\na: b\n"[..]));
+        assert_eq!(verify(b"---\na: b\n"), Some(&b"---\na: b\n"[..]));
+
+        let mut flipped = content.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        assert_eq!(verify(&flipped), None);
+        assert_eq!(verify(&content[..HEADER.len() + 4]), None);
+    }
+}
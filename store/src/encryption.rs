@@ -0,0 +1,158 @@
+//!
+//! sealing of the store file at rest.
+//! an encrypted store starts with `MAGIC`, followed by the checksum of
+//! the rest, the salt the key was derived with, the nonce and the AES-GCM
+//! ciphertext of the store. stores sealed before the checksum start with
+//! `MAGIC_V1` and go without it.
+//!
+use crate::checksum;
+use std::error;
+
+pub static MAGIC: &[u8] = b"AUTOSTASH-ENC\x02";
+static MAGIC_V1: &[u8] = b"AUTOSTASH-ENC\x01";
+
+const CHECKSUM_LENGTH: usize = 8;
+
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+/// the authentication tag AES-GCM appends to the ciphertext
+const TAG_LENGTH: usize = 16;
+
+/// A key derived from a passphrase together with the salt it was derived with.
+#[cfg_attr(not(feature = "encryption"), allow(dead_code))]
+pub struct Sealing {
+    salt: Vec<u8>,
+    key: Vec<u8>,
+}
+
+pub fn is_sealed(content: &[u8]) -> bool {
+    content.starts_with(MAGIC) || content.starts_with(MAGIC_V1)
+}
+
+///
+/// whether a sealed `content` fails its checksum or is too short to hold
+/// the header and the tag, the ciphertext itself is only authenticated
+/// with the passphrase
+///
+pub fn is_damaged(content: &[u8]) -> bool {
+    body(content).is_none_or(|body| body.len() < SALT_LENGTH + NONCE_LENGTH + TAG_LENGTH)
+}
+
+/// what follows the magic and the checksum, `None` if the checksum doesn't match
+fn body(content: &[u8]) -> Option<&[u8]> {
+    if let Some(body) = content.strip_prefix(MAGIC_V1) {
+        return Some(body);
+    }
+    let rest = content.strip_prefix(MAGIC)?;
+    if rest.len() < CHECKSUM_LENGTH {
+        return None;
+    }
+    let (stored, body) = rest.split_at(CHECKSUM_LENGTH);
+    (stored == checksum::of(body).to_be_bytes()).then_some(body)
+}
+
+/// `body` behind the magic and its checksum
+#[cfg_attr(not(feature = "encryption"), allow(dead_code))]
+fn with_header(body: &[u8]) -> Vec<u8> {
+    [MAGIC, &checksum::of(body).to_be_bytes(), body].concat()
+}
+
+#[cfg(feature = "encryption")]
+mod cipher {
+    use super::{Sealing, NONCE_LENGTH, SALT_LENGTH};
+    use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+    use aes_gcm::{Aes256Gcm, Key, Nonce};
+    use argon2::password_hash::rand_core::RngCore;
+    use argon2::Argon2;
+    use std::error;
+
+    fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Vec<u8>, Box<dyn error::Error>> {
+        let mut key = vec![0u8; 32];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|err| format!("couldn't derive store key: {}", err))?;
+        Ok(key)
+    }
+
+    pub fn new_sealing(passphrase: &str) -> Result<Sealing, Box<dyn error::Error>> {
+        let mut salt = vec![0u8; SALT_LENGTH];
+        OsRng.fill_bytes(&mut salt);
+        let key = derive_key(passphrase, &salt)?;
+
+        Ok(Sealing { salt, key })
+    }
+
+    pub fn seal(sealing: &Sealing, plain: &[u8]) -> Result<Vec<u8>, Box<dyn error::Error>> {
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&sealing.key));
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let sealed = cipher
+            .encrypt(&nonce, plain)
+            .map_err(|_| "couldn't encrypt store")?;
+
+        let body = [sealing.salt.as_slice(), nonce.as_slice(), sealed.as_slice()].concat();
+        Ok(super::with_header(&body))
+    }
+
+    pub fn unseal(
+        passphrase: &str,
+        content: &[u8],
+    ) -> Result<(Sealing, Vec<u8>), Box<dyn error::Error>> {
+        let content =
+            super::body(content).ok_or("encrypted store is damaged, its checksum fails")?;
+        let header_length = SALT_LENGTH + NONCE_LENGTH;
+        if content.len() < header_length {
+            return Err("encrypted store is truncated".into());
+        }
+        let salt = &content[..SALT_LENGTH];
+        let nonce = Nonce::from_slice(&content[SALT_LENGTH..header_length]);
+
+        let key = derive_key(passphrase, salt)?;
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+        let plain = cipher
+            .decrypt(nonce, &content[header_length..])
+            .map_err(|_| "couldn't decrypt store, wrong passphrase or corrupted file")?;
+
+        Ok((
+            Sealing {
+                salt: salt.to_vec(),
+                key,
+            },
+            plain,
+        ))
+    }
+}
+
+#[cfg(not(feature = "encryption"))]
+mod cipher {
+    use super::Sealing;
+    use std::error;
+
+    static UNSUPPORTED: &str = "auto-stash was built without the encryption feature";
+
+    pub fn new_sealing(_passphrase: &str) -> Result<Sealing, Box<dyn error::Error>> {
+        Err(UNSUPPORTED.into())
+    }
+
+    pub fn seal(_sealing: &Sealing, _plain: &[u8]) -> Result<Vec<u8>, Box<dyn error::Error>> {
+        Err(UNSUPPORTED.into())
+    }
+
+    pub fn unseal(
+        _passphrase: &str,
+        _content: &[u8],
+    ) -> Result<(Sealing, Vec<u8>), Box<dyn error::Error>> {
+        Err(UNSUPPORTED.into())
+    }
+}
+
+pub fn new_sealing(passphrase: &str) -> Result<Sealing, Box<dyn error::Error>> {
+    cipher::new_sealing(passphrase)
+}
+
+pub fn seal(sealing: &Sealing, plain: &[u8]) -> Result<Vec<u8>, Box<dyn error::Error>> {
+    cipher::seal(sealing, plain)
+}
+
+pub fn unseal(passphrase: &str, content: &[u8]) -> Result<(Sealing, Vec<u8>), Box<dyn error::Error>> {
+    cipher::unseal(passphrase, content)
+}
@@ -0,0 +1,200 @@
+//!
+//! the context-only tier of large files. their versions keep the changed
+//! lines and a few unchanged lines around them instead of everything it
+//! takes to rebuild the file. the lines of the latest version are kept as
+//! fingerprints, so the next version still finds out which lines changed.
+//!
+use diff::LineDifference;
+use serde::Deserialize;
+use std::collections::BTreeSet;
+
+/// the old text of a changed line that no earlier excerpt kept
+pub static UNKNOWN_LINE: &str = "[not kept]";
+
+/// lines kept around a change once a file is context-only without a `ContextOnly`
+pub static DEFAULT_CONTEXT_LINES: usize = 3;
+
+/// hex digits of the fingerprint of one line
+const FINGERPRINT_LENGTH: usize = 8;
+
+/// files larger than `above_bytes` keep `context_lines` lines around their changes
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ContextOnly {
+    pub above_bytes: u64,
+    pub context_lines: usize,
+}
+
+/// FNV-1a, unlike the std hasher it stays the same across builds
+fn fingerprint(line: &str) -> u32 {
+    line.bytes().fold(0x811c_9dc5, |hash: u32, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+fn lines(content: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(content)
+        .lines()
+        .map(|line| line.to_string())
+        .collect()
+}
+
+/// the fingerprints of the lines of `content`, `FINGERPRINT_LENGTH` hex digits each
+pub fn fingerprints(content: &[u8]) -> String {
+    lines(content)
+        .iter()
+        .map(|line| format!("{:08x}", fingerprint(line)))
+        .collect()
+}
+
+fn parse(fingerprints: &str) -> Vec<Option<u32>> {
+    fingerprints
+        .as_bytes()
+        .chunks(FINGERPRINT_LENGTH)
+        .map(|chunk| {
+            std::str::from_utf8(chunk)
+                .ok()
+                .and_then(|chunk| u32::from_str_radix(chunk, 16).ok())
+        })
+        .collect()
+}
+
+///
+/// the lines of `content` whose fingerprint differs from `previous` and the
+/// lines it lost, each with `context` unchanged lines before and after it.
+/// the fingerprints are aligned with Myers first, the inserted and deleted
+/// blocks come first as moves like with `diff::find_in_aligned`. the old
+/// text of a changed line is taken from `known`, the lines the earlier
+/// excerpts kept, or is `UNKNOWN_LINE`. the unchanged lines have the same
+/// text before and after.
+///
+pub fn excerpt(
+    path: &str,
+    content: &[u8],
+    previous: &str,
+    known: &[String],
+    context: usize,
+) -> Vec<LineDifference> {
+    let lines = lines(content);
+    let previous = parse(previous);
+    let current = lines
+        .iter()
+        .map(|line| Some(fingerprint(line)))
+        .collect::<Vec<_>>();
+    // the line of `previous` each line ends up at, the inserted ones have none
+    let mut sources = (0..previous.len()).map(Some).collect::<Vec<_>>();
+    let ops = similar::capture_diff_slices(similar::Algorithm::Myers, &previous, &current);
+    let moves = diff::align_by(&mut sources, &ops);
+    let source = |index: usize| sources.get(index).copied().flatten();
+    let old_line = |source: usize| match known.get(source) {
+        Some(line) if !line.is_empty() => line.clone(),
+        _ => UNKNOWN_LINE.to_string(),
+    };
+
+    let changed = (0..lines.len().max(sources.len()))
+        .filter(|index| match (current.get(*index), source(*index)) {
+            (Some(line), Some(source)) => previous[source] != *line,
+            (None, None) => false,
+            _ => true,
+        })
+        .collect::<BTreeSet<usize>>();
+    let shown = changed
+        .iter()
+        .flat_map(|index| index.saturating_sub(context)..=index + context)
+        .filter(|index| *index < lines.len() || changed.contains(index))
+        .collect::<BTreeSet<usize>>();
+
+    let moves = moves
+        .into_iter()
+        .map(|moved| LineDifference::moved(path.to_string(), moved));
+    let changes = shown.into_iter().map(|index| {
+        let (line, changed_line) = match (lines.get(index), source(index)) {
+            (Some(line), _) if !changed.contains(&index) => (line.clone(), line.clone()),
+            (Some(line), Some(source)) => (old_line(source), line.clone()),
+            (Some(line), None) => (String::new(), line.clone()),
+            (None, source) => (source.map_or_else(String::new, old_line), String::new()),
+        };
+        LineDifference::new(path.to_string(), index, line, changed_line)
+    });
+    moves.chain(changes).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diff::DiffSummary;
+
+    fn numbered(count: usize) -> String {
+        (0..count).map(|line| format!("line {}\n", line)).collect()
+    }
+
+    #[test]
+    fn changed_lines_come_with_their_context() {
+        let before = fingerprints(numbered(20).as_bytes());
+        let after = numbered(20).replace("line 10\n", "line ten\n");
+        let known = vec![String::new(); 10];
+
+        let changes = excerpt("big.log", after.as_bytes(), &before, &known, 2);
+        let shown = changes.iter().map(|change| change.line_number);
+        assert_eq!(shown.collect::<Vec<_>>(), vec![8, 9, 10, 11, 12]);
+        assert_eq!(changes[2].line, UNKNOWN_LINE);
+        assert_eq!(changes[2].changed_line, "line ten");
+        let mut unchanged = changes.iter().filter(|change| change.line_number != 10);
+        assert!(unchanged.all(|change| change.kind().is_none()));
+
+        // lines only the new content has are added, the ones it lost removed
+        let grown = excerpt("big.log", numbered(21).as_bytes(), &before, &[], 0);
+        assert_eq!(grown.len(), 1);
+        assert_eq!(grown[0].line, "");
+        let shrunk = excerpt("big.log", numbered(19).as_bytes(), &before, &[], 1);
+        assert_eq!(shrunk.last().unwrap().line_number, 19);
+        assert_eq!(shrunk.last().unwrap().changed_line, "");
+        assert!(excerpt("big.log", numbered(20).as_bytes(), &before, &[], 3).is_empty());
+    }
+
+    #[test]
+    fn lines_after_an_insertion_near_the_top_stay_unchanged() {
+        let before = fingerprints(numbered(20).as_bytes());
+        let known = numbered(20).lines().map(String::from).collect::<Vec<_>>();
+        let inserted = numbered(20).replace("line 2\n", "new line\nline 2\n");
+
+        let changes = excerpt("big.log", inserted.as_bytes(), &before, &known, 1);
+        assert_eq!(
+            changes[0].moved.as_ref().map(|moved| moved.to_line),
+            Some(2)
+        );
+        let summary = DiffSummary::of(&changes);
+        assert_eq!(
+            (summary.added, summary.removed, summary.modified),
+            (1, 0, 0)
+        );
+        let shown = changes[1..].iter().map(|change| change.line_number);
+        assert_eq!(shown.collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(
+            diff::replay(&[known_changes(&known), changes].concat()).join("\n") + "\n",
+            inserted
+        );
+
+        // the lines after a deleted one are unchanged as well
+        let deleted = numbered(20).replace("line 3\n", "");
+        let changes = excerpt("big.log", deleted.as_bytes(), &before, &known, 0);
+        let summary = DiffSummary::of(&changes);
+        assert_eq!(
+            (summary.added, summary.removed, summary.modified),
+            (0, 1, 0)
+        );
+        assert_eq!(changes.last().unwrap().line, "line 3");
+        let lines = diff::replay(&[known_changes(&known), changes].concat());
+        assert_eq!(lines[..19].join("\n") + "\n", deleted);
+    }
+
+    /// `known` as the changes of a baseline
+    fn known_changes(known: &[String]) -> Vec<LineDifference> {
+        known
+            .iter()
+            .enumerate()
+            .map(|(index, line)| {
+                LineDifference::new(String::new(), index, String::new(), line.clone())
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,233 @@
+//!
+//! include and ignore patterns deciding which files below the watch path
+//! are tracked. patterns are globs relative to the watch path, patterns
+//! without a `/` match the file name in every directory.
+//! with include patterns only matching files are tracked, ignore patterns
+//! are applied afterwards and carve exceptions out of the included files.
+//! ignore patterns starting with `!` re-include matching files, like
+//! hidden files that are skipped otherwise.
+//!
+use glob::{MatchOptions, Pattern};
+use std::error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub static INCLUDE_FILE: &str = ".autostashinclude";
+pub static IGNORE_FILE: &str = ".autostashignore";
+
+static MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+///
+/// an empty filter tracks every file
+///
+#[derive(Clone, Debug, Default)]
+pub struct PathFilter {
+    watch_path: PathBuf,
+    include: Vec<Pattern>,
+    ignore: Vec<Pattern>,
+    unignore: Vec<Pattern>,
+    exclude_hidden: bool,
+    /// directories kept by another store, see `without_roots`
+    excluded_roots: Vec<PathBuf>,
+}
+
+impl PathFilter {
+    ///
+    /// combines the `include` patterns of the config with the ones of the
+    /// `.autostashinclude` file and reads the `.autostashignore` file,
+    /// both are looked up in the watch path and may be missing.
+    /// `exclude_hidden` skips paths with a component starting with `.`
+    /// unless a pattern names them explicitly.
+    ///
+    pub fn new(
+        watch_path: &str,
+        include: &[String],
+        exclude_hidden: bool,
+    ) -> Result<PathFilter, Box<dyn error::Error>> {
+        let watch_path = Path::new(watch_path);
+        let include = include
+            .iter()
+            .cloned()
+            .chain(read_patterns(&watch_path.join(INCLUDE_FILE))?)
+            .collect::<Vec<String>>();
+        let ignore = read_patterns(&watch_path.join(IGNORE_FILE))?;
+
+        Ok(PathFilter {
+            exclude_hidden,
+            ..PathFilter::from_patterns(watch_path, &include, &ignore)?
+        })
+    }
+
+    fn from_patterns(
+        watch_path: &Path,
+        include: &[String],
+        ignore: &[String],
+    ) -> Result<PathFilter, Box<dyn error::Error>> {
+        let (unignore, ignore): (Vec<String>, Vec<String>) = ignore
+            .iter()
+            .cloned()
+            .partition(|pattern| pattern.starts_with('!'));
+        let unignore = unignore
+            .iter()
+            .map(|pattern| pattern[1..].to_string())
+            .collect::<Vec<String>>();
+
+        Ok(PathFilter {
+            watch_path: watch_path.to_path_buf(),
+            include: compile(include)?,
+            ignore: compile(&ignore)?,
+            unignore: compile(&unignore)?,
+            exclude_hidden: false,
+            excluded_roots: Vec::new(),
+        })
+    }
+
+    /// skips every file below one of `roots` whatever the patterns say
+    pub fn without_roots(self, roots: &[PathBuf]) -> PathFilter {
+        PathFilter {
+            excluded_roots: roots.to_vec(),
+            ..self
+        }
+    }
+
+    pub fn is_tracked(&self, path: &Path) -> bool {
+        if self
+            .excluded_roots
+            .iter()
+            .any(|root| path.starts_with(root))
+        {
+            return false;
+        }
+        let relative = path.strip_prefix(&self.watch_path).unwrap_or(path);
+        let named = |patterns: &[Pattern]| patterns.iter().any(|p| matches(p, relative));
+        let is_included = named(&self.include);
+        let is_unignored = named(&self.unignore);
+
+        if !self.include.is_empty() && !is_included {
+            return false;
+        }
+        if named(&self.ignore) && !is_unignored {
+            return false;
+        }
+        // explicitly named hidden files are tracked anyway
+        !self.exclude_hidden || !is_hidden(relative) || is_included || is_unignored
+    }
+}
+
+fn is_hidden(relative: &Path) -> bool {
+    relative
+        .components()
+        .any(|component| component.as_os_str().to_string_lossy().starts_with('.'))
+}
+
+pub(crate) fn matches(pattern: &Pattern, relative: &Path) -> bool {
+    if pattern.as_str().contains('/') {
+        return pattern.matches_path_with(relative, MATCH_OPTIONS);
+    }
+    relative
+        .file_name()
+        .map(|name| pattern.matches_with(&name.to_string_lossy(), MATCH_OPTIONS))
+        .unwrap_or(false)
+}
+
+pub(crate) fn compile(patterns: &[String]) -> Result<Vec<Pattern>, Box<dyn error::Error>> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            Pattern::new(pattern.trim_start_matches('/'))
+                .map_err(|err| format!("invalid pattern {}: {}", pattern, err).into())
+        })
+        .collect()
+}
+
+/// one pattern per line, empty lines and lines starting with `#` are skipped
+fn read_patterns(path: &Path) -> Result<Vec<String>, Box<dyn error::Error>> {
+    if !path.is_file() {
+        return Ok(Vec::new());
+    }
+
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.to_string())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|pattern| pattern.to_string()).collect()
+    }
+
+    #[test]
+    fn include_first_then_ignore() {
+        let filter = PathFilter::from_patterns(
+            Path::new("/watch"),
+            &patterns(&["src/**/*.rs", "Cargo.toml"]),
+            &patterns(&["generated_*.rs"]),
+        )
+        .unwrap();
+
+        assert!(filter.is_tracked(Path::new("/watch/src/lib.rs")));
+        assert!(filter.is_tracked(Path::new("/watch/src/store/mod.rs")));
+        assert!(filter.is_tracked(Path::new("/watch/sub/Cargo.toml")));
+        assert!(!filter.is_tracked(Path::new("/watch/src/generated_types.rs")));
+        assert!(!filter.is_tracked(Path::new("/watch/README.md")));
+        assert!(!filter.is_tracked(Path::new("/watch/tests/lib.rs")));
+
+        let filter = filter.without_roots(&[PathBuf::from("/watch/src/store")]);
+        assert!(filter.is_tracked(Path::new("/watch/src/lib.rs")));
+        assert!(!filter.is_tracked(Path::new("/watch/src/store/mod.rs")));
+        assert!(filter.is_tracked(Path::new("/watch/src/storefront.rs")));
+    }
+
+    #[test]
+    fn patterns_are_read_from_the_watch_path() {
+        let dir = std::env::temp_dir().join(format!("auto_stash_filter_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(INCLUDE_FILE), "# only notes\n\n*.md\n").unwrap();
+        fs::write(dir.join(IGNORE_FILE), "drafts/*\n").unwrap();
+
+        let filter = PathFilter::new(dir.to_str().unwrap(), &[], false).unwrap();
+        assert!(filter.is_tracked(&dir.join("notes.md")));
+        assert!(!filter.is_tracked(&dir.join("drafts/notes.md")));
+        assert!(!filter.is_tracked(&dir.join("notes.txt")));
+        assert!(PathFilter::default().is_tracked(&dir.join("notes.txt")));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn hidden_paths_unless_named_explicitly() {
+        let filter = PathFilter {
+            exclude_hidden: true,
+            ..PathFilter::from_patterns(
+                Path::new("/home/user/.config"),
+                &[],
+                &patterns(&["*.log", "!.env", "!keep.log"]),
+            )
+            .unwrap()
+        };
+
+        assert!(filter.is_tracked(Path::new("/home/user/.config/app.toml")));
+        assert!(!filter.is_tracked(Path::new("/home/user/.config/.git/HEAD")));
+        assert!(!filter.is_tracked(Path::new("/home/user/.config/.cache/app.toml")));
+        assert!(filter.is_tracked(Path::new("/home/user/.config/sub/.env")));
+        assert!(!filter.is_tracked(Path::new("/home/user/.config/debug.log")));
+        assert!(filter.is_tracked(Path::new("/home/user/.config/keep.log")));
+
+        let included = PathFilter {
+            exclude_hidden: true,
+            ..PathFilter::from_patterns(Path::new("/watch"), &patterns(&[".github/**"]), &[])
+                .unwrap()
+        };
+        assert!(included.is_tracked(Path::new("/watch/.github/workflows/ci.yml")));
+    }
+}
@@ -1,31 +1,701 @@
 extern crate simple_error;
 
+mod checksum;
+mod encryption;
+pub mod excerpt;
+pub mod filter;
+pub mod lock;
+pub mod patch;
+pub mod retention;
+
 pub mod store {
+    use crate::checksum;
+    use crate::encryption::{self, Sealing};
+    use crate::excerpt::{self, ContextOnly};
+    use crate::filter::{self, PathFilter};
+    use crate::lock::StoreLock;
+    use crate::patch;
+    use crate::retention::{Retention, RetentionPolicy};
     use chrono::NaiveDate;
     use chrono::NaiveDateTime;
     use chrono::Utc;
-    use diff::LineDifference;
+    use diff::{replay, DiffAlgorithm, DiffSummary, LineDifference};
     use itertools::Itertools;
     use pickledb::{PickleDb, PickleDbDumpPolicy, SerializationMethod};
-    use serde::de::DeserializeOwned;
     use serde::{Deserialize, Serialize};
+    use similar::{DiffTag, TextDiff};
+    use std::cell::RefCell;
+    use std::collections::{HashMap, HashSet};
     use std::error;
     use std::fs::File;
-    use std::io::Write;
+    use std::io::{Read, Write};
     use std::io::{self, BufRead};
+    use std::ops::{Deref, DerefMut};
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
     use walkdir::{DirEntry, WalkDir};
 
     static FILE_VERSION_STACK: &str = "FILE_VERSION_STACK";
     static FILE_VERSION_MARKER: &str = "FILE_VERSION_MARKER";
+    static SCHEMA_VERSION: &str = "SCHEMA_VERSION";
+    /// the auto-stash that created the store or brought it to its schema
+    static WRITTEN_BY: &str = "WRITTEN_BY";
+    /// prefix of the keys holding the line fingerprints of context-only files
+    static FINGERPRINTS: &str = "FINGERPRINTS";
+    /// the start and stop times of the runs that watched the store, the latest `MAX_SESSIONS`
+    static SESSIONS: &str = "SESSIONS";
+    /// the runs remembered, the gaps before the oldest of them aren't reported anymore
+    static MAX_SESSIONS: usize = 1000;
+    /// the files and patterns the watcher leaves alone, see `Store::disable`
+    static DISABLED: &str = "DISABLED";
+    /// the other paths of hard linked files and the path they are tracked by
+    static HARD_LINKS: &str = "HARD_LINKS";
+    /// the debounce in milliseconds of the files it was set for, see `Store::set_debounce`
+    static DEBOUNCES: &str = "DEBOUNCES";
+    /// the retention of the files it was set for, see `Store::set_retention`
+    static RETENTIONS: &str = "RETENTIONS";
+    /// the watch paths the history was recorded below, see `Store::previous_watch_roots`
+    static WATCH_ROOTS: &str = "WATCH_ROOTS";
+    /// the keys besides the histories `export_all_json` writes and `import_all_json` reads
+    static EXPORTED_SETTINGS: [&str; 5] =
+        [RETENTIONS, DISABLED, DEBOUNCES, HARD_LINKS, WATCH_ROOTS];
+    /// share of orphaned bytes above which `compact_on_open` collects them
+    pub static COMPACT_THRESHOLD: f64 = 0.3;
+
+    type Migration = fn(&mut Store) -> Result<(), Box<dyn error::Error>>;
+
+    ///
+    /// `MIGRATIONS[n]` brings a store of schema `n` to schema `n + 1`,
+    /// stores without a schema version are schema 0
+    ///
+    static MIGRATIONS: &[Migration] = &[migrate_version_entries];
+
+    fn current_schema() -> u64 {
+        MIGRATIONS.len() as u64
+    }
     pub struct Store {
         db: PickleDb,
         pub time_frame: TimeFrame,
+        store_path: String,
+        /// the patterns of `disable_matching` are relative to it
+        watch_path: String,
+        /// what `find_moved_files` looks at, like the files the store started with
+        excluded_files: Vec<String>,
+        excluded_paths: Vec<String>,
+        filter: PathFilter,
+        sealing: Option<Sealing>,
+        /// a store of a newer schema read as well as it goes, never written
+        read_only: bool,
+        /// a `Txn` is running, nothing gets written before it is committed
+        in_txn: bool,
+        /// a file was purged in the running `Txn`, the commit zeroes the store file it replaces
+        scrub_on_commit: bool,
+        line_edits: bool,
+        detect_moves: bool,
+        diff_algorithm: DiffAlgorithm,
+        max_line_length: Option<usize>,
+        max_store_bytes: Option<u64>,
+        keep_duplicate: KeepDuplicate,
+        timestamp_source: TimestampSource,
+        context_only: Option<ContextOnly>,
+        rename_detection: RenameDetection,
+        evictions: Vec<Eviction>,
+        gc_report: Option<GcReport>,
+        /// built by the first `recent`, kept up to date while the epoch stays
+        recency: RefCell<Option<Recency>>,
+        /// the disabled patterns compiled, until they change
+        disabled_globs: RefCell<Option<(Vec<String>, Vec<glob::Pattern>)>>,
+        /// what `versions_iter` and `view_page` loaded, by path
+        views: HashMap<String, CachedView>,
+        /// files that couldn't be read since their last version
+        inaccessible: HashSet<String>,
+        /// untracked paths with several links but none to a tracked file, by the file they had
+        not_hard_linked: HashMap<String, (u64, u64)>,
+        /// bumped whenever versions get dropped or renumbered, see `FileVersions::epoch`
+        epoch: u64,
+        /// keeps other processes off the store while it is open, see `lock`. readers take none
+        _lock: Option<StoreLock>,
+        /// set for stores opened with `open_reader`, what `reload` needs to read the store again
+        reader: Option<Reader>,
+    }
+
+    ///
+    /// the versions of a file as `Store::versions_iter` keeps them, loaded
+    /// again once anything they were built from changed
+    ///
+    struct CachedView {
+        key: ViewKey,
+        file_versions: FileVersions,
+    }
+
+    #[derive(PartialEq)]
+    struct ViewKey {
+        version_stack: VersionStack,
+        change_count: usize,
+        /// versions before the time frame
+        hidden: usize,
+        head: usize,
+        inaccessible: bool,
+        disabled: bool,
+        debounce: Option<Duration>,
+        retention: Option<Retention>,
+        epoch: u64,
+    }
+
+    /// What a store opened with `Store::open_reader` reads its file again with.
+    struct Reader {
+        passphrase: Option<String>,
+        /// of the store file read last
+        read: FileState,
+    }
+
+    ///
+    /// changes to several files that land together or not at all, see
+    /// `Store::begin`. the store is changed through it like through `&mut Store`.
+    ///
+    pub struct Txn<'a> {
+        store: &'a mut Store,
+        /// the db as it was on `begin`, `None` once the transaction ended
+        db_before: Option<PickleDb>,
+        epoch_before: u64,
+        evictions_before: usize,
+        inaccessible_before: HashSet<String>,
+    }
+
+    impl Txn<'_> {
+        /// writes every change made through the transaction
+        pub fn commit(mut self) -> Result<(), Box<dyn error::Error>> {
+            self.store.in_txn = false;
+            if self.store.sealing.is_some() || self.store.read_only {
+                // a failed flush leaves the store file as it was and the drop rolls back
+                self.store.flush()?;
+                self.db_before = None;
+                return Ok(());
+            }
+
+            let temp_path = format!("{}.temp", self.store.store_path);
+            std::fs::write(&temp_path, checksum::prepend(&snapshot(&self.store.db)?))?;
+            // dropping it dumps what the store file holds anyway
+            drop(self.db_before.take());
+            let previous = match std::mem::take(&mut self.store.scrub_on_commit) {
+                true => File::options().write(true).open(&self.store.store_path).ok(),
+                false => None,
+            };
+            let renamed = std::fs::rename(temp_path, &self.store.store_path);
+            let db = load(&self.store.store_path, PickleDbDumpPolicy::AutoDump)?;
+            match renamed {
+                Ok(()) => {
+                    self.store.db = db;
+                    previous.map_or(Ok(()), scrub).map_err(|err| err.into())
+                }
+                Err(err) => {
+                    self.put_back(db);
+                    Err(err.into())
+                }
+            }
+        }
+
+        /// drops every change made through the transaction
+        pub fn rollback(mut self) {
+            if let Some(before) = self.db_before.take() {
+                self.put_back(before);
+            }
+        }
+
+        fn put_back(&mut self, db: PickleDb) {
+            self.store.db = db;
+            self.store.in_txn = false;
+            self.store.scrub_on_commit = false;
+            self.store.epoch = self.epoch_before;
+            self.store.evictions.truncate(self.evictions_before);
+            self.store.inaccessible = std::mem::take(&mut self.inaccessible_before);
+            // it may have picked up versions that are gone again
+            self.store.recency.replace(None);
+        }
+    }
+
+    impl Deref for Txn<'_> {
+        type Target = Store;
+
+        fn deref(&self) -> &Store {
+            self.store
+        }
+    }
+
+    impl DerefMut for Txn<'_> {
+        fn deref_mut(&mut self) -> &mut Store {
+            self.store
+        }
+    }
+
+    impl Drop for Txn<'_> {
+        fn drop(&mut self) {
+            if let Some(before) = self.db_before.take() {
+                self.put_back(before);
+            }
+        }
+    }
+
+    ///
+    /// settings a store is opened with, everything besides the
+    /// excluded files and paths is optional
+    ///
+    #[derive(Clone, Default)]
+    pub struct StoreOptions {
+        pub excluded_files: Vec<String>,
+        pub excluded_paths: Vec<String>,
+        /// include and ignore patterns applied on top of the exclusions
+        pub filter: PathFilter,
+        /// encrypts the store file at rest, needs the `encryption` feature
+        pub passphrase: Option<String>,
+        /// keeps only the edited range of modified lines, see `diff::LineEdit`
+        pub line_edits: bool,
+        /// keeps a block of lines that moved as one move, see `diff::Moved`
+        pub detect_moves: bool,
+        /// how the lines of a snapshot are matched up, unless moves are detected
+        pub diff_algorithm: DiffAlgorithm,
+        /// opens a store of a newer schema read-only instead of refusing it
+        pub read_newer: bool,
+        /// files with a longer line are recorded as whole snapshots, see `diff::snapshot`
+        pub max_line_length: Option<usize>,
+        /// oldest versions across all files get evicted above this size
+        pub max_store_bytes: Option<u64>,
+        /// collects orphaned data on open once it takes up `COMPACT_THRESHOLD` of the store
+        pub compact_on_open: bool,
+        /// which of identical consecutive versions `merge_consecutive_duplicates` keeps
+        pub keep_duplicate: KeepDuplicate,
+        /// what the date of a new version is
+        pub timestamp_source: TimestampSource,
+        /// large files only keep their changed lines with some context, see `excerpt`
+        pub context_only: Option<ContextOnly>,
+        /// how alike a file has to be to a gone one to take over its history
+        pub rename_detection: RenameDetection,
+    }
+
+    ///
+    /// how alike a file that turned up has to be to a tracked one that is
+    /// gone to be taken for it by `find_moved_files`. identical files always
+    /// are, as long as no other file has the same content.
+    ///
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct RenameDetection {
+        /// the share of lines both have in common, `1` only takes identical files
+        pub similarity_threshold: f64,
+        /// how many times the size of the smaller one the larger one may have
+        pub max_size_ratio: f64,
+    }
+
+    /// how long comparing two files may take, see `RenameDetection::is_alike`
+    const RENAME_DIFF_DEADLINE: Duration = Duration::from_millis(100);
+
+    impl Default for RenameDetection {
+        fn default() -> RenameDetection {
+            RenameDetection {
+                similarity_threshold: 0.9,
+                max_size_ratio: 1.2,
+            }
+        }
+    }
+
+    impl RenameDetection {
+        fn sizes_match(&self, a: usize, b: usize) -> bool {
+            let (smaller, larger) = (a.min(b) as f64, a.max(b) as f64);
+            smaller > 0.0 && larger <= smaller * self.max_size_ratio.max(1.0)
+        }
+
+        ///
+        /// only text files are diffed. the lines both have bound how alike
+        /// they can be, files too far apart for it aren't diffed at all. a
+        /// diff running out of time is taken as far as it got.
+        ///
+        fn is_alike(&self, a: &[u8], b: &[u8]) -> bool {
+            if !self.sizes_match(a.len(), b.len()) {
+                return false;
+            }
+            let (a, b) = match (std::str::from_utf8(a), std::str::from_utf8(b)) {
+                (Ok(a), Ok(b)) => (a, b),
+                _ => return false,
+            };
+            let mut unmatched: HashMap<&str, usize> = HashMap::new();
+            a.split_inclusive('\n')
+                .for_each(|line| *unmatched.entry(line).or_default() += 1);
+            let mut lines = unmatched.values().sum::<usize>();
+            let mut common = 0;
+            for line in b.split_inclusive('\n') {
+                lines += 1;
+                if let Some(count) = unmatched.get_mut(line).filter(|count| **count > 0) {
+                    *count -= 1;
+                    common += 1;
+                }
+            }
+            if ((2 * common) as f64 / lines as f64) < self.similarity_threshold {
+                return false;
+            }
+            let diff = TextDiff::configure()
+                .deadline(Instant::now() + RENAME_DIFF_DEADLINE)
+                .diff_lines(a, b);
+            diff.ratio() as f64 >= self.similarity_threshold
+        }
+    }
+
+    /// Where the date of a new version comes from.
+    #[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+    #[serde(rename_all = "lowercase")]
+    pub enum TimestampSource {
+        /// when auto-stash recorded it
+        #[default]
+        Capture,
+        /// when the file was modified, if the file system knows it
+        Mtime,
+    }
+
+    impl TimestampSource {
+        ///
+        /// the date of a new version of `path`, never before the `previous`
+        /// version so the versions stay in order, and never in the future
+        ///
+        fn timestamp_of(&self, path: &str, previous: Option<i64>) -> i64 {
+            let now = Utc::now().naive_utc().timestamp();
+            let timestamp = match self {
+                TimestampSource::Capture => now,
+                TimestampSource::Mtime => {
+                    modified_at(path).map_or(now, |modified| modified.min(now))
+                }
+            };
+            previous.map_or(timestamp, |previous| timestamp.max(previous))
+        }
+    }
+
+    /// The version of a run of identical ones that stays with its timestamp.
+    #[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+    #[serde(rename_all = "lowercase")]
+    pub enum KeepDuplicate {
+        #[default]
+        Earliest,
+        Latest,
+    }
+
+    /// Data no version refers to anymore, see `Store::orphans`.
+    struct Orphans {
+        lists: Vec<String>,
+        /// paths with the length their change list should have
+        records: Vec<(String, usize)>,
+        markers: Vec<VersionMarker>,
+        bytes: u64,
+    }
+
+    /// What a garbage collection removed from the store.
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct GcReport {
+        /// change lists of paths without a version stack
+        pub orphaned_lists: usize,
+        /// changes recorded after the latest version of their file
+        pub orphaned_records: usize,
+        /// version markers of paths without a version stack
+        pub orphaned_markers: usize,
+        pub reclaimed_bytes: u64,
+    }
+
+    /// A problem `Store::verify_integrity` found.
+    #[derive(Clone, Debug, PartialEq)]
+    pub enum IntegrityIssue {
+        /// a change list of a path without a version stack
+        OrphanedList { path: String },
+        /// changes recorded after the latest version of a file
+        OrphanedRecords { path: String, count: usize },
+        /// a version marker of a path without a version stack
+        OrphanedMarker { path: String },
+        /// the path has more than one version stack
+        DuplicateStack { path: String },
+        /// the path has more than one version marker
+        DuplicateMarker { path: String },
+        /// the stack has a different number of timestamps and entries
+        MismatchedEntries {
+            path: String,
+            timestamps: usize,
+            entries: usize,
+        },
+        /// a version ends before the version preceding it
+        UnorderedVersions { path: String },
+        /// the changes of `version` and every later version are missing
+        DanglingVersion { path: String, version: usize },
+        MissingMarker { path: String },
+        /// undo and redo would start after the latest version
+        MarkerOutOfRange {
+            path: String,
+            marker: usize,
+            versions: usize,
+        },
+    }
+
+    impl IntegrityIssue {
+        pub fn path(&self) -> &str {
+            match self {
+                IntegrityIssue::OrphanedList { path }
+                | IntegrityIssue::OrphanedRecords { path, .. }
+                | IntegrityIssue::OrphanedMarker { path }
+                | IntegrityIssue::DuplicateStack { path }
+                | IntegrityIssue::DuplicateMarker { path }
+                | IntegrityIssue::MismatchedEntries { path, .. }
+                | IntegrityIssue::UnorderedVersions { path }
+                | IntegrityIssue::DanglingVersion { path, .. }
+                | IntegrityIssue::MissingMarker { path }
+                | IntegrityIssue::MarkerOutOfRange { path, .. } => path,
+            }
+        }
+
+        /// whether `Store::repair` knows which data is the right one
+        pub fn is_repairable(&self) -> bool {
+            match self {
+                IntegrityIssue::DuplicateStack { .. }
+                | IntegrityIssue::UnorderedVersions { .. } => false,
+                IntegrityIssue::MismatchedEntries {
+                    timestamps,
+                    entries,
+                    ..
+                } => *timestamps > 0 && *entries > 0,
+                _ => true,
+            }
+        }
+    }
+
+    impl std::fmt::Display for IntegrityIssue {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            let path = self.path();
+            match self {
+                IntegrityIssue::OrphanedList { .. } => {
+                    write!(f, "{}: changes without versions", path)
+                }
+                IntegrityIssue::OrphanedRecords { count, .. } => {
+                    write!(f, "{}: {} changes after the latest version", path, count)
+                }
+                IntegrityIssue::OrphanedMarker { .. } => {
+                    write!(f, "{}: version marker without versions", path)
+                }
+                IntegrityIssue::DuplicateStack { .. } => write!(f, "{}: duplicate versions", path),
+                IntegrityIssue::DuplicateMarker { .. } => {
+                    write!(f, "{}: duplicate version marker", path)
+                }
+                IntegrityIssue::MismatchedEntries {
+                    timestamps,
+                    entries,
+                    ..
+                } => write!(f, "{}: {} timestamps for {} versions", path, timestamps, entries),
+                IntegrityIssue::UnorderedVersions { .. } => {
+                    write!(f, "{}: versions out of order", path)
+                }
+                IntegrityIssue::DanglingVersion { version, .. } => {
+                    write!(f, "{}: changes of version {} onwards are missing", path, version)
+                }
+                IntegrityIssue::MissingMarker { .. } => write!(f, "{}: no version marker", path),
+                IntegrityIssue::MarkerOutOfRange {
+                    marker, versions, ..
+                } => write!(f, "{}: marker {} past {} versions", path, marker, versions),
+            }
+        }
+    }
+
+    ///
+    /// a store written by a newer auto-stash. it isn't corrupt, replacing it
+    /// with a backup would lose what the newer version recorded.
+    ///
+    #[derive(Debug)]
+    pub struct UnsupportedSchema {
+        pub store_path: String,
+        pub schema: u64,
+        /// the version of the auto-stash that wrote it, unknown for older stores
+        pub written_by: Option<String>,
+    }
+
+    impl std::fmt::Display for UnsupportedSchema {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            match &self.written_by {
+                Some(written_by) => write!(
+                    f,
+                    "{} was written by auto-stash >= {}, upgrade to read it",
+                    self.store_path, written_by
+                )?,
+                None => write!(
+                    f,
+                    "{} was written by a newer auto-stash, upgrade to read it",
+                    self.store_path
+                )?,
+            }
+            write!(
+                f,
+                ". it has schema version {}, auto-stash {} only reads up to {}",
+                self.schema,
+                env!("CARGO_PKG_VERSION"),
+                current_schema()
+            )
+        }
+    }
+
+    impl error::Error for UnsupportedSchema {}
+
+    /// What `Store::repair` did.
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct RepairReport {
+        /// the copy of the store from before the repair
+        pub backup: Option<String>,
+        pub repaired: Vec<IntegrityIssue>,
+        /// the issues it couldn't repair safely
+        pub remaining: Vec<IntegrityIssue>,
+    }
+
+    /// What `Store::restore_latest_matching` did.
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct RestoreReport {
+        /// the files written back to their latest version
+        pub restored: Vec<String>,
+        /// the files that couldn't be rebuilt or written, with why
+        pub failed: Vec<(String, String)>,
+    }
+
+    /// The whole history as written by `Store::export_all_json`.
+    #[derive(Serialize, Deserialize)]
+    struct StoreExport {
+        schema: u64,
+        files: Vec<FileExport>,
+        /// the value of each of the `EXPORTED_SETTINGS` the store has
+        #[serde(default)]
+        settings: HashMap<String, serde_json::Value>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct FileExport {
+        path: String,
+        timestamps: Vec<i64>,
+        entries: Vec<VersionEntry>,
+        /// the version marker, undo and redo continue from it
+        marker: usize,
+        /// as stored, line edits aren't expanded
+        changes: Vec<LineDifference>,
+        /// the lines of the latest version of a context-only file
+        #[serde(default)]
+        fingerprints: Option<String>,
+    }
+
+    /// Totals of every recorded change, see `Store::diff_stat`.
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct DiffStat {
+        pub total: DiffSummary,
+        /// oldest day first
+        pub per_day: Vec<(NaiveDate, DiffSummary)>,
+        /// sorted by path
+        pub per_file: Vec<(String, DiffSummary)>,
+    }
+
+    /// combines the stats of several stores, like the per-project ones
+    impl std::ops::AddAssign for DiffStat {
+        fn add_assign(&mut self, other: DiffStat) {
+            self.total += other.total;
+            self.per_file.extend(other.per_file);
+            self.per_file.sort_by(|a, b| a.0.cmp(&b.0));
+            self.per_day = self
+                .per_day
+                .drain(..)
+                .chain(other.per_day)
+                .sorted_by_key(|(date, _)| *date)
+                .coalesce(|(date, mut summary), (next_date, next)| {
+                    if date != next_date {
+                        return Err(((date, summary), (next_date, next)));
+                    }
+                    summary += next;
+                    Ok((date, summary))
+                })
+                .collect_vec();
+        }
+    }
+
+    impl DiffStat {
+        /// the `n` files with the most changed lines
+        pub fn top_files(&self, n: usize) -> Vec<(String, DiffSummary)> {
+            self.per_file
+                .iter()
+                .sorted_by_key(|(_, summary)| std::cmp::Reverse(summary.churn()))
+                .take(n)
+                .cloned()
+                .collect_vec()
+        }
+    }
+
+    /// what a reference to an interned line would take up in place of the line
+    static INTERNED_REFERENCE_BYTES: u64 = 8;
+
+    /// How much interning the recorded lines would save, see `Store::dedup_report`.
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct DedupReport {
+        /// every line the changes keep, before and after the change
+        pub lines: usize,
+        pub unique_lines: usize,
+        pub bytes: u64,
+        pub unique_bytes: u64,
+    }
+
+    /// combines the reports of several stores, each of them would intern its own lines
+    impl std::ops::AddAssign for DedupReport {
+        fn add_assign(&mut self, other: DedupReport) {
+            self.lines += other.lines;
+            self.unique_lines += other.unique_lines;
+            self.bytes += other.bytes;
+            self.unique_bytes += other.unique_bytes;
+        }
+    }
+
+    impl DedupReport {
+        /// the lines kept once plus a reference for every line
+        pub fn saved_bytes(&self) -> u64 {
+            let interned = self.unique_bytes + self.lines as u64 * INTERNED_REFERENCE_BYTES;
+            self.bytes.saturating_sub(interned)
+        }
+    }
+
+    /// A version as the timeline of a file shows it, see `Store::timeline`.
+    #[derive(Serialize, Clone, Debug, PartialEq)]
+    pub struct TimelineEntry {
+        pub index: usize,
+        /// unix seconds
+        pub timestamp: i64,
+        pub added: usize,
+        pub removed: usize,
+        pub modified: usize,
+        pub label: Option<String>,
+    }
+
+    /// The oldest version of `path`, evicted to keep the store below its size limit.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Eviction {
+        pub path: String,
+        pub datetime: NaiveDateTime,
     }
 
     #[derive(Clone, Debug)]
     pub struct Version {
+        pub index: usize,
         pub datetime: NaiveDateTime,
         pub changes: Vec<LineDifference>,
+        pub source: VersionSource,
+        /// unix permission bits of the file at this version
+        pub mode: Option<u32>,
+        /// what a checkpoint was labeled with
+        pub label: Option<String>,
+        /// only the changed lines and their context were kept, it can't be rebuilt
+        pub context_only: bool,
+        /// every line was kept instead of the changed ones, see `StoreOptions::max_line_length`
+        pub long_lines: bool,
+        /// what integrations stamped the version with, see `Store::set_version_meta`
+        pub meta: HashMap<String, String>,
+    }
+
+    /// A version as `Store::latest_n_versions` lists it, without its changes.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct VersionSummary {
+        pub index: usize,
+        pub datetime: NaiveDateTime,
+        pub source: VersionSource,
+        pub label: Option<String>,
+        /// see `Store::diff_summary_for_version`
+        pub summary: DiffSummary,
     }
 
     #[derive(Clone, Debug)]
@@ -33,12 +703,143 @@ pub mod store {
         pub path: String,
         pub versions: Vec<Version>,
         pub hits_of_codes: Vec<HitsOfCode>,
+        /// the version the file is at, see `Store::latest_version_index`
+        pub head: usize,
+        /// every version of the file, `versions` only holds the time frame
+        pub version_count: usize,
+        /// the file couldn't be read when it last changed, see `Store::mark_inaccessible`
+        pub inaccessible: bool,
+        /// the file is too large to keep its content, see `Store::is_context_only`
+        pub context_only: bool,
+        /// one of `versions` kept every line, they were too long to diff, see `Version::long_lines`
+        pub long_lines: bool,
+        /// see `Store::first_seen`
+        pub first_seen: SystemTime,
+        /// see `Store::last_modified`
+        pub last_modified: SystemTime,
+        /// its changes aren't recorded, see `Store::is_disabled`
+        pub disabled: bool,
+        /// waits this long instead of the global debounce, see `Store::set_debounce`
+        pub debounce: Option<Duration>,
+        /// keeps its versions whatever the policy, see `Store::set_retention`
+        pub retention: Option<Retention>,
+        ///
+        /// the `Store::epoch` the view was built in. indices into the versions
+        /// of a view from an earlier epoch may point to other versions or none.
+        ///
+        pub epoch: u64,
     }
 
-    #[derive(Serialize, Deserialize, Clone)]
+    #[derive(Serialize, Deserialize, Clone, PartialEq)]
     struct VersionStack {
         path: String,
         timestamps: Vec<i64>,
+        #[serde(default)]
+        entries: Vec<VersionEntry>,
+    }
+
+    /// Where a version ends in the change list of its file and
+    /// which shape the file had at that point.
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    struct VersionEntry {
+        offset: usize,
+        line_count: usize,
+        trailing_newline: bool,
+        #[serde(default)]
+        deleted: bool,
+        #[serde(default)]
+        source: VersionSource,
+        /// unix permission bits, `None` for stores recorded without them
+        #[serde(default)]
+        mode: Option<u32>,
+        #[serde(default)]
+        label: Option<String>,
+        #[serde(default)]
+        context_only: bool,
+        #[serde(default)]
+        long_lines: bool,
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        meta: HashMap<String, String>,
+    }
+
+    /// What a new version keeps of the lines of its file.
+    #[derive(Clone, Copy, PartialEq)]
+    enum Kept {
+        /// the changed lines
+        Changes,
+        /// the changed lines with some context, see `excerpt`
+        ContextOnly,
+        /// every line, see `StoreOptions::max_line_length`
+        LongLines,
+    }
+
+    /// What made the store record a version.
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub enum VersionSource {
+        /// a change reported by the file watcher
+        #[default]
+        Watcher,
+        /// the initial walk or the catch-up after the watch root came back
+        Scan,
+        /// auto-stash itself wrote an older version back to the file
+        Restore,
+        /// marked by hand as a known-good point, see `Store::touch`
+        Checkpoint,
+        /// a patch applied to the history only, see `Store::apply_patch`
+        Patch,
+    }
+
+    impl std::fmt::Display for VersionSource {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            let source = match self {
+                VersionSource::Watcher => "auto",
+                VersionSource::Scan => "scan",
+                VersionSource::Restore => "restore",
+                VersionSource::Checkpoint => "checkpoint",
+                VersionSource::Patch => "patch",
+            };
+            f.write_str(source)
+        }
+    }
+
+    /// A path the store keeps a history for.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct TrackedPath {
+        pub path: String,
+        /// the latest version recorded the removal of the file
+        pub deleted: bool,
+    }
+
+    /// One run of auto-stash, `stop` is missing if it didn't quit cleanly.
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+    struct Session {
+        start: i64,
+        stop: Option<i64>,
+    }
+
+    #[derive(Serialize, Deserialize, Default)]
+    struct Disabled {
+        paths: Vec<String>,
+        /// globs relative to the watch path like the include patterns
+        patterns: Vec<String>,
+    }
+
+    ///
+    /// every version of the store with its timestamp, the oldest first. a
+    /// version recorded later goes in behind the ones with the same timestamp.
+    ///
+    struct Recency {
+        epoch: u64,
+        versions: Vec<(i64, String, usize)>,
+    }
+
+    impl Recency {
+        fn add(&mut self, timestamp: i64, path: &str, version: usize) {
+            let position = self
+                .versions
+                .partition_point(|(recorded, _, _)| *recorded <= timestamp);
+            self.versions.insert(position, (timestamp, path.to_string(), version));
+        }
     }
 
     #[derive(Serialize, Deserialize)]
@@ -48,12 +849,16 @@ pub mod store {
         timestamp_marker: usize,
     }
 
-    #[derive(Clone)]
+    #[derive(Clone, Debug, PartialEq)]
     pub enum TimeFrame {
         MINUTE,
         HOUR,
         DAY,
         WEEK,
+        /// any number of seconds
+        SECONDS(i64),
+        /// every version, however old
+        ALL,
     }
 
     #[derive(Clone, Debug)]
@@ -69,28 +874,75 @@ pub mod store {
                 Self::HOUR => 60 * Self::MINUTE.value(),
                 Self::DAY => 24 * Self::HOUR.value(),
                 Self::WEEK => 7 * Self::DAY.value(),
+                Self::SECONDS(seconds) => *seconds,
+                Self::ALL => i64::MAX,
+            }
+        }
+    }
+
+    impl std::str::FromStr for TimeFrame {
+        type Err = String;
+
+        /// `all` or an amount followed by `s`, `m`, `h`, `d` or `w`, like `15m`
+        fn from_str(time_frame: &str) -> Result<TimeFrame, String> {
+            let invalid = || {
+                format!(
+                    "invalid time frame {}, expected e.g. 15m, 1d or all",
+                    time_frame
+                )
+            };
+            if time_frame == "all" {
+                return Ok(TimeFrame::ALL);
             }
+            let unit_start = time_frame.char_indices().last().map_or(0, |(start, _)| start);
+            let (amount, unit) = time_frame.split_at(unit_start);
+            let amount = amount.parse::<i64>().map_err(|_| invalid())?;
+            let unit = match unit {
+                "s" => 1,
+                "m" => TimeFrame::MINUTE.value(),
+                "h" => TimeFrame::HOUR.value(),
+                "d" => TimeFrame::DAY.value(),
+                "w" => TimeFrame::WEEK.value(),
+                _ => return Err(invalid()),
+            };
+            amount
+                .checked_mul(unit)
+                .filter(|seconds| *seconds > 0)
+                .map(TimeFrame::SECONDS)
+                .ok_or_else(invalid)
         }
     }
 
     fn init(
         store_path: &str,
         watch_path: &str,
-        excluded_files: Vec<String>,
-        excluded_paths: Vec<String>,
+        options: &StoreOptions,
+        dump_policy: PickleDbDumpPolicy,
     ) -> Result<PickleDb, Box<dyn error::Error>> {
-        let mut db = PickleDb::new(
-            store_path,
-            PickleDbDumpPolicy::AutoDump,
-            SerializationMethod::Yaml,
-        );
+        let mut db = PickleDb::new(store_path, dump_policy, SerializationMethod::Yaml);
 
         create_stack(&mut db)?;
-        init_store(watch_path, &mut db, excluded_files, excluded_paths)?;
+        db.set(SCHEMA_VERSION, &current_schema())?;
+        db.set(WRITTEN_BY, &env!("CARGO_PKG_VERSION"))?;
+        db.set(WATCH_ROOTS, &vec![watch_path])?;
+        init_store(watch_path, &mut db, options)?;
 
         Ok(db)
     }
 
+    /// stores without a schema version are schema 0
+    fn stored_schema(db: &PickleDb) -> u64 {
+        db.get::<u64>(SCHEMA_VERSION).unwrap_or(0)
+    }
+
+    fn unsupported_schema(store_path: &str, db: &PickleDb) -> UnsupportedSchema {
+        UnsupportedSchema {
+            store_path: store_path.to_string(),
+            schema: stored_schema(db),
+            written_by: db.get(WRITTEN_BY),
+        }
+    }
+
     fn create_stack(db: &mut PickleDb) -> Result<(), pickledb::error::Error> {
         db.lcreate(FILE_VERSION_STACK)?;
         db.lcreate(FILE_VERSION_MARKER).map(|_| ())
@@ -120,13 +972,18 @@ pub mod store {
     fn init_store(
         watch_path: &str,
         db: &mut PickleDb,
-        excluded_files: Vec<String>,
-        excluded_paths: Vec<String>,
+        options: &StoreOptions,
     ) -> Result<(), Box<dyn error::Error>> {
+        // the first path of a hard linked file is tracked, the others become aliases
+        let mut linked = HashMap::new();
+        let mut hard_links = HashMap::new();
         WalkDir::new(watch_path)
             .into_iter()
             .filter(|entry| match entry {
-                Ok(entry) => is_not_excluded(entry, &excluded_files, &excluded_paths),
+                Ok(entry) => {
+                    is_not_excluded(entry, &options.excluded_files, &options.excluded_paths)
+                        && options.filter.is_tracked(entry.path())
+                }
                 _ => false,
             })
             .map(
@@ -134,14 +991,37 @@ pub mod store {
                     let entry = entry?;
                     let path = entry.path();
                     let path = path.to_str().unwrap_or("couldn't find path");
+                    if let Some(id) = hard_link_id(path) {
+                        if let Some(canonical) = linked.get(&id) {
+                            hard_links.insert(path.to_string(), String::clone(canonical));
+                            return Ok(());
+                        }
+                        linked.insert(id, path.to_string());
+                    }
 
-                    init_file_version_stack(path.to_string(), db);
+                    // a large file starts from the fingerprints of its lines instead
+                    let size = entry.metadata().map_or(0, |metadata| metadata.len());
+                    let context_only = is_large(options.context_only, size);
+                    if context_only {
+                        db.lcreate(path)?;
+                        let fingerprints = excerpt::fingerprints(&std::fs::read(path)?);
+                        db.set(&fingerprints_key(path), &fingerprints)?;
+                    } else {
+                        init_file_changes(path.to_string(), db)?;
+                    }
+                    let timestamp = options.timestamp_source.timestamp_of(path, None);
+                    let source = VersionSource::Scan;
+                    init_file_version_stack(path.to_string(), db, source, timestamp, context_only);
                     init_file_version_marker(path.to_string(), db);
-                    init_file_changes(path.to_string(), db)
+                    Ok(())
                 },
             )
             .find(|e| e.is_err())
-            .unwrap_or(Ok(()))
+            .unwrap_or(Ok(()))?;
+        if !hard_links.is_empty() {
+            db.set(HARD_LINKS, &hard_links)?;
+        }
+        Ok(())
     }
 
     fn is_not_excluded(
@@ -164,346 +1044,6232 @@ pub mod store {
                 .all(|p| !entry.path().parent().unwrap().ends_with(p))
     }
 
-    fn init_file_version_stack(path: String, db: &mut PickleDb) {
-        let timestamps = vec![Utc::now().naive_utc().timestamp()];
-        let version_stack = VersionStack { path, timestamps };
+    fn init_file_version_stack(
+        path: String,
+        db: &mut PickleDb,
+        source: VersionSource,
+        timestamp: i64,
+        context_only: bool,
+    ) {
+        let timestamps = vec![timestamp];
+        let entries = vec![VersionEntry {
+            context_only,
+            ..VersionEntry::new(path.as_str(), db.llen(path.as_str()), source)
+        }];
+        let version_stack = VersionStack {
+            path,
+            timestamps,
+            entries,
+        };
 
         db.ladd(FILE_VERSION_STACK, &version_stack);
     }
 
-    fn init_file_version_marker(path: String, db: &mut PickleDb) {
-        let version_marker = VersionMarker {
-            path,
-            timestamp_marker: 1,
-        };
+    impl VersionEntry {
+        fn new(path: &str, offset: usize, source: VersionSource) -> VersionEntry {
+            VersionEntry::with_shape(path, offset, source, file_shape(path))
+        }
 
-        db.ladd(FILE_VERSION_MARKER, &version_marker);
+        fn with_shape(
+            path: &str,
+            offset: usize,
+            source: VersionSource,
+            shape: Option<(usize, bool)>,
+        ) -> VersionEntry {
+            let (line_count, trailing_newline) = shape.unwrap_or((0, false));
+            VersionEntry {
+                offset,
+                line_count,
+                trailing_newline,
+                deleted: shape.is_none(),
+                source,
+                mode: file_mode(path),
+                label: None,
+                context_only: false,
+                long_lines: false,
+                meta: HashMap::new(),
+            }
+        }
     }
 
-    fn init_file_changes(path: String, db: &mut PickleDb) -> Result<(), Box<dyn error::Error>> {
-        db.lcreate(path.as_str())?;
-        let file = File::open(path.clone())?;
-        io::BufReader::new(file)
-            .lines()
-            .enumerate()
-            .map(|(index, line)| {
-                db.ladd(
-                    path.as_str(),
-                    &LineDifference::new(path.clone(), index, "".to_string(), line.unwrap()),
-                )
-                .map(|_| ())
-                .ok_or_else(|| "couldn't add line difference".into())
-            })
-            .find(|e| e.is_err())
-            .unwrap_or(Ok(()))
+    fn is_large(context_only: Option<ContextOnly>, size: u64) -> bool {
+        context_only.is_some_and(|context_only| size > context_only.above_bytes)
     }
 
-    fn load(store_path: &str) -> Result<PickleDb, Box<dyn error::Error>> {
-        PickleDb::load(
-            store_path,
-            PickleDbDumpPolicy::AutoDump,
-            SerializationMethod::Yaml,
-        )
-        .map_err(|err| err.into())
+    fn fingerprints_key(path: &str) -> String {
+        format!("{}:{}", FINGERPRINTS, path)
     }
 
-    impl Store {
-        pub fn new(
-            store_path: &str,
-            watch_path: &str,
-            excluded_files: Vec<String>,
-            excluded_paths: Vec<String>,
-        ) -> Result<Store, Box<dyn error::Error>> {
-            let mut db = load(store_path);
-            if db.is_err() {
-                db = init(store_path, watch_path, excluded_files, excluded_paths);
-            }
-            let db = db?;
+    ///
+    /// the device and inode of `path` if another path links the same file,
+    /// `None` for a file with a single link
+    ///
+    #[cfg(unix)]
+    fn hard_link_id(path: &str) -> Option<(u64, u64)> {
+        use std::os::unix::fs::MetadataExt;
+        let metadata = std::fs::metadata(path).ok()?;
+        (metadata.nlink() > 1).then_some((metadata.dev(), metadata.ino()))
+    }
 
-            Ok(Store {
-                db,
-                time_frame: TimeFrame::HOUR,
-            })
-        }
+    #[cfg(not(unix))]
+    fn hard_link_id(_path: &str) -> Option<(u64, u64)> {
+        None
+    }
 
-        pub fn store_changes(
-            &mut self,
-            path: &str,
-            changes: &[LineDifference],
-        ) -> Result<(), Box<dyn error::Error>> {
-            self.db.lextend(path, changes);
-            let version_stack = self.db.liter(FILE_VERSION_STACK).find(|version_stack| {
-                let version_stack: VersionStack = version_stack.get_item().unwrap();
-                version_stack.path.eq(path)
-            });
-            if let Some(version_stack) = version_stack {
-                let now = Utc::now().naive_utc().timestamp();
-                let mut version_stack: VersionStack = version_stack.get_item().unwrap();
-                let version_marker: VersionMarker =
-                    self.get_version_marker(path.to_string()).unwrap();
+    /// the device and inode of `path` however many links it has
+    #[cfg(unix)]
+    fn file_id(path: &str) -> Option<(u64, u64)> {
+        use std::os::unix::fs::MetadataExt;
+        let metadata = std::fs::metadata(path).ok()?;
+        Some((metadata.dev(), metadata.ino()))
+    }
 
-                self.db.lrem_value(FILE_VERSION_STACK, &version_stack)?;
-                version_stack.timestamps.push(now);
-                self.db.ladd(FILE_VERSION_STACK, &version_stack);
+    #[cfg(not(unix))]
+    fn file_id(_path: &str) -> Option<(u64, u64)> {
+        None
+    }
 
-                self.increment_version_marker_by(version_marker, 1);
-            } else {
-                init_file_version_stack(path.to_string(), &mut self.db);
-                init_file_version_marker(path.to_string(), &mut self.db);
-            }
+    /// the mtime of `path` in seconds since the epoch
+    fn modified_at(path: &str) -> Option<i64> {
+        let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+        Some(modified.duration_since(UNIX_EPOCH).ok()?.as_secs() as i64)
+    }
 
-            Ok(())
-        }
+    /// The permission bits of the file on disk, `None` if the file is gone.
+    #[cfg(unix)]
+    fn file_mode(path: &str) -> Option<u32> {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::metadata(path)
+            .ok()
+            .map(|metadata| metadata.permissions().mode() & 0o7777)
+    }
 
-        pub fn change_time_frame(&mut self, time_frame: TimeFrame) {
+    #[cfg(not(unix))]
+    fn file_mode(_path: &str) -> Option<u32> {
+        None
+    }
+
+    #[cfg(unix)]
+    fn set_file_mode(path: &str, mode: u32) -> Result<(), Box<dyn error::Error>> {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).map_err(|err| err.into())
+    }
+
+    #[cfg(not(unix))]
+    fn set_file_mode(_path: &str, _mode: u32) -> Result<(), Box<dyn error::Error>> {
+        Ok(())
+    }
+
+    /// Counts the lines of the file on disk and checks whether it ends with
+    /// a line break, `None` if the file is gone.
+    fn file_shape(path: &str) -> Option<(usize, bool)> {
+        std::fs::read(path).ok().map(|content| content_shape(&content))
+    }
+
+    fn content_shape(content: &[u8]) -> (usize, bool) {
+        (content.lines().count(), content.last() == Some(&b'\n'))
+    }
+
+    ///
+    /// follows line `position` of `content` back to `previous`. returns
+    /// whether the line changed in between and where it was before,
+    /// `None` if it didn't exist yet.
+    ///
+    fn trace_line(previous: &str, content: &str, position: usize) -> (bool, Option<usize>) {
+        let op = TextDiff::from_lines(previous, content)
+            .ops()
+            .iter()
+            .map(|op| op.as_tag_tuple())
+            .find(|(_, _, new)| new.contains(&position));
+
+        match op {
+            Some((DiffTag::Equal, old, new)) => (false, Some(old.start + position - new.start)),
+            // a replaced line continues the line at the same offset
+            Some((DiffTag::Replace, old, new)) if position - new.start < old.len() => {
+                (true, Some(old.start + position - new.start))
+            }
+            _ => (true, None),
+        }
+    }
+
+    /// a line edit only pays off once the line is long enough
+    const LINE_EDIT_OVERHEAD: usize = 48;
+
+    ///
+    /// stores modifications as the edited range of the line instead of
+    /// both full lines. `lines` is the content the changes apply to, an
+    /// edit is only taken if the line it was computed from really is the
+    /// current content, otherwise it couldn't be expanded again.
+    ///
+    fn compact_line_edits(mut lines: Vec<String>, changes: &[LineDifference]) -> Vec<LineDifference> {
+        changes
+            .iter()
+            .map(|change| {
+                if change.moved.is_some() {
+                    change.replay_onto(&mut lines);
+                    return change.clone();
+                }
+                if change.line_number >= lines.len() {
+                    lines.resize(change.line_number + 1, String::new());
+                }
+                let previous =
+                    std::mem::replace(&mut lines[change.line_number], change.changed_line.clone());
+                if change.line.is_empty() || change.edit.is_some() || previous != change.line {
+                    return change.clone();
+                }
+
+                let edit = diff::LineEdit::between(&change.line, &change.changed_line);
+                if edit.inserted.len() + LINE_EDIT_OVERHEAD
+                    >= change.line.len() + change.changed_line.len()
+                {
+                    return change.clone();
+                }
+                LineDifference {
+                    line: String::new(),
+                    changed_line: String::new(),
+                    edit: Some(edit),
+                    ..change.clone()
+                }
+            })
+            .collect_vec()
+    }
+
+    /// turns line edits back into full lines by replaying them
+    fn expand_line_edits(changes: Vec<LineDifference>) -> Vec<LineDifference> {
+        let mut lines: Vec<String> = vec![];
+        changes
+            .into_iter()
+            .map(|mut change| {
+                if let Some(edit) = change.edit.take() {
+                    let previous = lines.get(change.line_number).cloned().unwrap_or_default();
+                    change.changed_line = edit.apply(&previous);
+                    change.line = previous;
+                }
+                change.replay_onto(&mut lines);
+                change
+            })
+            .collect_vec()
+    }
+
+    /// quotes `field` if it holds a comma, a quote or a line break
+    fn csv_field(field: &str) -> String {
+        if field.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_string()
+        }
+    }
+
+    fn untracked(path: &str) -> Box<dyn error::Error> {
+        format!("{} is not tracked", path).into()
+    }
+
+    fn out_of_range(path: &str, version: usize, version_count: usize) -> Box<dyn error::Error> {
+        format!(
+            "version {} of {} is out of range, latest version is {}",
+            version,
+            path,
+            version_count.saturating_sub(1)
+        )
+        .into()
+    }
+
+    fn unix_seconds(time: SystemTime) -> i64 {
+        time.duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs() as i64)
+            .unwrap_or(0)
+    }
+
+    fn system_time(timestamp: i64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(timestamp.max(0) as u64)
+    }
+
+    fn change_timestamp(change: &LineDifference) -> i64 {
+        NaiveDateTime::parse_from_str(change.date_time.as_str(), diff::RFC3339)
+            .unwrap()
+            .timestamp()
+    }
+
+    fn init_file_version_marker(path: String, db: &mut PickleDb) {
+        let version_marker = VersionMarker {
+            path,
+            timestamp_marker: 1,
+        };
+
+        db.ladd(FILE_VERSION_MARKER, &version_marker);
+    }
+
+    fn init_file_changes(path: String, db: &mut PickleDb) -> Result<(), Box<dyn error::Error>> {
+        db.lcreate(path.as_str())?;
+        let file = File::open(path.clone())?;
+        io::BufReader::new(file)
+            .lines()
+            .enumerate()
+            .map(|(index, line)| {
+                db.ladd(
+                    path.as_str(),
+                    &LineDifference::new(path.clone(), index, "".to_string(), line.unwrap()),
+                )
+                .map(|_| ())
+                .ok_or_else(|| "couldn't add line difference".into())
+            })
+            .find(|e| e.is_err())
+            .unwrap_or(Ok(()))
+    }
+
+    fn load(
+        store_path: &str,
+        dump_policy: PickleDbDumpPolicy,
+    ) -> Result<PickleDb, Box<dyn error::Error>> {
+        PickleDb::load(store_path, dump_policy, SerializationMethod::Yaml)
+            .map_err(|err| err.into())
+    }
+
+    /// schema 1 records where each version ends instead of relying on timestamps
+    fn migrate_version_entries(store: &mut Store) -> Result<(), Box<dyn error::Error>> {
+        let version_stacks = store
+            .db
+            .liter(FILE_VERSION_STACK)
+            .map(|version_stack| version_stack.get_item::<VersionStack>().unwrap())
+            .filter(|version_stack| version_stack.entries.len() != version_stack.timestamps.len())
+            .collect_vec();
+
+        version_stacks.into_iter().try_for_each(|mut version_stack| {
+            version_stack.entries = store.get_version_entries(&version_stack);
+            store.replace_version_stack(version_stack)
+        })
+    }
+
+    type Snapshot = (HashMap<String, String>, HashMap<String, Vec<String>>);
+
+    ///
+    /// serializes the whole db the same way pickledb writes its yaml file,
+    /// used wherever the store has to be written by hand
+    ///
+    fn snapshot(db: &PickleDb) -> Result<Vec<u8>, Box<dyn error::Error>> {
+        let mut values: HashMap<String, String> = HashMap::new();
+        let mut lists: HashMap<String, Vec<String>> = HashMap::new();
+
+        for key in db.get_all() {
+            if db.lexists(key.as_str()) {
+                let items = db
+                    .liter(key.as_str())
+                    .map(|item| {
+                        let item = item
+                            .get_item::<serde_yaml::Value>()
+                            .ok_or_else(|| format!("couldn't read an item of {}", key))?;
+                        serde_yaml::to_string(&item).map_err(|err| err.into())
+                    })
+                    .collect::<Result<Vec<String>, Box<dyn error::Error>>>()?;
+                lists.insert(key, items);
+            } else {
+                let value = db
+                    .get::<serde_yaml::Value>(key.as_str())
+                    .ok_or_else(|| format!("couldn't read {}", key))?;
+                values.insert(key, serde_yaml::to_string(&value)?);
+            }
+        }
+
+        Ok(serde_yaml::to_string(&(values, lists))?.into_bytes())
+    }
+
+    ///
+    /// what `entries[version]` changed when it kept every line, see
+    /// `Version::long_lines`, its lines against the ones of the version
+    /// before. `changes` are all the changes of the file.
+    ///
+    fn snapshot_summary(
+        changes: &[LineDifference],
+        entries: &[VersionEntry],
+        version: usize,
+    ) -> DiffSummary {
+        let lines_of = |entry: &VersionEntry| {
+            let mut lines = replay(&changes[..entry.offset.min(changes.len())]);
+            lines.resize(entry.line_count, String::new());
+            lines
+        };
+        let before = match version {
+            0 => Vec::new(),
+            _ => lines_of(&entries[version - 1]),
+        };
+        diff::summary_between(&before, &lines_of(&entries[version]))
+    }
+
+    /// `path` below `new_prefix` where it is below `old_prefix`, `None` if it isn't below it
+    fn moved_below(path: &str, old_prefix: &str, new_prefix: &str) -> Option<String> {
+        let relative = std::path::Path::new(path).strip_prefix(old_prefix).ok()?;
+        Some(match relative.as_os_str().is_empty() {
+            true => new_prefix.to_string(),
+            false => std::path::Path::new(new_prefix)
+                .join(relative)
+                .to_string_lossy()
+                .into_owned(),
+        })
+    }
+
+    /// overwrites the content of a replaced store file with zeros, a purged file may be in it
+    fn scrub(mut file: File) -> io::Result<()> {
+        let length = file.metadata()?.len() as usize;
+        file.write_all(&vec![0u8; length])?;
+        file.sync_all()
+    }
+
+    /// whether one of the paths is the other or below it
+    fn is_nested(a: &str, b: &str) -> bool {
+        let (a, b) = (std::path::Path::new(a), std::path::Path::new(b));
+        a.starts_with(b) || b.starts_with(a)
+    }
+
+    /// the modification time and the size of a file, it changed if they did
+    type FileState = (Option<SystemTime>, u64);
+
+    fn file_state(path: &str) -> Result<FileState, Box<dyn error::Error>> {
+        let metadata = std::fs::metadata(path)?;
+        Ok((metadata.modified().ok(), metadata.len()))
+    }
+
+    ///
+    /// reads the store file at `store_path` into a db that never dumps. a
+    /// purge overwrites the replaced file with zeros, a read of it at that
+    /// moment is torn and gets the file that replaced it instead.
+    ///
+    fn read_store(
+        store_path: &str,
+        passphrase: Option<&str>,
+    ) -> Result<(PickleDb, FileState), Box<dyn error::Error>> {
+        let read = || -> Result<_, Box<dyn error::Error>> {
+            let state = file_state(store_path)?;
+            let content = std::fs::read(store_path)?;
+            let plain = match passphrase {
+                _ if !encryption::is_sealed(&content) => content,
+                Some(passphrase) => encryption::unseal(passphrase, &content)?.1,
+                None => {
+                    let err = format!("{} is encrypted, a passphrase is required", store_path);
+                    return Err(err.into());
+                }
+            };
+            Ok((restore(store_path, &plain)?, state))
+        };
+        read().or_else(|_| read())
+    }
+
+    /// builds a db that never dumps by itself from a snapshot
+    fn restore(store_path: &str, content: &[u8]) -> Result<PickleDb, Box<dyn error::Error>> {
+        let (values, lists): Snapshot = serde_yaml::from_slice(content)?;
+        let mut db = PickleDb::new(
+            store_path,
+            PickleDbDumpPolicy::NeverDump,
+            SerializationMethod::Yaml,
+        );
+
+        for (key, value) in values {
+            db.set(key.as_str(), &serde_yaml::from_str::<serde_yaml::Value>(&value)?)?;
+        }
+        for (key, items) in lists {
+            let items = items
+                .iter()
+                .map(|item| serde_yaml::from_str::<serde_yaml::Value>(item))
+                .collect::<Result<Vec<serde_yaml::Value>, serde_yaml::Error>>()?;
+            db.lcreate(key.as_str())?.lextend(&items);
+        }
+
+        Ok(db)
+    }
+
+    impl Store {
+        pub fn new(
+            store_path: &str,
+            watch_path: &str,
+            excluded_files: Vec<String>,
+            excluded_paths: Vec<String>,
+        ) -> Result<Store, Box<dyn error::Error>> {
+            Store::open(
+                store_path,
+                watch_path,
+                StoreOptions {
+                    excluded_files,
+                    excluded_paths,
+                    ..StoreOptions::default()
+                },
+            )
+        }
+
+        ///
+        /// loads the store at `store_path` or creates it from the files below
+        /// `watch_path`. with a passphrase the store is kept encrypted on disk,
+        /// plaintext stores opened with a passphrase get encrypted on the first flush.
+        ///
+        pub fn open(
+            store_path: &str,
+            watch_path: &str,
+            options: StoreOptions,
+        ) -> Result<Store, Box<dyn error::Error>> {
+            let lock = StoreLock::acquire(store_path)?;
+            if Store::is_encrypted(store_path) || options.passphrase.is_some() {
+                return Store::open_sealed(store_path, watch_path, options, lock);
+            }
+
+            // loaded without dumping first, the store of a newer auto-stash stays as it wrote it
+            let (db, read_only) = match load(store_path, PickleDbDumpPolicy::NeverDump) {
+                Ok(db) if stored_schema(&db) > current_schema() => {
+                    if !options.read_newer {
+                        return Err(unsupported_schema(store_path, &db).into());
+                    }
+                    (db, true)
+                }
+                Ok(_) => (load(store_path, PickleDbDumpPolicy::AutoDump)?, false),
+                Err(_) => {
+                    let db = init(store_path, watch_path, &options, PickleDbDumpPolicy::AutoDump)?;
+                    (db, false)
+                }
+            };
+
+            let mut store = Store {
+                db,
+                time_frame: TimeFrame::HOUR,
+                store_path: store_path.to_string(),
+                watch_path: watch_path.to_string(),
+                excluded_files: options.excluded_files.clone(),
+                excluded_paths: options.excluded_paths.clone(),
+                filter: options.filter.clone(),
+                sealing: None,
+                read_only,
+                in_txn: false,
+                scrub_on_commit: false,
+                line_edits: options.line_edits,
+                detect_moves: options.detect_moves,
+                diff_algorithm: options.diff_algorithm,
+                max_line_length: options.max_line_length,
+                max_store_bytes: options.max_store_bytes,
+                keep_duplicate: options.keep_duplicate,
+                timestamp_source: options.timestamp_source,
+                context_only: options.context_only,
+                rename_detection: options.rename_detection,
+                evictions: Vec::new(),
+                gc_report: None,
+                recency: RefCell::new(None),
+                disabled_globs: RefCell::new(None),
+                views: HashMap::new(),
+                inaccessible: HashSet::new(),
+                not_hard_linked: HashMap::new(),
+                epoch: 0,
+                _lock: Some(lock),
+                reader: None,
+            };
+            store.migrate()?;
+            store.record_watch_root()?;
+            if options.compact_on_open && !read_only {
+                store.compact_if_fragmented()?;
+            }
+            Ok(store)
+        }
+
+        ///
+        /// opens the store at `store_path` read-only, while another process
+        /// may be writing it, e.g. a dashboard next to a running auto-stash.
+        /// it takes no lock and needs none: the writer never changes the
+        /// store file in place, pickledb, `flush` and `Txn::commit` write a
+        /// temporary file and rename it over the store file. the file read
+        /// is a complete store as of one write. the only write to an old
+        /// file is a purge zeroing it, a read torn by it is read again, see
+        /// `read_store`. `reload` picks up what got recorded since.
+        ///
+        pub fn open_reader(
+            store_path: &str,
+            watch_path: &str,
+            options: StoreOptions,
+        ) -> Result<Store, Box<dyn error::Error>> {
+            let (db, read) = read_store(store_path, options.passphrase.as_deref())?;
+            if stored_schema(&db) > current_schema() && !options.read_newer {
+                return Err(unsupported_schema(store_path, &db).into());
+            }
+
+            let mut store = Store {
+                db,
+                time_frame: TimeFrame::HOUR,
+                store_path: store_path.to_string(),
+                watch_path: watch_path.to_string(),
+                excluded_files: options.excluded_files.clone(),
+                excluded_paths: options.excluded_paths.clone(),
+                filter: options.filter.clone(),
+                sealing: None,
+                read_only: true,
+                in_txn: false,
+                scrub_on_commit: false,
+                line_edits: options.line_edits,
+                detect_moves: options.detect_moves,
+                diff_algorithm: options.diff_algorithm,
+                max_line_length: options.max_line_length,
+                max_store_bytes: options.max_store_bytes,
+                keep_duplicate: options.keep_duplicate,
+                timestamp_source: options.timestamp_source,
+                context_only: options.context_only,
+                rename_detection: options.rename_detection,
+                evictions: Vec::new(),
+                gc_report: None,
+                recency: RefCell::new(None),
+                disabled_globs: RefCell::new(None),
+                views: HashMap::new(),
+                inaccessible: HashSet::new(),
+                not_hard_linked: HashMap::new(),
+                epoch: 0,
+                _lock: None,
+                reader: Some(Reader {
+                    passphrase: options.passphrase,
+                    read,
+                }),
+            };
+            // a store of an older schema is brought up to date in memory only
+            store.migrate()?;
+            Ok(store)
+        }
+
+        ///
+        /// reads the store file of a store opened with `open_reader` again
+        /// if it got written since. returns whether it did.
+        ///
+        pub fn reload(&mut self) -> Result<bool, Box<dyn error::Error>> {
+            let reader = self
+                .reader
+                .as_ref()
+                .ok_or("only stores opened with open_reader are reloaded")?;
+            if file_state(&self.store_path)? == reader.read {
+                return Ok(false);
+            }
+            let (db, read) = read_store(&self.store_path, reader.passphrase.as_deref())?;
+            self.db = db;
+            self.reader.as_mut().unwrap().read = read;
+            self.migrate()?;
+            self.epoch += 1;
+            self.recency.replace(None);
+            Ok(true)
+        }
+
+        fn open_sealed(
+            store_path: &str,
+            watch_path: &str,
+            options: StoreOptions,
+            lock: StoreLock,
+        ) -> Result<Store, Box<dyn error::Error>> {
+            let passphrase = options
+                .passphrase
+                .clone()
+                .ok_or_else(|| format!("{} is encrypted, a passphrase is required", store_path))?;
+
+            let (db, sealing) = match std::fs::read(store_path) {
+                Ok(content) if encryption::is_sealed(&content) => {
+                    let (sealing, plain) = encryption::unseal(passphrase.as_str(), &content)?;
+                    (restore(store_path, &plain)?, sealing)
+                }
+                Ok(_) => (
+                    load(store_path, PickleDbDumpPolicy::NeverDump)?,
+                    encryption::new_sealing(passphrase.as_str())?,
+                ),
+                Err(_) => {
+                    let sealing = encryption::new_sealing(passphrase.as_str())?;
+                    let db = init(store_path, watch_path, &options, PickleDbDumpPolicy::NeverDump)?;
+                    (db, sealing)
+                }
+            };
+            let read_only = stored_schema(&db) > current_schema();
+            if read_only && !options.read_newer {
+                return Err(unsupported_schema(store_path, &db).into());
+            }
+
+            let mut store = Store {
+                db,
+                time_frame: TimeFrame::HOUR,
+                store_path: store_path.to_string(),
+                watch_path: watch_path.to_string(),
+                excluded_files: options.excluded_files.clone(),
+                excluded_paths: options.excluded_paths.clone(),
+                filter: options.filter.clone(),
+                sealing: Some(sealing),
+                read_only,
+                in_txn: false,
+                scrub_on_commit: false,
+                line_edits: options.line_edits,
+                detect_moves: options.detect_moves,
+                diff_algorithm: options.diff_algorithm,
+                max_line_length: options.max_line_length,
+                max_store_bytes: options.max_store_bytes,
+                keep_duplicate: options.keep_duplicate,
+                timestamp_source: options.timestamp_source,
+                context_only: options.context_only,
+                rename_detection: options.rename_detection,
+                evictions: Vec::new(),
+                gc_report: None,
+                recency: RefCell::new(None),
+                disabled_globs: RefCell::new(None),
+                views: HashMap::new(),
+                inaccessible: HashSet::new(),
+                not_hard_linked: HashMap::new(),
+                epoch: 0,
+                _lock: Some(lock),
+                reader: None,
+            };
+            store.migrate()?;
+            store.record_watch_root()?;
+            if options.compact_on_open && !read_only {
+                store.compact_if_fragmented()?;
+            }
+            store.flush()?;
+            Ok(store)
+        }
+
+        ///
+        /// brings a store written by an older auto-stash to the current schema,
+        /// the original file is copied next to the store before the first migration.
+        /// stores of a newer schema are refused instead of being misread,
+        /// unless they were opened read-only.
+        ///
+        fn migrate(&mut self) -> Result<(), Box<dyn error::Error>> {
+            let schema = stored_schema(&self.db);
+            if schema > current_schema() && self.read_only {
+                return Ok(());
+            }
+            if schema > current_schema() {
+                return Err(unsupported_schema(&self.store_path, &self.db).into());
+            }
+            if schema == current_schema() {
+                return Ok(());
+            }
+
+            let backup_path = format!("{}.schema{}.bak", self.store_path, schema);
+            if std::path::Path::new(&self.store_path).exists() && !self.read_only {
+                std::fs::copy(&self.store_path, &backup_path)?;
+            }
+            MIGRATIONS[schema as usize..]
+                .iter()
+                .try_for_each(|migration| migration(self))?;
+            self.db.set(SCHEMA_VERSION, &current_schema())?;
+            self.db.set(WRITTEN_BY, &env!("CARGO_PKG_VERSION"))?;
+            self.flush()
+        }
+
+        ///
+        /// whether the store is of a newer schema and was opened with
+        /// `read_newer`. what gets recorded isn't written back to disk.
+        ///
+        pub fn is_read_only(&self) -> bool {
+            self.read_only
+        }
+
+        ///
+        /// checks the store file at `store_path` without opening it, e.g.
+        /// before a supervisor launches auto-stash on it. a plaintext store
+        /// has to match the checksum of its last flush, if pickledb didn't
+        /// dump it since, and be a pickledb document with a readable schema
+        /// version, its lists are parsed but not kept. an encrypted store has
+        /// to match its checksum, its ciphertext is authenticated once it is
+        /// opened. a store of a newer schema is an `UnsupportedSchema` error,
+        /// not corrupt.
+        ///
+        pub fn is_corrupt(store_path: &std::path::Path) -> Result<bool, Box<dyn error::Error>> {
+            let content = std::fs::read(store_path)?;
+            if encryption::is_sealed(&content) {
+                return Ok(encryption::is_damaged(&content));
+            }
+            let content = match checksum::verify(&content) {
+                Some(payload) => payload,
+                None => return Ok(true),
+            };
+            if !content.starts_with(b"---") {
+                return Ok(true);
+            }
+            // the values and the lists of the pickledb document
+            type Document = (HashMap<String, String>, serde::de::IgnoredAny);
+            let (values, _) = match serde_yaml::from_slice::<Document>(content) {
+                Ok(document) => document,
+                Err(_) => return Ok(true),
+            };
+            let schema = match values.get(SCHEMA_VERSION) {
+                Some(schema) => match serde_yaml::from_str::<u64>(schema) {
+                    Ok(schema) => schema,
+                    Err(_) => return Ok(true),
+                },
+                None => 0,
+            };
+            if schema > current_schema() {
+                return Err(UnsupportedSchema {
+                    store_path: store_path.display().to_string(),
+                    schema,
+                    written_by: values
+                        .get(WRITTEN_BY)
+                        .and_then(|written_by| serde_yaml::from_str(written_by).ok()),
+                }
+                .into());
+            }
+            Ok(false)
+        }
+
+        /// checks the header of the store file for the encryption marker
+        pub fn is_encrypted(store_path: &str) -> bool {
+            let mut header = vec![0u8; encryption::MAGIC.len()];
+            File::open(store_path)
+                .and_then(|mut file| file.read_exact(&mut header))
+                .map(|_| encryption::is_sealed(&header))
+                .unwrap_or(false)
+        }
+
+        ///
+        /// writes the store to disk with a checksum for `is_corrupt`. pickledb
+        /// dumps a plaintext store on every change already, but without it.
+        ///
+        pub fn flush(&mut self) -> Result<(), Box<dyn error::Error>> {
+            if self.read_only || self.in_txn {
+                return Ok(());
+            }
+            let content = match &self.sealing {
+                Some(sealing) => encryption::seal(sealing, &snapshot(&self.db)?)?,
+                None => checksum::prepend(&snapshot(&self.db)?),
+            };
+            let temp_path = format!("{}.temp", self.store_path);
+            std::fs::write(&temp_path, content)?;
+            std::fs::rename(temp_path, &self.store_path)?;
+            Ok(())
+        }
+
+        ///
+        /// starts a transaction, the changes made through it are written
+        /// all at once on `commit`. dropping it uncommitted, e.g. on an
+        /// error or a panic halfway, puts the store back as it was.
+        ///
+        pub fn begin(&mut self) -> Result<Txn<'_>, Box<dyn error::Error>> {
+            if self.in_txn {
+                return Err("a transaction is running already".into());
+            }
+            // the copy doesn't dump by itself, the original is what a rollback puts back
+            let working = restore(&self.store_path, &snapshot(&self.db)?)?;
+            let before = std::mem::replace(&mut self.db, working);
+            self.in_txn = true;
+            Ok(Txn {
+                db_before: Some(before),
+                epoch_before: self.epoch,
+                evictions_before: self.evictions.len(),
+                inaccessible_before: self.inaccessible.clone(),
+                store: self,
+            })
+        }
+
+        pub fn store_changes(
+            &mut self,
+            path: &str,
+            changes: &[LineDifference],
+        ) -> Result<(), Box<dyn error::Error>> {
+            self.store_changes_from(path, changes, VersionSource::Watcher)
+        }
+
+        /// records `changes` as a new version of `path` that came from `source`
+        pub fn store_changes_from(
+            &mut self,
+            path: &str,
+            changes: &[LineDifference],
+            source: VersionSource,
+        ) -> Result<(), Box<dyn error::Error>> {
+            let context_only = self.is_context_only(path);
+            if context_only {
+                let content = std::fs::read(path).unwrap_or_default();
+                self.db.set(&fingerprints_key(path), &excerpt::fingerprints(&content))?;
+            }
+            let kept = if context_only {
+                Kept::ContextOnly
+            } else {
+                Kept::Changes
+            };
+            self.record_version(path, changes, source, file_shape(path), None, kept)
+        }
+
+        ///
+        /// like `store_changes_from` for `changes` diffed from `content`, the
+        /// version takes its shape from `content` instead of the file on disk,
+        /// which may have changed in the meantime
+        ///
+        pub fn store_snapshot(
+            &mut self,
+            path: &str,
+            content: &[u8],
+            changes: &[LineDifference],
+            source: VersionSource,
+        ) -> Result<(), Box<dyn error::Error>> {
+            self.record_snapshot(path, content, changes, source, None)
+        }
+
+        ///
+        /// the changes of `content` against the latest version of `path`,
+        /// an excerpt of the changed lines for a context-only file. a file
+        /// that just became context-only diffs against what it has recorded,
+        /// a new one starts without changes.
+        ///
+        pub fn diff_snapshot(
+            &self,
+            path: &str,
+            content: &[u8],
+        ) -> Result<Vec<LineDifference>, Box<dyn error::Error>> {
+            let changes = self.file_changes(path);
+            if !self.keeps_context_only(path, content.len() as u64) {
+                return diff::find_in(path, content, &changes);
+            }
+            let known = replay(&changes);
+            let previous = match self.db.get::<String>(&fingerprints_key(path)) {
+                Some(previous) => previous,
+                None if changes.is_empty() => excerpt::fingerprints(content),
+                None => excerpt::fingerprints(known.join("\n").as_bytes()),
+            };
+            let context = self
+                .context_only
+                .map_or(excerpt::DEFAULT_CONTEXT_LINES, |context_only| context_only.context_lines);
+            Ok(excerpt::excerpt(path, content, &previous, &known, context))
+        }
+
+        ///
+        /// whether the versions of `path` only keep their changed lines with
+        /// some context. a file stays context-only once it was large enough,
+        /// its fingerprints are needed to diff it.
+        ///
+        pub fn is_context_only(&self, path: &str) -> bool {
+            let size = std::fs::metadata(path).map_or(0, |metadata| metadata.len());
+            self.keeps_context_only(path, size)
+        }
+
+        fn keeps_context_only(&self, path: &str, size: u64) -> bool {
+            is_large(self.context_only, size)
+                || self.get_version_stack(path).is_some_and(|version_stack| {
+                    let entries = self.get_version_entries(&version_stack);
+                    entries.iter().any(|entry| entry.context_only)
+                })
+        }
+
+        fn record_snapshot(
+            &mut self,
+            path: &str,
+            content: &[u8],
+            changes: &[LineDifference],
+            source: VersionSource,
+            label: Option<String>,
+        ) -> Result<(), Box<dyn error::Error>> {
+            let context_only = self.keeps_context_only(path, content.len() as u64);
+            if context_only {
+                self.db.set(&fingerprints_key(path), &excerpt::fingerprints(content))?;
+            }
+            // the watcher diffs without the store, lines too long to diff,
+            // moves and aligned lines are dealt with here
+            let kept = if context_only {
+                Kept::ContextOnly
+            } else if !changes.is_empty() && self.has_long_line(content) {
+                Kept::LongLines
+            } else {
+                Kept::Changes
+            };
+            let recomputed;
+            let changes = if kept == Kept::LongLines {
+                recomputed = diff::snapshot(path, content);
+                &recomputed
+            } else if self.detect_moves && kept == Kept::Changes && !changes.is_empty() {
+                recomputed = diff::find_in_with_moves(path, content, &self.file_changes(path))?;
+                &recomputed
+            } else if self.diff_algorithm != DiffAlgorithm::Lines
+                && kept == Kept::Changes
+                && !changes.is_empty()
+            {
+                let previous = self.file_changes(path);
+                recomputed = diff::find_in_aligned(path, content, &previous, self.diff_algorithm)?;
+                &recomputed
+            } else {
+                changes
+            };
+            let shape = Some(content_shape(content));
+            self.record_version(path, changes, source, shape, label, kept)
+        }
+
+        fn has_long_line(&self, content: &[u8]) -> bool {
+            self.max_line_length.is_some_and(|max| {
+                content
+                    .split(|byte| *byte == b'\n')
+                    .any(|line| line.len() > max)
+            })
+        }
+
+        ///
+        /// records the file as it is as a checkpoint, a known-good point
+        /// labeled with `label`. an unchanged file gets a version without
+        /// changes, changes the watcher didn't record yet become part of it.
+        ///
+        pub fn touch(&mut self, path: &str, label: Option<String>) -> Result<(), Box<dyn error::Error>> {
+            if !self.db.lexists(path) {
+                return Err(untracked(path));
+            }
+            let content = std::fs::read(path)?;
+            let changes = self.diff_snapshot(path, &content)?;
+            self.record_snapshot(path, &content, &changes, VersionSource::Checkpoint, label)
+        }
+
+        ///
+        /// applies `unified_diff` to the version `path` is at and records
+        /// the result as a new version, the file itself stays untouched.
+        /// a patch whose context doesn't match that version is rejected.
+        /// returns the index of the new version.
+        ///
+        pub fn apply_patch(
+            &mut self,
+            path: &str,
+            unified_diff: &str,
+        ) -> Result<usize, Box<dyn error::Error>> {
+            let head = self.latest_version_index(path).ok_or_else(|| untracked(path))?;
+            let content = patch::apply(&self.reconstruct(path, head)?, unified_diff)
+                .map_err(|err| format!("can't apply the patch to {}: {}", path, err))?;
+            let changes = self.diff_snapshot(path, content.as_bytes())?;
+            self.record_snapshot(path, content.as_bytes(), &changes, VersionSource::Patch, None)?;
+            self.flush()?;
+            Ok(self.count_versions(path) - 1)
+        }
+
+        fn record_version(
+            &mut self,
+            path: &str,
+            changes: &[LineDifference],
+            source: VersionSource,
+            shape: Option<(usize, bool)>,
+            label: Option<String>,
+            kept: Kept,
+        ) -> Result<(), Box<dyn error::Error>> {
+            let context_only = kept == Kept::ContextOnly;
+            // an excerpt doesn't replay into the lines the edits would refer to
+            let changes = if self.line_edits && kept == Kept::Changes && self.db.lexists(path) {
+                compact_line_edits(replay(&self.file_changes(path)), changes)
+            } else {
+                changes.to_vec()
+            };
+            self.db.lextend(path, &changes);
+            let version_stack = self.get_version_stack(path);
+            if let Some(version_stack) = version_stack {
+                let previous = version_stack.timestamps.last().copied();
+                let timestamp = self.timestamp_source.timestamp_of(path, previous);
+                let mut entries = self.get_version_entries(&version_stack);
+                let mut version_stack = version_stack;
+                let version_marker: VersionMarker =
+                    self.get_version_marker(path.to_string()).unwrap();
+
+                entries.push(VersionEntry {
+                    label,
+                    context_only,
+                    long_lines: kept == Kept::LongLines,
+                    ..VersionEntry::with_shape(path, self.db.llen(path), source, shape)
+                });
+                version_stack.timestamps.push(timestamp);
+                version_stack.entries = entries;
+                let version_count = version_stack.timestamps.len();
+                self.replace_version_stack(version_stack)?;
+                self.note_recent(timestamp, path, version_count - 1);
+
+                // writing back an older version keeps the file at that version
+                if source != VersionSource::Restore {
+                    self.set_version_marker(version_marker, version_count);
+                }
+            } else {
+                let timestamp = self.timestamp_source.timestamp_of(path, None);
+                let db = &mut self.db;
+                init_file_version_stack(path.to_string(), db, source, timestamp, context_only);
+                init_file_version_marker(path.to_string(), &mut self.db);
+                self.note_recent(timestamp, path, 0);
+            }
+
+            self.inaccessible.remove(path);
+            self.flush()?;
+            self.evict_to_limit()
+        }
+
+        ///
+        /// attaches each key with its value to `version` of `path`, e.g. a
+        /// build id or the git HEAD at capture time, and writes them at once.
+        /// an earlier value of a key is replaced.
+        ///
+        pub fn set_version_meta<V: AsRef<str>>(
+            &mut self,
+            path: &str,
+            version: usize,
+            meta: &[(&str, V)],
+        ) -> Result<(), Box<dyn error::Error>> {
+            let mut version_stack = self
+                .get_version_stack(path)
+                .ok_or_else(|| untracked(path))?;
+            let mut entries = self.get_version_entries(&version_stack);
+            let count = entries.len();
+            let entry = entries
+                .get_mut(version)
+                .ok_or_else(|| out_of_range(path, version, count))?;
+            for (key, value) in meta {
+                entry.meta.insert(key.to_string(), value.as_ref().to_string());
+            }
+            version_stack.entries = entries;
+            self.replace_version_stack(version_stack)?;
+            self.flush()
+        }
+
+        /// the metadata `set_version_meta` attached to `version` of `path`
+        pub fn version_meta(
+            &self,
+            path: &str,
+            version: usize,
+        ) -> Result<HashMap<String, String>, Box<dyn error::Error>> {
+            let version_stack = self
+                .get_version_stack(path)
+                .ok_or_else(|| untracked(path))?;
+            let entries = self.get_version_entries(&version_stack);
+            entries
+                .get(version)
+                .map(|entry| entry.meta.clone())
+                .ok_or_else(|| out_of_range(path, version, entries.len()))
+        }
+
+        ///
+        /// flags `path` in the view as unreadable, e.g. after its permissions
+        /// changed. its history stays, the next version clears the flag.
+        ///
+        pub fn mark_inaccessible(&mut self, path: &str) {
+            self.inaccessible.insert(path.to_string());
+        }
+
+        ///
+        /// whether changes of `path` are left unrecorded, because it was
+        /// disabled or matches a disabled pattern. its history stays.
+        ///
+        pub fn is_disabled(&self, path: &str) -> bool {
+            let disabled = self.disabled();
+            if disabled.paths.iter().any(|disabled| disabled == path) {
+                return true;
+            }
+            if disabled.patterns.is_empty() {
+                return false;
+            }
+            let path = std::path::Path::new(path);
+            let relative = path.strip_prefix(&self.watch_path).unwrap_or(path);
+            let mut globs = self.disabled_globs.borrow_mut();
+            if globs.as_ref().is_none_or(|(patterns, _)| *patterns != disabled.patterns) {
+                let compiled = filter::compile(&disabled.patterns).unwrap_or_default();
+                *globs = Some((disabled.patterns, compiled));
+            }
+            globs
+                .as_ref()
+                .is_some_and(|(_, compiled)| {
+                    compiled.iter().any(|pattern| filter::matches(pattern, relative))
+                })
+        }
+
+        fn disabled(&self) -> Disabled {
+            self.db.get(DISABLED).unwrap_or_default()
+        }
+
+        fn set_disabled(&mut self, disabled: &Disabled) -> Result<(), Box<dyn error::Error>> {
+            self.db.set(DISABLED, disabled).map_err(|err| err.into())
+        }
+
+        /// stops recording `path` until `enable` is called, across restarts
+        pub fn disable(&mut self, path: &str) -> Result<(), Box<dyn error::Error>> {
+            let mut disabled = self.disabled();
+            if !disabled.paths.iter().any(|disabled| disabled == path) {
+                disabled.paths.push(path.to_string());
+            }
+            self.set_disabled(&disabled)
+        }
+
+        ///
+        /// records `path` again. what changed while it was disabled becomes
+        /// one version, returns whether there was such a change. a file a
+        /// disabled pattern matches stays disabled.
+        ///
+        pub fn enable(&mut self, path: &str) -> Result<bool, Box<dyn error::Error>> {
+            let mut disabled = self.disabled();
+            disabled.paths.retain(|disabled| disabled != path);
+            self.set_disabled(&disabled)?;
+            if self.is_disabled(path) {
+                return Err(format!("{} is still disabled by a pattern", path).into());
+            }
+            self.record_missed_changes(path)
+        }
+
+        /// `disable` for every file matching `pattern`, including files created later
+        pub fn disable_matching(&mut self, pattern: &str) -> Result<(), Box<dyn error::Error>> {
+            filter::compile(&[pattern.to_string()])?;
+            let mut disabled = self.disabled();
+            if !disabled.patterns.iter().any(|disabled| disabled == pattern) {
+                disabled.patterns.push(pattern.to_string());
+            }
+            self.set_disabled(&disabled)
+        }
+
+        ///
+        /// drops a pattern of `disable_matching`, the tracked files it
+        /// disabled get their missed changes recorded like with `enable`.
+        /// returns the files that had changed.
+        ///
+        pub fn enable_matching(
+            &mut self,
+            pattern: &str,
+        ) -> Result<Vec<String>, Box<dyn error::Error>> {
+            let mut disabled = self.disabled();
+            let count = disabled.patterns.len();
+            disabled.patterns.retain(|disabled| disabled != pattern);
+            if disabled.patterns.len() == count {
+                return Err(format!("{} isn't a disabled pattern", pattern).into());
+            }
+            let mut txn = self.begin()?;
+            txn.set_disabled(&disabled)?;
+
+            let mut recorded = vec![];
+            for tracked in txn.tracked_paths() {
+                if !txn.is_disabled(&tracked.path) && txn.record_missed_changes(&tracked.path)? {
+                    recorded.push(tracked.path);
+                }
+            }
+            txn.commit()?;
+            Ok(recorded)
+        }
+
+        pub fn disabled_patterns(&self) -> Vec<String> {
+            self.disabled().patterns
+        }
+
+        /// the debounce set for `path`, `None` if it uses the global one
+        pub fn debounce_of(&self, path: &str) -> Option<Duration> {
+            let path = self.canonical_path(path);
+            self.debounces()
+                .get(&path)
+                .map(|millis| Duration::from_millis(*millis))
+        }
+
+        ///
+        /// makes the watcher wait `debounce` for saves of `path` to settle
+        /// instead of the global or adaptive debounce, across restarts.
+        /// `None` goes back to the global one.
+        ///
+        pub fn set_debounce(
+            &mut self,
+            path: &str,
+            debounce: Option<Duration>,
+        ) -> Result<(), Box<dyn error::Error>> {
+            let mut debounces = self.debounces();
+            match debounce {
+                Some(debounce) => debounces.insert(path.to_string(), debounce.as_millis() as u64),
+                None => debounces.remove(path),
+            };
+            self.db.set(DEBOUNCES, &debounces)?;
+            self.flush()
+        }
+
+        fn debounces(&self) -> HashMap<String, u64> {
+            self.db.get(DEBOUNCES).unwrap_or_default()
+        }
+
+        /// the retention set for `path`, `None` if the policy decides
+        pub fn retention_of(&self, path: &str) -> Option<Retention> {
+            self.retentions().remove(path)
+        }
+
+        /// whether `path` keeps every version, see `Retention::Forever`
+        pub fn is_pinned(&self, path: &str) -> bool {
+            self.retention_of(path) == Some(Retention::Forever)
+        }
+
+        ///
+        /// keeps the versions of `path` by `retention` instead of the
+        /// retention policy, across restarts. `Retention::Forever` also
+        /// spares it the eviction above `max_store_bytes` and forgetting it
+        /// once it is deleted or strayed from the working set.
+        ///
+        pub fn set_retention(
+            &mut self,
+            path: &str,
+            retention: Retention,
+        ) -> Result<(), Box<dyn error::Error>> {
+            if !self.is_tracked(path) {
+                return Err(untracked(path));
+            }
+            let mut retentions = self.retentions();
+            retentions.insert(path.to_string(), retention);
+            self.db.set(RETENTIONS, &retentions)?;
+            self.flush()
+        }
+
+        /// leaves the retention of `path` to the policy again
+        pub fn clear_retention(&mut self, path: &str) -> Result<(), Box<dyn error::Error>> {
+            let mut retentions = self.retentions();
+            if retentions.remove(path).is_some() {
+                self.db.set(RETENTIONS, &retentions)?;
+            }
+            self.flush()
+        }
+
+        fn retentions(&self) -> HashMap<String, Retention> {
+            self.db.get(RETENTIONS).unwrap_or_default()
+        }
+
+        /// records the file as one new version if it exists and differs from its history
+        fn record_missed_changes(&mut self, path: &str) -> Result<bool, Box<dyn error::Error>> {
+            if !self.is_tracked(path) {
+                return Ok(false);
+            }
+            let content = match std::fs::read(path) {
+                Ok(content) => content,
+                Err(_) => return Ok(false),
+            };
+            let changes = self.diff_snapshot(path, &content)?;
+            if changes.is_empty() {
+                return Ok(false);
+            }
+            self.record_snapshot(path, &content, &changes, VersionSource::Watcher, None)?;
+            Ok(true)
+        }
+
+        ///
+        /// whether the permissions of `path` differ from the ones of its
+        /// latest version, e.g. after a `chmod` that left the content as is
+        ///
+        pub fn mode_changed(&self, path: &str) -> bool {
+            let recorded = self
+                .get_version_stack(path)
+                .and_then(|version_stack| self.get_version_entries(&version_stack).pop())
+                .and_then(|entry| entry.mode);
+            match (recorded, file_mode(path)) {
+                (Some(recorded), Some(mode)) => recorded != mode,
+                _ => false,
+            }
+        }
+
+        /// the size of the store file, 0 until it is written
+        pub fn store_size(&self) -> u64 {
+            std::fs::metadata(&self.store_path)
+                .map(|metadata| metadata.len())
+                .unwrap_or(0)
+        }
+
+        ///
+        /// evicts the globally oldest versions until the store file fits
+        /// `max_store_bytes`. the next version of a file becomes its new
+        /// baseline, so a file keeps at least its latest version, only the
+        /// history of a deleted file can vanish completely. pinned files are
+        /// left alone, even if the store stays above the limit.
+        ///
+        fn evict_to_limit(&mut self) -> Result<(), Box<dyn error::Error>> {
+            let limit = match self.max_store_bytes {
+                Some(limit) => limit,
+                None => return Ok(()),
+            };
+
+            while self.store_size() > limit {
+                let retentions = self.retentions();
+                let oldest = self
+                    .db
+                    .liter(FILE_VERSION_STACK)
+                    .map(|version_stack| version_stack.get_item::<VersionStack>().unwrap())
+                    .filter(|version_stack| {
+                        retentions.get(&version_stack.path) != Some(&Retention::Forever)
+                    })
+                    .filter(|version_stack| {
+                        version_stack.timestamps.len() > 1
+                            || version_stack.entries.last().is_some_and(|entry| entry.deleted)
+                    })
+                    .min_by_key(|version_stack| version_stack.timestamps[0]);
+                let version_stack = match oldest {
+                    Some(version_stack) => version_stack,
+                    None => break,
+                };
+
+                if version_stack.timestamps.len() > 1 {
+                    self.set_baseline(&version_stack.path, 1)?;
+                } else {
+                    self.purge(&version_stack.path)?;
+                }
+                self.evictions.push(Eviction {
+                    datetime: NaiveDateTime::from_timestamp(version_stack.timestamps[0], 0),
+                    path: version_stack.path,
+                });
+            }
+            Ok(())
+        }
+
+        ///
+        /// finds data no version refers to anymore, left behind e.g. by
+        /// interrupted writes: change lists and markers of paths without
+        /// a version stack and changes after the latest version of a file.
+        ///
+        fn orphans(&self) -> Orphans {
+            let version_stacks = self
+                .db
+                .liter(FILE_VERSION_STACK)
+                .map(|version_stack| version_stack.get_item::<VersionStack>().unwrap())
+                .collect_vec();
+            let is_tracked = |path: &str| version_stacks.iter().any(|stack| stack.path == path);
+            let item_bytes = |path: &str, skip: usize| -> u64 {
+                self.db
+                    .liter(path)
+                    .skip(skip)
+                    .filter_map(|item| item.get_item::<serde_yaml::Value>())
+                    .map(|item| serde_yaml::to_string(&item).map_or(0, |item| item.len() as u64))
+                    .sum()
+            };
+
+            let lists = self
+                .db
+                .get_all()
+                .into_iter()
+                .filter(|key| self.db.lexists(key))
+                .filter(|key| key != FILE_VERSION_STACK && key != FILE_VERSION_MARKER)
+                .filter(|key| !is_tracked(key))
+                .collect_vec();
+            let records = version_stacks
+                .iter()
+                .filter_map(|version_stack| {
+                    let offset = self.get_version_entries(version_stack).last()?.offset;
+                    Some((version_stack.path.clone(), offset))
+                })
+                .filter(|(path, offset)| self.db.llen(path) > *offset)
+                .collect_vec();
+            let markers = self
+                .db
+                .liter(FILE_VERSION_MARKER)
+                .map(|version_marker| version_marker.get_item::<VersionMarker>().unwrap())
+                .filter(|version_marker| !is_tracked(&version_marker.path))
+                .collect_vec();
+
+            let bytes = lists.iter().map(|path| item_bytes(path, 0)).sum::<u64>()
+                + records
+                    .iter()
+                    .map(|(path, offset)| item_bytes(path, *offset))
+                    .sum::<u64>();
+            Orphans {
+                lists,
+                records,
+                markers,
+                bytes,
+            }
+        }
+
+        /// the share of the store file taken up by orphaned data
+        pub fn fragmentation(&self) -> f64 {
+            let size = self.store_size();
+            if size == 0 {
+                return 0.0;
+            }
+            self.orphans().bytes as f64 / size as f64
+        }
+
+        /// removes everything `orphans` finds and rewrites the store
+        pub fn gc(&mut self) -> Result<GcReport, Box<dyn error::Error>> {
+            let size = self.store_size();
+            let Orphans {
+                lists,
+                records,
+                markers,
+                ..
+            } = self.orphans();
+
+            for path in &lists {
+                self.db.lrem_list(path)?;
+            }
+            let orphaned_records = records
+                .iter()
+                .map(|(path, offset)| -> Result<usize, Box<dyn error::Error>> {
+                    let items = self
+                        .db
+                        .liter(path)
+                        .filter_map(|item| item.get_item::<serde_yaml::Value>())
+                        .collect_vec();
+                    self.db.lrem_list(path)?;
+                    self.db.lcreate(path)?.lextend(&items[..*offset]);
+                    Ok(items.len() - offset)
+                })
+                .sum::<Result<usize, Box<dyn error::Error>>>()?;
+            for version_marker in &markers {
+                self.db.lrem_value(FILE_VERSION_MARKER, version_marker)?;
+            }
+            self.flush()?;
+
+            Ok(GcReport {
+                orphaned_lists: lists.len(),
+                orphaned_records,
+                orphaned_markers: markers.len(),
+                reclaimed_bytes: size.saturating_sub(self.store_size()),
+            })
+        }
+
+        ///
+        /// checks that the version stacks, markers and change lists agree
+        /// with each other, nothing gets changed
+        ///
+        pub fn verify_integrity(&self) -> Vec<IntegrityIssue> {
+            let version_stacks = self
+                .db
+                .liter(FILE_VERSION_STACK)
+                .map(|version_stack| version_stack.get_item::<VersionStack>().unwrap())
+                .collect_vec();
+            let markers = self
+                .db
+                .liter(FILE_VERSION_MARKER)
+                .map(|version_marker| version_marker.get_item::<VersionMarker>().unwrap())
+                .collect_vec();
+
+            let mut issues = version_stacks
+                .iter()
+                .map(|version_stack| version_stack.path.clone())
+                .sorted()
+                .dedup_with_count()
+                .filter(|(count, _)| *count > 1)
+                .map(|(_, path)| IntegrityIssue::DuplicateStack { path })
+                .collect_vec();
+            issues.extend(
+                markers
+                    .iter()
+                    .map(|version_marker| version_marker.path.clone())
+                    .sorted()
+                    .dedup_with_count()
+                    .filter(|(count, _)| *count > 1)
+                    .map(|(_, path)| IntegrityIssue::DuplicateMarker { path }),
+            );
+            version_stacks
+                .iter()
+                .unique_by(|version_stack| version_stack.path.clone())
+                .for_each(|version_stack| {
+                    let path = version_stack.path.clone();
+                    let versions = version_stack.timestamps.len();
+                    let entries = version_stack.entries.len();
+                    if entries > 0 && entries != versions {
+                        issues.push(IntegrityIssue::MismatchedEntries {
+                            path: path.clone(),
+                            timestamps: versions,
+                            entries,
+                        });
+                    }
+                    let entries = self.get_version_entries(version_stack);
+                    if entries.windows(2).any(|pair| pair[1].offset < pair[0].offset) {
+                        issues.push(IntegrityIssue::UnorderedVersions { path: path.clone() });
+                    }
+                    let recorded = self.db.llen(&path);
+                    let dangling = entries.iter().position(|entry| entry.offset > recorded);
+                    if let Some(version) = dangling {
+                        issues.push(IntegrityIssue::DanglingVersion {
+                            path: path.clone(),
+                            version,
+                        });
+                    }
+                    match markers.iter().find(|version_marker| version_marker.path == path) {
+                        None => issues.push(IntegrityIssue::MissingMarker { path }),
+                        Some(version_marker) if version_marker.timestamp_marker > versions => {
+                            issues.push(IntegrityIssue::MarkerOutOfRange {
+                                path,
+                                marker: version_marker.timestamp_marker,
+                                versions,
+                            })
+                        }
+                        Some(_) => {}
+                    }
+                });
+
+            let orphans = self.orphans();
+            issues.extend(
+                orphans
+                    .lists
+                    .into_iter()
+                    .map(|path| IntegrityIssue::OrphanedList { path }),
+            );
+            issues.extend(orphans.records.into_iter().map(|(path, offset)| {
+                IntegrityIssue::OrphanedRecords {
+                    count: self.db.llen(&path) - offset,
+                    path,
+                }
+            }));
+            issues.extend(
+                orphans
+                    .markers
+                    .into_iter()
+                    .map(|version_marker| IntegrityIssue::OrphanedMarker {
+                        path: version_marker.path,
+                    }),
+            );
+            issues
+        }
+
+        ///
+        /// repairs what `verify_integrity` finds, as far as it is clear which
+        /// data is right: stacks get cut to their recorded versions, markers
+        /// get clamped and orphaned data is collected. paths with an issue
+        /// that can't be repaired are left alone. the store file is copied
+        /// next to it first. the repair is one transaction, if a step fails
+        /// or it leaves new issues behind the store stays as it was.
+        ///
+        pub fn repair(&mut self) -> Result<RepairReport, Box<dyn error::Error>> {
+            let issues = self.verify_integrity();
+            if issues.is_empty() {
+                return Ok(RepairReport::default());
+            }
+            let backup = format!("{}.fsck.bak", self.store_path);
+            self.flush()?;
+            std::fs::copy(&self.store_path, &backup)?;
+            let mut txn = self.begin()?;
+
+            let unsafe_paths = issues
+                .iter()
+                .filter(|issue| !issue.is_repairable())
+                .map(|issue| issue.path().to_string())
+                .collect::<HashSet<String>>();
+            let (repaired, remaining): (Vec<IntegrityIssue>, Vec<IntegrityIssue>) = issues
+                .into_iter()
+                .partition(|issue| !unsafe_paths.contains(issue.path()));
+
+            for issue in &repaired {
+                match issue {
+                    IntegrityIssue::MismatchedEntries {
+                        path,
+                        timestamps,
+                        entries,
+                    } => txn.truncate_versions(path, *timestamps.min(entries))?,
+                    IntegrityIssue::DanglingVersion { path, version } => {
+                        txn.truncate_versions(path, (*version).max(1))?
+                    }
+                    IntegrityIssue::DuplicateMarker { path } => {
+                        let first = txn.get_version_marker(path.clone());
+                        while let Some(version_marker) = txn.get_version_marker(path.clone()) {
+                            txn.db.lrem_value(FILE_VERSION_MARKER, &version_marker)?;
+                        }
+                        if let Some(version_marker) = first {
+                            txn.db.ladd(FILE_VERSION_MARKER, &version_marker);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            // after the cuts, a marker may be past the versions only now
+            for version_stack in txn.tracked_paths() {
+                let path = version_stack.path;
+                if unsafe_paths.contains(&path) {
+                    continue;
+                }
+                let versions = txn.count_versions(&path);
+                match txn.get_version_marker(path.clone()) {
+                    Some(version_marker) if version_marker.timestamp_marker > versions => {
+                        txn.set_version_marker(version_marker, versions)
+                    }
+                    Some(_) => {}
+                    None => {
+                        let version_marker = VersionMarker {
+                            path,
+                            timestamp_marker: versions,
+                        };
+                        txn.db.ladd(FILE_VERSION_MARKER, &version_marker);
+                    }
+                }
+            }
+            // the orphans of a path nobody knows the right stack of may be its data
+            if unsafe_paths.is_empty() {
+                txn.gc()?;
+            }
+
+            let left = txn.verify_integrity();
+            if left.iter().any(|issue| !remaining.contains(issue)) {
+                txn.rollback();
+                return Err(format!(
+                    "the repair left inconsistencies, the store was left as it was, see {}",
+                    backup
+                )
+                .into());
+            }
+            txn.commit()?;
+            Ok(RepairReport {
+                backup: Some(backup),
+                repaired,
+                remaining,
+            })
+        }
+
+        /// keeps the first `versions` versions of `path`, the dangling baseline gets clamped
+        fn truncate_versions(
+            &mut self,
+            path: &str,
+            versions: usize,
+        ) -> Result<(), Box<dyn error::Error>> {
+            let mut version_stack = self.get_version_stack(path).ok_or_else(|| untracked(path))?;
+            let recorded = self.db.llen(path);
+            let mut entries = self.get_version_entries(&version_stack);
+            entries.truncate(versions);
+            entries.iter_mut().for_each(|entry| entry.offset = entry.offset.min(recorded));
+            version_stack.timestamps.truncate(versions);
+            version_stack.entries = entries;
+            self.epoch += 1;
+            self.replace_version_stack(version_stack)
+        }
+
+        /// whether enough of the store is orphaned for `gc` to pay off
+        pub fn needs_compaction(&self) -> bool {
+            self.fragmentation() > COMPACT_THRESHOLD
+        }
+
+        fn compact_if_fragmented(&mut self) -> Result<(), Box<dyn error::Error>> {
+            if self.needs_compaction() {
+                self.gc_report = Some(self.gc()?);
+            }
+            Ok(())
+        }
+
+        /// what `compact_on_open` collected, if it had to
+        pub fn take_gc_report(&mut self) -> Option<GcReport> {
+            self.gc_report.take()
+        }
+
+        /// the versions evicted since the last call, see `StoreOptions::max_store_bytes`
+        pub fn take_evictions(&mut self) -> Vec<Eviction> {
+            std::mem::take(&mut self.evictions)
+        }
+
+        pub fn change_time_frame(&mut self, time_frame: TimeFrame) {
             self.time_frame = time_frame;
         }
 
-        pub fn undo_by(&mut self, path: String, count: usize) -> Result<(), Box<dyn error::Error>> {
-            self.undo(path, count)
+        pub fn undo_by(&mut self, path: String, count: usize) -> Result<(), Box<dyn error::Error>> {
+            self.undo(path, count)?;
+            self.flush()
+        }
+
+        pub fn redo_by(&mut self, path: String, count: usize) -> Result<(), Box<dyn error::Error>> {
+            self.redo(path, count)?;
+            self.flush()
+        }
+
+        ///
+        /// counts the maintenance runs that dropped or renumbered versions
+        /// since the store was opened, like pruning, merging or a repair.
+        /// versions are only ever added while it stays the same.
+        ///
+        pub fn epoch(&self) -> u64 {
+            self.epoch
+        }
+
+        ///
+        /// the versions of every file inside the time frame, ordered by path.
+        /// `None` for files without versions in the time frame.
+        ///
+        pub fn view(&mut self) -> Result<Vec<Option<FileVersions>>, Box<dyn error::Error>> {
+            self.view_with_progress(|_, _| {})
+        }
+
+        /// like `view`, calls `progress` with the files done and the files in total
+        pub fn view_with_progress(
+            &mut self,
+            mut progress: impl FnMut(usize, usize),
+        ) -> Result<Vec<Option<FileVersions>>, Box<dyn error::Error>> {
+            let since = self.time_frame_start();
+            let version_stacks = self.sorted_version_stacks();
+            let total = version_stacks.len();
+            Ok(version_stacks
+                .iter()
+                .enumerate()
+                .map(|(done, version_stack)| {
+                    let file_versions = self.file_versions(version_stack, since);
+                    progress(done + 1, total);
+                    file_versions
+                })
+                .collect_vec())
+        }
+
+        ///
+        /// the files with versions in the time frame sorted by path like
+        /// `view`, without cloning them. only the files that changed since
+        /// the last call are loaded again.
+        ///
+        pub fn versions_iter(&mut self) -> impl Iterator<Item = (&str, &FileVersions)> + '_ {
+            let since = self.time_frame_start();
+            let version_stacks = self.visible_version_stacks(since).collect_vec();
+            self.forget_views_besides(&version_stacks);
+            let paths = version_stacks
+                .into_iter()
+                .map(|version_stack| {
+                    let path = version_stack.path.clone();
+                    self.load_view(version_stack, since);
+                    path
+                })
+                .collect_vec();
+            let views = &self.views;
+            paths.into_iter().filter_map(move |path| {
+                views
+                    .get_key_value(&path)
+                    .map(|(path, cached)| (path.as_str(), &cached.file_versions))
+            })
+        }
+
+        /// `limit` files of `versions_iter` after skipping `offset`, the others aren't loaded
+        pub fn view_page(&mut self, offset: usize, limit: usize) -> Vec<FileVersions> {
+            let since = self.time_frame_start();
+            let version_stacks = self.visible_version_stacks(since).collect_vec();
+            self.forget_views_besides(&version_stacks);
+            version_stacks
+                .into_iter()
+                .skip(offset)
+                .take(limit)
+                .filter_map(|version_stack| {
+                    let path = version_stack.path.clone();
+                    self.load_view(version_stack, since);
+                    self.views.get(&path).map(|cached| cached.file_versions.clone())
+                })
+                .collect_vec()
+        }
+
+        /// the files `versions_iter` goes through, decided on their timestamps alone
+        pub fn visible_paths(&self) -> Vec<String> {
+            self.visible_version_stacks(self.time_frame_start())
+                .map(|version_stack| version_stack.path)
+                .collect_vec()
+        }
+
+        /// the versions of `path` in the time frame like `view_page` loads them
+        pub fn file_view(&mut self, path: &str) -> Option<FileVersions> {
+            let since = self.time_frame_start();
+            let version_stack = self.get_version_stack(path)?;
+            self.load_view(version_stack, since);
+            self.views.get(path).map(|cached| cached.file_versions.clone())
+        }
+
+        fn load_view(&mut self, version_stack: VersionStack, since: i64) {
+            let path = version_stack.path.clone();
+            let key = ViewKey {
+                change_count: self.db.llen(&path),
+                hidden: version_stack
+                    .timestamps
+                    .iter()
+                    .filter(|timestamp| **timestamp <= since)
+                    .count(),
+                head: self.latest_version_index(&path).unwrap_or(0),
+                inaccessible: self.inaccessible.contains(&path),
+                disabled: self.is_disabled(&path),
+                debounce: self.debounce_of(&path),
+                retention: self.retention_of(&path),
+                epoch: self.epoch,
+                version_stack,
+            };
+            if self.views.get(&path).is_some_and(|cached| cached.key == key) {
+                return;
+            }
+            match self.file_versions(&key.version_stack, since) {
+                Some(file_versions) => {
+                    self.views.insert(path, CachedView { key, file_versions });
+                }
+                None => {
+                    self.views.remove(&path);
+                }
+            }
+        }
+
+        /// a file that left the time frame or the store isn't kept loaded
+        fn forget_views_besides(&mut self, version_stacks: &[VersionStack]) {
+            let paths = version_stacks
+                .iter()
+                .map(|version_stack| version_stack.path.as_str())
+                .collect::<HashSet<_>>();
+            self.views.retain(|path, _| paths.contains(path.as_str()));
+        }
+
+        fn time_frame_start(&self) -> i64 {
+            Utc::now()
+                .naive_utc()
+                .timestamp()
+                .saturating_sub(self.time_frame.value())
+        }
+
+        fn sorted_version_stacks(&self) -> Vec<VersionStack> {
+            self.db
+                .liter(FILE_VERSION_STACK)
+                .map(|version_stack| version_stack.get_item::<VersionStack>().unwrap())
+                // updated stacks move to the end of the list
+                .sorted_by(|a, b| a.path.cmp(&b.path))
+                .collect_vec()
+        }
+
+        /// the stacks with a version after `since`, decided on their timestamps alone
+        fn visible_version_stacks(&self, since: i64) -> impl Iterator<Item = VersionStack> {
+            self.sorted_version_stacks()
+                .into_iter()
+                .filter(move |version_stack| {
+                    version_stack
+                        .timestamps
+                        .iter()
+                        .any(|timestamp| since < *timestamp)
+                })
+        }
+
+        fn file_versions(&self, version_stack: &VersionStack, since: i64) -> Option<FileVersions> {
+            let versions = self
+                .get_versions(version_stack)
+                .into_iter()
+                .filter(|version| since < version.datetime.timestamp())
+                .collect_vec();
+
+            if versions.is_empty() {
+                return None;
+            }
+            let hits_of_codes = hits_of_codes(versions.clone());
+            let context_only = versions[0].context_only;
+            let long_lines = versions.iter().any(|version| version.long_lines);
+
+            Some(FileVersions {
+                path: version_stack.path.clone(),
+                versions,
+                hits_of_codes,
+                head: self.latest_version_index(&version_stack.path).unwrap_or(0),
+                version_count: version_stack.timestamps.len(),
+                inaccessible: self.inaccessible.contains(&version_stack.path),
+                context_only,
+                long_lines,
+                first_seen: system_time(version_stack.timestamps.first().copied().unwrap_or(0)),
+                last_modified: system_time(version_stack.timestamps.last().copied().unwrap_or(0)),
+                disabled: self.is_disabled(&version_stack.path),
+                debounce: self.debounce_of(&version_stack.path),
+                retention: self.retention_of(&version_stack.path),
+                epoch: self.epoch,
+            })
+        }
+
+        /// when the baseline of `path` was recorded, `None` if it isn't tracked
+        pub fn first_seen(&self, path: &str) -> Option<SystemTime> {
+            let version_stack = self.get_version_stack(path)?;
+            version_stack.timestamps.first().copied().map(system_time)
+        }
+
+        /// when the latest version of `path` was recorded, `None` if it isn't tracked
+        pub fn last_modified(&self, path: &str) -> Option<SystemTime> {
+            let version_stack = self.get_version_stack(path)?;
+            version_stack.timestamps.last().copied().map(system_time)
+        }
+
+        /// how many versions of all files were recorded at `since` or later
+        pub fn versions_since(&self, since: SystemTime) -> usize {
+            let since = since
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs() as i64);
+            self.db
+                .liter(FILE_VERSION_STACK)
+                .map(|version_stack| {
+                    let version_stack: VersionStack = version_stack.get_item().unwrap();
+                    version_stack
+                        .timestamps
+                        .iter()
+                        .filter(|timestamp| **timestamp >= since)
+                        .count()
+                })
+                .sum()
+        }
+
+        /// lists every tracked path without loading any changes
+        pub fn tracked_paths(&self) -> Vec<TrackedPath> {
+            self.tracked_paths_iter().collect_vec()
+        }
+
+        pub fn tracked_paths_iter(&self) -> impl Iterator<Item = TrackedPath> + '_ {
+            self.db.liter(FILE_VERSION_STACK).map(|version_stack| {
+                let version_stack: VersionStack = version_stack.get_item().unwrap();
+                TrackedPath {
+                    deleted: version_stack
+                        .entries
+                        .last()
+                        .is_some_and(|entry| entry.deleted),
+                    path: version_stack.path,
+                }
+            })
+        }
+
+        ///
+        /// every path with at least one version between `t1` and `t2`
+        /// (inclusive) and the number of those versions, sorted by path.
+        /// a file first tracked inside the window counts its baseline too.
+        ///
+        pub fn changed_files_between(&self, t1: SystemTime, t2: SystemTime) -> Vec<(String, usize)> {
+            let (from, to) = (unix_seconds(t1), unix_seconds(t2));
+
+            self.db
+                .liter(FILE_VERSION_STACK)
+                .map(|version_stack| version_stack.get_item::<VersionStack>().unwrap())
+                .filter_map(|version_stack| {
+                    // timestamps are recorded in order
+                    let timestamps = &version_stack.timestamps;
+                    let count = timestamps.partition_point(|timestamp| *timestamp <= to)
+                        - timestamps.partition_point(|timestamp| *timestamp < from);
+                    if from > to || count == 0 {
+                        return None;
+                    }
+                    Some((version_stack.path, count))
+                })
+                .sorted()
+                .collect_vec()
+        }
+
+        ///
+        /// the indices of the versions of `path` recorded between `t1` and
+        /// `t2` (inclusive), oldest first. empty if `path` isn't tracked.
+        ///
+        pub fn versions_between_times(
+            &self,
+            path: &str,
+            t1: SystemTime,
+            t2: SystemTime,
+        ) -> Vec<usize> {
+            let (from, to) = (unix_seconds(t1), unix_seconds(t2));
+            let version_stack = match self.get_version_stack(path) {
+                Some(version_stack) if from <= to => version_stack,
+                _ => return Vec::new(),
+            };
+            let timestamps = &version_stack.timestamps;
+            let start = timestamps.partition_point(|timestamp| *timestamp < from);
+            let end = timestamps.partition_point(|timestamp| *timestamp <= to);
+            (start..end).collect_vec()
+        }
+
+        ///
+        /// the `limit` latest versions of all files as path, version and
+        /// timestamp, the latest first. the index behind it is built once
+        /// and rebuilt after maintenance dropped or renumbered versions.
+        ///
+        pub fn recent(&self, limit: usize) -> Vec<(String, usize, SystemTime)> {
+            let mut recency = self.recency.borrow_mut();
+            if recency.as_ref().is_none_or(|recency| recency.epoch != self.epoch) {
+                *recency = Some(self.index_recency());
+            }
+            recency
+                .as_ref()
+                .unwrap()
+                .versions
+                .iter()
+                .rev()
+                .take(limit)
+                .map(|(timestamp, path, version)| (path.clone(), *version, system_time(*timestamp)))
+                .collect()
+        }
+
+        fn index_recency(&self) -> Recency {
+            let versions = self
+                .db
+                .liter(FILE_VERSION_STACK)
+                .map(|version_stack| version_stack.get_item::<VersionStack>().unwrap())
+                .flat_map(|version_stack| {
+                    let path = version_stack.path;
+                    version_stack
+                        .timestamps
+                        .into_iter()
+                        .enumerate()
+                        .map(move |(version, timestamp)| (timestamp, path.clone(), version))
+                })
+                .sorted_by_key(|(timestamp, _, _)| *timestamp)
+                .collect();
+            Recency {
+                epoch: self.epoch,
+                versions,
+            }
+        }
+
+        /// adds a new version to the index of `recent` once there is one
+        fn note_recent(&mut self, timestamp: i64, path: &str, version: usize) {
+            if let Some(recency) = self.recency.get_mut() {
+                if recency.epoch == self.epoch {
+                    recency.add(timestamp, path, version);
+                }
+            }
+        }
+
+        /// remembers that a run starts watching the store now, see `MAX_SESSIONS`
+        pub fn start_session(&mut self) -> Result<(), Box<dyn error::Error>> {
+            let mut sessions = self.sessions();
+            sessions.push(Session {
+                start: Utc::now().naive_utc().timestamp(),
+                stop: None,
+            });
+            sessions.drain(..sessions.len().saturating_sub(MAX_SESSIONS));
+            self.db.set(SESSIONS, &sessions).map_err(|err| err.into())
+        }
+
+        /// remembers that the current run stops watching the store now
+        pub fn end_session(&mut self) -> Result<(), Box<dyn error::Error>> {
+            let mut sessions = self.sessions();
+            match sessions.last_mut() {
+                Some(session) => session.stop = Some(Utc::now().naive_utc().timestamp()),
+                None => return Ok(()),
+            }
+            self.db.set(SESSIONS, &sessions).map_err(|err| err.into())
+        }
+
+        fn sessions(&self) -> Vec<Session> {
+            self.db.get(SESSIONS).unwrap_or_default()
+        }
+
+        ///
+        /// when a run without a stop ended, the last version it recorded is
+        /// as close as it gets. a run that recorded nothing ended right away.
+        ///
+        fn session_end(&self, session: &Session, next_start: i64) -> i64 {
+            session.stop.unwrap_or_else(|| {
+                self.db
+                    .liter(FILE_VERSION_STACK)
+                    .map(|version_stack| version_stack.get_item::<VersionStack>().unwrap())
+                    .flat_map(|version_stack| version_stack.timestamps)
+                    .filter(|timestamp| (session.start..next_start).contains(timestamp))
+                    .max()
+                    .unwrap_or(session.start)
+            })
+        }
+
+        ///
+        /// the times nothing watched the store, from the end of a run to the
+        /// start of the next one, oldest first. stores written before runs
+        /// were remembered have no gaps.
+        ///
+        pub fn watch_gap_report(&self) -> Vec<(SystemTime, SystemTime)> {
+            self.sessions()
+                .iter()
+                .tuple_windows()
+                .filter_map(|(session, next)| {
+                    let end = self.session_end(session, next.start);
+                    (end < next.start).then(|| (system_time(end), system_time(next.start)))
+                })
+                .collect_vec()
+        }
+
+        ///
+        /// the tracked files changed on disk while nothing watched the store,
+        /// their mtime falls into a gap and their content isn't the one of
+        /// their latest version. files whose versions can't be rebuilt count
+        /// as changed, recording them again only stores what differs.
+        ///
+        pub fn changed_while_unwatched(&self) -> Vec<String> {
+            let gaps = self
+                .watch_gap_report()
+                .into_iter()
+                .map(|(end, start)| (unix_seconds(end), unix_seconds(start)))
+                .collect_vec();
+            if gaps.is_empty() {
+                return Vec::new();
+            }
+
+            self.tracked_paths_iter()
+                .filter(|tracked| !tracked.deleted)
+                .map(|tracked| tracked.path)
+                .filter(|path| {
+                    modified_at(path).is_some_and(|modified| {
+                        gaps.iter()
+                            .any(|(end, start)| (*end..=*start).contains(&modified))
+                    })
+                })
+                .filter(|path| {
+                    let latest = self.latest_version_index(path).unwrap_or(0);
+                    match (self.reconstruct(path, latest), std::fs::read(path)) {
+                        (Ok(stored), Ok(content)) => stored.as_bytes() != content.as_slice(),
+                        (Err(_), Ok(_)) => true,
+                        (_, Err(_)) => false,
+                    }
+                })
+                .collect_vec()
+        }
+
+        ///
+        /// the changes `version` of `path` made by their `ChangeKind`, read
+        /// from the changes recorded for that version alone. the baseline adds
+        /// every line and a removal removes them. untracked paths and versions
+        /// out of range made no changes.
+        ///
+        pub fn diff_summary_for_version(&self, path: &str, version: usize) -> DiffSummary {
+            let entries = match self.get_version_stack(path) {
+                Some(version_stack) => self.get_version_entries(&version_stack),
+                None => return DiffSummary::default(),
+            };
+            let recorded = self.db.llen(path);
+            let end = match entries.get(version) {
+                Some(entry) => entry.offset.min(recorded),
+                None => return DiffSummary::default(),
+            };
+            let start = match version {
+                0 => 0,
+                _ => entries[version - 1].offset.min(end),
+            };
+            if entries[version].long_lines {
+                return snapshot_summary(&self.file_changes(path), &entries, version);
+            }
+
+            let changes = self
+                .db
+                .liter(path)
+                .skip(start)
+                .take(end - start)
+                .map(|change| change.get_item::<LineDifference>().unwrap())
+                .collect_vec();
+            // a line edit only knows its line once the changes before it are replayed
+            if changes.iter().any(|change| change.edit.is_some()) {
+                return DiffSummary::of(&self.file_changes(path)[start..end]);
+            }
+            DiffSummary::of(&changes)
+        }
+
+        ///
+        /// the latest `n` versions of `path` with their `diff_summary_for_version`,
+        /// the latest first. the changes of the window are read once, neither
+        /// the content nor the other versions are loaded. untracked paths have
+        /// none.
+        ///
+        pub fn latest_n_versions(&self, path: &str, n: usize) -> Vec<VersionSummary> {
+            let version_stack = match self.get_version_stack(path) {
+                Some(version_stack) => version_stack,
+                None => return Vec::new(),
+            };
+            let entries = self.get_version_entries(&version_stack);
+            let first = entries.len().saturating_sub(n);
+            let recorded = self.db.llen(path);
+            let start = match first {
+                0 => 0,
+                _ => entries[first - 1].offset.min(recorded),
+            };
+            let end = entries.last().map_or(start, |entry| entry.offset.min(recorded).max(start));
+
+            let mut changes = self
+                .db
+                .liter(path)
+                .skip(start)
+                .take(end - start)
+                .map(|change| change.get_item::<LineDifference>().unwrap())
+                .collect_vec();
+            // a line edit only knows its line once the changes before it are
+            // replayed, a snapshot is compared with the lines before it
+            let replayed = changes.iter().any(|change| change.edit.is_some())
+                || entries[first..].iter().any(|entry| entry.long_lines);
+            let file_changes = match replayed {
+                true => self.file_changes(path),
+                false => Vec::new(),
+            };
+            if replayed {
+                changes = file_changes[start..end].to_vec();
+            }
+            let mut offset = start;
+            version_stack
+                .timestamps
+                .iter()
+                .zip(&entries)
+                .enumerate()
+                .skip(first)
+                .map(|(index, (timestamp, entry))| {
+                    let version_end = entry.offset.min(end).max(offset);
+                    let summary = match entry.long_lines {
+                        true => snapshot_summary(&file_changes, &entries, index),
+                        false => DiffSummary::of(&changes[offset - start..version_end - start]),
+                    };
+                    offset = version_end;
+                    VersionSummary {
+                        index,
+                        datetime: NaiveDateTime::from_timestamp(*timestamp, 0),
+                        source: entry.source,
+                        label: entry.label.clone(),
+                        summary,
+                    }
+                })
+                .collect_vec()
+                .into_iter()
+                .rev()
+                .collect_vec()
+        }
+
+        /// the `diff_summary_for_version` of every version of `path`, the oldest first
+        fn version_summaries(&self, path: &str) -> Vec<DiffSummary> {
+            let listed = self.latest_n_versions(path, usize::MAX);
+            listed.into_iter().rev().map(|version| version.summary).collect()
+        }
+
+        ///
+        /// sums up the changes of every version across all files by their
+        /// `ChangeKind`. baselines are left out, their lines weren't edited.
+        ///
+        pub fn diff_stat(&self) -> DiffStat {
+            let mut per_day: HashMap<NaiveDate, DiffSummary> = HashMap::new();
+            let mut stat = DiffStat::default();
+
+            self.db
+                .liter(FILE_VERSION_STACK)
+                .map(|version_stack| version_stack.get_item::<VersionStack>().unwrap())
+                .sorted_by(|a, b| a.path.cmp(&b.path))
+                .for_each(|version_stack| {
+                    let mut file = DiffSummary::default();
+                    let summaries = self.version_summaries(&version_stack.path);
+                    self.get_versions(&version_stack)
+                        .iter()
+                        .filter(|version| version.index > 0)
+                        .for_each(|version| {
+                            let summary = summaries[version.index];
+                            *per_day.entry(version.datetime.date()).or_default() += summary;
+                            file += summary;
+                        });
+                    stat.total += file;
+                    stat.per_file.push((version_stack.path, file));
+                });
+
+            stat.per_day = per_day
+                .into_iter()
+                .sorted_by_key(|(date, _)| *date)
+                .collect_vec();
+            stat
+        }
+
+        ///
+        /// counts the lines every recorded change keeps and how many of them
+        /// are unique, to see what interning them would save. nothing gets
+        /// changed, empty lines are left out.
+        ///
+        pub fn dedup_report(&self) -> DedupReport {
+            let mut unique = HashSet::new();
+            let mut report = DedupReport::default();
+            for version_stack in self.db.liter(FILE_VERSION_STACK) {
+                let path = version_stack.get_item::<VersionStack>().unwrap().path;
+                let changes = self
+                    .db
+                    .liter(&path)
+                    .filter_map(|change| change.get_item::<LineDifference>());
+                for change in changes {
+                    let inserted = change.edit.map(|edit| edit.inserted);
+                    let lines = vec![Some(change.line), Some(change.changed_line), inserted];
+                    for line in lines.into_iter().flatten().filter(|line| !line.is_empty()) {
+                        report.lines += 1;
+                        report.bytes += line.len() as u64;
+                        if !unique.contains(&line) {
+                            report.unique_lines += 1;
+                            report.unique_bytes += line.len() as u64;
+                            unique.insert(line);
+                        }
+                    }
+                }
+            }
+            report
+        }
+
+        ///
+        /// describes the history of `path` as a graphviz digraph, one node
+        /// per version labeled with its date and change counts, e.g. for
+        /// `dot -Tpng`. untracked paths give an empty graph.
+        ///
+        pub fn history_graph_dot(&self, path: &str) -> String {
+            let escaped = path.replace('\\', "\\\\").replace('"', "\\\"");
+            let versions = self
+                .get_version_stack(path)
+                .map(|version_stack| self.get_versions(&version_stack))
+                .unwrap_or_default()
+                .into_iter()
+                .rev()
+                .collect_vec();
+
+            let summaries = self.version_summaries(path);
+            let nodes = versions.iter().map(|version| {
+                format!(
+                    "    v{} [label=\"v{}\\n{}\\n{}\"];\n",
+                    version.index, version.index, version.datetime, summaries[version.index]
+                )
+            });
+            let edges = versions
+                .windows(2)
+                .map(|pair| format!("    v{} -> v{};\n", pair[0].index, pair[1].index));
+
+            format!(
+                "digraph \"{}\" {{\n    node [shape=box];\n{}}}\n",
+                escaped,
+                nodes.chain(edges).collect::<String>()
+            )
+        }
+
+        ///
+        /// the versions that changed line `line` (counting from 0 like
+        /// `LineDifference::line_number`) of the latest version, oldest
+        /// first. the line is followed back through the lines inserted and
+        /// removed above it until the version that added it.
+        ///
+        pub fn versions_touching_line(&self, path: &str, line: usize) -> Vec<usize> {
+            let mut touching = vec![];
+            let mut version = match self.count_versions(path).checked_sub(1) {
+                Some(version) => version,
+                None => return touching,
+            };
+            let mut content = match self.reconstruct(path, version) {
+                Ok(content) => content,
+                Err(_) => return touching,
+            };
+            let mut line = Some(line).filter(|line| *line < content.lines().count());
+
+            while let Some(position) = line {
+                if version == 0 {
+                    touching.push(0);
+                    break;
+                }
+                let previous = match self.reconstruct(path, version - 1) {
+                    Ok(previous) => previous,
+                    Err(_) => break,
+                };
+                let (changed, previous_line) = trace_line(&previous, &content, position);
+                if changed {
+                    touching.push(version);
+                }
+                line = previous_line;
+                version -= 1;
+                content = previous;
+            }
+            touching.reverse();
+            touching
+        }
+
+        ///
+        /// the unified diff from the version before `version` to `version`,
+        /// the baseline is diffed against an empty file
+        ///
+        pub fn unified_diff(
+            &self,
+            path: &str,
+            version: usize,
+        ) -> Result<String, Box<dyn error::Error>> {
+            self.unified_diff_between(path, version.checked_sub(1), version)
+        }
+
+        ///
+        /// the unified diff from version `from` of `path` to version `to`,
+        /// e.g. the ends of a range marked in the TUI. without a `from` the
+        /// diff starts at an empty file.
+        ///
+        pub fn unified_diff_between(
+            &self,
+            path: &str,
+            from: Option<usize>,
+            to: usize,
+        ) -> Result<String, Box<dyn error::Error>> {
+            let content = self.reconstruct(path, to)?;
+            let previous = match from {
+                Some(from) => self.reconstruct(path, from)?,
+                None => String::new(),
+            };
+
+            Ok(TextDiff::from_lines(&previous, &content)
+                .unified_diff()
+                .header(
+                    format!("{}@{}", path, from.unwrap_or(0)).as_str(),
+                    format!("{}@{}", path, to).as_str(),
+                )
+                .to_string())
+        }
+
+        ///
+        /// the changes from version `va` of `a` to version `vb` of `b`, e.g.
+        /// a file and a renamed copy that went its own way. they read like
+        /// the changes of a version of `b` that followed `a`, aligned by the
+        /// `diff_algorithm`.
+        ///
+        pub fn diff_two_files(
+            &self,
+            a: &str,
+            va: usize,
+            b: &str,
+            vb: usize,
+        ) -> Result<Vec<LineDifference>, Box<dyn error::Error>> {
+            let content_a = self.reconstruct(a, va)?;
+            let content_b = self.reconstruct(b, vb)?;
+            let lines_a = diff::snapshot(a, content_a.as_bytes());
+            diff::find_in_aligned(b, content_b.as_bytes(), &lines_a, self.diff_algorithm)
+        }
+
+        ///
+        /// rebuilds the content of `path` as it was at `version`.
+        /// version `0` is the baseline, every following index is one
+        /// recorded change set, so the latest version is the one before
+        /// the number of timestamps.
+        ///
+        pub fn reconstruct(
+            &self,
+            path: &str,
+            version: usize,
+        ) -> Result<String, Box<dyn error::Error>> {
+            let version_stack = self
+                .get_version_stack(path)
+                .ok_or_else(|| untracked(path))?;
+            let entries = self.get_version_entries(&version_stack);
+            let entry = entries
+                .get(version)
+                .ok_or_else(|| out_of_range(path, version, entries.len()))?;
+            if entries[..=version].iter().any(|entry| entry.context_only) {
+                return Err(format!(
+                    "version {} of {} can't be rebuilt, only its changed lines were kept",
+                    version, path
+                )
+                .into());
+            }
+
+            let changes = self.file_changes(path);
+            let mut lines = replay(&changes[..entry.offset.min(changes.len())]);
+            lines.resize(entry.line_count, String::new());
+
+            let mut content = lines.join("\n");
+            if entry.trailing_newline && !lines.is_empty() {
+                content.push('\n');
+            }
+            Ok(content)
+        }
+
+        ///
+        /// line `line` (counting from 0) of `path` as it was at `version`,
+        /// `None` if the version has fewer lines. the changes are read back
+        /// from the version until the last one that wrote the line, the rest
+        /// of the file isn't rebuilt.
+        ///
+        pub fn content_at_line(
+            &self,
+            path: &str,
+            version: usize,
+            mut line: usize,
+        ) -> Result<Option<String>, Box<dyn error::Error>> {
+            let version_stack = self
+                .get_version_stack(path)
+                .ok_or_else(|| untracked(path))?;
+            let entries = self.get_version_entries(&version_stack);
+            let entry = entries
+                .get(version)
+                .ok_or_else(|| out_of_range(path, version, entries.len()))?;
+            if entries[..=version].iter().any(|entry| entry.context_only) {
+                return Err(format!(
+                    "version {} of {} can't be rebuilt, only its changed lines were kept",
+                    version, path
+                )
+                .into());
+            }
+            if line >= entry.line_count {
+                return Ok(None);
+            }
+
+            // a line edit needs the line it was made on, so those are
+            // collected until a change with the full line turns up
+            let mut edits = vec![];
+            let mut content = String::new();
+            for position in (0..entry.offset.min(self.db.llen(path))).rev() {
+                let change = match self.db.lget::<LineDifference>(path, position) {
+                    Some(change) => change,
+                    None => continue,
+                };
+                // before a move the line was somewhere else
+                if let Some(moved) = &change.moved {
+                    line = moved.source_of(line);
+                    continue;
+                }
+                if change.line_number != line {
+                    continue;
+                }
+                match change.edit {
+                    Some(edit) => edits.push(edit),
+                    None => {
+                        content = change.changed_line;
+                        break;
+                    }
+                }
+            }
+            Ok(Some(
+                edits
+                    .iter()
+                    .rev()
+                    .fold(content, |content, edit| edit.apply(&content)),
+            ))
+        }
+
+        ///
+        /// writes `version` of `path` to `dest` instead of the tracked file,
+        /// missing parent directories get created. `dest` gets the permissions
+        /// of the version if they were recorded.
+        ///
+        pub fn restore_into(
+            &self,
+            path: &str,
+            version: usize,
+            dest: &std::path::Path,
+        ) -> Result<(), Box<dyn error::Error>> {
+            let content = self.reconstruct(path, version)?;
+            // reconstruct already checked that the version exists
+            let entry = self.get_version_entry(path, version).unwrap();
+            if entry.deleted {
+                return Err(format!("version {} of {} is its removal", version, path).into());
+            }
+
+            if let Some(parent) = dest.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(dest, content)?;
+            match (entry.mode, dest.to_str()) {
+                (Some(mode), Some(dest)) => set_file_mode(dest, mode),
+                _ => Ok(()),
+            }
+        }
+
+        ///
+        /// writes the version `path` is at back to the file unless the file
+        /// already has its content, returns whether it did. a file whose
+        /// latest version is its removal stays as it is.
+        ///
+        pub fn restore_latest(&self, path: &str) -> Result<bool, Box<dyn error::Error>> {
+            match self.latest_to_restore(path)? {
+                Some(latest) => {
+                    self.restore_into(path, latest, std::path::Path::new(path))?;
+                    Ok(true)
+                }
+                None => Ok(false),
+            }
+        }
+
+        /// the version `restore_latest` writes back to `path`, `None` if it leaves the file
+        fn latest_to_restore(&self, path: &str) -> Result<Option<usize>, Box<dyn error::Error>> {
+            let latest = self
+                .latest_version_index(path)
+                .ok_or_else(|| untracked(path))?;
+            if self.get_version_entry(path, latest).is_some_and(|entry| entry.deleted) {
+                return Ok(None);
+            }
+            let content = self.reconstruct(path, latest)?;
+            if std::fs::read(path).is_ok_and(|current| current == content.as_bytes()) {
+                return Ok(None);
+            }
+            Ok(Some(latest))
+        }
+
+        ///
+        /// `restore_latest` for every tracked file matching one of the
+        /// `patterns`, globs relative to `watch_path` like the include
+        /// patterns. the store stays as it is, so there is nothing for a
+        /// `Txn` to cover, but every version is rebuilt before the first
+        /// file gets written. a file that can't be rebuilt or written is
+        /// reported and the others are restored all the same.
+        ///
+        pub fn restore_latest_matching(
+            &self,
+            watch_path: &str,
+            patterns: &[String],
+        ) -> Result<RestoreReport, Box<dyn error::Error>> {
+            let patterns = filter::compile(patterns)?;
+            let mut report = RestoreReport::default();
+            let mut restores = vec![];
+            for tracked in self.tracked_paths_iter() {
+                let path = std::path::Path::new(&tracked.path);
+                let relative = path.strip_prefix(watch_path).unwrap_or(path);
+                let matching = patterns
+                    .iter()
+                    .any(|pattern| filter::matches(pattern, relative));
+                if !matching {
+                    continue;
+                }
+                match self.latest_to_restore(&tracked.path) {
+                    Ok(Some(latest)) => restores.push((tracked.path, latest)),
+                    Ok(None) => {}
+                    Err(err) => report.failed.push((tracked.path, err.to_string())),
+                }
+            }
+            for (path, latest) in restores {
+                match self.restore_into(&path, latest, std::path::Path::new(&path)) {
+                    Ok(()) => report.restored.push(path),
+                    Err(err) => report.failed.push((path, err.to_string())),
+                }
+            }
+            Ok(report)
+        }
+
+        ///
+        /// what the timeline of `path` shows of each version, the oldest
+        /// first, without their content. empty for untracked files.
+        ///
+        pub fn timeline(&self, path: &str) -> Vec<TimelineEntry> {
+            let version_stack = match self.get_version_stack(path) {
+                Some(version_stack) => version_stack,
+                None => return Vec::new(),
+            };
+            let summaries = self.version_summaries(path);
+            self.get_versions(&version_stack)
+                .into_iter()
+                .rev()
+                .map(|version| {
+                    let summary = summaries[version.index];
+                    TimelineEntry {
+                        index: version.index,
+                        timestamp: version.datetime.timestamp(),
+                        added: summary.added,
+                        removed: summary.removed,
+                        modified: summary.modified,
+                        label: version.label,
+                    }
+                })
+                .collect()
+        }
+
+        /// the `timeline` of `path` as a JSON array, for visualizers outside of auto-stash
+        pub fn export_timeline_json(&self, path: &str) -> Result<String, Box<dyn error::Error>> {
+            Ok(serde_json::to_string(&self.timeline(path))?)
+        }
+
+        ///
+        /// writes every tracked file with its versions, changes and version
+        /// marker as one JSON document, along with the settings of the files
+        /// and the watch roots. `import_all_json` reads it back.
+        ///
+        pub fn export_all_json(&self, writer: impl Write) -> Result<(), Box<dyn error::Error>> {
+            let files = self
+                .db
+                .liter(FILE_VERSION_STACK)
+                .map(|version_stack| version_stack.get_item::<VersionStack>().unwrap())
+                .map(|version_stack| self.file_export(version_stack))
+                .collect();
+            let settings = EXPORTED_SETTINGS
+                .iter()
+                .filter_map(|key| Some((key.to_string(), self.db.get(key)?)))
+                .collect();
+
+            let export = StoreExport {
+                schema: current_schema(),
+                files,
+                settings,
+            };
+            serde_json::to_writer(writer, &export)?;
+            Ok(())
+        }
+
+        /// the history of the file of `version_stack` like `export_all_json` writes it
+        fn file_export(&self, version_stack: VersionStack) -> FileExport {
+            FileExport {
+                entries: self.get_version_entries(&version_stack),
+                marker: self
+                    .get_version_marker(version_stack.path.clone())
+                    .map_or(version_stack.timestamps.len(), |marker| marker.timestamp_marker),
+                changes: self
+                    .db
+                    .liter(&version_stack.path)
+                    .filter_map(|change| change.get_item::<LineDifference>())
+                    .collect(),
+                fingerprints: self.db.get(&fingerprints_key(&version_stack.path)),
+                path: version_stack.path,
+                timestamps: version_stack.timestamps,
+            }
+        }
+
+        /// adds the history of `file`, the epoch and the flush are left to the caller
+        fn import_file(&mut self, file: FileExport) -> Result<(), Box<dyn error::Error>> {
+            self.db.lcreate(&file.path)?.lextend(&file.changes);
+            if let Some(fingerprints) = &file.fingerprints {
+                self.db.set(&fingerprints_key(&file.path), fingerprints)?;
+            }
+            self.db.ladd(
+                FILE_VERSION_MARKER,
+                &VersionMarker {
+                    path: file.path.clone(),
+                    timestamp_marker: file.marker,
+                },
+            );
+            self.db.ladd(
+                FILE_VERSION_STACK,
+                &VersionStack {
+                    path: file.path,
+                    timestamps: file.timestamps,
+                    entries: file.entries,
+                },
+            );
+            Ok(())
+        }
+
+        ///
+        /// writes one row per version of every file as CSV, with a header
+        /// row. the changes are loaded one file at a time.
+        ///
+        pub fn export_csv(&self, mut writer: impl Write) -> Result<(), Box<dyn error::Error>> {
+            writeln!(writer, "path,version,timestamp,added,removed,modified,label")?;
+            self.export_csv_rows(writer)
+        }
+
+        /// like `export_csv` without the header, to add the rows of another store
+        pub fn export_csv_rows(&self, mut writer: impl Write) -> Result<(), Box<dyn error::Error>> {
+            let version_stacks = self
+                .db
+                .liter(FILE_VERSION_STACK)
+                .map(|version_stack| version_stack.get_item::<VersionStack>().unwrap())
+                .sorted_by(|a, b| a.path.cmp(&b.path));
+            for version_stack in version_stacks {
+                let summaries = self.version_summaries(&version_stack.path);
+                for version in self.get_versions(&version_stack).iter().rev() {
+                    let summary = summaries[version.index];
+                    writeln!(
+                        writer,
+                        "{},{},{},{},{},{},{}",
+                        csv_field(&version_stack.path),
+                        version.index,
+                        version.datetime.format("%Y-%m-%d %H:%M:%S"),
+                        summary.added,
+                        summary.removed,
+                        summary.modified,
+                        csv_field(version.label.as_deref().unwrap_or(""))
+                    )?;
+                }
+            }
+            writer.flush()?;
+            Ok(())
+        }
+
+        ///
+        /// replaces the whole history and the settings of the store with the
+        /// ones written by `export_all_json`, only exports of the current
+        /// schema are read
+        ///
+        pub fn import_all_json(&mut self, reader: impl Read) -> Result<(), Box<dyn error::Error>> {
+            let export: StoreExport = serde_json::from_reader(reader)?;
+            if export.schema != current_schema() {
+                return Err(format!(
+                    "the export has schema {}, this store reads schema {}",
+                    export.schema,
+                    current_schema()
+                )
+                .into());
+            }
+
+            let mut txn = self.begin()?;
+            let lists = txn
+                .db
+                .get_all()
+                .into_iter()
+                .filter(|key| txn.db.lexists(key))
+                .collect_vec();
+            for list in &lists {
+                txn.db.lrem_list(list)?;
+            }
+            txn.db.lcreate(FILE_VERSION_STACK)?;
+            txn.db.lcreate(FILE_VERSION_MARKER)?;
+            let fingerprints = txn
+                .db
+                .get_all()
+                .into_iter()
+                .filter(|key| key.starts_with(FINGERPRINTS))
+                .collect_vec();
+            for key in &fingerprints {
+                txn.db.rem(key)?;
+            }
+            txn.not_hard_linked.clear();
+            for key in EXPORTED_SETTINGS.iter() {
+                if txn.db.exists(key) {
+                    txn.db.rem(key)?;
+                }
+                if let Some(value) = export.settings.get(*key) {
+                    txn.db.set(key, value)?;
+                }
+            }
+            for file in export.files {
+                txn.import_file(file)?;
+            }
+            txn.epoch += 1;
+            txn.commit()
+        }
+
+        /// returns all versions of a file, the latest version first
+        fn get_versions(&self, version_stack: &VersionStack) -> Vec<Version> {
+            let changes = self.file_changes(version_stack.path.as_str());
+            let entries = self.get_version_entries(version_stack);
+
+            version_stack
+                .timestamps
+                .iter()
+                .zip(entries.iter())
+                .enumerate()
+                .scan(0, |start, (index, (timestamp, entry))| {
+                    let end = entry.offset.min(changes.len()).max(*start);
+                    let version_changes = changes[*start..end]
+                        .iter()
+                        .cloned()
+                        .sorted_by(|a, b| diff::sort(b.date_time.as_str(), a.date_time.as_str()))
+                        .collect_vec();
+                    *start = end;
+
+                    Some(Version {
+                        index,
+                        datetime: NaiveDateTime::from_timestamp(*timestamp, 0),
+                        changes: version_changes,
+                        source: entry.source,
+                        mode: entry.mode,
+                        label: entry.label.clone(),
+                        context_only: entry.context_only,
+                        long_lines: entry.long_lines,
+                        meta: entry.meta.clone(),
+                    })
+                })
+                .collect_vec()
+                .into_iter()
+                .rev()
+                .collect_vec()
+        }
+
+        fn get_version_stack(&self, path: &str) -> Option<VersionStack> {
+            self.db
+                .liter(FILE_VERSION_STACK)
+                .map(|version_stack| version_stack.get_item::<VersionStack>().unwrap())
+                .find(|version_stack| version_stack.path.eq(path))
+        }
+
+        fn replace_version_stack(
+            &mut self,
+            version_stack: VersionStack,
+        ) -> Result<(), Box<dyn error::Error>> {
+            let position = self
+                .db
+                .liter(FILE_VERSION_STACK)
+                .position(|stored| {
+                    stored.get_item::<VersionStack>().unwrap().path == version_stack.path
+                })
+                .ok_or_else(|| untracked(&version_stack.path))?;
+
+            self.db.lpop::<VersionStack>(FILE_VERSION_STACK, position);
+            self.db
+                .ladd(FILE_VERSION_STACK, &version_stack)
+                .map(|_| ())
+                .ok_or_else(|| "couldn't update version stack".into())
+        }
+
+        /// Stores written before version entries were recorded only carry
+        /// timestamps, for those the entries are recovered from the dates
+        /// of the changes.
+        fn get_version_entries(&self, version_stack: &VersionStack) -> Vec<VersionEntry> {
+            if version_stack.entries.len() == version_stack.timestamps.len() {
+                return version_stack.entries.clone();
+            }
+
+            let changes = self.file_changes(version_stack.path.as_str());
+            let last = version_stack.timestamps.len() - 1;
+            version_stack
+                .timestamps
+                .iter()
+                .enumerate()
+                .map(|(index, timestamp)| {
+                    let offset = if index == last {
+                        changes.len()
+                    } else {
+                        changes
+                            .iter()
+                            .take_while(|change| change_timestamp(change) <= *timestamp)
+                            .count()
+                    };
+                    let lines = replay(&changes[..offset]);
+                    let line_count = lines
+                        .iter()
+                        .rposition(|line| !line.is_empty())
+                        .map_or(0, |position| position + 1);
+
+                    VersionEntry {
+                        offset,
+                        line_count,
+                        trailing_newline: true,
+                        deleted: false,
+                        source: VersionSource::default(),
+                        mode: None,
+                        label: None,
+                        context_only: false,
+                        long_lines: false,
+                        meta: HashMap::new(),
+                    }
+                })
+                .collect_vec()
+        }
+
+        fn get_version_entry(&self, path: &str, version: usize) -> Option<VersionEntry> {
+            let version_stack = self.get_version_stack(path)?;
+            self.get_version_entries(&version_stack).get(version).cloned()
+        }
+
+        fn get_version_marker(&self, path: String) -> Option<VersionMarker> {
+            self.db
+                .liter(FILE_VERSION_MARKER)
+                .find(|version_marker| {
+                    let version_marker: VersionMarker = version_marker.get_item().unwrap();
+                    version_marker.path.eq(&path)
+                })
+                .map(|version_marker| version_marker.get_item().unwrap())
+        }
+
+        fn undo(&mut self, path: String, count: usize) -> Result<(), Box<dyn error::Error>> {
+            if !self.can_undo(&path, count) {
+                return Err(format!("can't undo {} versions of {}", count, path).into());
+            }
+            let head = self.latest_version_index(&path).unwrap();
+            self.write_version(&path, head - count)
+        }
+
+        fn redo(&mut self, path: String, count: usize) -> Result<(), Box<dyn error::Error>> {
+            if !self.can_redo(&path, count) {
+                return Err(format!("can't redo {} versions of {}", count, path).into());
+            }
+            let head = self.latest_version_index(&path).unwrap();
+            self.write_version(&path, head + count)
+        }
+
+        fn decrement_version_marker_by(&mut self, version_marker: VersionMarker, count: usize) {
+            let timestamp_marker = version_marker.timestamp_marker - count;
+            self.set_version_marker(version_marker, timestamp_marker);
+        }
+
+        fn set_version_marker(&mut self, mut version_marker: VersionMarker, timestamp_marker: usize) {
+            self.db
+                .lrem_value(FILE_VERSION_MARKER, &version_marker)
+                .unwrap();
+            version_marker.timestamp_marker = timestamp_marker;
+            self.db.ladd(FILE_VERSION_MARKER, &version_marker);
+        }
+
+        ///
+        /// the version `path` is at, the latest one unless undo moved it back.
+        /// `None` if the path isn't tracked.
+        ///
+        pub fn latest_version_index(&self, path: &str) -> Option<usize> {
+            let version_count = self.count_versions(path);
+            if version_count == 0 {
+                return None;
+            }
+            let version_marker = self.get_version_marker(path.to_string())?;
+            Some(
+                version_marker
+                    .timestamp_marker
+                    .saturating_sub(1)
+                    .min(version_count - 1),
+            )
+        }
+
+        /// the number of versions of `path` including its baseline
+        pub fn version_count(&self, path: &str) -> Result<usize, Box<dyn error::Error>> {
+            if !self.is_tracked(path) {
+                return Err(untracked(path));
+            }
+            Ok(self.count_versions(path))
+        }
+
+        /// like `version_count`, an untracked path has no versions
+        fn count_versions(&self, path: &str) -> usize {
+            self.get_version_stack(path)
+                .map_or(0, |version_stack| version_stack.timestamps.len())
+        }
+
+        pub fn can_undo(&self, path: &str, count: usize) -> bool {
+            self.latest_version_index(path)
+                .is_some_and(|head| count > 0 && count <= head)
+        }
+
+        pub fn can_redo(&self, path: &str, count: usize) -> bool {
+            self.latest_version_index(path)
+                .is_some_and(|head| count > 0 && head + count < self.count_versions(path))
+        }
+
+        /// writes `version` back to `path` and moves the marker to it
+        fn write_version(&mut self, path: &str, version: usize) -> Result<(), Box<dyn error::Error>> {
+            let content = self.reconstruct(path, version)?;
+            let deleted = self
+                .get_version_entry(path, version)
+                .is_some_and(|entry| entry.deleted);
+            let version_marker = self.get_version_marker(path.to_string()).unwrap();
+            self.set_version_marker(version_marker, version + 1);
+
+            if deleted {
+                if std::path::Path::new(path).exists() {
+                    std::fs::remove_file(path)?;
+                }
+                return Ok(());
+            }
+            std::fs::write(path, content)?;
+            self.apply_marked_mode(path)
+        }
+
+        /// reapplies the permissions of the version the marker of `path` points at
+        fn apply_marked_mode(&self, path: &str) -> Result<(), Box<dyn error::Error>> {
+            let (version_stack, version_marker) =
+                match (self.get_version_stack(path), self.get_version_marker(path.to_string())) {
+                    (Some(version_stack), Some(version_marker)) => (version_stack, version_marker),
+                    _ => return Ok(()),
+                };
+            let index = version_marker.timestamp_marker.saturating_sub(1);
+            match self.get_version_entries(&version_stack).get(index) {
+                Some(VersionEntry {
+                    mode: Some(mode), ..
+                }) if file_mode(path) != Some(*mode) => set_file_mode(path, *mode),
+                _ => Ok(()),
+            }
+        }
+
+        ///
+        /// drops the versions `policy` doesn't keep by moving the baseline
+        /// of each file forward, a retention set on the file wins over it.
+        /// the version a file is at and its latest version always stay.
+        /// returns the number of dropped versions.
+        ///
+        pub fn prune(&mut self, policy: &RetentionPolicy) -> Result<usize, Box<dyn error::Error>> {
+            let now = Utc::now().naive_utc().timestamp();
+            let version_stacks = self
+                .db
+                .liter(FILE_VERSION_STACK)
+                .map(|version_stack| version_stack.get_item::<VersionStack>().unwrap())
+                .collect_vec();
+            let retentions = self.retentions();
+
+            let mut txn = self.begin()?;
+            let pruned = version_stacks.iter().try_fold(0, |pruned, version_stack| {
+                let path = version_stack.path.as_str();
+                let head = txn.latest_version_index(path).unwrap_or(0);
+                let retention = retentions
+                    .get(path)
+                    .copied()
+                    .unwrap_or_else(|| policy.for_path(std::path::Path::new(path)));
+                let first_kept = retention
+                    .first_kept(&version_stack.timestamps, now)
+                    .min(head);
+                txn.set_baseline(path, first_kept)?;
+                Ok::<usize, Box<dyn error::Error>>(pruned + first_kept)
+            })?;
+            if pruned > 0 {
+                txn.commit()?;
+            }
+            Ok(pruned)
+        }
+
+        ///
+        /// forgets the files removed more than `older_than` ago with their
+        /// whole history, like `purge`. a file counts as removed while its
+        /// latest version is a removal, pinned files are never forgotten.
+        /// returns the number of forgotten files.
+        ///
+        pub fn prune_deleted(
+            &mut self,
+            older_than: Duration,
+        ) -> Result<usize, Box<dyn error::Error>> {
+            let older_than = older_than.as_secs().min(i64::MAX as u64) as i64;
+            let cutoff = Utc::now().naive_utc().timestamp().saturating_sub(older_than);
+            let deleted = self
+                .db
+                .liter(FILE_VERSION_STACK)
+                .map(|version_stack| version_stack.get_item::<VersionStack>().unwrap())
+                .filter(|version_stack| {
+                    let removed = self
+                        .get_version_entries(version_stack)
+                        .last()
+                        .is_some_and(|entry| entry.deleted);
+                    removed && version_stack.timestamps.last().is_some_and(|at| *at <= cutoff)
+                })
+                .map(|version_stack| version_stack.path)
+                .filter(|path| !self.is_pinned(path))
+                .collect_vec();
+
+            deleted.iter().try_for_each(|path| self.purge(path))?;
+            Ok(deleted.len())
+        }
+
+        ///
+        /// the tracked files below one of `roots` that aren't on disk anymore,
+        /// whether a removal got recorded or not. a root that is missing
+        /// itself, e.g. an unmounted drive, keeps its files, so do pinned files.
+        ///
+        pub fn working_set_strays(&self, roots: &[&std::path::Path]) -> Vec<String> {
+            let roots = roots.iter().filter(|root| root.is_dir()).collect_vec();
+            self.tracked_paths_iter()
+                .map(|tracked| tracked.path)
+                .filter(|path| {
+                    let path = std::path::Path::new(path);
+                    roots.iter().any(|root| path.starts_with(root))
+                        && std::fs::symlink_metadata(path).is_err()
+                })
+                .filter(|path| !self.is_pinned(path))
+                .sorted()
+                .collect_vec()
+        }
+
+        ///
+        /// forgets the `working_set_strays` of `roots` with their whole
+        /// history, like `purge`. returns the number of forgotten files.
+        ///
+        pub fn trim_to_working_set(
+            &mut self,
+            roots: &[&std::path::Path],
+        ) -> Result<usize, Box<dyn error::Error>> {
+            let strays = self.working_set_strays(roots);
+            if strays.is_empty() {
+                return Ok(0);
+            }
+            let mut txn = self.begin()?;
+            strays.iter().try_for_each(|path| txn.purge(path))?;
+            txn.commit()?;
+            Ok(strays.len())
+        }
+
+        ///
+        /// collapses consecutive versions with the same content, mode and
+        /// removal state into one, of `path` or of every file with `None`.
+        /// the changes between them cancel out, so they stay and only the
+        /// versions go. returns the number of dropped versions.
+        ///
+        pub fn merge_consecutive_duplicates(
+            &mut self,
+            path: Option<&str>,
+        ) -> Result<usize, Box<dyn error::Error>> {
+            let paths = match path {
+                Some(path) if !self.is_tracked(path) => return Err(untracked(path)),
+                Some(path) => vec![path.to_string()],
+                None => self.tracked_paths_iter().map(|tracked| tracked.path).collect_vec(),
+            };
+            let mut txn = self.begin()?;
+            let merged = paths.iter().try_fold(0, |merged, path| {
+                txn.merge_duplicates_of(path).map(|count| merged + count)
+            })?;
+            if merged > 0 {
+                txn.commit()?;
+            }
+            Ok(merged)
+        }
+
+        fn merge_duplicates_of(&mut self, path: &str) -> Result<usize, Box<dyn error::Error>> {
+            let mut version_stack = match self.get_version_stack(path) {
+                Some(version_stack) => version_stack,
+                None => return Ok(0),
+            };
+            let entries = self.get_version_entries(&version_stack);
+            let contents = (0..entries.len())
+                .map(|version| self.reconstruct(path, version).ok())
+                .collect_vec();
+            // context-only versions can't be compared
+            let is_duplicate = |a: usize, b: usize| {
+                contents[a].is_some()
+                    && contents[a] == contents[b]
+                    && entries[a].deleted == entries[b].deleted
+                    && entries[a].mode == entries[b].mode
+            };
+
+            // the versions that stay and the new index of every version
+            let mut kept: Vec<usize> = Vec::new();
+            let mut merged_into = Vec::with_capacity(entries.len());
+            (0..entries.len()).for_each(|version| {
+                match kept.last_mut() {
+                    Some(last) if is_duplicate(*last, version) => {
+                        if self.keep_duplicate == KeepDuplicate::Latest {
+                            *last = version;
+                        }
+                    }
+                    _ => kept.push(version),
+                }
+                merged_into.push(kept.len() - 1);
+            });
+            let merged = entries.len() - kept.len();
+            if merged == 0 {
+                return Ok(0);
+            }
+
+            // a label of a dropped version moves to the one that stays
+            let labels = (0..kept.len())
+                .map(|index| {
+                    (0..entries.len())
+                        .filter(|version| merged_into[*version] == index)
+                        .find_map(|version| entries[version].label.clone())
+                })
+                .collect_vec();
+            version_stack.timestamps = kept
+                .iter()
+                .map(|version| version_stack.timestamps[*version])
+                .collect_vec();
+            version_stack.entries = kept
+                .iter()
+                .zip(labels)
+                .map(|(version, label)| VersionEntry {
+                    label: entries[*version].label.clone().or(label),
+                    ..entries[*version].clone()
+                })
+                .collect_vec();
+            self.replace_version_stack(version_stack)?;
+
+            if let Some(version_marker) = self.get_version_marker(path.to_string()) {
+                let at = version_marker.timestamp_marker.saturating_sub(1);
+                let marker = merged_into.get(at).map_or(kept.len(), |index| index + 1);
+                self.set_version_marker(version_marker, marker);
+            }
+            self.epoch += 1;
+            Ok(merged)
+        }
+
+        ///
+        /// turns `version` into the new baseline of `path` and drops every
+        /// older version. the baseline keeps the dates of the lines it was
+        /// built from, so the following versions still diff against it and
+        /// keep their content, only their indices move down by `version`.
+        ///
+        pub fn set_baseline(&mut self, path: &str, version: usize) -> Result<(), Box<dyn error::Error>> {
+            let mut version_stack = self
+                .get_version_stack(path)
+                .ok_or_else(|| untracked(path))?;
+            let entries = self.get_version_entries(&version_stack);
+            let entry = entries
+                .get(version)
+                .cloned()
+                .ok_or_else(|| out_of_range(path, version, entries.len()))?;
+            if version == 0 {
+                return Ok(());
+            }
+
+            let changes = self.file_changes(path);
+            let split = entry.offset.min(changes.len());
+            let baseline = if changes[..split].iter().any(|change| change.moved.is_some()) {
+                // after a move the lines aren't at the numbers they were written to
+                let date_time = changes[split - 1].date_time.clone();
+                replay(&changes[..split])
+                    .into_iter()
+                    .take(entry.line_count)
+                    .enumerate()
+                    .map(|(index, line)| LineDifference {
+                        date_time: date_time.clone(),
+                        ..LineDifference::new(path.to_string(), index, String::new(), line)
+                    })
+                    .collect_vec()
+            } else {
+                changes[..split]
+                    .iter()
+                    .rev()
+                    .unique_by(|change| change.line_number)
+                    .filter(|change| change.line_number < entry.line_count)
+                    .sorted_by_key(|change| change.line_number)
+                    .map(|change| LineDifference {
+                        line: String::new(),
+                        ..change.clone()
+                    })
+                    .collect_vec()
+            };
+            let shift = split - baseline.len();
+
+            let following = if self.line_edits {
+                compact_line_edits(replay(&baseline), &changes[split..])
+            } else {
+                changes[split..].to_vec()
+            };
+            self.db.lrem_list(path)?;
+            self.db
+                .lcreate(path)?
+                .lextend(&baseline)
+                .lextend(&following);
+
+            version_stack.timestamps.drain(..version);
+            version_stack.entries = entries
+                .into_iter()
+                .skip(version)
+                .map(|entry| VersionEntry {
+                    offset: entry.offset.min(changes.len()) - shift,
+                    ..entry
+                })
+                .collect_vec();
+            self.replace_version_stack(version_stack)?;
+
+            if let Some(version_marker) = self.get_version_marker(path.to_string()) {
+                let count = version.min(version_marker.timestamp_marker.saturating_sub(1));
+                self.decrement_version_marker_by(version_marker, count);
+            }
+
+            self.epoch += 1;
+            self.flush()
+        }
+
+        ///
+        /// forgets `path` completely, its version stack, marker and every
+        /// recorded change. a plaintext store file gets overwritten with zeros
+        /// before it is replaced, so the freed bytes don't keep the content.
+        /// editing the file again starts a new history.
+        ///
+        pub fn purge(&mut self, path: &str) -> Result<(), Box<dyn error::Error>> {
+            let position = self
+                .db
+                .liter(FILE_VERSION_STACK)
+                .position(|stored| stored.get_item::<VersionStack>().unwrap().path == path)
+                .ok_or_else(|| untracked(path))?;
+            // pickledb replaces the file on every dump, the handle keeps the old one
+            let previous = match self.sealing {
+                None if !self.read_only && !self.in_txn => {
+                    File::options().write(true).open(&self.store_path).ok()
+                }
+                _ => None,
+            };
+            // a transaction writes the store file once, on its commit
+            self.scrub_on_commit |= self.in_txn && self.sealing.is_none();
+
+            // the changes go first, so no later dump holds the content anymore
+            if self.db.lexists(path) {
+                self.db.lrem_list(path)?;
+            }
+            self.db.lpop::<VersionStack>(FILE_VERSION_STACK, position);
+            if let Some(version_marker) = self.get_version_marker(path.to_string()) {
+                self.db.lrem_value(FILE_VERSION_MARKER, &version_marker)?;
+            }
+            self.db.rem(&fingerprints_key(path))?;
+            let mut hard_links = self.hard_links();
+            hard_links.retain(|alias, canonical| alias != path && canonical != path);
+            self.set_hard_links(&hard_links)?;
+            let mut debounces = self.debounces();
+            if debounces.remove(path).is_some() {
+                self.db.set(DEBOUNCES, &debounces)?;
+            }
+            let mut retentions = self.retentions();
+            if retentions.remove(path).is_some() {
+                self.db.set(RETENTIONS, &retentions)?;
+            }
+            self.epoch += 1;
+            self.flush()?;
+
+            if let Some(previous) = previous {
+                scrub(previous)?;
+            }
+            Ok(())
+        }
+
+        ///
+        /// moves the history of every file below `old_prefix` to the same
+        /// path below `new_prefix`, e.g. after the project was moved. nothing
+        /// gets rewritten if one of the new paths has a history already.
+        /// returns the number of rewritten files.
+        ///
+        pub fn rewrite_paths(
+            &mut self,
+            old_prefix: &str,
+            new_prefix: &str,
+        ) -> Result<usize, Box<dyn error::Error>> {
+            let moves = self
+                .tracked_paths_iter()
+                .filter_map(|tracked| {
+                    let moved = moved_below(&tracked.path, old_prefix, new_prefix)?;
+                    Some((tracked.path, moved))
+                })
+                .filter(|(path, moved)| path != moved)
+                .collect_vec();
+            if let Some((_, moved)) = moves.iter().find(|(_, moved)| self.db.lexists(moved)) {
+                return Err(format!("{} has a history already", moved).into());
+            }
+
+            let mut txn = self.begin()?;
+            for (path, moved) in &moves {
+                txn.move_file(path, moved)?;
+            }
+            if !moves.is_empty() {
+                txn.epoch += 1;
+            }
+            // aliases and disabled paths have no history to move them along
+            let moved = |path: String| moved_below(&path, old_prefix, new_prefix).unwrap_or(path);
+            let below = |path: &String| moved_below(path, old_prefix, new_prefix).is_some();
+            let hard_links = txn.hard_links();
+            if hard_links.keys().any(below) {
+                let hard_links = hard_links
+                    .into_iter()
+                    .map(|(alias, canonical)| (moved(alias), canonical))
+                    .collect();
+                txn.set_hard_links(&hard_links)?;
+            }
+            let mut disabled = txn.disabled();
+            if disabled.paths.iter().any(below) {
+                disabled.paths = disabled.paths.into_iter().map(moved).unique().collect();
+                txn.set_disabled(&disabled)?;
+            }
+            // the history below a recorded watch root lives below its new path now
+            let roots = txn
+                .watch_roots()
+                .into_iter()
+                .map(|root| moved_below(&root, old_prefix, new_prefix).unwrap_or(root))
+                .unique()
+                .collect_vec();
+            if !txn.read_only && txn.db.exists(WATCH_ROOTS) {
+                txn.db.set(WATCH_ROOTS, &roots)?;
+            }
+            txn.commit()?;
+            Ok(moves.len())
+        }
+
+        /// the watch paths the history was recorded below, the first one the store was created with
+        pub fn watch_roots(&self) -> Vec<String> {
+            self.db.get(WATCH_ROOTS).unwrap_or_default()
+        }
+
+        ///
+        /// the recorded watch roots if the store is watched apart from all of
+        /// them, e.g. after `watch_path` changed. the history recorded below
+        /// them doesn't match the watched files anymore. a watch path above
+        /// or below a recorded root, one that got widened or narrowed, keeps
+        /// the paths of the history as they are.
+        ///
+        pub fn previous_watch_roots(&self) -> Option<Vec<String>> {
+            let roots = self.watch_roots();
+            match roots.iter().any(|root| is_nested(root, &self.watch_path)) {
+                true => None,
+                false => Some(roots),
+            }
+        }
+
+        ///
+        /// moves the history below every previous watch root to the current
+        /// one with `rewrite_paths`. a root whose files have a history below
+        /// the current one already is left where it is and reported. returns
+        /// the number of moved files.
+        ///
+        pub fn rekey_watch_root(&mut self) -> Result<usize, Box<dyn error::Error>> {
+            let watch_path = self.watch_path.clone();
+            let mut moved = 0;
+            for root in self.previous_watch_roots().unwrap_or_default() {
+                match self.rewrite_paths(&root, &watch_path) {
+                    Ok(count) => moved += count,
+                    Err(err) => eprintln!("Left the history below {} where it is: {}", root, err),
+                }
+            }
+            Ok(moved)
+        }
+
+        /// records the current watch root next to the previous ones, their history stays as it is
+        pub fn keep_watch_roots(&mut self) -> Result<(), Box<dyn error::Error>> {
+            let mut roots = self.watch_roots();
+            if !roots.contains(&self.watch_path) {
+                roots.push(self.watch_path.clone());
+                self.db.set(WATCH_ROOTS, &roots)?;
+            }
+            self.flush()
+        }
+
+        ///
+        /// stores from before the watch roots were recorded take the current
+        /// one for theirs. a root above or below a recorded one is added to
+        /// them, the history is still where it was.
+        ///
+        fn record_watch_root(&mut self) -> Result<(), Box<dyn error::Error>> {
+            if self.read_only {
+                return Ok(());
+            }
+            let mut roots = match self.db.get::<Vec<String>>(WATCH_ROOTS) {
+                Some(roots) => roots,
+                None => return Ok(self.db.set(WATCH_ROOTS, &vec![self.watch_path.clone()])?),
+            };
+            let nested = roots.iter().any(|root| is_nested(root, &self.watch_path));
+            if nested && !roots.contains(&self.watch_path) {
+                roots.push(self.watch_path.clone());
+                self.db.set(WATCH_ROOTS, &roots)?;
+            }
+            Ok(())
+        }
+
+        ///
+        /// moves the history of `from` to `to`, like `rewrite_paths` does
+        /// for a single file. `to` must not have a history yet.
+        ///
+        pub fn move_history(&mut self, from: &str, to: &str) -> Result<(), Box<dyn error::Error>> {
+            if !self.is_tracked(from) {
+                return Err(untracked(from));
+            }
+            if self.is_tracked(to) {
+                return Err(format!("{} has a history already", to).into());
+            }
+            self.move_file(from, to)?;
+            self.epoch += 1;
+            self.flush()
+        }
+
+        ///
+        /// the tracked files below the watch path that are gone without their
+        /// removal being recorded, each with the untracked file that has its
+        /// latest content, e.g. after a reorganization while nothing watched.
+        /// a file without an identical one is taken for the one it is alike,
+        /// see `RenameDetection`. a content more than one file is gone with or
+        /// turned up with is left alone, so are empty files.
+        ///
+        pub fn find_moved_files(&self) -> Vec<(String, String)> {
+            let missing = self.gone_files();
+            if missing.is_empty() {
+                return Vec::new();
+            }
+
+            let detection = self.rename_detection;
+            let appeared = self.untracked_files(&missing.keys().map(Vec::len).collect());
+            let mut moves = Vec::new();
+            let mut unmatched = Vec::new();
+            for (content, from) in &missing {
+                match (from.as_slice(), appeared.get(content).map(Vec::as_slice)) {
+                    ([from], Some([to])) => moves.push((from.clone(), to.clone())),
+                    ([from], None) => unmatched.push((content, from)),
+                    _ => {}
+                }
+            }
+            // a file identical to a gone one is its, a content that turned up twice no one's
+            let candidates = appeared
+                .iter()
+                .filter(|(content, _)| !missing.contains_key(*content))
+                .filter_map(|(content, to)| match to.as_slice() {
+                    [to] => Some((content, to)),
+                    _ => None,
+                })
+                .collect_vec();
+            let alike = unmatched
+                .into_iter()
+                .filter_map(|(content, from)| {
+                    let mut alike = candidates
+                        .iter()
+                        .filter(|(candidate, _)| detection.is_alike(content, candidate));
+                    match (alike.next(), alike.next()) {
+                        (Some((_, to)), None) => Some((from.clone(), to.to_string())),
+                        _ => None,
+                    }
+                })
+                .collect_vec();
+            // a file two gone ones are alike could be either of them
+            let mut taken: HashMap<&str, usize> = HashMap::new();
+            alike.iter().for_each(|(_, to)| *taken.entry(to.as_str()).or_default() += 1);
+            let alike = alike
+                .iter()
+                .filter(|(_, to)| taken[to.as_str()] == 1)
+                .cloned()
+                .collect_vec();
+            moves.into_iter().chain(alike).sorted().collect_vec()
+        }
+
+        ///
+        /// the tracked files below the watch path that are gone without their
+        /// removal being recorded, by their latest content. empty files and
+        /// the ones whose versions can't be rebuilt are left out.
+        ///
+        pub fn gone_files(&self) -> HashMap<Vec<u8>, Vec<String>> {
+            let mut gone: HashMap<Vec<u8>, Vec<String>> = HashMap::new();
+            self.tracked_paths_iter()
+                .filter(|tracked| !tracked.deleted)
+                .filter(|tracked| !std::path::Path::new(&tracked.path).exists())
+                .filter(|tracked| !self.is_disabled(&tracked.path))
+                .for_each(|tracked| {
+                    let latest = self.latest_version_index(&tracked.path).unwrap_or(0);
+                    if let Ok(content) = self.reconstruct(&tracked.path, latest) {
+                        if !content.is_empty() {
+                            gone.entry(content.into_bytes()).or_default().push(tracked.path);
+                        }
+                    }
+                });
+            gone
+        }
+
+        ///
+        /// the files below the watch path the store doesn't track, by their
+        /// content. only those are read whose size `RenameDetection` finds
+        /// close to one of `sizes`, the sizes of gone files.
+        ///
+        pub fn untracked_files(&self, sizes: &HashSet<usize>) -> HashMap<Vec<u8>, Vec<String>> {
+            let detection = self.rename_detection;
+            let aliases = self.hard_links();
+            let mut untracked: HashMap<Vec<u8>, Vec<String>> = HashMap::new();
+            WalkDir::new(&self.watch_path)
+                .into_iter()
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| {
+                    is_not_excluded(entry, &self.excluded_files, &self.excluded_paths)
+                        && self.filter.is_tracked(entry.path())
+                })
+                .filter(|entry| {
+                    entry.metadata().is_ok_and(|metadata| {
+                        let size = metadata.len() as usize;
+                        sizes.iter().any(|missing| detection.sizes_match(*missing, size))
+                    })
+                })
+                .filter_map(|entry| Some(entry.path().to_str()?.to_string()))
+                .filter(|path| !self.is_tracked(path) && !aliases.contains_key(path))
+                .for_each(|path| {
+                    if let Ok(content) = std::fs::read(&path) {
+                        untracked.entry(content).or_default().push(path);
+                    }
+                });
+            untracked
+        }
+
+        ///
+        /// moves the history of `from` into `other` under `to`, e.g. to the
+        /// store of another root the file was moved below. `other` keeps
+        /// it before it is purged here, a failure leaves it here. the
+        /// settings of the file stay behind. `to` must not have a history
+        /// in `other` yet.
+        ///
+        pub fn move_history_to(
+            &mut self,
+            from: &str,
+            other: &mut Store,
+            to: &str,
+        ) -> Result<(), Box<dyn error::Error>> {
+            let version_stack = self
+                .db
+                .liter(FILE_VERSION_STACK)
+                .map(|version_stack| version_stack.get_item::<VersionStack>().unwrap())
+                .find(|version_stack| version_stack.path == from)
+                .ok_or_else(|| untracked(from))?;
+            if other.is_tracked(to) {
+                return Err(format!("{} has a history already", to).into());
+            }
+            let file = self.file_export(version_stack);
+            other.import_file(FileExport {
+                path: to.to_string(),
+                changes: file
+                    .changes
+                    .into_iter()
+                    .map(|change| LineDifference {
+                        path: to.to_string(),
+                        ..change
+                    })
+                    .collect(),
+                ..file
+            })?;
+            other.epoch += 1;
+            other.flush()?;
+            self.purge(from)
+        }
+
+        /// the history of `path` under `moved`, the epoch and the flush are left to the caller
+        fn move_file(&mut self, path: &str, moved: &str) -> Result<(), Box<dyn error::Error>> {
+            let changes = self
+                .file_changes(path)
+                .into_iter()
+                .map(|change| LineDifference {
+                    path: moved.to_string(),
+                    ..change
+                })
+                .collect_vec();
+            if self.db.lexists(path) {
+                self.db.lrem_list(path)?;
+            }
+            self.db.lcreate(moved)?.lextend(&changes);
+
+            let position = self
+                .db
+                .liter(FILE_VERSION_STACK)
+                .position(|stored| stored.get_item::<VersionStack>().unwrap().path == path)
+                .ok_or_else(|| untracked(path))?;
+            let version_stack = self.db.lpop::<VersionStack>(FILE_VERSION_STACK, position);
+            if let Some(version_stack) = version_stack {
+                let version_stack = VersionStack {
+                    path: moved.to_string(),
+                    ..version_stack
+                };
+                self.db.ladd(FILE_VERSION_STACK, &version_stack);
+            }
+            if let Some(version_marker) = self.get_version_marker(path.to_string()) {
+                self.db.lrem_value(FILE_VERSION_MARKER, &version_marker)?;
+                let version_marker = VersionMarker {
+                    path: moved.to_string(),
+                    ..version_marker
+                };
+                self.db.ladd(FILE_VERSION_MARKER, &version_marker);
+            }
+            if let Some(fingerprints) = self.db.get::<String>(&fingerprints_key(path)) {
+                self.db.rem(&fingerprints_key(path))?;
+                self.db.set(&fingerprints_key(moved), &fingerprints)?;
+            }
+            let mut retentions = self.retentions();
+            if let Some(retention) = retentions.remove(path) {
+                retentions.insert(moved.to_string(), retention);
+                self.db.set(RETENTIONS, &retentions)?;
+            }
+            let mut debounces = self.debounces();
+            if let Some(debounce) = debounces.remove(path) {
+                debounces.insert(moved.to_string(), debounce);
+                self.db.set(DEBOUNCES, &debounces)?;
+            }
+            let mut disabled = self.disabled();
+            if disabled.paths.iter().any(|disabled| disabled == path) {
+                disabled.paths.retain(|disabled| disabled != path && disabled != moved);
+                disabled.paths.push(moved.to_string());
+                self.set_disabled(&disabled)?;
+            }
+            let hard_links = self.hard_links();
+            if hard_links.iter().any(|link| link.0 == path || link.1 == path) {
+                let rename = |linked: String| match linked == path {
+                    true => moved.to_string(),
+                    false => linked,
+                };
+                let hard_links = hard_links
+                    .into_iter()
+                    .map(|(alias, canonical)| (rename(alias), rename(canonical)))
+                    .filter(|(alias, canonical)| alias != canonical)
+                    .collect();
+                self.set_hard_links(&hard_links)?;
+            }
+            Ok(())
+        }
+
+        ///
+        /// copies the history of `src` to `dst`, like `cp` does with the file.
+        /// both go their own way from then on. a `dst` with a history of its
+        /// own is refused, purge it first to overwrite it.
+        ///
+        pub fn fork(&mut self, src: &str, dst: &str) -> Result<(), Box<dyn error::Error>> {
+            let version_stack = self.get_version_stack(src).ok_or_else(|| untracked(src))?;
+            if self.db.lexists(dst) {
+                return Err(format!("{} has a history already", dst).into());
+            }
+
+            let changes = self
+                .file_changes(src)
+                .into_iter()
+                .map(|change| LineDifference {
+                    path: dst.to_string(),
+                    ..change
+                })
+                .collect_vec();
+            self.db.lcreate(dst)?.lextend(&changes);
+            self.db.ladd(
+                FILE_VERSION_STACK,
+                &VersionStack {
+                    path: dst.to_string(),
+                    ..version_stack
+                },
+            );
+            if let Some(version_marker) = self.get_version_marker(src.to_string()) {
+                let version_marker = VersionMarker {
+                    path: dst.to_string(),
+                    ..version_marker
+                };
+                self.db.ladd(FILE_VERSION_MARKER, &version_marker);
+            }
+            if let Some(fingerprints) = self.db.get::<String>(&fingerprints_key(src)) {
+                self.db.set(&fingerprints_key(dst), &fingerprints)?;
+            }
+            self.epoch += 1;
+            self.flush()
+        }
+
+        pub fn create_new_file_entry(&mut self, path: &str) -> Result<(), Box<dyn error::Error>> {
+            if !self.db.lexists(path) {
+                self.db.lcreate(path)?;
+                self.flush()?;
+            }
+
+            Ok(())
+        }
+
+        ///
+        /// whether the store keeps a history for `path`, a tracked path may
+        /// still have no changes yet
+        ///
+        pub fn is_tracked(&self, path: &str) -> bool {
+            path != FILE_VERSION_STACK && path != FILE_VERSION_MARKER && self.db.lexists(path)
+        }
+
+        fn hard_links(&self) -> HashMap<String, String> {
+            self.db.get(HARD_LINKS).unwrap_or_default()
+        }
+
+        fn set_hard_links(
+            &mut self,
+            hard_links: &HashMap<String, String>,
+        ) -> Result<(), Box<dyn error::Error>> {
+            self.db.set(HARD_LINKS, hard_links).map_err(|err| err.into())
+        }
+
+        /// the tracked path `path` is a hard link of, `path` itself otherwise
+        pub fn canonical_path(&self, path: &str) -> String {
+            self.hard_links()
+                .remove(path)
+                .unwrap_or_else(|| path.to_string())
+        }
+
+        /// the other paths of the file tracked by `path`, sorted
+        pub fn aliases(&self, path: &str) -> Vec<String> {
+            self.hard_links()
+                .into_iter()
+                .filter(|(_, canonical)| canonical == path)
+                .map(|(alias, _)| alias)
+                .sorted()
+                .collect_vec()
+        }
+
+        ///
+        /// the path a change of `path` gets recorded under. an untracked
+        /// path linking the same inode as a tracked file becomes an alias
+        /// of that file, so both paths share one history. an alias whose
+        /// link got broken, e.g. by the atomic save of an editor, is a file
+        /// of its own again.
+        ///
+        pub fn resolve_hard_link(&mut self, path: &str) -> Result<String, Box<dyn error::Error>> {
+            let canonical = self.canonical_path(path);
+            if canonical != path {
+                let ids = (file_id(path), file_id(&canonical));
+                // a gone file is left to `unlink`
+                if ids.0.is_none() || ids.1.is_none() || ids.0 == ids.1 {
+                    return Ok(canonical);
+                }
+                let mut hard_links = self.hard_links();
+                hard_links.remove(path);
+                self.set_hard_links(&hard_links)?;
+                self.flush()?;
+            }
+            if self.is_tracked(path) {
+                return Ok(path.to_string());
+            }
+            let id = match hard_link_id(path) {
+                Some(id) => id,
+                None => return Ok(path.to_string()),
+            };
+            // every tracked file was looked at for this one already
+            if self.not_hard_linked.get(path) == Some(&id) {
+                return Ok(path.to_string());
+            }
+            let linked = self
+                .tracked_paths_iter()
+                .map(|tracked| tracked.path)
+                .find(|tracked| hard_link_id(tracked) == Some(id));
+            match linked {
+                Some(linked) => {
+                    self.not_hard_linked.remove(path);
+                    let mut hard_links = self.hard_links();
+                    hard_links.insert(path.to_string(), linked.clone());
+                    self.set_hard_links(&hard_links)?;
+                    self.flush()?;
+                    Ok(linked)
+                }
+                None => {
+                    self.not_hard_linked.insert(path.to_string(), id);
+                    Ok(path.to_string())
+                }
+            }
+        }
+
+        ///
+        /// forgets the removed link `path` of a hard linked file. the history
+        /// of a removed tracked path moves to one of its aliases that is still
+        /// on disk. returns whether the history lives on under another path.
+        ///
+        pub fn unlink(&mut self, path: &str) -> Result<bool, Box<dyn error::Error>> {
+            let mut hard_links = self.hard_links();
+            if hard_links.remove(path).is_some() {
+                self.set_hard_links(&hard_links)?;
+                self.flush()?;
+                return Ok(true);
+            }
+            let successor = self
+                .aliases(path)
+                .into_iter()
+                .find(|alias| std::path::Path::new(alias).is_file());
+            let successor = match successor {
+                Some(successor) => successor,
+                None => return Ok(false),
+            };
+            self.rewrite_paths(path, &successor)?;
+            let hard_links = hard_links
+                .into_iter()
+                .filter(|(alias, _)| *alias != successor)
+                .map(|(alias, canonical)| {
+                    if canonical == path {
+                        (alias, successor.clone())
+                    } else {
+                        (alias, canonical)
+                    }
+                })
+                .collect();
+            self.set_hard_links(&hard_links)?;
+            self.flush()?;
+            Ok(true)
+        }
+
+        /// returns the changes of `path` in the order they were recorded
+        pub fn get_file_changes(
+            &self,
+            path: &str,
+        ) -> Result<Vec<LineDifference>, Box<dyn error::Error>> {
+            if !self.is_tracked(path) {
+                return Err(untracked(path));
+            }
+            Ok(self.file_changes(path))
+        }
+
+        /// like `get_file_changes`, an untracked path has no changes
+        fn file_changes(&self, path: &str) -> Vec<LineDifference> {
+            if !self.is_tracked(path) {
+                return Vec::new();
+            }
+            expand_line_edits(
+                self.db
+                    .liter(path)
+                    .map(|e| e.get_item::<LineDifference>().unwrap())
+                    .collect(),
+            )
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::retention::Retention;
+        use std::fs;
+        use std::path::PathBuf;
+
+        struct Fixture {
+            dir: PathBuf,
+            store: Store,
+        }
+
+        impl Fixture {
+            fn new(name: &str, files: &[(&str, &str)]) -> Fixture {
+                Fixture::open(name, files, StoreOptions::default()).unwrap()
+            }
+
+            fn open(
+                name: &str,
+                files: &[(&str, &str)],
+                options: StoreOptions,
+            ) -> Result<Fixture, Box<dyn error::Error>> {
+                let dir = std::env::temp_dir().join(format!(
+                    "auto_stash_store_{}_{}",
+                    name,
+                    std::process::id()
+                ));
+                let _ = fs::remove_dir_all(&dir);
+                fs::create_dir_all(dir.join("watch")).unwrap();
+                files.iter().for_each(|(file, content)| {
+                    fs::write(dir.join("watch").join(file), content).unwrap();
+                });
+
+                let store = Store::open(
+                    dir.join("store.db").to_str().unwrap(),
+                    dir.join("watch").to_str().unwrap(),
+                    options,
+                )?;
+                Ok(Fixture { dir, store })
+            }
+
+            fn store_path(&self) -> String {
+                self.dir.join("store.db").to_str().unwrap().to_string()
+            }
+
+            fn reopen(&self, options: StoreOptions) -> Result<Store, Box<dyn error::Error>> {
+                Store::open(
+                    self.store_path().as_str(),
+                    self.dir.join("watch").to_str().unwrap(),
+                    options,
+                )
+            }
+
+            fn path(&self, file: &str) -> String {
+                self.dir.join("watch").join(file).to_str().unwrap().to_string()
+            }
+
+            fn write(&mut self, file: &str, content: &str) {
+                let path = self.path(file);
+                fs::write(&path, content).unwrap();
+                self.record(&path);
+            }
+
+            fn remove(&mut self, file: &str) {
+                let path = self.path(file);
+                let changes = self
+                    .store
+                    .get_file_changes(&path)
+                    .unwrap()
+                    .iter()
+                    .map(|change| {
+                        LineDifference::new(
+                            path.clone(),
+                            change.line_number,
+                            change.changed_line.to_string(),
+                            "".to_string(),
+                        )
+                    })
+                    .collect_vec();
+                fs::remove_file(&path).unwrap();
+                self.store.store_changes(&path, &changes).unwrap();
+            }
+
+            fn record(&mut self, path: &str) {
+                self.store.create_new_file_entry(path).unwrap();
+                let changes = self.store.get_file_changes(path).unwrap();
+                let changes = diff::find(path, &changes, DiffAlgorithm::Lines).unwrap();
+                self.store.store_changes(path, &changes).unwrap();
+            }
+        }
+
+        impl Drop for Fixture {
+            fn drop(&mut self) {
+                let _ = fs::remove_dir_all(&self.dir);
+            }
+        }
+
+        #[test]
+        fn time_frames_parse_from_durations() {
+            assert_eq!("15m".parse(), Ok(TimeFrame::SECONDS(15 * 60)));
+            assert_eq!("1w".parse(), Ok(TimeFrame::SECONDS(TimeFrame::WEEK.value())));
+            assert_eq!("all".parse(), Ok(TimeFrame::ALL));
+            assert!("0d".parse::<TimeFrame>().is_err());
+            assert!("1y".parse::<TimeFrame>().is_err());
+            assert!("d".parse::<TimeFrame>().is_err());
+        }
+
+        #[test]
+        fn reconstruct_every_version() {
+            let mut fixture = Fixture::new("reconstruct", &[("a.txt", "one\ntwo\n")]);
+            fixture.write("a.txt", "one\n2\nthree\n");
+            fixture.write("a.txt", "one\n");
+            let path = fixture.path("a.txt");
+
+            assert_eq!(fixture.store.reconstruct(&path, 0).unwrap(), "one\ntwo\n");
+            assert_eq!(
+                fixture.store.reconstruct(&path, 1).unwrap(),
+                "one\n2\nthree\n"
+            );
+            assert_eq!(fixture.store.reconstruct(&path, 2).unwrap(), "one\n");
+        }
+
+        /// an edit of the line at a position, wrapped to the current length
+        #[derive(Clone, Debug)]
+        enum Edit {
+            Insert(usize, String),
+            Delete(usize),
+            Modify(usize, String),
+        }
+
+        fn apply(lines: &mut Vec<String>, edit: &Edit) {
+            match edit {
+                Edit::Insert(position, line) => {
+                    lines.insert(position % (lines.len() + 1), line.clone());
+                }
+                Edit::Delete(position) if !lines.is_empty() => {
+                    lines.remove(position % lines.len());
+                }
+                Edit::Modify(position, line) if !lines.is_empty() => {
+                    let position = position % lines.len();
+                    lines[position] = line.clone();
+                }
+                _ => {}
+            }
+        }
+
+        fn edit_strategy() -> impl proptest::strategy::Strategy<Value = Edit> {
+            use proptest::prelude::*;
+            // short lines from a tiny alphabet, so empty and repeated lines are common
+            let line = "[ab ]{0,3}";
+            prop_oneof![
+                (any::<usize>(), line).prop_map(|(position, line)| Edit::Insert(position, line)),
+                any::<usize>().prop_map(Edit::Delete),
+                (any::<usize>(), line).prop_map(|(position, line)| Edit::Modify(position, line)),
+            ]
+        }
+
+        proptest::proptest! {
+            #![proptest_config(proptest::test_runner::Config::with_cases(64))]
+
+            #[test]
+            fn reconstruct_matches_every_recorded_content(
+                initial in proptest::collection::vec("[ab ]{0,3}", 0..5),
+                edits in proptest::collection::vec(
+                    (proptest::collection::vec(edit_strategy(), 1..4), proptest::bool::ANY),
+                    1..6,
+                ),
+            ) {
+                let content = |lines: &[String], trailing_newline: bool| {
+                    let mut content = lines.join("\n");
+                    if trailing_newline && !lines.is_empty() {
+                        content.push('\n');
+                    }
+                    content
+                };
+                let mut lines = initial;
+                let mut contents = vec![content(&lines, true)];
+                let mut fixture = Fixture::new("proptest", &[("a.txt", contents[0].as_str())]);
+                let path = fixture.path("a.txt");
+
+                for (version_edits, trailing_newline) in &edits {
+                    version_edits.iter().for_each(|edit| apply(&mut lines, edit));
+                    contents.push(content(&lines, *trailing_newline));
+                    fixture.write("a.txt", contents.last().unwrap());
+                }
+
+                for (version, expected) in contents.iter().enumerate() {
+                    let reconstructed = fixture.store.reconstruct(&path, version).unwrap();
+                    proptest::prop_assert_eq!(&reconstructed, expected);
+                }
+            }
+        }
+
+        #[test]
+        fn reconstruct_empty_file() {
+            let mut fixture = Fixture::new("reconstruct_empty", &[("empty.txt", "")]);
+            let path = fixture.path("empty.txt");
+            assert_eq!(fixture.store.reconstruct(&path, 0).unwrap(), "");
+
+            fixture.write("empty.txt", "content");
+            fixture.write("empty.txt", "");
+            assert_eq!(fixture.store.reconstruct(&path, 1).unwrap(), "content");
+            assert_eq!(fixture.store.reconstruct(&path, 2).unwrap(), "");
+        }
+
+        #[test]
+        fn reconstruct_deleted_then_restored_file() {
+            let mut fixture = Fixture::new("reconstruct_deleted", &[("a.txt", "one\ntwo\n")]);
+            let path = fixture.path("a.txt");
+            fixture.remove("a.txt");
+            fixture.write("a.txt", "one\ntwo\n");
+
+            assert_eq!(fixture.store.reconstruct(&path, 1).unwrap(), "");
+            assert_eq!(fixture.store.reconstruct(&path, 2).unwrap(), "one\ntwo\n");
+        }
+
+        fn with_passphrase(passphrase: &str) -> StoreOptions {
+            StoreOptions {
+                passphrase: Some(passphrase.to_string()),
+                ..StoreOptions::default()
+            }
+        }
+
+        #[cfg(feature = "encryption")]
+        #[test]
+        fn encrypted_store_round_trip() {
+            let mut fixture = Fixture::open(
+                "encrypted",
+                &[("secret.txt", "password=hunter2\n")],
+                with_passphrase("correct horse"),
+            )
+            .unwrap();
+            fixture.write("secret.txt", "password=hunter3\n");
+            let path = fixture.path("secret.txt");
+
+            let content = fs::read(fixture.store_path()).unwrap();
+            assert!(Store::is_encrypted(&fixture.store_path()));
+            assert!(!String::from_utf8_lossy(&content).contains("hunter"));
+
+            let store = fixture.reopen(with_passphrase("correct horse")).unwrap();
+            assert_eq!(store.reconstruct(&path, 1).unwrap(), "password=hunter3\n");
+            assert!(fixture.reopen(with_passphrase("wrong")).is_err());
+            assert!(fixture.reopen(StoreOptions::default()).is_err());
+        }
+
+        #[cfg(feature = "encryption")]
+        #[test]
+        fn a_flipped_byte_of_an_encrypted_store_is_corrupt() {
+            let mut fixture =
+                Fixture::open("encrypted_corrupt", &[("a.txt", "one\n")], with_passphrase("secret"))
+                    .unwrap();
+            fixture.store.flush().unwrap();
+            let store_path = fixture.store_path();
+            assert!(!Store::is_corrupt(std::path::Path::new(&store_path)).unwrap());
+
+            let mut content = fs::read(&store_path).unwrap();
+            *content.last_mut().unwrap() ^= 1;
+            fs::write(&store_path, &content).unwrap();
+            assert!(Store::is_corrupt(std::path::Path::new(&store_path)).unwrap());
+            let err = fixture.reopen(with_passphrase("secret")).err().unwrap();
+            assert!(err.to_string().contains("checksum"));
+        }
+
+        #[cfg(feature = "encryption")]
+        #[test]
+        fn passphrase_encrypts_plaintext_store() {
+            let fixture = Fixture::new("encrypt_plaintext", &[("a.txt", "one\n")]);
+            assert!(!Store::is_encrypted(&fixture.store_path()));
+
+            let store = fixture.reopen(with_passphrase("secret")).unwrap();
+            assert!(Store::is_encrypted(&fixture.store_path()));
+            assert_eq!(store.reconstruct(&fixture.path("a.txt"), 0).unwrap(), "one\n");
+        }
+
+        #[test]
+        fn plaintext_store_reopens() {
+            let mut fixture = Fixture::new("plaintext", &[("a.txt", "one\n")]);
+            fixture.write("a.txt", "two\n");
+
+            assert!(!Store::is_encrypted(&fixture.store_path()));
+            let store = fixture.reopen(StoreOptions::default()).unwrap();
+            assert_eq!(store.reconstruct(&fixture.path("a.txt"), 1).unwrap(), "two\n");
+        }
+
+        #[test]
+        fn a_changed_watch_root_is_noticed_and_rekeyed() {
+            let mut fixture = Fixture::new("watch_roots", &[("a.txt", "one\n")]);
+            let root = fixture.dir.join("watch").to_str().unwrap().to_string();
+            let moved = fixture.dir.join("moved").to_str().unwrap().to_string();
+            let store_path = fixture.store_path();
+            let open = |watch_path: &str| {
+                Store::open(&store_path, watch_path, StoreOptions::default()).unwrap()
+            };
+            assert_eq!(fixture.store.watch_roots(), vec![root.clone()]);
+            assert_eq!(fixture.store.previous_watch_roots(), None);
+
+            // stores from before the roots were recorded take the current one
+            fixture.store.db.rem(WATCH_ROOTS).unwrap();
+            let mut store = open(&moved);
+            assert_eq!(store.previous_watch_roots(), None);
+            store.db.set(WATCH_ROOTS, &vec![root.clone()]).unwrap();
+
+            let mut store = open(&moved);
+            assert_eq!(store.previous_watch_roots(), Some(vec![root.clone()]));
+            assert_eq!(store.rekey_watch_root().unwrap(), 1);
+            assert_eq!(store.watch_roots(), vec![moved.clone()]);
+            let path = format!("{}/a.txt", moved);
+            assert_eq!(store.reconstruct(&path, 0).unwrap(), "one\n");
+
+            let mut store = open(&root);
+            assert_eq!(store.previous_watch_roots(), Some(vec![moved.clone()]));
+            store.keep_watch_roots().unwrap();
+            assert_eq!(open(&root).watch_roots(), vec![moved, root.clone()]);
+            assert_eq!(open(&root).previous_watch_roots(), None);
+        }
+
+        #[test]
+        fn a_widened_or_narrowed_watch_root_keeps_its_history() {
+            let files = [("a.txt", "one\n"), ("b.txt", "two\n"), ("c.txt", "2\n")];
+            let mut fixture = Fixture::new("nested_watch_roots", &files);
+            let root = fixture.dir.join("watch").to_str().unwrap().to_string();
+            let store_path = fixture.store_path();
+            let open = |watch_path: &str| {
+                Store::open(&store_path, watch_path, StoreOptions::default()).unwrap()
+            };
+            let a = fixture.path("a.txt");
+            let (old, new) = (fixture.path("old/b.txt"), fixture.path("new/b.txt"));
+            fixture.store.move_history(&fixture.path("b.txt"), &old).unwrap();
+            fixture.store.move_history(&fixture.path("c.txt"), &new).unwrap();
+
+            let (widened, narrowed) = (fixture.dir.to_str().unwrap(), format!("{}/old", root));
+            fs::create_dir_all(&narrowed).unwrap();
+            for watch_path in [widened, narrowed.as_str()].iter() {
+                let mut store = open(watch_path);
+                assert_eq!(store.previous_watch_roots(), None);
+                assert_eq!(store.rekey_watch_root().unwrap(), 0);
+                assert_eq!(store.reconstruct(&a, 0).unwrap(), "one\n");
+            }
+            let roots = vec![root.clone(), widened.to_string(), narrowed.clone()];
+            assert_eq!(open(&root).watch_roots(), roots);
+
+            // the file below the old root has a history below the new one already
+            let mut store = open(&root);
+            store.db.set(WATCH_ROOTS, &vec![narrowed]).unwrap();
+            store.watch_path = format!("{}/new", root);
+            assert_eq!(store.rekey_watch_root().unwrap(), 0);
+            assert_eq!(store.reconstruct(&old, 0).unwrap(), "two\n");
+        }
+
+        #[test]
+        fn dedup_report_counts_the_unique_lines() {
+            let lines = (0..20)
+                .map(|line| format!("a line worth interning, number {}\n", line))
+                .join("");
+            let mut fixture =
+                Fixture::new("dedup_report", &[("a.txt", lines.as_str()), ("b.txt", "")]);
+            fixture.write("b.txt", lines.as_str());
+            fixture.write("a.txt", &lines.replace("number 3\n", "changed\n"));
+
+            let report = fixture.store.dedup_report();
+            // both files keep the 650 bytes, "number 3" is once before and once after the change
+            assert_eq!(report.lines, 42);
+            assert_eq!(report.unique_lines, 21);
+            assert_eq!(report.bytes, 650 + 650 + 32 + 31);
+            assert_eq!(report.unique_bytes, 650 + 31);
+            assert_eq!(report.saved_bytes(), 1363 - 681 - 42 * 8);
+
+            let mut combined = report.clone();
+            combined += report;
+            assert_eq!(combined.unique_lines, 42);
+        }
+
+        #[test]
+        fn a_reader_follows_the_store_another_one_writes() {
+            let mut fixture = Fixture::new("reader", &[("a.txt", "one\n")]);
+            let a = fixture.path("a.txt");
+            let (store_path, watch_path) = (fixture.store_path(), fixture.path(""));
+            let open_reader =
+                || Store::open_reader(&store_path, &watch_path, StoreOptions::default());
+            let mut reader = open_reader().unwrap();
+            assert!(reader.is_read_only());
+            assert!(!reader.reload().unwrap());
+
+            fixture.write("a.txt", "two\n");
+            assert_eq!(reader.version_count(&a).unwrap(), 1);
+            assert!(reader.reload().unwrap());
+            assert_eq!(reader.reconstruct(&a, 1).unwrap(), "two\n");
+            assert!(!reader.reload().unwrap());
+
+            // what a reader records stays in memory
+            fs::write(&a, "three\n").unwrap();
+            let previous = reader.get_file_changes(&a).unwrap();
+            let changes = diff::find(&a, &previous, DiffAlgorithm::Lines).unwrap();
+            reader.store_changes(&a, &changes).unwrap();
+            assert_eq!(open_reader().unwrap().version_count(&a).unwrap(), 2);
+            assert!(fixture.store.reload().is_err());
+            assert!(Store::open_reader("/missing.db", "/", StoreOptions::default()).is_err());
+        }
+
+        #[test]
+        fn changes_of_a_transaction_land_together_or_not_at_all() {
+            let mut fixture = Fixture::new("txn", &[("a.txt", "a\n"), ("b.txt", "b\n")]);
+            let (a, b) = (fixture.path("a.txt"), fixture.path("b.txt"));
+            let record = |store: &mut Store, path: &str, content: &str| {
+                fs::write(path, content).unwrap();
+                let previous = store.get_file_changes(path).unwrap();
+                let changes = diff::find(path, &previous, DiffAlgorithm::Lines).unwrap();
+                store.store_changes(path, &changes).unwrap();
+            };
+            let versions = |store: &Store| {
+                (store.version_count(&a).unwrap(), store.version_count(&b).unwrap())
+            };
+            let (store_path, watch_path) = (fixture.store_path(), fixture.path(""));
+            let on_disk = || Store::open(&store_path, &watch_path, StoreOptions::default());
+
+            // an error halfway drops the transaction, nothing of it is kept
+            let failed = (|| -> Result<(), Box<dyn error::Error>> {
+                let mut txn = fixture.store.begin()?;
+                record(&mut txn, &a, "a2\n");
+                record(&mut txn, &b, "b2\n");
+                assert_eq!(versions(&txn), (2, 2));
+                assert_eq!(on_disk()?.version_count(&a)?, 1);
+                Err("halfway".into())
+            })();
+            assert!(failed.is_err());
+            assert_eq!(versions(&fixture.store), (1, 1));
+            assert_eq!(versions(&on_disk().unwrap()), (1, 1));
+
+            let mut txn = fixture.store.begin().unwrap();
+            assert!(txn.begin().is_err());
+            record(&mut txn, &a, "a3\n");
+            txn.rollback();
+            assert_eq!(versions(&fixture.store), (1, 1));
+
+            let mut txn = fixture.store.begin().unwrap();
+            record(&mut txn, &a, "a4\n");
+            record(&mut txn, &b, "b4\n");
+            txn.commit().unwrap();
+            let reopened = on_disk().unwrap();
+            assert_eq!(versions(&reopened), (2, 2));
+            assert_eq!(reopened.reconstruct(&b, 1).unwrap(), "b4\n");
+            // the store keeps writing every change by itself afterwards
+            fixture.write("a.txt", "a5\n");
+            assert_eq!(versions(&on_disk().unwrap()), (3, 2));
+
+            // a purge zeroes the store file it was in once the commit replaced it
+            let mut txn = fixture.store.begin().unwrap();
+            txn.purge(&b).unwrap();
+            txn.rollback();
+            assert!(on_disk().unwrap().is_tracked(&b));
+            let mut txn = fixture.store.begin().unwrap();
+            txn.purge(&b).unwrap();
+            txn.commit().unwrap();
+            let reopened = on_disk().unwrap();
+            assert!(!reopened.is_tracked(&b));
+            assert_eq!(reopened.reconstruct(&a, 2).unwrap(), "a5\n");
+        }
+
+        #[cfg(not(feature = "encryption"))]
+        #[test]
+        fn passphrase_needs_encryption_feature() {
+            assert!(Fixture::open("no_encryption", &[], with_passphrase("secret")).is_err());
+        }
+
+        #[test]
+        fn set_baseline_drops_older_versions() {
+            let mut fixture = Fixture::new("set_baseline", &[("a.txt", "one\ntwo\nthree\n")]);
+            fixture.write("a.txt", "one\n2\n");
+            fixture.write("a.txt", "1\n2\nthree\n");
+            let path = fixture.path("a.txt");
+
+            fixture.store.set_baseline(&path, 1).unwrap();
+            assert_eq!(fixture.store.reconstruct(&path, 0).unwrap(), "one\n2\n");
+            assert_eq!(
+                fixture.store.reconstruct(&path, 1).unwrap(),
+                "1\n2\nthree\n"
+            );
+            assert!(fixture.store.reconstruct(&path, 2).is_err());
+
+            fixture.write("a.txt", "1\n2\n3\n");
+            assert_eq!(fixture.store.reconstruct(&path, 2).unwrap(), "1\n2\n3\n");
+            assert!(fixture.store.set_baseline(&path, 3).is_err());
+        }
+
+        #[test]
+        fn epoch_moves_when_versions_get_renumbered() {
+            let mut fixture = Fixture::new("epoch", &[("a.txt", "one\n")]);
+            fixture.write("a.txt", "two\n");
+            fixture.write("a.txt", "three\n");
+            let path = fixture.path("a.txt");
+            let epoch = fixture.store.epoch();
+            let view_epoch = |store: &mut Store| store.view().unwrap()[0].as_ref().unwrap().epoch;
+            assert_eq!(view_epoch(&mut fixture.store), epoch);
+
+            // new versions only append
+            fixture.write("a.txt", "four\n");
+            fixture.store.set_baseline(&path, 0).unwrap();
+            assert_eq!(fixture.store.merge_consecutive_duplicates(None).unwrap(), 0);
+            assert_eq!(fixture.store.epoch(), epoch);
+
+            fixture.store.set_baseline(&path, 2).unwrap();
+            assert_eq!(fixture.store.epoch(), epoch + 1);
+            assert_eq!(view_epoch(&mut fixture.store), epoch + 1);
+        }
+
+        fn long_lines(edit: usize) -> String {
+            (0..5)
+                .map(|line| {
+                    let mut content = format!("{}", line).repeat(400);
+                    if line == edit % 5 {
+                        content.replace_range(edit..edit + 1, "x");
+                    }
+                    content + "\n"
+                })
+                .collect()
+        }
+
+        fn store_size_after_small_edits(name: &str, line_edits: bool) -> u64 {
+            let mut fixture = Fixture::open(
+                name,
+                &[("long.txt", long_lines(0).as_str())],
+                StoreOptions {
+                    line_edits,
+                    ..StoreOptions::default()
+                },
+            )
+            .unwrap();
+            let path = fixture.path("long.txt");
+
+            (1..20).for_each(|edit| {
+                fixture.write("long.txt", long_lines(edit).as_str());
+                assert_eq!(
+                    fixture.store.reconstruct(&path, edit).unwrap(),
+                    long_lines(edit)
+                );
+            });
+            assert_eq!(fixture.store.reconstruct(&path, 3).unwrap(), long_lines(3));
+            fs::metadata(fixture.store_path()).unwrap().len()
+        }
+
+        #[test]
+        fn line_edits_save_space_on_long_lines() {
+            let whole_lines = store_size_after_small_edits("whole_lines", false);
+            let line_edits = store_size_after_small_edits("line_edits", true);
+
+            // 19 versions each changing one char of a 400 char line
+            assert!(
+                line_edits * 2 < whole_lines,
+                "{} bytes with line edits, {} bytes with whole lines",
+                line_edits,
+                whole_lines
+            );
+        }
+
+        #[test]
+        fn history_graph_is_a_chain_of_versions() {
+            let mut fixture = Fixture::new("history_graph", &[("a\"b.txt", "one\n")]);
+            fixture.write("a\"b.txt", "one\ntwo\n");
+            fixture.write("a\"b.txt", "two\n");
+            let path = fixture.path("a\"b.txt");
+
+            let dot = fixture.store.history_graph_dot(&path);
+            assert!(dot.starts_with(&format!("digraph \"{}\" {{", path.replace('"', "\\\""))));
+            assert!(dot.contains("v1 [label=\"v1\\n"));
+            assert!(dot.contains("\\n+1 -0 ~0\"];"));
+            assert!(dot.contains("v0 -> v1;\n    v1 -> v2;"));
+            assert!(dot.ends_with("}\n"));
+
+            assert_eq!(
+                fixture.store.history_graph_dot("untracked"),
+                "digraph \"untracked\" {\n    node [shape=box];\n}\n"
+            );
+        }
+
+        #[test]
+        fn purge_forgets_every_trace_of_a_file() {
+            let secret = "password=hunter2";
+            let mut fixture = Fixture::new(
+                "purge",
+                &[("secret.txt", secret), ("b.txt", "one\n")],
+            );
+            fixture.write("secret.txt", "password=hunter3\n");
+            let path = fixture.path("secret.txt");
+            let store_file = fs::File::open(fixture.store_path()).unwrap();
+
+            fixture.store.purge(&path).unwrap();
+            assert!(fixture
+                .store
+                .tracked_paths()
+                .iter()
+                .all(|tracked| tracked.path != path));
+            assert!(fixture.store.reconstruct(&path, 0).is_err());
+            assert!(fixture.store.purge(&path).is_err());
+
+            let mut replaced = Vec::new();
+            let mut store_file = store_file;
+            store_file.read_to_end(&mut replaced).unwrap();
+            assert!(replaced.iter().all(|byte| *byte == 0));
+            let content = fs::read_to_string(fixture.store_path()).unwrap();
+            assert!(!content.contains("hunter"));
+            assert!(!content.contains("secret.txt"));
+
+            let reopened = fixture.reopen(StoreOptions::default()).unwrap();
+            assert_eq!(reopened.tracked_paths().len(), 1);
+        }
+
+        #[test]
+        fn changed_files_between_counts_versions_in_the_window() {
+            let mut fixture = Fixture::new("changed_files", &[("a.txt", "a\n"), ("b.txt", "b\n")]);
+            // versions are dated in seconds, the window starts after the baseline
+            let subsec = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().subsec_nanos();
+            std::thread::sleep(std::time::Duration::from_nanos(1_000_000_000 - subsec as u64));
+            let start = SystemTime::now();
+            fixture.write("a.txt", "a\n1\n");
+            fixture.write("a.txt", "a\n2\n");
+            let end = SystemTime::now() + std::time::Duration::from_secs(1);
+
+            let changed = fixture.store.changed_files_between(start, end);
+            assert_eq!(changed, vec![(fixture.path("a.txt"), 2)]);
+
+            let before = start - std::time::Duration::from_secs(3600);
+            let all = fixture.store.changed_files_between(before, end);
+            assert_eq!(
+                all,
+                vec![(fixture.path("a.txt"), 3), (fixture.path("b.txt"), 1)]
+            );
+            assert!(fixture.store.changed_files_between(end, start).is_empty());
+        }
+
+        #[test]
+        fn recent_versions_of_all_files_come_latest_first() {
+            let mut fixture = Fixture::new("recent", &[("a.txt", "a\n"), ("b.txt", "b\n")]);
+            let (a, b) = (fixture.path("a.txt"), fixture.path("b.txt"));
+            assert_eq!(fixture.store.recent(10).len(), 2);
+
+            fixture.write("a.txt", "a\n1\n");
+            fixture.write("b.txt", "b\n1\n");
+            fixture.write("a.txt", "a\n2\n");
+            let recent = |store: &Store, limit| {
+                store
+                    .recent(limit)
+                    .into_iter()
+                    .map(|(path, version, _)| (path, version))
+                    .collect_vec()
+            };
+            assert_eq!(recent(&fixture.store, 2), vec![(a.clone(), 2), (b.clone(), 1)]);
+            assert_eq!(fixture.store.recent(10).len(), 5);
+
+            // the index is rebuilt without the purged versions
+            fixture.store.purge(&b).unwrap();
+            assert_eq!(
+                recent(&fixture.store, 10),
+                vec![(a.clone(), 2), (a.clone(), 1), (a, 0)]
+            );
+        }
+
+        #[test]
+        fn first_seen_and_last_modified_span_the_versions() {
+            let mtime = StoreOptions {
+                timestamp_source: TimestampSource::Mtime,
+                ..StoreOptions::default()
+            };
+            let mut fixture = Fixture::open("first_seen", &[], mtime).unwrap();
+            fixture.store.change_time_frame(TimeFrame::ALL);
+            let path = fixture.path("a.txt");
+            let now = SystemTime::now();
+            let write = |content: &str, ago: u64| {
+                fs::write(&path, content).unwrap();
+                let modified = now - Duration::from_secs(ago);
+                File::options()
+                    .write(true)
+                    .open(&path)
+                    .unwrap()
+                    .set_modified(modified)
+                    .unwrap();
+                system_time(unix_seconds(modified))
+            };
+
+            let created = write("one\n", 7200);
+            fixture.record(&path);
+            write("two\n", 5400);
+            fixture.record(&path);
+            let changed = write("three\n", 3600);
+            fixture.record(&path);
+
+            assert_eq!(fixture.store.first_seen(&path), Some(created));
+            assert_eq!(fixture.store.last_modified(&path), Some(changed));
+            let file_versions = fixture.store.view().unwrap().remove(0).unwrap();
+            assert_eq!(
+                (file_versions.first_seen, file_versions.last_modified),
+                (created, changed)
+            );
+            assert_eq!(fixture.store.first_seen(&fixture.path("b.txt")), None);
+            assert_eq!(fixture.store.versions_since(now - Duration::from_secs(6000)), 2);
+            assert_eq!(fixture.store.versions_since(now), 0);
+            let between = |from: u64, to: u64| {
+                let (from, to) = (now - Duration::from_secs(from), now - Duration::from_secs(to));
+                fixture.store.versions_between_times(&path, from, to)
+            };
+            assert_eq!(between(8000, 0), vec![0, 1, 2]);
+            assert_eq!(between(5400, 3600), vec![1, 2]);
+            assert_eq!(between(7000, 6000), Vec::<usize>::new());
+            assert_eq!(between(0, 8000), Vec::<usize>::new());
+        }
+
+        #[test]
+        fn files_changed_in_a_watch_gap_need_catching_up() {
+            let files = [("a.txt", "a\n"), ("b.txt", "b\n"), ("c.txt", "c\n")];
+            let mut fixture = Fixture::new("watch_gap", &files);
+            assert!(fixture.store.watch_gap_report().is_empty());
+
+            let now = Utc::now().naive_utc().timestamp();
+            let sessions = [
+                Session {
+                    start: now - 7200,
+                    stop: Some(now - 3600),
+                },
+                // crashed without recording anything
+                Session {
+                    start: now - 3000,
+                    stop: None,
+                },
+            ];
+            fixture.store.db.set(SESSIONS, &sessions).unwrap();
+            fixture.store.start_session().unwrap();
+            let at = |timestamp: i64| UNIX_EPOCH + Duration::from_secs(timestamp as u64);
+            let gaps = fixture.store.watch_gap_report();
+            assert_eq!(gaps[..], [(at(now - 3600), at(now - 3000)), (at(now - 3000), at(now))][..]);
+
+            let change = |file: &str, content: &str, ago: i64| {
+                let path = fixture.path(file);
+                fs::write(&path, content).unwrap();
+                File::options()
+                    .write(true)
+                    .open(&path)
+                    .unwrap()
+                    .set_modified(at(now - ago))
+                    .unwrap();
+            };
+            change("a.txt", "a\n1\n", 3300);
+            // touched in the gap but still the latest version
+            change("b.txt", "b\n", 3300);
+            // changed while the first run watched, it saw the change
+            change("c.txt", "c\n1\n", 5000);
+            assert_eq!(fixture.store.changed_while_unwatched(), [fixture.path("a.txt")]);
+
+            fixture.store.end_session().unwrap();
+            let reopened = fixture.reopen(StoreOptions::default()).unwrap();
+            assert_eq!(reopened.watch_gap_report().len(), 2);
+        }
+
+        #[test]
+        fn only_the_latest_sessions_are_kept() {
+            let mut fixture = Fixture::new("max_sessions", &[("a.txt", "a\n")]);
+            let now = Utc::now().naive_utc().timestamp();
+            let sessions = (0..MAX_SESSIONS as i64)
+                .map(|ago| Session {
+                    start: now - 7200 - 2 * ago,
+                    stop: Some(now - 7200 - 2 * ago + 1),
+                })
+                .rev()
+                .collect_vec();
+            fixture.store.db.set(SESSIONS, &sessions).unwrap();
+            fixture.store.start_session().unwrap();
+
+            let kept = fixture.store.sessions();
+            assert_eq!(kept.len(), MAX_SESSIONS);
+            assert_eq!(kept[0].start, sessions[1].start);
+            assert_eq!(kept.last().unwrap().stop, None);
+        }
+
+        #[test]
+        fn evicts_oldest_versions_above_the_size_limit() {
+            let mut fixture = Fixture::new("evict", &[("a.txt", "a\n"), ("b.txt", "b\n")]);
+            (0..10).for_each(|version| {
+                fixture.write("a.txt", &format!("a\n{}\n", "a".repeat(100 + version)));
+            });
+            fixture.write("b.txt", "b\nb\n");
+            let limit = fs::metadata(fixture.store_path()).unwrap().len() - 500;
+
+            let mut store = fixture
+                .reopen(StoreOptions {
+                    max_store_bytes: Some(limit),
+                    ..StoreOptions::default()
+                })
+                .unwrap();
+            let a = fixture.path("a.txt");
+            store.create_new_file_entry(&a).unwrap();
+            let previous = store.get_file_changes(&a).unwrap();
+            let changes = diff::find(&a, &previous, DiffAlgorithm::Lines).unwrap();
+            store.store_changes(&a, &changes).unwrap();
+
+            assert!(fs::metadata(fixture.store_path()).unwrap().len() <= limit);
+            let evictions = store.take_evictions();
+            assert!(!evictions.is_empty());
+            assert!(evictions
+                .windows(2)
+                .all(|pair| pair[0].datetime <= pair[1].datetime));
+            assert!(store.take_evictions().is_empty());
+
+            [a, fixture.path("b.txt")].iter().for_each(|path| {
+                let versions = (0..)
+                    .take_while(|version| store.reconstruct(path, *version).is_ok())
+                    .count();
+                assert_eq!(
+                    store.reconstruct(path, versions - 1).unwrap(),
+                    fs::read_to_string(path).unwrap()
+                );
+            });
+        }
+
+        /// the version stack as it was stored before version entries existed
+        #[derive(Serialize)]
+        struct LegacyVersionStack {
+            path: String,
+            timestamps: Vec<i64>,
+        }
+
+        #[test]
+        fn legacy_store_is_migrated_after_a_backup() {
+            let mut fixture = Fixture::new("migrate", &[("a.txt", "one\n")]);
+            fixture.write("a.txt", "one\ntwo\n");
+            let path = fixture.path("a.txt");
+            {
+                let mut db = load(&fixture.store_path(), PickleDbDumpPolicy::AutoDump).unwrap();
+                let legacy = db
+                    .liter(FILE_VERSION_STACK)
+                    .map(|version_stack| {
+                        let version_stack = version_stack.get_item::<VersionStack>().unwrap();
+                        LegacyVersionStack {
+                            path: version_stack.path,
+                            timestamps: version_stack.timestamps,
+                        }
+                    })
+                    .collect_vec();
+                db.lrem_list(FILE_VERSION_STACK).unwrap();
+                db.lcreate(FILE_VERSION_STACK).unwrap().lextend(&legacy);
+                db.rem(SCHEMA_VERSION).unwrap();
+            }
+            let legacy = fs::read(fixture.store_path()).unwrap();
+
+            let store = fixture.reopen(StoreOptions::default()).unwrap();
+            assert_eq!(
+                fs::read(format!("{}.schema0.bak", fixture.store_path())).unwrap(),
+                legacy
+            );
+            assert_eq!(store.db.get::<u64>(SCHEMA_VERSION), Some(current_schema()));
+            let version_stack = store.get_version_stack(&path).unwrap();
+            assert_eq!(version_stack.entries.len(), version_stack.timestamps.len());
+            assert_eq!(store.reconstruct(&path, 1).unwrap(), "one\ntwo\n");
+        }
+
+        #[test]
+        fn newer_schema_is_refused() {
+            let fixture = Fixture::new("future_schema", &[("a.txt", "one\n")]);
+            let mut db = load(&fixture.store_path(), PickleDbDumpPolicy::AutoDump).unwrap();
+            db.set(SCHEMA_VERSION, &(current_schema() + 1)).unwrap();
+            db.set(WRITTEN_BY, &"9.0.0").unwrap();
+            drop(db);
+            let newer = fs::read(fixture.store_path()).unwrap();
+
+            let err = fixture.reopen(StoreOptions::default()).err().unwrap();
+            assert!(err.to_string().contains("schema version"));
+            assert!(err.to_string().contains("written by auto-stash >= 9.0.0, upgrade"));
+            assert_eq!(fs::read(fixture.store_path()).unwrap(), newer);
+
+            let mut store = fixture
+                .reopen(StoreOptions {
+                    read_newer: true,
+                    ..StoreOptions::default()
+                })
+                .unwrap();
+            assert!(store.is_read_only());
+            let path = fixture.path("a.txt");
+            assert_eq!(store.reconstruct(&path, 0).unwrap(), "one\n");
+            fs::write(&path, "one\ntwo\n").unwrap();
+            let changes = store.diff_snapshot(&path, b"one\ntwo\n").unwrap();
+            store
+                .store_snapshot(&path, b"one\ntwo\n", &changes, VersionSource::Watcher)
+                .unwrap();
+            store.flush().unwrap();
+            drop(store);
+            assert_eq!(fs::read(fixture.store_path()).unwrap(), newer);
+        }
+
+        #[test]
+        fn corrupt_stores_are_told_apart_from_newer_ones() {
+            let mut fixture = Fixture::new("is_corrupt", &[("a.txt", "one\n")]);
+            fixture.store.flush().unwrap();
+            let store_path = fixture.store_path();
+            let store_path = std::path::Path::new(&store_path);
+            assert!(!Store::is_corrupt(store_path).unwrap());
+
+            let content = fs::read(store_path).unwrap();
+            let corrupt = fixture.dir.join("corrupt.db");
+            fs::write(&corrupt, &content[..content.len() / 2]).unwrap();
+            assert!(Store::is_corrupt(&corrupt).unwrap());
+            // still a pickledb document, only the checksum tells
+            let mut flipped = content.clone();
+            let at = content.windows(3).rposition(|window| window == b"one").unwrap();
+            flipped[at] = b'O';
+            fs::write(&corrupt, &flipped).unwrap();
+            load(corrupt.to_str().unwrap(), PickleDbDumpPolicy::NeverDump).unwrap();
+            assert!(Store::is_corrupt(&corrupt).unwrap());
+            fs::write(&corrupt, "not a store").unwrap();
+            assert!(Store::is_corrupt(&corrupt).unwrap());
+            fs::write(&corrupt, [encryption::MAGIC, b"salt"].concat()).unwrap();
+            assert!(Store::is_corrupt(&corrupt).unwrap());
+
+            load(&fixture.store_path(), PickleDbDumpPolicy::AutoDump)
+                .unwrap()
+                .set(SCHEMA_VERSION, &(current_schema() + 1))
+                .unwrap();
+            let err = Store::is_corrupt(store_path).unwrap_err();
+            assert!(err.downcast_ref::<UnsupportedSchema>().is_some());
+            assert!(Store::is_corrupt(&fixture.dir.join("missing.db")).is_err());
         }
 
-        pub fn redo_by(&mut self, path: String, count: usize) -> Result<(), Box<dyn error::Error>> {
-            self.redo(path, count)
+        #[test]
+        fn versions_keep_their_source() {
+            let mut fixture = Fixture::new("version_source", &[("a.txt", "one\n")]);
+            fixture.write("a.txt", "one\ntwo\n");
+            let path = fixture.path("a.txt");
+            fs::write(&path, "one\n").unwrap();
+            let changes = fixture.store.get_file_changes(&path).unwrap();
+            let changes = diff::find(&path, &changes, DiffAlgorithm::Lines).unwrap();
+            fixture
+                .store
+                .store_changes_from(&path, &changes, VersionSource::Restore)
+                .unwrap();
+
+            let sources = fixture
+                .reopen(StoreOptions::default())
+                .unwrap()
+                .view()
+                .unwrap()
+                .into_iter()
+                .flatten()
+                .flat_map(|file_versions| file_versions.versions)
+                .map(|version| version.source)
+                .collect_vec();
+            assert_eq!(
+                sources,
+                vec![
+                    VersionSource::Restore,
+                    VersionSource::Watcher,
+                    VersionSource::Scan
+                ]
+            );
         }
 
-        pub fn view(&mut self) -> Result<Vec<Option<FileVersions>>, Box<dyn error::Error>> {
-            let now = Utc::now().naive_utc();
+        #[test]
+        fn unified_diff_of_a_version() {
+            let mut fixture = Fixture::new("unified_diff", &[("a.txt", "one\ntwo\n")]);
+            fixture.write("a.txt", "one\n2\n");
+            let path = fixture.path("a.txt");
 
-            Ok(self
-                .db
-                .liter(FILE_VERSION_STACK)
-                .map(|version_stack| {
-                    let version_stack: VersionStack = version_stack.get_item().unwrap();
-                    let timestamps = version_stack
-                        .timestamps
-                        .iter()
-                        .filter(|timestamp| {
-                            now.timestamp() - self.time_frame.value() < *<&i64>::clone(timestamp)
-                        })
-                        .copied()
-                        .collect_vec();
+            assert_eq!(
+                fixture.store.unified_diff(&path, 1).unwrap(),
+                format!("--- {0}@0\n+++ {0}@1\n@@ -1,2 +1,2 @@\n one\n-two\n+2\n", path)
+            );
+            assert!(fixture
+                .store
+                .unified_diff(&path, 0)
+                .unwrap()
+                .ends_with("@@ -0,0 +1,2 @@\n+one\n+two\n"));
+            assert!(fixture.store.unified_diff(&path, 2).is_err());
 
-                    if timestamps.is_empty() {
-                        return None;
-                    }
-                    Some(VersionStack {
-                        timestamps,
-                        path: version_stack.path,
-                    })
+            fixture.write("a.txt", "one\n2\nthree\n");
+            assert_eq!(
+                fixture.store.unified_diff_between(&path, Some(0), 2).unwrap(),
+                format!(
+                    "--- {0}@0\n+++ {0}@2\n@@ -1,2 +1,3 @@\n one\n-two\n+2\n+three\n",
+                    path
+                )
+            );
+        }
+
+        #[test]
+        fn versions_of_two_files_are_compared() {
+            let files = [("a.txt", "one\ntwo\nthree\n"), ("b.txt", "one\ntwo\nthree\n")];
+            let mut fixture = Fixture::new("diff_two_files", &files);
+            fixture.write("b.txt", "one\n2\nthree\nfour\n");
+            let (a, b) = (fixture.path("a.txt"), fixture.path("b.txt"));
+
+            let changes = fixture.store.diff_two_files(&a, 0, &b, 1).unwrap();
+            let changes = changes
+                .iter()
+                .map(|change| {
+                    let (line, changed_line) = (&change.line, &change.changed_line);
+                    (change.line_number, line.as_str(), changed_line.as_str())
                 })
-                .map(|version_stack| -> Option<FileVersions> {
-                    version_stack.as_ref()?;
-                    let mut version_stack = version_stack.unwrap();
-                    let path = version_stack.clone().path;
-                    version_stack.timestamps.push(0);
-                    let versions = self.get_versions(version_stack);
-                    let hits_of_codes = hits_of_codes(versions.clone());
+                .collect_vec();
+            assert_eq!(changes, vec![(1, "two", "2"), (3, "", "four")]);
+            assert!(fixture.store.diff_two_files(&a, 0, &b, 0).unwrap().is_empty());
 
-                    Some(FileVersions {
-                        path,
-                        versions,
-                        hits_of_codes,
-                    })
+            let err = fixture.store.diff_two_files(&a, 3, &b, 0).unwrap_err();
+            assert!(err.to_string().contains("out of range"));
+            let c = fixture.path("c.txt");
+            let err = fixture.store.diff_two_files(&a, 0, &c, 0).unwrap_err();
+            assert_eq!(err.to_string(), format!("{} is not tracked", c));
+        }
+
+        #[test]
+        fn patches_become_versions_without_touching_the_file() {
+            let mut fixture = Fixture::new("apply_patch", &[("a.txt", "one\ntwo\n")]);
+            let path = fixture.path("a.txt");
+            let patch = "@@ -1,2 +1,3 @@\n one\n-two\n+2\n+three\n";
+
+            assert_eq!(fixture.store.apply_patch(&path, patch).unwrap(), 1);
+            assert_eq!(fixture.store.reconstruct(&path, 1).unwrap(), "one\n2\nthree\n");
+            assert_eq!(fs::read_to_string(&path).unwrap(), "one\ntwo\n");
+            let view = fixture.store.view().unwrap();
+            assert_eq!(view[0].as_ref().unwrap().versions[0].source, VersionSource::Patch);
+
+            // made against the baseline, it doesn't match anymore
+            assert!(fixture.store.apply_patch(&path, patch).is_err());
+            assert_eq!(fixture.store.version_count(&path).unwrap(), 2);
+        }
+
+        #[test]
+        fn view_is_ordered_by_path() {
+            let mut fixture = Fixture::new(
+                "view_order",
+                &[("c.txt", "c\n"), ("a.txt", "a\n"), ("b.txt", "b\n")],
+            );
+            let expected = ["a.txt", "b.txt", "c.txt"]
+                .iter()
+                .map(|file| fixture.path(file))
+                .collect_vec();
+            let paths = |store: &mut Store| {
+                store
+                    .view()
+                    .unwrap()
+                    .into_iter()
+                    .flatten()
+                    .map(|file_versions| file_versions.path)
+                    .collect_vec()
+            };
+
+            assert_eq!(paths(&mut fixture.store), expected);
+            fixture.write("a.txt", "a\n1\n");
+            fixture.write("b.txt", "b\n1\n");
+            assert_eq!(paths(&mut fixture.store), expected);
+            assert_eq!(paths(&mut fixture.store), expected);
+        }
+
+        #[test]
+        fn compact_on_open_collects_orphans() {
+            let mut fixture = Fixture::new("compact", &[("a.txt", "one\n")]);
+            fixture.write("a.txt", "one\ntwo\n");
+            let path = fixture.path("a.txt");
+            let compact = StoreOptions {
+                compact_on_open: true,
+                ..StoreOptions::default()
+            };
+            let healthy_size = fs::metadata(fixture.store_path()).unwrap().len();
+            assert_eq!(
+                fixture.reopen(compact.clone()).unwrap().take_gc_report(),
+                None
+            );
+
+            let orphan = |line: usize| {
+                LineDifference::new(path.clone(), line, String::new(), "x".repeat(200))
+            };
+            fixture.store.db.lextend(&path, &[orphan(5), orphan(6)]);
+            fixture
+                .store
+                .db
+                .lcreate("ghost.txt")
+                .unwrap()
+                .lextend(&(0..5).map(orphan).collect_vec());
+            init_file_version_marker("ghost.txt".to_string(), &mut fixture.store.db);
+            assert!(fixture.store.fragmentation() > COMPACT_THRESHOLD);
+
+            let mut store = fixture.reopen(compact).unwrap();
+            let report = store.take_gc_report().unwrap();
+            assert_eq!(
+                (
+                    report.orphaned_lists,
+                    report.orphaned_records,
+                    report.orphaned_markers
+                ),
+                (1, 2, 1)
+            );
+            assert!(report.reclaimed_bytes > 0);
+            assert_eq!(
+                fs::metadata(fixture.store_path()).unwrap().len(),
+                healthy_size
+            );
+            assert_eq!(store.fragmentation(), 0.0);
+            assert_eq!(store.reconstruct(&path, 1).unwrap(), "one\ntwo\n");
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn mode_changes_are_recorded_and_restored() {
+            use std::os::unix::fs::PermissionsExt;
+            let mut fixture = Fixture::new("mode", &[("run.sh", "echo one\n")]);
+            let path = fixture.path("run.sh");
+            let mode = || fs::metadata(&path).unwrap().permissions().mode() & 0o7777;
+            let initial = mode();
+            assert!(!fixture.store.mode_changed(&path));
+
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o751)).unwrap();
+            assert!(fixture.store.mode_changed(&path));
+            fixture.store.store_changes(&path, &[]).unwrap();
+            assert!(!fixture.store.mode_changed(&path));
+            let modes = fixture.store.view().unwrap()[0]
+                .as_ref()
+                .unwrap()
+                .versions
+                .iter()
+                .map(|version| version.mode)
+                .collect_vec();
+            assert_eq!(modes, vec![Some(0o751), Some(initial)]);
+
+            fixture.store.undo_by(path.clone(), 1).unwrap();
+            assert_eq!(mode(), initial);
+            fixture.store.redo_by(path.clone(), 1).unwrap();
+            assert_eq!(mode(), 0o751);
+            assert_eq!(fs::read_to_string(&path).unwrap(), "echo one\n");
+        }
+
+        #[test]
+        fn undo_and_redo_stay_within_the_history() {
+            let mut fixture = Fixture::new("undo_bounds", &[("a.txt", "one\n")]);
+            fixture.write("a.txt", "two\n");
+            fixture.write("a.txt", "three\n");
+            let path = fixture.path("a.txt");
+            let store = &mut fixture.store;
+            assert_eq!(store.version_count(&path).unwrap(), 3);
+            assert_eq!(store.latest_version_index(&path), Some(2));
+            assert!(store.can_undo(&path, 2) && !store.can_undo(&path, 3));
+            assert!(!store.can_redo(&path, 1));
+
+            store.undo_by(path.clone(), 2).unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), "one\n");
+            assert_eq!(store.latest_version_index(&path), Some(0));
+            assert!(!store.can_undo(&path, 1));
+            assert!(store.undo_by(path.clone(), 1).is_err());
+
+            store.redo_by(path.clone(), 1).unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), "two\n");
+            assert!(store.can_redo(&path, 1) && !store.can_redo(&path, 2));
+            assert!(store.redo_by(path.clone(), 2).is_err());
+
+            // the write back is recorded without moving the file off its version
+            store.store_changes_from(&path, &[], VersionSource::Restore).unwrap();
+            assert_eq!(store.latest_version_index(&path), Some(1));
+            assert_eq!(store.version_count(&path).unwrap(), 4);
+            fixture.write("a.txt", "four\n");
+            assert_eq!(fixture.store.latest_version_index(&path), Some(4));
+            assert!(fixture.store.version_count("missing.txt").is_err());
+            assert_eq!(fixture.store.latest_version_index("missing.txt"), None);
+        }
+
+        #[test]
+        fn untracked_paths_differ_from_empty_histories() {
+            let mut fixture = Fixture::new("is_tracked", &[("a.txt", "one\n")]);
+            let (tracked, untracked) = (fixture.path("a.txt"), fixture.path("b.txt"));
+            let store = &mut fixture.store;
+
+            assert!(store.is_tracked(&tracked));
+            assert_eq!(store.get_file_changes(&tracked).unwrap().len(), 1);
+            assert_eq!(store.version_count(&tracked).unwrap(), 1);
+
+            assert!(!store.is_tracked(&untracked));
+            assert!(!store.is_tracked(FILE_VERSION_STACK));
+            assert!(store.get_file_changes(&untracked).is_err());
+            assert!(store.version_count(&untracked).is_err());
+
+            // tracked from now on, but nothing recorded yet
+            store.create_new_file_entry(&untracked).unwrap();
+            assert!(store.is_tracked(&untracked));
+            assert!(store.get_file_changes(&untracked).unwrap().is_empty());
+            assert_eq!(store.version_count(&untracked).unwrap(), 0);
+        }
+
+        #[test]
+        fn prune_keeps_what_the_retention_of_each_file_asks_for() {
+            let mut fixture = Fixture::new("prune", &[("a.txt", "1\n"), ("b.log", "1\n")]);
+            (2..=5).for_each(|n| {
+                fixture.write("a.txt", &format!("{}\n", n));
+                fixture.write("b.log", &format!("{}\n", n));
+            });
+            let (a, b) = (fixture.path("a.txt"), fixture.path("b.log"));
+            fixture.store.undo_by(b.clone(), 3).unwrap();
+            let policy = RetentionPolicy::new(
+                fixture.dir.join("watch").to_str().unwrap(),
+                Retention::KeepLast(2),
+                &[(String::from("*.log"), Retention::KeepLast(1))],
+            )
+            .unwrap();
+
+            // b.log stays at version 1
+            assert_eq!(fixture.store.prune(&policy).unwrap(), 4);
+            assert_eq!(fixture.store.version_count(&a).unwrap(), 2);
+            assert_eq!(fixture.store.reconstruct(&a, 0).unwrap(), "4\n");
+            assert_eq!(fixture.store.reconstruct(&a, 1).unwrap(), "5\n");
+            assert_eq!(fixture.store.version_count(&b).unwrap(), 4);
+            assert_eq!(fixture.store.latest_version_index(&b), Some(0));
+            assert_eq!(fixture.store.reconstruct(&b, 0).unwrap(), "2\n");
+            assert_eq!(fixture.store.prune(&policy).unwrap(), 0);
+        }
+
+        #[test]
+        fn pinned_files_keep_every_version() {
+            let mut fixture = Fixture::new("pinned", &[("a.txt", "1\n"), ("b.txt", "1\n")]);
+            (2..=5).for_each(|n| {
+                fixture.write("a.txt", &format!("{}\n", n));
+                fixture.write("b.txt", &format!("{}\n", n));
+            });
+            let (a, b) = (fixture.path("a.txt"), fixture.path("b.txt"));
+            fixture.store.set_retention(&a, Retention::Forever).unwrap();
+            let untracked = fixture.path("c.txt");
+            assert!(fixture.store.set_retention(&untracked, Retention::Forever).is_err());
+            let policy = RetentionPolicy::new("/", Retention::KeepLast(1), &[]).unwrap();
+
+            assert_eq!(fixture.store.prune(&policy).unwrap(), 4);
+            assert_eq!(fixture.store.version_count(&a).unwrap(), 5);
+            assert_eq!(fixture.store.version_count(&b).unwrap(), 1);
+            fixture.remove("a.txt");
+            assert_eq!(fixture.store.prune_deleted(Duration::ZERO).unwrap(), 0);
+            assert!(fixture.store.working_set_strays(&[fixture.dir.as_path()]).is_empty());
+
+            // b has nothing left to evict, a is kept above the limit
+            let mut store = fixture
+                .reopen(StoreOptions {
+                    max_store_bytes: Some(1),
+                    ..StoreOptions::default()
                 })
-                .collect_vec())
+                .unwrap();
+            assert!(store.is_pinned(&a));
+            fs::write(&b, "6\n").unwrap();
+            let previous = store.get_file_changes(&b).unwrap();
+            let changes = diff::find(&b, &previous, DiffAlgorithm::Lines).unwrap();
+            store.store_changes(&b, &changes).unwrap();
+            assert_eq!(store.version_count(&a).unwrap(), 6);
+            let view = store.view().unwrap();
+            let pinned = view.iter().flatten().find(|file| file.path == a).unwrap();
+            assert_eq!(pinned.retention, Some(Retention::Forever));
+
+            store.clear_retention(&a).unwrap();
+            assert_eq!(store.prune_deleted(Duration::ZERO).unwrap(), 1);
         }
 
-        fn get_versions(&self, version_stack: VersionStack) -> Vec<Version> {
-            if version_stack.timestamps.len() == 1 {
-                return version_stack
-                    .timestamps
-                    .iter()
-                    .map(|timestamp| {
-                        let changes: Vec<LineDifference> = self
-                            .get_file_changes::<LineDifference>(version_stack.path.as_str())
-                            .into_iter()
-                            .sorted_by(|a, b| {
-                                diff::sort(b.date_time.as_str(), a.date_time.as_str())
-                            })
-                            .filter(|e| {
-                                let diff_timestamp = NaiveDateTime::parse_from_str(
-                                    e.date_time.as_str(),
-                                    diff::RFC3339,
-                                )
-                                .unwrap()
-                                .timestamp();
-
-                                diff_timestamp >= *timestamp
-                            })
-                            .collect_vec();
+        #[test]
+        fn csv_export_has_a_row_per_version() {
+            let mut fixture = Fixture::new("export_csv", &[("a.txt", "one\n"), ("b,c.txt", "1\n")]);
+            fixture.write("a.txt", "one\ntwo\n");
+            let a = fixture.path("a.txt");
+            fixture.store.touch(&a, Some(String::from("say \"hi\""))).unwrap();
+            let mut csv = vec![];
+            fixture.store.export_csv(&mut csv).unwrap();
 
-                        Version {
-                            datetime: NaiveDateTime::from_timestamp(*timestamp, 0),
-                            changes,
-                        }
-                    })
-                    .collect_vec();
-            }
+            let csv = String::from_utf8(csv).unwrap();
+            let rows = csv.lines().collect_vec();
+            assert_eq!(rows[0], "path,version,timestamp,added,removed,modified,label");
+            assert_eq!(rows.len(), 5);
+            // the timestamp left out
+            let fields = |row: &str| {
+                let fields = row.split(',').collect_vec();
+                [&fields[..2], &fields[3..]].concat().join(",")
+            };
+            assert_eq!(fields(rows[1]), format!("{},0,1,0,0,", a));
+            assert_eq!(fields(rows[2]), format!("{},1,1,0,0,", a));
+            assert_eq!(fields(rows[3]), format!("{},2,0,0,0,\"say \"\"hi\"\"\"", a));
+            let quoted = format!("\"{}\",0,", fixture.path("b,c.txt"));
+            assert!(rows[4].starts_with(&quoted));
+        }
 
-            version_stack
-                .timestamps
+        #[test]
+        fn timeline_has_the_counts_and_labels_of_every_version() {
+            let mut fixture = Fixture::new("timeline", &[("a.txt", "one\n")]);
+            fixture.write("a.txt", "1\ntwo\n");
+            let a = fixture.path("a.txt");
+            fixture.store.touch(&a, Some(String::from("done"))).unwrap();
+
+            let timeline = fixture.store.timeline(&a);
+            let counts = timeline
                 .iter()
-                .sorted_by(|a, b| Ord::cmp(b, a))
-                .collect_vec()
-                .windows(2)
-                .map(|diff_pairs| {
-                    let changes = self
-                        .get_file_changes::<LineDifference>(version_stack.path.as_str())
-                        .into_iter()
-                        .sorted_by(|a, b| diff::sort(b.date_time.as_str(), a.date_time.as_str()))
-                        .filter(|e| {
-                            let diff_timestamp =
-                                NaiveDateTime::parse_from_str(e.date_time.as_str(), diff::RFC3339)
-                                    .unwrap()
-                                    .timestamp();
-
-                            diff_timestamp <= *diff_pairs[0]
-                                && diff_timestamp > *diff_pairs[1]
-                        })
-                        .collect_vec();
+                .map(|entry| (entry.index, entry.added, entry.removed, entry.modified))
+                .collect_vec();
+            assert_eq!(counts, vec![(0, 1, 0, 0), (1, 1, 0, 1), (2, 0, 0, 0)]);
+            assert_eq!(timeline[2].label.as_deref(), Some("done"));
+            assert!(timeline[0].timestamp <= timeline[2].timestamp);
 
-                    Version {
-                        datetime: NaiveDateTime::from_timestamp(*diff_pairs[0], 0),
-                        changes,
-                    }
+            let json = fixture.store.export_timeline_json(&a).unwrap();
+            let fields = serde_json::from_str::<Vec<serde_json::Value>>(&json).unwrap()[1].clone();
+            let keys = fields.as_object().unwrap().keys().cloned().sorted().collect_vec();
+            assert_eq!(keys, ["added", "index", "label", "modified", "removed", "timestamp"]);
+            assert_eq!(fields["label"], serde_json::Value::Null);
+            assert_eq!(fixture.store.export_timeline_json("/untracked").unwrap(), "[]");
+        }
+
+        #[test]
+        fn export_and_import_the_whole_store() {
+            let mut fixture = Fixture::new("export", &[("a.txt", "one\n"), ("b.txt", "1\n")]);
+            fixture.write("a.txt", "two\n");
+            fixture.write("a.txt", "three\n");
+            fixture.write("b.txt", "2\n");
+            let (a, b) = (fixture.path("a.txt"), fixture.path("b.txt"));
+            fixture.store.touch(&b, Some(String::from("good"))).unwrap();
+            fixture.store.undo_by(a.clone(), 1).unwrap();
+            let debounce = Duration::from_millis(700);
+            fixture.store.set_debounce(&a, Some(debounce)).unwrap();
+            fixture.store.set_retention(&b, Retention::KeepLast(5)).unwrap();
+            fixture.store.disable(&b).unwrap();
+            let link = fixture.path("link.txt");
+            fs::hard_link(&a, &link).unwrap();
+            fixture.store.resolve_hard_link(&link).unwrap();
+            let mut export = vec![];
+            fixture.store.export_all_json(&mut export).unwrap();
+
+            let mut imported = Fixture::new("import", &[("c.txt", "other\n")]);
+            let c = imported.path("c.txt");
+            imported.store.set_debounce(&c, Some(debounce)).unwrap();
+            imported.store.disable(&c).unwrap();
+            imported.store.import_all_json(export.as_slice()).unwrap();
+            assert_eq!(imported.store.debounce_of(&a), Some(debounce));
+            assert_eq!(imported.store.debounce_of(&c), None);
+            assert_eq!(imported.store.retention_of(&b), Some(Retention::KeepLast(5)));
+            assert!(imported.store.is_disabled(&b));
+            assert!(!imported.store.is_disabled(&c));
+            assert_eq!(imported.store.aliases(&a), [link]);
+            assert_eq!(imported.store.watch_roots(), fixture.store.watch_roots());
+            // the import counts as maintenance in the store it went into
+            let view = |store: &mut Store| {
+                let view = store.view().unwrap().into_iter().flatten();
+                let view = view.map(|file_versions| FileVersions {
+                    epoch: 0,
+                    ..file_versions
+                });
+                format!("{:?}", view.collect_vec())
+            };
+            assert_eq!(view(&mut imported.store), view(&mut fixture.store));
+            assert!(imported.store.epoch() > fixture.store.epoch());
+            assert!(!imported.store.is_tracked(&imported.path("c.txt")));
+            assert_eq!(imported.store.reconstruct(&a, 2).unwrap(), "three\n");
+            assert_eq!(imported.store.latest_version_index(&a), Some(1));
+            assert!(imported.store.can_redo(&a, 1));
+
+            let reopened = imported.reopen(StoreOptions::default()).unwrap();
+            assert_eq!(reopened.version_count(&b).unwrap(), 3);
+        }
+
+        #[test]
+        fn restore_into_leaves_the_tracked_file_alone() {
+            let mut fixture = Fixture::new("restore_into", &[("a.txt", "one\n")]);
+            fixture.write("a.txt", "two\n");
+            fixture.remove("a.txt");
+            let path = fixture.path("a.txt");
+            let dest = fixture.dir.join("compare").join("nested").join("a.txt");
+
+            fixture.store.restore_into(&path, 0, &dest).unwrap();
+            assert_eq!(fs::read_to_string(&dest).unwrap(), "one\n");
+            fixture.store.restore_into(&path, 1, &dest).unwrap();
+            assert_eq!(fs::read_to_string(&dest).unwrap(), "two\n");
+            assert!(fixture.store.restore_into(&path, 2, &dest).is_err());
+            assert!(fixture.store.restore_into(&path, 3, &dest).is_err());
+            assert!(!std::path::Path::new(&path).exists());
+            assert_eq!(fixture.store.version_count(&path).unwrap(), 3);
+        }
+
+        #[test]
+        fn restore_latest_writes_back_only_what_differs() {
+            let files = [
+                ("app.toml", "good\n"),
+                ("b.toml", "b\n"),
+                ("c.txt", "c\n"),
+                ("e.toml", "e\n"),
+            ];
+            let mut fixture = Fixture::new("restore_latest", &files);
+            fixture.write("app.toml", "better\n");
+            fs::write(fixture.path("app.toml"), "corrupt").unwrap();
+            fs::write(fixture.path("c.txt"), "changed\n").unwrap();
+            // it can't be written back, the others are restored anyway
+            fs::remove_file(fixture.path("e.toml")).unwrap();
+            fs::create_dir(fixture.path("e.toml")).unwrap();
+
+            let watch_path = fixture.dir.join("watch");
+            let patterns = [String::from("*.toml")];
+            let report = fixture
+                .store
+                .restore_latest_matching(watch_path.to_str().unwrap(), &patterns)
+                .unwrap();
+            assert_eq!(report.restored, [fixture.path("app.toml")]);
+            assert_eq!(report.failed.len(), 1);
+            assert_eq!(report.failed[0].0, fixture.path("e.toml"));
+            assert_eq!(fs::read_to_string(fixture.path("app.toml")).unwrap(), "better\n");
+            assert_eq!(fs::read_to_string(fixture.path("c.txt")).unwrap(), "changed\n");
+            assert_eq!(fixture.store.version_count(&fixture.path("app.toml")).unwrap(), 2);
+
+            fixture.remove("b.toml");
+            assert!(!fixture.store.restore_latest(&fixture.path("b.toml")).unwrap());
+            assert!(!std::path::Path::new(&fixture.path("b.toml")).exists());
+            assert!(fixture.store.restore_latest(&fixture.path("d.toml")).is_err());
+        }
+
+        #[test]
+        fn touch_records_a_labeled_checkpoint() {
+            let mut fixture = Fixture::new("touch", &[("a.txt", "one\n")]);
+            let path = fixture.path("a.txt");
+
+            fixture.store.touch(&path, Some(String::from("works"))).unwrap();
+            fs::write(&path, "two\n").unwrap();
+            fixture.store.touch(&path, None).unwrap();
+            assert!(fixture.store.touch(&fixture.path("b.txt"), None).is_err());
+
+            let versions = fixture.store.view().unwrap().remove(0).unwrap().versions;
+            assert_eq!(versions.len(), 3);
+            assert_eq!(versions[1].source, VersionSource::Checkpoint);
+            assert_eq!(versions[1].label, Some(String::from("works")));
+            assert!(versions[1].changes.is_empty());
+            assert_eq!(versions[0].label, None);
+            assert_eq!(fixture.store.reconstruct(&path, 1).unwrap(), "one\n");
+            assert_eq!(fixture.store.reconstruct(&path, 2).unwrap(), "two\n");
+            assert_eq!(fixture.store.latest_version_index(&path), Some(2));
+        }
+
+        #[test]
+        fn version_meta_is_kept_with_the_store() {
+            let mut fixture = Fixture::new("meta", &[("a.txt", "one\n")]);
+            let path = fixture.path("a.txt");
+            fixture.write("a.txt", "two\n");
+
+            fixture.store.set_version_meta(&path, 1, &[("build", "41")]).unwrap();
+            let meta = [("build", "42"), ("ticket", "AS-7")];
+            fixture.store.set_version_meta(&path, 1, &meta).unwrap();
+            assert!(fixture.store.set_version_meta(&path, 2, &[("build", "43")]).is_err());
+            assert!(fixture.store.version_meta(&fixture.path("b.txt"), 0).is_err());
+
+            let store = fixture.reopen(StoreOptions::default()).unwrap();
+            let meta = store.version_meta(&path, 1).unwrap();
+            assert_eq!(meta.get("build").map(String::as_str), Some("42"));
+            assert_eq!(meta.len(), 2);
+            assert!(store.version_meta(&path, 0).unwrap().is_empty());
+            let versions = fixture.store.view().unwrap().remove(0).unwrap().versions;
+            assert_eq!(versions[0].meta, meta);
+        }
+
+        #[test]
+        fn consecutive_duplicates_merge_into_one_version() {
+            let mut fixture = Fixture::new("duplicates", &[("a.txt", "one\n"), ("b.txt", "b\n")]);
+            let (a, b) = (fixture.path("a.txt"), fixture.path("b.txt"));
+            fixture.write("a.txt", "two\n");
+            fixture.write("a.txt", "two!\n");
+            fixture.write("a.txt", "two\n");
+            // 4 and 5 are the same as 3
+            fixture.write("a.txt", "two\n");
+            fixture.store.touch(&a, Some(String::from("kept"))).unwrap();
+            fixture.write("a.txt", "end\n");
+            fixture.store.undo_by(a.clone(), 2).unwrap();
+            assert!(fixture.store.merge_consecutive_duplicates(Some("b.txt")).is_err());
+
+            assert_eq!(fixture.store.merge_consecutive_duplicates(Some(&a)).unwrap(), 2);
+            let contents = (0..5)
+                .map(|version| fixture.store.reconstruct(&a, version).unwrap())
+                .collect_vec();
+            assert_eq!(contents, ["one\n", "two\n", "two!\n", "two\n", "end\n"]);
+            assert_eq!(fixture.store.version_count(&a).unwrap(), 5);
+            assert_eq!(fixture.store.latest_version_index(&a), Some(3));
+            let versions = fixture.store.view().unwrap().remove(0).unwrap().versions;
+            let merged = versions.iter().find(|version| version.index == 3).unwrap();
+            assert_eq!(merged.source, VersionSource::Watcher);
+            assert_eq!(merged.label, Some(String::from("kept")));
+            assert_eq!(fixture.store.merge_consecutive_duplicates(None).unwrap(), 0);
+
+            fixture.store.touch(&b, None).unwrap();
+            let mut store = fixture
+                .reopen(StoreOptions {
+                    keep_duplicate: KeepDuplicate::Latest,
+                    ..StoreOptions::default()
                 })
-                .collect_vec()
+                .unwrap();
+            assert_eq!(store.merge_consecutive_duplicates(None).unwrap(), 1);
+            assert_eq!(store.version_count(&b).unwrap(), 1);
+            assert_eq!(store.reconstruct(&b, 0).unwrap(), "b\n");
+            assert_eq!(store.latest_version_index(&b), Some(0));
         }
 
-        fn get_version_marker(&self, path: String) -> Option<VersionMarker> {
-            self.db
-                .liter(FILE_VERSION_MARKER)
-                .find(|version_marker| {
-                    let version_marker: VersionMarker = version_marker.get_item().unwrap();
-                    version_marker.path.eq(&path)
+        #[test]
+        fn repair_fixes_what_verify_integrity_finds() {
+            let mut fixture = Fixture::new("fsck", &[("a.txt", "one\n"), ("b.txt", "b\n")]);
+            let (a, b) = (fixture.path("a.txt"), fixture.path("b.txt"));
+            fixture.write("a.txt", "one\ntwo\n");
+            fixture.write("a.txt", "one\ntwo\nthree\n");
+            fixture.write("b.txt", "b\nb\n");
+            assert!(fixture.store.verify_integrity().is_empty());
+            assert_eq!(fixture.store.repair().unwrap(), RepairReport::default());
+
+            // the changes of the latest version of a.txt got lost
+            let changes = fixture.store.get_file_changes(&a).unwrap();
+            fixture.store.db.lrem_list(&a).unwrap();
+            let kept = &changes[..changes.len() - 1];
+            fixture.store.db.lcreate(&a).unwrap().lextend(kept);
+            let marker = fixture.store.get_version_marker(b.clone()).unwrap();
+            fixture.store.set_version_marker(marker, 7);
+            fixture.store.db.lcreate("ghost.txt").unwrap();
+
+            let issues = fixture.store.verify_integrity();
+            assert_eq!(
+                issues,
+                [
+                    IntegrityIssue::DanglingVersion {
+                        path: a.clone(),
+                        version: 2
+                    },
+                    IntegrityIssue::MarkerOutOfRange {
+                        path: b.clone(),
+                        marker: 7,
+                        versions: 2
+                    },
+                    IntegrityIssue::OrphanedList {
+                        path: String::from("ghost.txt")
+                    },
+                ]
+            );
+            let report = fixture.store.repair().unwrap();
+            assert_eq!(report.repaired, issues);
+            assert!(report.remaining.is_empty());
+            assert!(fs::metadata(report.backup.unwrap()).is_ok());
+            assert!(fixture.store.verify_integrity().is_empty());
+            assert_eq!(fixture.store.version_count(&a).unwrap(), 2);
+            assert_eq!(fixture.store.reconstruct(&a, 1).unwrap(), "one\ntwo\n");
+            assert_eq!(fixture.store.latest_version_index(&b), Some(1));
+            let reopened = fixture.reopen(StoreOptions::default()).unwrap();
+            assert!(reopened.verify_integrity().is_empty());
+            drop(reopened);
+
+            // which of two stacks is right is anybody's guess
+            let version_stack = fixture.store.get_version_stack(&b).unwrap();
+            fixture.store.db.ladd(FILE_VERSION_STACK, &version_stack);
+            let report = fixture.store.repair().unwrap();
+            let duplicate = vec![IntegrityIssue::DuplicateStack { path: b.clone() }];
+            assert_eq!(report.remaining, duplicate);
+            assert_eq!(fixture.store.verify_integrity(), duplicate);
+        }
+
+        #[test]
+        fn versions_can_be_dated_with_the_mtime() {
+            let mtime = StoreOptions {
+                timestamp_source: TimestampSource::Mtime,
+                ..StoreOptions::default()
+            };
+            let mut fixture = Fixture::open("mtime", &[], mtime).unwrap();
+            fixture.store.change_time_frame(TimeFrame::ALL);
+            let path = fixture.path("a.txt");
+            let now = SystemTime::now();
+            let write = |content: &str, ago: u64| {
+                fs::write(&path, content).unwrap();
+                let modified = now - std::time::Duration::from_secs(ago);
+                File::options()
+                    .write(true)
+                    .open(&path)
+                    .unwrap()
+                    .set_modified(modified)
+                    .unwrap();
+                modified.duration_since(UNIX_EPOCH).unwrap().as_secs() as i64
+            };
+
+            let created = write("one\n", 7200);
+            fixture.record(&path);
+            let changed = write("two\n", 3600);
+            fixture.record(&path);
+            // older than its previous version
+            write("three\n", 9000);
+            fixture.record(&path);
+
+            let versions = fixture.store.view().unwrap().remove(0).unwrap().versions;
+            let dates = versions
+                .iter()
+                .rev()
+                .map(|version| version.datetime.timestamp())
+                .collect_vec();
+            assert_eq!(dates, [created, changed, changed]);
+        }
+
+        #[test]
+        fn large_files_only_keep_their_changes_with_context() {
+            let big = (0..50).map(|line| format!("line {}\n", line)).collect::<String>();
+            let options = StoreOptions {
+                context_only: Some(ContextOnly {
+                    above_bytes: 100,
+                    context_lines: 1,
+                }),
+                ..StoreOptions::default()
+            };
+            let files = [("big.log", big.as_str()), ("small.txt", "one\n")];
+            let mut fixture = Fixture::open("context_only", &files, options).unwrap();
+            fixture.store.change_time_frame(TimeFrame::ALL);
+            let path = fixture.path("big.log");
+            assert!(fixture.store.get_file_changes(&path).unwrap().is_empty());
+
+            let content = big.replace("line 20\n", "line twenty\n");
+            fs::write(&path, &content).unwrap();
+            let changes = fixture.store.diff_snapshot(&path, content.as_bytes()).unwrap();
+            let source = VersionSource::Watcher;
+            fixture.store.store_snapshot(&path, content.as_bytes(), &changes, source).unwrap();
+            fixture.write("small.txt", "two\n");
+
+            let view = fixture.store.view().unwrap();
+            let big_versions = view[0].as_ref().unwrap();
+            assert!(big_versions.context_only);
+            assert!(big_versions.versions.iter().all(|version| version.context_only));
+            let lines = big_versions.versions[0]
+                .changes
+                .iter()
+                .sorted_by_key(|change| change.line_number)
+                .map(|change| change.changed_line.as_str())
+                .collect_vec();
+            assert_eq!(lines, ["line 19", "line twenty", "line 21"]);
+            assert!(fixture.store.reconstruct(&path, 1).is_err());
+            assert!(!view[1].as_ref().unwrap().context_only);
+            assert_eq!(fixture.store.reconstruct(&fixture.path("small.txt"), 1).unwrap(), "two\n");
+
+            // a file stays context-only after it shrank
+            fs::write(&path, "short\n").unwrap();
+            assert!(fixture.store.is_context_only(&path));
+            let changes = fixture.store.diff_snapshot(&path, b"short\n").unwrap();
+            assert_eq!(changes[0].line, excerpt::UNKNOWN_LINE);
+            assert_eq!(changes.last().unwrap().line_number, 49);
+        }
+
+        #[test]
+        fn pages_of_the_view_load_only_their_files() {
+            let mut fixture = Fixture::new(
+                "view_page",
+                &[("a.txt", "a\n"), ("b.txt", "b\n"), ("c.txt", "c\n")],
+            );
+            fixture.write("b.txt", "b\nb\n");
+            let paths = |files: Vec<FileVersions>| {
+                files
+                    .into_iter()
+                    .map(|file_versions| file_versions.path)
+                    .collect_vec()
+            };
+
+            let view = fixture.store.view().unwrap().into_iter().flatten().collect_vec();
+            let iterated = fixture
+                .store
+                .versions_iter()
+                .map(|(path, file_versions)| {
+                    assert_eq!(path, file_versions.path);
+                    file_versions.clone()
                 })
-                .map(|version_marker| version_marker.get_item().unwrap())
+                .collect_vec();
+            assert_eq!(paths(iterated), paths(view));
+            let page = fixture.store.view_page(1, 1);
+            assert_eq!(paths(page.clone()), [fixture.path("b.txt")]);
+            assert_eq!(page[0].version_count, 2);
+            assert_eq!(paths(fixture.store.view_page(2, 5)), [fixture.path("c.txt")]);
+            assert!(fixture.store.view_page(3, 5).is_empty());
         }
 
-        fn undo(&mut self, path: String, count: usize) -> Result<(), Box<dyn error::Error>> {
-            let versions = self.peek_versions(path.clone(), count, true);
-            let version_marker: VersionMarker = self.get_version_marker(path).unwrap();
+        #[test]
+        fn loaded_views_follow_the_changes_of_their_files() {
+            let mut fixture = Fixture::new("view_cache", &[("a.txt", "a\n"), ("b.txt", "b\n")]);
+            let a = fixture.path("a.txt");
+            assert_eq!(fixture.store.view_page(0, 2)[0].version_count, 1);
 
-            self.decrement_version_marker_by(version_marker, count);
-            self.undo_versions(versions)
+            fixture.write("a.txt", "a\na\n");
+            let debounce = Duration::from_millis(700);
+            fixture.store.set_debounce(&a, Some(debounce)).unwrap();
+            let page = fixture.store.view_page(0, 1);
+            assert_eq!(page[0].version_count, 2);
+            assert_eq!(page[0].versions.len(), 2);
+            assert_eq!(page[0].debounce, Some(debounce));
+            assert_eq!(fixture.store.file_view(&a).unwrap().version_count, 2);
+            assert_eq!(fixture.store.visible_paths(), [a, fixture.path("b.txt")]);
         }
 
-        fn decrement_version_marker_by(&mut self, mut version_marker: VersionMarker, count: usize) {
-            self.db
-                .lrem_value(FILE_VERSION_MARKER, &version_marker)
-                .unwrap();
-            version_marker.timestamp_marker -= count;
-            self.db.ladd(FILE_VERSION_MARKER, &version_marker);
+        #[test]
+        fn rewritten_paths_keep_their_history() {
+            let mut fixture = Fixture::new("rewrite", &[("a.txt", "one\n"), ("b.txt", "b\n")]);
+            fixture.write("a.txt", "one\ntwo\n");
+            fixture.write("a.txt", "one\ntwo\nthree\n");
+            let a = fixture.path("a.txt");
+            fixture.store.undo_by(a.clone(), 1).unwrap();
+            let old = fixture.dir.join("watch");
+            let new = fixture.dir.join("moved");
+            let (old, new) = (old.to_str().unwrap(), new.to_str().unwrap());
+            let link = fixture.path("link.txt");
+            fs::hard_link(&a, &link).unwrap();
+            assert_eq!(fixture.store.resolve_hard_link(&link).unwrap(), a);
+            fixture.store.disable(&fixture.path("b.txt")).unwrap();
+
+            assert_eq!(fixture.store.rewrite_paths(old, new).unwrap(), 2);
+            assert!(!fixture.store.is_tracked(&a));
+            let moved = format!("{}/a.txt", new);
+            let moved_link = format!("{}/link.txt", new);
+            assert_eq!(fixture.store.canonical_path(&moved_link), moved);
+            assert_eq!(fixture.store.aliases(&moved), [moved_link]);
+            assert_eq!(fixture.store.canonical_path(&link), link);
+            assert!(fixture.store.is_disabled(&format!("{}/b.txt", new)));
+            assert_eq!(fixture.store.version_count(&moved).unwrap(), 3);
+            assert_eq!(fixture.store.reconstruct(&moved, 2).unwrap(), "one\ntwo\nthree\n");
+            assert_eq!(fixture.store.latest_version_index(&moved), Some(1));
+            let b = format!("{}/b.txt", new);
+            assert_eq!(fixture.store.reconstruct(&b, 0).unwrap(), "b\n");
+            assert!(fixture
+                .store
+                .get_file_changes(&moved)
+                .unwrap()
+                .iter()
+                .all(|change| change.path == moved));
+
+            // a prefix is a whole path component
+            assert_eq!(fixture.store.rewrite_paths(&new[..new.len() - 1], old).unwrap(), 0);
+            fixture.store.create_new_file_entry(&a).unwrap();
+            assert!(fixture.store.rewrite_paths(new, old).is_err());
+            assert!(fixture.store.is_tracked(&moved));
         }
 
-        fn increment_version_marker_by(&mut self, mut version_marker: VersionMarker, count: usize) {
-            self.db
-                .lrem_value(FILE_VERSION_MARKER, &version_marker)
+        #[test]
+        fn moved_files_are_matched_by_content() {
+            let files = [
+                ("a.txt", "one\n"),
+                ("b.txt", "same\n"),
+                ("c.txt", "same\n"),
+                ("e.txt", ""),
+            ];
+            let mut fixture = Fixture::new("find_moved_files", &files);
+            fixture.write("a.txt", "one\ntwo\n");
+            ["a.txt", "b.txt", "c.txt", "e.txt"].iter().for_each(|file| {
+                fs::remove_file(fixture.path(file)).unwrap();
+            });
+            fs::write(fixture.path("moved_a.txt"), "one\ntwo\n").unwrap();
+            // two files went with the same content, which one moved can't be told
+            fs::write(fixture.path("moved_b.txt"), "same\n").unwrap();
+            fs::write(fixture.path("moved_e.txt"), "").unwrap();
+
+            let (a, moved_a) = (fixture.path("a.txt"), fixture.path("moved_a.txt"));
+            let moves = fixture.store.find_moved_files();
+            assert_eq!(moves, vec![(a.clone(), moved_a.clone())]);
+            let debounce = Duration::from_millis(500);
+            fixture.store.set_debounce(&a, Some(debounce)).unwrap();
+            fixture.store.set_retention(&a, Retention::Forever).unwrap();
+            fixture.store.disable(&a).unwrap();
+            fixture.store.move_history(&a, &moved_a).unwrap();
+            assert_eq!(fixture.store.reconstruct(&moved_a, 1).unwrap(), "one\ntwo\n");
+            assert_eq!(fixture.store.debounce_of(&moved_a), Some(debounce));
+            assert_eq!(fixture.store.debounce_of(&a), None);
+            assert!(fixture.store.is_pinned(&moved_a));
+            assert!(fixture.store.is_disabled(&moved_a));
+            assert!(!fixture.store.is_disabled(&a));
+            assert!(!fixture.store.is_tracked(&a));
+            assert!(fixture.store.find_moved_files().is_empty());
+            assert!(fixture.store.move_history(&a, &moved_a).is_err());
+        }
+
+        #[test]
+        fn histories_move_to_another_store() {
+            let mut from = Fixture::new("move_from_store", &[("a.txt", "one\n")]);
+            let mut to = Fixture::new("move_to_store", &[("b.txt", "b\n")]);
+            from.write("a.txt", "one\ntwo\n");
+            let (a, moved) = (from.path("a.txt"), to.path("moved.txt"));
+            fs::remove_file(&a).unwrap();
+            fs::write(&moved, "one\ntwo\n").unwrap();
+
+            let gone = from.store.gone_files();
+            assert_eq!(gone[&b"one\ntwo\n"[..]], vec![a.clone()]);
+            let untracked = to.store.untracked_files(&gone.keys().map(Vec::len).collect());
+            assert_eq!(untracked[&b"one\ntwo\n"[..]], vec![moved.clone()]);
+
+            from.store.move_history_to(&a, &mut to.store, &moved).unwrap();
+            assert!(!from.store.is_tracked(&a));
+            assert_eq!(to.store.version_count(&moved).unwrap(), 2);
+            assert_eq!(to.store.reconstruct(&moved, 0).unwrap(), "one\n");
+            assert_eq!(to.store.reconstruct(&moved, 1).unwrap(), "one\ntwo\n");
+            assert!(from.store.move_history_to(&a, &mut to.store, &moved).is_err());
+            let reopened = to.reopen(StoreOptions::default()).unwrap();
+            assert_eq!(reopened.reconstruct(&moved, 1).unwrap(), "one\ntwo\n");
+        }
+
+        #[test]
+        fn moved_files_edited_meanwhile_are_matched_if_alike_enough() {
+            let long = (1..=15).map(|line| format!("line {}\n", line)).collect::<String>();
+            let files = [("long.txt", long.as_str()), ("main.rs", "fn main() {\n}\n")];
+            let moved = long.replace("line 15\n", "line fifteen\n");
+            let reorganize = |fixture: &Fixture| {
+                fs::remove_file(fixture.path("long.txt")).unwrap();
+                fs::remove_file(fixture.path("main.rs")).unwrap();
+                fs::write(fixture.path("moved_long.txt"), &moved).unwrap();
+                // boilerplate that only looks like a rename
+                fs::write(fixture.path("lib.rs"), "fn main() {\n    run();\n}\n").unwrap();
+            };
+
+            let fixture = Fixture::new("find_alike_files", &files);
+            reorganize(&fixture);
+            let (long, moved_long) = (fixture.path("long.txt"), fixture.path("moved_long.txt"));
+            assert_eq!(fixture.store.find_moved_files(), vec![(long, moved_long)]);
+
+            let exact = StoreOptions {
+                rename_detection: RenameDetection {
+                    similarity_threshold: 1.0,
+                    ..RenameDetection::default()
+                },
+                ..StoreOptions::default()
+            };
+            let fixture = Fixture::open("find_identical_files", &files, exact).unwrap();
+            reorganize(&fixture);
+            assert!(fixture.store.find_moved_files().is_empty());
+
+            let sizes = RenameDetection::default();
+            assert!(sizes.sizes_match(100, 120) && !sizes.sizes_match(100, 121));
+            assert!(!sizes.sizes_match(0, 0));
+            let text = "line 1\nline 2\n".repeat(10);
+            let binary = [text.as_bytes(), &[0xff]].concat();
+            assert!(sizes.is_alike(text.as_bytes(), text.as_bytes()));
+            assert!(!sizes.is_alike(text.as_bytes(), &binary));
+            assert!(!sizes.is_alike(text.as_bytes(), text.replace("line", "item").as_bytes()));
+        }
+
+        #[test]
+        fn forked_histories_diverge() {
+            let mut fixture = Fixture::new("fork", &[("a.txt", "one\n"), ("c.txt", "c\n")]);
+            fixture.write("a.txt", "one\ntwo\n");
+            let (a, b) = (fixture.path("a.txt"), fixture.path("b.txt"));
+
+            fixture.store.fork(&a, &b).unwrap();
+            fs::copy(&a, &b).unwrap();
+            fixture.write("b.txt", "one\ntwo\nthree\n");
+            fixture.write("a.txt", "zero\none\ntwo\n");
+            assert_eq!(fixture.store.version_count(&b).unwrap(), 3);
+            assert_eq!(fixture.store.reconstruct(&b, 1).unwrap(), "one\ntwo\n");
+            assert_eq!(fixture.store.reconstruct(&b, 2).unwrap(), "one\ntwo\nthree\n");
+            assert_eq!(fixture.store.version_count(&a).unwrap(), 3);
+            assert_eq!(fixture.store.reconstruct(&a, 2).unwrap(), "zero\none\ntwo\n");
+
+            assert!(fixture.store.fork(&a, &fixture.path("c.txt")).is_err());
+            assert!(fixture.store.fork(&fixture.path("d.txt"), &b).is_err());
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn hard_links_share_one_history() {
+            let mut fixture = Fixture::new("hard_links", &[("a.txt", "one\n"), ("c.txt", "c\n")]);
+            let (a, b, c) = (fixture.path("a.txt"), fixture.path("b.txt"), fixture.path("c.txt"));
+            fs::hard_link(&a, &b).unwrap();
+            assert_eq!(fixture.store.resolve_hard_link(&b).unwrap(), a);
+            assert_eq!(fixture.store.resolve_hard_link(&c).unwrap(), c);
+            assert_eq!(fixture.store.aliases(&a), vec![b.clone()]);
+            fixture.write("a.txt", "one\ntwo\n");
+            assert!(!fixture.store.is_tracked(&b));
+
+            // a new store scans one of the links only
+            let scanned = fixture.dir.join("scanned.db");
+            let scanned = Store::open(
+                scanned.to_str().unwrap(),
+                fixture.dir.join("watch").to_str().unwrap(),
+                StoreOptions::default(),
+            )
+            .unwrap();
+            assert_eq!(scanned.tracked_paths().len(), 2);
+            let canonical = scanned.canonical_path(&b);
+            assert_eq!(scanned.canonical_path(&a), canonical);
+            assert_eq!(scanned.aliases(&canonical).len(), 1);
+
+            // the history moves to the link left over
+            fs::remove_file(&a).unwrap();
+            assert!(fixture.store.unlink(&a).unwrap());
+            assert!(!fixture.store.is_tracked(&a));
+            assert_eq!(fixture.store.reconstruct(&b, 1).unwrap(), "one\ntwo\n");
+            assert_eq!(fixture.store.canonical_path(&b), b);
+            assert!(!fixture.store.unlink(&b).unwrap());
+
+            // saving through a temp file breaks the link
+            let d = fixture.path("d.txt");
+            fs::hard_link(&c, &d).unwrap();
+            assert_eq!(fixture.store.resolve_hard_link(&d).unwrap(), c);
+            fs::write(fixture.path("d.txt.tmp"), "d\n").unwrap();
+            fs::rename(fixture.path("d.txt.tmp"), &d).unwrap();
+            assert_eq!(fixture.store.resolve_hard_link(&d).unwrap(), d);
+            assert!(fixture.store.aliases(&c).is_empty());
+
+            // a link to a file nothing tracks is looked up once
+            let outside = fixture.dir.join("outside.txt");
+            fs::write(&outside, "e\n").unwrap();
+            let e = fixture.path("e.txt");
+            fs::hard_link(&outside, &e).unwrap();
+            assert_eq!(fixture.store.resolve_hard_link(&e).unwrap(), e);
+            assert!(fixture.store.not_hard_linked.contains_key(&e));
+            assert_eq!(fixture.store.resolve_hard_link(&e).unwrap(), e);
+        }
+
+        #[test]
+        fn versions_touching_a_line_follow_its_shifts() {
+            let mut fixture = Fixture::new("touching_line", &[("a.txt", "a\nb\nc\n")]);
+            // 1: changes b
+            fixture.write("a.txt", "a\nB\nc\n");
+            // 2: inserts above b, which moves to line 2
+            fixture.write("a.txt", "x\ny\na\nB\nc\n");
+            // 3: changes c only
+            fixture.write("a.txt", "x\ny\na\nB\nC\n");
+            // 4: removes x and changes b again, now on line 2
+            fixture.write("a.txt", "y\na\nbb\nC\n");
+            let path = fixture.path("a.txt");
+
+            assert_eq!(fixture.store.versions_touching_line(&path, 2), vec![0, 1, 4]);
+            assert_eq!(fixture.store.versions_touching_line(&path, 3), vec![0, 3]);
+            assert_eq!(fixture.store.versions_touching_line(&path, 0), vec![2]);
+            assert!(fixture.store.versions_touching_line(&path, 4).is_empty());
+            assert!(fixture.store.versions_touching_line("untracked", 0).is_empty());
+        }
+
+        #[test]
+        fn diff_stat_sums_up_every_version() {
+            let mut fixture = Fixture::new(
+                "diff_stat",
+                &[("a.txt", "one\ntwo\n"), ("b.txt", "one\n")],
+            );
+            fixture.write("a.txt", "one\n2\nthree\n");
+            fixture.write("a.txt", "one\n");
+            fixture.write("b.txt", "1\n");
+
+            let stat = fixture.store.diff_stat();
+            let summary = |added, removed, modified| DiffSummary {
+                added,
+                removed,
+                modified,
+            };
+            assert_eq!(stat.total, summary(1, 2, 2));
+            assert_eq!(
+                stat.per_file,
+                vec![
+                    (fixture.path("a.txt"), summary(1, 2, 1)),
+                    (fixture.path("b.txt"), summary(0, 0, 1)),
+                ]
+            );
+            assert_eq!(stat.per_day.len(), 1);
+            assert_eq!(stat.per_day[0].1, stat.total);
+            assert_eq!(stat.top_files(1), vec![(fixture.path("a.txt"), summary(1, 2, 1))]);
+
+            // the stats of another store add up per day and per file
+            let mut combined = stat.clone();
+            combined += stat.clone();
+            assert_eq!(combined.total, summary(2, 4, 4));
+            assert_eq!(combined.per_day, vec![(stat.per_day[0].0, summary(2, 4, 4))]);
+            assert_eq!(combined.per_file.len(), 4);
+        }
+
+        #[test]
+        fn diff_summary_of_a_single_version() {
+            let mut fixture = Fixture::new(
+                "diff_summary",
+                &[("a.txt", "one\ntwo\n"), ("b.txt", "1\n2\n3\n")],
+            );
+            fixture.write("a.txt", "one\n2\nthree\n");
+            fixture.remove("b.txt");
+            let (a, b) = (fixture.path("a.txt"), fixture.path("b.txt"));
+            let summary = |path, version| fixture.store.diff_summary_for_version(path, version);
+
+            assert_eq!(summary(&a, 0).to_string(), "+2 -0 ~0");
+            assert_eq!(summary(&a, 1).to_string(), "+1 -0 ~1");
+            assert_eq!(summary(&a, 2), DiffSummary::default());
+            assert_eq!(summary(&b, 1).to_string(), "+0 -3 ~0");
+            assert_eq!(
+                fixture.store.diff_summary_for_version("untracked", 0),
+                DiffSummary::default()
+            );
+
+            // the line edits are replayed to tell what they changed
+            let line_edits = StoreOptions {
+                line_edits: true,
+                ..StoreOptions::default()
+            };
+            let long = &[("long.txt", long_lines(0))];
+            let long = long.iter().map(|(file, content)| (*file, content.as_str()));
+            let mut fixture = Fixture::open("diff_summary_edits", &long.collect_vec(), line_edits)
                 .unwrap();
-            version_marker.timestamp_marker += count;
-            self.db.ladd(FILE_VERSION_MARKER, &version_marker);
+            fixture.write("long.txt", &long_lines(1));
+            let path = fixture.path("long.txt");
+            assert_eq!(fixture.store.diff_summary_for_version(&path, 1).to_string(), "+0 -0 ~2");
+            let latest = fixture.store.latest_n_versions(&path, 1);
+            assert_eq!(latest[0].summary.to_string(), "+0 -0 ~2");
         }
 
-        fn redo(&mut self, path: String, count: usize) -> Result<(), Box<dyn error::Error>> {
-            let versions = self.peek_versions(path.clone(), count, true);
-            let version_marker: VersionMarker = self.get_version_marker(path).unwrap();
+        #[test]
+        fn latest_n_versions_are_summed_up_the_latest_first() {
+            let mut fixture = Fixture::new("latest_n_versions", &[("a.txt", "one\ntwo\n")]);
+            fixture.write("a.txt", "one\n2\nthree\n");
+            fixture.write("a.txt", "one\n");
+            let a = fixture.path("a.txt");
 
-            self.increment_version_marker_by(version_marker, count);
-            self.undo_versions(versions)
+            fixture.store.touch(&a, Some(String::from("good"))).unwrap();
+            let latest = fixture.store.latest_n_versions(&a, 3);
+            let summaries = latest
+                .iter()
+                .map(|version| (version.index, version.summary.to_string()))
+                .collect_vec();
+            assert_eq!(
+                summaries,
+                vec![
+                    (3, String::from("+0 -0 ~0")),
+                    (2, String::from("+0 -2 ~0")),
+                    (1, String::from("+1 -0 ~1"))
+                ]
+            );
+            let view = fixture.store.view().unwrap().into_iter().flatten().next().unwrap();
+            for (listed, version) in latest.iter().zip(&view.versions) {
+                assert_eq!(listed.datetime, version.datetime);
+                assert_eq!(listed.source, version.source);
+                assert_eq!(listed.label, version.label);
+                let version = version.index;
+                assert_eq!(listed.summary, fixture.store.diff_summary_for_version(&a, version));
+            }
+            assert_eq!(fixture.store.latest_n_versions(&a, 10).len(), 4);
+            assert!(fixture.store.latest_n_versions(&a, 0).is_empty());
+            assert!(fixture.store.latest_n_versions("untracked", 2).is_empty());
         }
 
-        pub fn create_new_file_entry(&mut self, path: &str) -> Result<(), Box<dyn error::Error>> {
-            if !self.db.lexists(path) {
-                self.db.lcreate(path)?;
+        /// every line of the `versions` first versions of `path` and one past them
+        fn assert_lines_match(store: &Store, path: &str, versions: usize) {
+            for version in 0..versions {
+                let content = store.reconstruct(path, version).unwrap();
+                let lines = content.lines().map(Some).chain([None]).collect_vec();
+                for (line, expected) in lines.into_iter().enumerate() {
+                    let at_line = store.content_at_line(path, version, line).unwrap();
+                    assert_eq!(at_line.as_deref(), expected, "line {} of {}", line, version);
+                }
             }
+        }
 
-            Ok(())
+        #[test]
+        fn content_at_line_matches_the_rebuilt_version() {
+            let mut fixture = Fixture::new("content_at_line", &[("a.txt", "one\ntwo\nthree\n")]);
+            fixture.write("a.txt", "one\n2\nthree\nfour\n");
+            fixture.write("a.txt", "zero\none\n2\n");
+            fixture.write("a.txt", "");
+            let a = fixture.path("a.txt");
+            assert_lines_match(&fixture.store, &a, 4);
+            assert!(fixture.store.content_at_line(&a, 4, 0).is_err());
+            assert!(fixture.store.content_at_line("untracked", 0, 0).is_err());
+
+            // line edits are applied to the full line they were made on
+            let line_edits = StoreOptions {
+                line_edits: true,
+                ..StoreOptions::default()
+            };
+            let long = long_lines(0);
+            let mut fixture =
+                Fixture::open("content_at_line_edits", &[("long.txt", &long)], line_edits).unwrap();
+            [1, 6, 2].iter().for_each(|edit| fixture.write("long.txt", &long_lines(*edit)));
+            let path = fixture.path("long.txt");
+            assert_lines_match(&fixture.store, &path, 4);
         }
 
-        pub fn get_file_changes<T: DeserializeOwned + std::fmt::Debug>(
-            &self,
-            path: &str,
-        ) -> Vec<T> {
-            self.db
-                .liter(path)
-                .map(|e| e.get_item::<T>().unwrap())
-                .collect()
+        #[test]
+        fn moved_blocks_replay_to_the_content() {
+            let detect_moves = StoreOptions {
+                detect_moves: true,
+                ..StoreOptions::default()
+            };
+            let blocks = ["fn a() {\n    1\n}\n", "fn b() {\n    2\n}\n", "fn c() {\n    3\n}\n"];
+            let content = |order: &[usize]| order.iter().map(|block| blocks[*block]).join("");
+            let mut fixture =
+                Fixture::open("moved_blocks", &[("a.rs", &content(&[0, 1, 2]))], detect_moves)
+                    .unwrap();
+            let path = fixture.path("a.rs");
+            let versions = [
+                content(&[2, 0, 1]),
+                content(&[2, 1, 0]),
+                format!("// top\n{}", content(&[1, 2, 0])),
+            ];
+            for version in &versions {
+                fs::write(&path, version).unwrap();
+                let changes = fixture.store.diff_snapshot(&path, version.as_bytes()).unwrap();
+                fixture
+                    .store
+                    .store_snapshot(&path, version.as_bytes(), &changes, VersionSource::Watcher)
+                    .unwrap();
+            }
+
+            // putting the last function first is a single move
+            assert_eq!(fixture.store.diff_summary_for_version(&path, 1).churn(), 0);
+            for (version, expected) in versions.iter().enumerate() {
+                assert_eq!(fixture.store.reconstruct(&path, version + 1).unwrap(), *expected);
+            }
+            assert_lines_match(&fixture.store, &path, 4);
+
+            // the moves before the baseline are replayed into it
+            fixture.store.set_baseline(&path, 2).unwrap();
+            assert_eq!(fixture.store.reconstruct(&path, 0).unwrap(), versions[1]);
+            assert_eq!(fixture.store.reconstruct(&path, 1).unwrap(), versions[2]);
+            assert_lines_match(&fixture.store, &path, 2);
         }
 
-        fn peek_versions(&mut self, path: String, count: usize, previous: bool) -> Vec<Version> {
-            let version_marker = self.get_version_marker(path.clone()).unwrap();
-            let pos;
-            if previous {
-                pos = version_marker.timestamp_marker - count;
-            } else {
-                pos = version_marker.timestamp_marker;
+        #[test]
+        fn aligned_versions_replay_to_the_content() {
+            let patience = StoreOptions {
+                diff_algorithm: DiffAlgorithm::Patience,
+                ..StoreOptions::default()
+            };
+            let mut fixture =
+                Fixture::open("aligned", &[("a.txt", "a\nb\nc\nd\n")], patience).unwrap();
+            let path = fixture.path("a.txt");
+            let versions = ["top\na\nb\nc\nd\n", "top\na\nc\nd\nD\n", "c\nd\n"];
+            for version in &versions {
+                fs::write(&path, version).unwrap();
+                let changes = fixture.store.diff_snapshot(&path, version.as_bytes()).unwrap();
+                fixture
+                    .store
+                    .store_snapshot(&path, version.as_bytes(), &changes, VersionSource::Watcher)
+                    .unwrap();
             }
 
-            let mut version_stack = self
-                .db
-                .liter(FILE_VERSION_STACK)
-                .map(|version_stack| version_stack.get_item().unwrap())
-                .find(|version_stack: &VersionStack| version_stack.path.eq(&path))
-                .unwrap();
+            // the line on top leaves the lines below it alone
+            assert_eq!(fixture.store.diff_summary_for_version(&path, 1).churn(), 1);
+            for (version, expected) in versions.iter().enumerate() {
+                assert_eq!(fixture.store.reconstruct(&path, version + 1).unwrap(), *expected);
+            }
+            assert_lines_match(&fixture.store, &path, 4);
+        }
 
-            version_stack.timestamps = version_stack
-                .timestamps
+        #[test]
+        fn long_lines_are_kept_whole() {
+            let options = StoreOptions {
+                max_line_length: Some(16),
+                line_edits: true,
+                ..StoreOptions::default()
+            };
+            let mut fixture =
+                Fixture::open("long_lines", &[("a.js", "let a = 1;\n")], options).unwrap();
+            let path = fixture.path("a.js");
+            let versions = [
+                "let a = 1; let b = 2; let c = 3;\nlet d = 4;\n",
+                "let a = 1; let b = 5; let c = 3;\n",
+                "let a = 1;\nlet d = 4;",
+            ];
+            for version in &versions {
+                fs::write(&path, version).unwrap();
+                let changes = fixture.store.diff_snapshot(&path, version.as_bytes()).unwrap();
+                fixture
+                    .store
+                    .store_snapshot(&path, version.as_bytes(), &changes, VersionSource::Watcher)
+                    .unwrap();
+            }
+
+            for (version, expected) in versions.iter().enumerate() {
+                assert_eq!(fixture.store.reconstruct(&path, version + 1).unwrap(), *expected);
+            }
+            assert_lines_match(&fixture.store, &path, 4);
+            // the latest first
+            let file_versions = fixture.store.view().unwrap().remove(0).unwrap();
+            let long_lines = file_versions.versions.iter().map(|version| version.long_lines);
+            assert_eq!(long_lines.collect_vec(), vec![false, true, true, false]);
+            assert!(file_versions.long_lines);
+            // a snapshot is summed up against the version before it
+            let summary = |added, removed, modified| DiffSummary {
+                added,
+                removed,
+                modified,
+            };
+            assert_eq!(fixture.store.diff_summary_for_version(&path, 1), summary(1, 0, 1));
+            assert_eq!(fixture.store.diff_summary_for_version(&path, 2), summary(0, 1, 1));
+            let listed = fixture.store.latest_n_versions(&path, 3);
+            let listed = listed.iter().map(|version| version.summary).collect_vec();
+            assert_eq!(listed[1..], [summary(0, 1, 1), summary(1, 0, 1)]);
+            // a snapshot doesn't keep the lines it replaced
+            let snapshot = &file_versions.versions[1].changes;
+            assert!(snapshot.iter().all(|change| change.line.is_empty() && change.edit.is_none()));
+            assert_eq!(snapshot.len(), 1);
+        }
+
+        #[test]
+        fn disabled_files_catch_up_once_enabled() {
+            let files = [("a.txt", "one\n"), ("b.log", "1\n"), ("c.log", "i\n")];
+            let mut fixture = Fixture::new("disabled", &files);
+            let (a, b) = (fixture.path("a.txt"), fixture.path("b.log"));
+            fixture.store.disable(&a).unwrap();
+            fixture.store.disable_matching("*.log").unwrap();
+            assert!(fixture.store.disable_matching("[").is_err());
+
+            // the list is kept in the store
+            let mut reopened = fixture.reopen(StoreOptions::default()).unwrap();
+            assert!(reopened.is_disabled(&a) && reopened.is_disabled(&b));
+            assert!(reopened.view().unwrap().into_iter().flatten().all(|file| file.disabled));
+            fs::write(&a, "two\n").unwrap();
+            fs::write(&a, "three\n").unwrap();
+            fs::write(&b, "2\n").unwrap();
+            assert!(reopened.enable(&b).is_err());
+
+            assert!(reopened.enable(&a).unwrap());
+            assert!(!reopened.enable(&a).unwrap());
+            assert_eq!(reopened.version_count(&a).unwrap(), 2);
+            assert_eq!(reopened.reconstruct(&a, 1).unwrap(), "three\n");
+            assert_eq!(reopened.enable_matching("*.log").unwrap(), vec![b.clone()]);
+            assert!(reopened.disabled_patterns().is_empty());
+            assert!(reopened.enable_matching("*.log").is_err());
+            assert_eq!(reopened.version_count(&fixture.path("c.log")).unwrap(), 1);
+            assert!(!reopened.is_disabled(&b));
+            // the compiled patterns follow the ones in the store
+            reopened.disable_matching("*.txt").unwrap();
+            assert!(reopened.is_disabled(&a) && !reopened.is_disabled(&b));
+        }
+
+        #[test]
+        fn debounces_are_kept_per_file() {
+            let mut fixture = Fixture::new("debounces", &[("a.txt", "one\n"), ("b.txt", "1\n")]);
+            let (a, b) = (fixture.path("a.txt"), fixture.path("b.txt"));
+            let debounce = Duration::from_millis(2000);
+            fixture.store.set_debounce(&a, Some(debounce)).unwrap();
+
+            let mut reopened = fixture.reopen(StoreOptions::default()).unwrap();
+            assert_eq!(reopened.debounce_of(&a), Some(debounce));
+            assert_eq!(reopened.debounce_of(&b), None);
+            let debounces = reopened
+                .view()
+                .unwrap()
                 .into_iter()
-                .skip(pos)
-                .take(count)
+                .flatten()
+                .map(|file| (file.path, file.debounce))
+                .sorted()
                 .collect_vec();
+            assert_eq!(debounces, vec![(a.clone(), Some(debounce)), (b.clone(), None)]);
 
-            self.get_versions(
-                self.db
-                    .liter(FILE_VERSION_STACK)
-                    .map(|version_stack| version_stack.get_item().unwrap())
-                    .find(|version_stack: &VersionStack| version_stack.path.eq(&path))
-                    .unwrap(),
-            )
+            reopened.set_debounce(&b, Some(debounce)).unwrap();
+            reopened.set_debounce(&b, None).unwrap();
+            assert_eq!(reopened.debounce_of(&b), None);
+            reopened.purge(&a).unwrap();
+            assert_eq!(reopened.debounce_of(&a), None);
         }
 
-        fn undo_versions(&self, versions: Vec<Version>) -> Result<(), Box<dyn error::Error>> {
-            versions.iter().for_each(|version| {
-                // TODO: propagate error
-                self.undo_changes(&version.changes).unwrap()
-            });
-            Ok(())
+        #[test]
+        fn tracked_paths_flag_deleted_files() {
+            let mut fixture =
+                Fixture::new("tracked_paths", &[("a.txt", "one\n"), ("b.txt", "two\n")]);
+            fixture.remove("b.txt");
+
+            let tracked = fixture
+                .store
+                .tracked_paths()
+                .into_iter()
+                .sorted_by(|a, b| a.path.cmp(&b.path))
+                .collect_vec();
+            assert_eq!(
+                tracked,
+                vec![
+                    TrackedPath {
+                        path: fixture.path("a.txt"),
+                        deleted: false
+                    },
+                    TrackedPath {
+                        path: fixture.path("b.txt"),
+                        deleted: true
+                    },
+                ]
+            );
         }
 
-        fn undo_changes(&self, changes: &[LineDifference]) -> Result<(), Box<dyn error::Error>> {
-            let path = changes.first().unwrap().path.clone();
-            let file = File::open(path.clone())?;
-            let lines = io::BufReader::new(file).lines().collect_vec();
-            let mut redone_lines: Vec<String> = vec![];
+        #[test]
+        fn prune_deleted_forgets_files_removed_long_enough_ago() {
+            let mut fixture =
+                Fixture::new("prune_deleted", &[("a.txt", "one\n"), ("b.txt", "two\n")]);
+            fixture.remove("b.txt");
 
-            if lines.is_empty() {
-                redone_lines.push(
-                    changes
-                        .iter()
-                        .find(|l| l.line_number.eq(&0))
-                        .unwrap()
-                        .line
-                        .clone(),
-                );
-            } else {
-                redone_lines = lines
-                    .iter()
-                    .map(|l| l.as_ref().unwrap())
-                    .enumerate()
-                    .map(|(index, line)| {
-                        let found = changes.iter().find(|l| l.line_number.eq(&index));
-                        if let Some(found) = found {
-                            return found.line.clone();
-                        }
-                        line.clone()
-                    })
-                    .collect();
-            }
+            assert_eq!(fixture.store.prune_deleted(Duration::from_secs(60)).unwrap(), 0);
+            assert!(fixture.store.is_tracked(&fixture.path("b.txt")));
+            assert_eq!(fixture.store.prune_deleted(Duration::ZERO).unwrap(), 1);
+            assert!(!fixture.store.is_tracked(&fixture.path("b.txt")));
+            assert!(fixture.store.is_tracked(&fixture.path("a.txt")));
+            assert_eq!(fixture.store.view().unwrap().len(), 1);
+        }
+
+        #[test]
+        fn trimming_forgets_the_files_gone_from_disk() {
+            let files = [("a.txt", "a\n"), ("b.txt", "b\n"), ("c.txt", "c\n")];
+            let mut fixture = Fixture::new("trim", &files);
+            fixture.remove("b.txt");
+            fs::remove_file(fixture.path("c.txt")).unwrap();
+            let root = fixture.dir.clone();
+            let elsewhere = root.join("elsewhere");
+
+            assert!(fixture.store.working_set_strays(&[&elsewhere]).is_empty());
+            let strays = fixture.store.working_set_strays(&[&root]);
+            assert_eq!(strays, vec![fixture.path("b.txt"), fixture.path("c.txt")]);
+            assert_eq!(fixture.store.trim_to_working_set(&[&root]).unwrap(), 2);
+            let tracked = fixture.store.tracked_paths_iter().map(|tracked| tracked.path);
+            assert_eq!(tracked.collect_vec(), vec![fixture.path("a.txt")]);
+        }
+
+        #[test]
+        fn reconstruct_rejects_unknown_versions_and_paths() {
+            let fixture = Fixture::new("reconstruct_errors", &[("a.txt", "one\n")]);
+            let path = fixture.path("a.txt");
 
-            let mut file = File::create(path)?;
-            file.write_all(redone_lines.join("\n").as_bytes())
-                .map_err(|err| err.into())
+            assert!(fixture.store.reconstruct(&path, 1).is_err());
+            assert!(fixture
+                .store
+                .reconstruct(&fixture.path("b.txt"), 0)
+                .is_err());
         }
     }
 }
@@ -0,0 +1,191 @@
+//!
+//! an advisory lock file next to the store holding the PID of the process
+//! that has it open. a second auto-stash on the same store is refused, a
+//! lock left by a process that is gone gets taken over. stores opened more
+//! than once inside one process share the lock.
+//!
+use std::error;
+use std::fs;
+use std::io;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+/// the lock files this process holds and how many stores use each
+static HELD: Mutex<Vec<(String, usize)>> = Mutex::new(Vec::new());
+/// how often a lock file without a PID is read before it counts as stale
+const HOLDER_READS: usize = 5;
+
+pub fn lock_path(store_path: &str) -> String {
+    format!("{}.lock", store_path)
+}
+
+#[derive(Debug)]
+pub struct StoreLock {
+    path: String,
+}
+
+impl StoreLock {
+    pub fn acquire(store_path: &str) -> Result<StoreLock, Box<dyn error::Error>> {
+        let path = lock_path(store_path);
+        let mut held = HELD.lock().unwrap();
+        if let Some((_, count)) = held.iter_mut().find(|(held, _)| *held == path) {
+            *count += 1;
+            return Ok(StoreLock { path });
+        }
+
+        // a stale lock is removed once, a lock that is taken again right
+        // after belongs to a process that just started
+        for _ in 0..2 {
+            match create(&path) {
+                Ok(()) => {
+                    held.push((path.clone(), 1));
+                    return Ok(StoreLock { path });
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    if let Some(pid) = holder(&path).filter(|pid| is_alive(*pid)) {
+                        return Err(format!(
+                            "{} is in use by PID {}, remove {} if that isn't auto-stash",
+                            store_path, pid, path
+                        )
+                        .into());
+                    }
+                    match fs::remove_file(&path) {
+                        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+                        _ => {}
+                    }
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Err(format!(
+            "{} could not be locked, {} keeps coming back",
+            store_path, path
+        )
+        .into())
+    }
+}
+
+impl Drop for StoreLock {
+    fn drop(&mut self) {
+        let mut held = HELD.lock().unwrap();
+        if let Some(position) = held.iter().position(|(held, _)| *held == self.path) {
+            held[position].1 -= 1;
+            if held[position].1 == 0 {
+                held.remove(position);
+                let _ = fs::remove_file(&self.path);
+            }
+        }
+    }
+}
+
+///
+/// creates the lock file at `path` with the PID of this process unless it
+/// exists. the PID goes into a file of its own first, the lock file shows
+/// up with it or not at all.
+///
+fn create(path: &str) -> io::Result<()> {
+    let temp_path = format!("{}.{}", path, std::process::id());
+    fs::write(&temp_path, std::process::id().to_string())?;
+    let linked = fs::hard_link(&temp_path, path);
+    let _ = fs::remove_file(&temp_path);
+    linked
+}
+
+///
+/// the PID written to the lock file, `None` if it can't be read. an older
+/// auto-stash creates the lock file before it writes its PID, an empty one
+/// is read again for a moment before it counts as stale.
+///
+fn holder(path: &str) -> Option<u32> {
+    for attempt in 0..HOLDER_READS {
+        let pid = fs::read_to_string(path).ok();
+        match pid.as_deref().map(str::trim) {
+            Some("") => {}
+            pid => return pid?.parse().ok(),
+        }
+        if attempt + 1 < HOLDER_READS {
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+    None
+}
+
+///
+/// whether another process with `pid` is running. this process never
+/// counts, its lock would be in `HELD`.
+///
+#[cfg(unix)]
+fn is_alive(pid: u32) -> bool {
+    if pid == std::process::id() {
+        return false;
+    }
+    if std::path::Path::new("/proc/self").exists() {
+        return std::path::Path::new(&format!("/proc/{}", pid)).exists();
+    }
+    std::process::Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// asks `tasklist`, a lock is kept if it can't tell
+#[cfg(windows)]
+fn is_alive(pid: u32) -> bool {
+    if pid == std::process::id() {
+        return false;
+    }
+    let filter = format!("PID eq {}", pid);
+    std::process::Command::new("tasklist")
+        .args(["/FI", filter.as_str(), "/FO", "CSV", "/NH"])
+        .output()
+        .map_or(true, |output| {
+            String::from_utf8_lossy(&output.stdout).contains(&format!("\"{}\"", pid))
+        })
+}
+
+///
+/// without a way to tell, a lock is only taken over once it is removed,
+/// the error refusing the store names the lock file
+///
+#[cfg(not(any(unix, windows)))]
+fn is_alive(pid: u32) -> bool {
+    pid != std::process::id()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_live_holder_keeps_the_store() {
+        let dir = std::env::temp_dir().join(format!("auto_stash_lock_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let store_path = dir.join("store.db").to_str().unwrap().to_string();
+
+        // pid 1 is always running
+        fs::write(lock_path(&store_path), "1").unwrap();
+        let err = StoreLock::acquire(&store_path).unwrap_err();
+        assert!(err.to_string().contains("in use by PID 1"));
+
+        // no PID reaches that far
+        fs::write(lock_path(&store_path), u32::MAX.to_string()).unwrap();
+        let lock = StoreLock::acquire(&store_path).unwrap();
+        assert_eq!(holder(&lock_path(&store_path)), Some(std::process::id()));
+        let shared = StoreLock::acquire(&store_path).unwrap();
+        drop(lock);
+        assert!(fs::metadata(lock_path(&store_path)).is_ok());
+        drop(shared);
+        assert!(fs::metadata(lock_path(&store_path)).is_err());
+
+        // a lock that stays without a PID is stale
+        fs::write(lock_path(&store_path), "").unwrap();
+        let lock = StoreLock::acquire(&store_path).unwrap();
+        assert_eq!(holder(&lock_path(&store_path)), Some(std::process::id()));
+        drop(lock);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
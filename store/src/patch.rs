@@ -0,0 +1,162 @@
+//!
+//! applies unified diffs like the ones `Store::unified_diff` writes. the
+//! context and removed lines of every hunk have to match the content
+//! exactly, a patch made against other content is rejected as a whole.
+//!
+use std::error;
+
+/// the marker after a line that has no newline at its end
+static NO_NEWLINE: &str = "\\ No newline at end of file";
+
+#[derive(Debug, Default, PartialEq)]
+struct Hunk {
+    /// 1-based, the line after which the lines go in without old lines
+    old_start: usize,
+    /// the lines the hunk replaces, with their line endings
+    old_lines: Vec<String>,
+    new_lines: Vec<String>,
+}
+
+/// `start,count` or `start` with a count of 1
+fn parse_range(range: &str) -> Option<(usize, usize)> {
+    match range.split_once(',') {
+        Some((start, count)) => Some((start.parse().ok()?, count.parse().ok()?)),
+        None => Some((range.parse().ok()?, 1)),
+    }
+}
+
+/// the old and the new range of a `@@ -1,3 +1,4 @@` line
+fn parse_header(line: &str) -> Option<((usize, usize), (usize, usize))> {
+    let (ranges, _) = line.strip_prefix("@@ -")?.split_once(" @@")?;
+    let (old, new) = ranges.split_once(" +")?;
+    Some((parse_range(old)?, parse_range(new)?))
+}
+
+fn parse(unified_diff: &str) -> Result<Vec<Hunk>, Box<dyn error::Error>> {
+    let mut hunks = Vec::new();
+    let mut lines = unified_diff.lines().enumerate().peekable();
+    // the file headers in front of the first hunk
+    while lines.next_if(|(_, line)| !line.starts_with("@@")).is_some() {}
+
+    while let Some((number, header)) = lines.next() {
+        if header.is_empty() {
+            continue;
+        }
+        let ((old_start, old_count), (_, new_count)) =
+            parse_header(header).ok_or_else(|| unexpected(number, header))?;
+        let mut hunk = Hunk {
+            old_start,
+            ..Hunk::default()
+        };
+        let mut last_kind = " ";
+
+        while hunk.old_lines.len() < old_count || hunk.new_lines.len() < new_count {
+            let (number, line) = lines.next().ok_or_else(|| {
+                format!("the hunk at line {} of the patch ends early", number + 1)
+            })?;
+            if line == NO_NEWLINE {
+                strip_newline(&mut hunk, last_kind);
+                continue;
+            }
+            // some tools drop the space in front of empty context lines
+            let (kind, text) = match line.char_indices().nth(1) {
+                Some((start, _)) => line.split_at(start),
+                None if line.is_empty() => (" ", ""),
+                None => (line, ""),
+            };
+            let text = format!("{}\n", text);
+            match kind {
+                " " => {
+                    hunk.old_lines.push(text.clone());
+                    hunk.new_lines.push(text);
+                }
+                "-" => hunk.old_lines.push(text),
+                "+" => hunk.new_lines.push(text),
+                _ => return Err(unexpected(number, line)),
+            }
+            last_kind = kind;
+        }
+        if lines.next_if(|(_, line)| *line == NO_NEWLINE).is_some() {
+            strip_newline(&mut hunk, last_kind);
+        }
+        hunks.push(hunk);
+    }
+    Ok(hunks)
+}
+
+fn unexpected(number: usize, line: &str) -> Box<dyn error::Error> {
+    format!("unexpected line {} in the patch: {}", number + 1, line).into()
+}
+
+/// the marker belongs to the line before it, a context line is on both sides
+fn strip_newline(hunk: &mut Hunk, kind: &str) {
+    let (old, new) = (hunk.old_lines.last_mut(), hunk.new_lines.last_mut());
+    let lines = match kind {
+        " " => vec![old, new],
+        "-" => vec![old],
+        _ => vec![new],
+    };
+    lines.into_iter().flatten().for_each(|line| {
+        line.pop();
+    });
+}
+
+/// `content` with the hunks of `unified_diff` applied
+pub fn apply(content: &str, unified_diff: &str) -> Result<String, Box<dyn error::Error>> {
+    let lines = content.split_inclusive('\n').collect::<Vec<&str>>();
+    let mut patched = String::new();
+    let mut next = 0;
+
+    for hunk in parse(unified_diff)? {
+        let start = match hunk.old_lines.is_empty() {
+            true => hunk.old_start,
+            false => hunk.old_start.saturating_sub(1),
+        };
+        let end = start + hunk.old_lines.len();
+        let matches = start >= next
+            && end <= lines.len()
+            && lines[start..end].iter().eq(hunk.old_lines.iter());
+        if !matches {
+            return Err(format!(
+                "the hunk at line {} doesn't match the content",
+                hunk.old_start
+            )
+            .into());
+        }
+        patched.extend(lines[next..start].iter().copied());
+        patched.extend(hunk.new_lines.iter().map(String::as_str));
+        next = end;
+    }
+    patched.extend(lines[next..].iter().copied());
+    Ok(patched)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hunks_apply_only_to_matching_content() {
+        let patch = "--- a.txt@1\n+++ a.txt@2\n\
+                     @@ -1,3 +1,3 @@\n one\n-two\n+2\n three\n\
+                     @@ -5 +5,2 @@\n five\n+six\n";
+        let content = "one\ntwo\nthree\nfour\nfive\n";
+        assert_eq!(
+            apply(content, patch).unwrap(),
+            "one\n2\nthree\nfour\nfive\nsix\n"
+        );
+
+        // the second hunk expects `five` where there is `5`
+        assert!(apply("one\ntwo\nthree\nfour\n5\n", patch).is_err());
+        assert!(apply("one\ntwo\n", patch).is_err());
+        assert!(apply(content, "@@ -1,2 +1,2 @@\n one\n").is_err());
+        assert!(apply(content, "@@ one two @@\n").is_err());
+
+        // into an empty file and at the end without a newline
+        assert_eq!(apply("", "@@ -0,0 +1 @@\n+one\n").unwrap(), "one\n");
+        let unterminated = "@@ -1 +1,2 @@\n-one\n\\ No newline at end of file\n\
+                            +one\n+two\n\\ No newline at end of file\n";
+        assert_eq!(apply("one", unterminated).unwrap(), "one\ntwo");
+        assert!(apply("one\n", unterminated).is_err());
+    }
+}
@@ -0,0 +1,114 @@
+//!
+//! how many versions of a file are kept. a default retention applies to
+//! every file, overrides pick a different one for the files matching
+//! their pattern, the first matching override wins. patterns follow the
+//! rules of the path filter.
+//!
+use crate::filter;
+use glob::Pattern;
+use serde::{Deserialize, Serialize};
+use std::error;
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Retention {
+    #[default]
+    KeepAll,
+    /// the latest `n` versions
+    KeepLast(usize),
+    /// the versions recorded within the last that many seconds
+    OlderThan(i64),
+    /// every version, not even evicted, see `Store::set_retention`
+    Forever,
+}
+
+impl Retention {
+    ///
+    /// the index of the oldest version to keep out of the versions
+    /// recorded at `timestamps`, everything before it gets pruned
+    ///
+    pub fn first_kept(&self, timestamps: &[i64], now: i64) -> usize {
+        match self {
+            Retention::KeepAll | Retention::Forever => 0,
+            Retention::KeepLast(n) => timestamps.len().saturating_sub(*n),
+            Retention::OlderThan(seconds) => {
+                let oldest = now.saturating_sub(*seconds);
+                timestamps
+                    .iter()
+                    .position(|timestamp| *timestamp >= oldest)
+                    .unwrap_or(timestamps.len())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RetentionPolicy {
+    watch_path: PathBuf,
+    default: Retention,
+    overrides: Vec<(Pattern, Retention)>,
+}
+
+impl RetentionPolicy {
+    pub fn new(
+        watch_path: &str,
+        default: Retention,
+        overrides: &[(String, Retention)],
+    ) -> Result<RetentionPolicy, Box<dyn error::Error>> {
+        let patterns = overrides
+            .iter()
+            .map(|(pattern, _)| pattern.clone())
+            .collect::<Vec<String>>();
+        let overrides = filter::compile(&patterns)?
+            .into_iter()
+            .zip(overrides.iter().map(|(_, retention)| *retention))
+            .collect();
+
+        Ok(RetentionPolicy {
+            watch_path: PathBuf::from(watch_path),
+            default,
+            overrides,
+        })
+    }
+
+    pub fn for_path(&self, path: &Path) -> Retention {
+        let relative = path.strip_prefix(&self.watch_path).unwrap_or(path);
+        self.overrides
+            .iter()
+            .find(|(pattern, _)| filter::matches(pattern, relative))
+            .map_or(self.default, |(_, retention)| *retention)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_pick_the_retention_per_file() {
+        let policy = RetentionPolicy::new(
+            "/watch",
+            Retention::KeepLast(10),
+            &[
+                (String::from("src/**"), Retention::KeepAll),
+                (String::from("build/**"), Retention::KeepLast(1)),
+                (String::from("*.log"), Retention::OlderThan(60)),
+            ],
+        )
+        .unwrap();
+        let timestamps = [100, 200, 300, 400];
+
+        let first_kept = |path: &str| {
+            policy
+                .for_path(Path::new(path))
+                .first_kept(&timestamps, 400)
+        };
+        assert_eq!(first_kept("/watch/src/lib.rs"), 0);
+        assert_eq!(first_kept("/watch/build/out.o"), 3);
+        assert_eq!(first_kept("/watch/logs/app.log"), 3);
+        assert_eq!(first_kept("/watch/README.md"), 0);
+        assert_eq!(Retention::KeepLast(2).first_kept(&timestamps, 400), 2);
+        assert_eq!(Retention::OlderThan(150).first_kept(&timestamps, 400), 2);
+        assert_eq!(Retention::OlderThan(10).first_kept(&timestamps, 1000), 4);
+    }
+}
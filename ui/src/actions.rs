@@ -0,0 +1,270 @@
+//!
+//! every action of the TUI with its key. the keymap and the command
+//! palette both look them up here, an action without a key is only
+//! reachable through the palette.
+//!
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Quit,
+    SwitchPanes,
+    OpenInEditor,
+    MarkRange,
+    ClearMarks,
+    SortFiles,
+    ToggleFileView,
+    CopyVersion,
+    CopyDiff,
+    Checkpoint,
+    ToggleDisabled,
+    SetDebounce,
+    TogglePinned,
+    RecordNow,
+    LoadMoreVersions,
+    Purge,
+    Undo,
+    Redo,
+    Replay,
+    PauseReplay,
+    StepReplay,
+    ReplayFaster,
+    ReplaySlower,
+}
+
+pub struct Binding {
+    pub action: Action,
+    /// the key as it is shown, a single character is looked up by `for_key`
+    pub key: Option<&'static str>,
+    pub name: &'static str,
+}
+
+pub static ACTIONS: &[Binding] = &[
+    Binding {
+        action: Action::Undo,
+        key: Some("esc"),
+        name: "Undo",
+    },
+    Binding {
+        action: Action::Redo,
+        key: Some("tab"),
+        name: "Redo",
+    },
+    Binding {
+        action: Action::SwitchPanes,
+        key: Some("s"),
+        name: "Switch panes",
+    },
+    Binding {
+        action: Action::OpenInEditor,
+        key: Some("e"),
+        name: "Open in editor",
+    },
+    Binding {
+        action: Action::MarkRange,
+        key: Some("m"),
+        name: "Mark range",
+    },
+    Binding {
+        action: Action::ClearMarks,
+        key: None,
+        name: "Clear marked range",
+    },
+    Binding {
+        action: Action::SortFiles,
+        key: Some("o"),
+        name: "Sort files",
+    },
+    Binding {
+        action: Action::ToggleFileView,
+        key: Some("f"),
+        name: "Full file",
+    },
+    Binding {
+        action: Action::CopyVersion,
+        key: Some("c"),
+        name: "Copy version",
+    },
+    Binding {
+        action: Action::CopyDiff,
+        key: Some("d"),
+        name: "Copy diff",
+    },
+    Binding {
+        action: Action::Checkpoint,
+        key: Some("k"),
+        name: "Checkpoint",
+    },
+    Binding {
+        action: Action::ToggleDisabled,
+        key: Some("r"),
+        name: "Toggle recording",
+    },
+    Binding {
+        action: Action::SetDebounce,
+        key: Some("b"),
+        name: "Set file debounce",
+    },
+    Binding {
+        action: Action::TogglePinned,
+        key: Some("h"),
+        name: "Pin history",
+    },
+    Binding {
+        action: Action::RecordNow,
+        key: Some("w"),
+        name: "Record now",
+    },
+    Binding {
+        action: Action::LoadMoreVersions,
+        key: Some("l"),
+        name: "Load more versions",
+    },
+    Binding {
+        action: Action::Purge,
+        key: Some("p"),
+        name: "Purge file",
+    },
+    Binding {
+        action: Action::Replay,
+        key: Some("v"),
+        name: "Replay versions",
+    },
+    Binding {
+        action: Action::PauseReplay,
+        key: Some("space"),
+        name: "Play/pause replay",
+    },
+    Binding {
+        action: Action::StepReplay,
+        key: Some("."),
+        name: "Step replay",
+    },
+    Binding {
+        action: Action::ReplayFaster,
+        key: Some("+"),
+        name: "Replay faster",
+    },
+    Binding {
+        action: Action::ReplaySlower,
+        key: Some("-"),
+        name: "Replay slower",
+    },
+    Binding {
+        action: Action::Quit,
+        key: Some("q"),
+        name: "Quit",
+    },
+];
+
+/// the action bound to the character key `c`, a space is shown as "space"
+pub fn for_key(c: char) -> Option<Action> {
+    let mut buffer = [0; 4];
+    let key = match c {
+        ' ' => "space",
+        c => c.encode_utf8(&mut buffer),
+    };
+    ACTIONS
+        .iter()
+        .find(|binding| binding.key == Some(key))
+        .map(|binding| binding.action)
+}
+
+///
+/// whether the characters of `query` appear in `name` in order, ignoring
+/// case and spaces. the score sums up the characters skipped in between,
+/// lower is closer.
+///
+fn fuzzy_score(query: &str, name: &str) -> Option<usize> {
+    let name = name.to_lowercase();
+    let mut chars = name.chars().enumerate();
+    let mut last = None;
+    let mut score = 0;
+    for wanted in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let (index, _) = chars.find(|(_, c)| *c == wanted)?;
+        score += index - last.map_or(0, |last| last + 1);
+        last = Some(index);
+    }
+    Some(score)
+}
+
+/// the actions matching `query`, the closest first
+pub fn matching(query: &str) -> Vec<&'static Binding> {
+    let mut matches = ACTIONS
+        .iter()
+        .filter_map(|binding| Some((fuzzy_score(query, binding.name)?, binding)))
+        .collect::<Vec<_>>();
+    matches.sort_by_key(|(score, _)| *score);
+    matches.into_iter().map(|(_, binding)| binding).collect()
+}
+
+/// The open command palette, opened with ':' or ctrl-p.
+#[derive(Debug, Default, PartialEq)]
+pub struct CommandPalette {
+    pub query: String,
+    /// the position in the matches
+    pub selected: usize,
+}
+
+impl CommandPalette {
+    pub fn matches(&self) -> Vec<&'static Binding> {
+        matching(&self.query)
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.query.push(c);
+        self.selected = 0;
+    }
+
+    pub fn pop(&mut self) {
+        self.query.pop();
+        self.selected = 0;
+    }
+
+    pub fn next(&mut self) {
+        let count = self.matches().len().max(1);
+        self.selected = (self.selected + 1) % count;
+    }
+
+    pub fn previous(&mut self) {
+        let count = self.matches().len().max(1);
+        self.selected = (self.selected + count - 1) % count;
+    }
+
+    pub fn chosen(&self) -> Option<Action> {
+        self.matches()
+            .get(self.selected)
+            .map(|binding| binding.action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palette_filters_the_actions_the_keys_are_bound_to() {
+        assert_eq!(for_key('p'), Some(Action::Purge));
+        assert_eq!(for_key('x'), None);
+        assert_eq!(for_key(' '), Some(Action::PauseReplay));
+
+        let mut palette = CommandPalette::default();
+        assert_eq!(palette.matches().len(), ACTIONS.len());
+        "cpy".chars().for_each(|c| palette.push(c));
+        let names = palette.matches().iter().map(|b| b.name).collect::<Vec<_>>();
+        assert_eq!(names, vec!["Copy version", "Copy diff"]);
+        palette.previous();
+        assert_eq!(palette.chosen(), Some(Action::CopyDiff));
+
+        // the closer match comes first, whatever the order of the actions
+        let chosen = |query: &str| {
+            CommandPalette {
+                query: query.to_string(),
+                selected: 0,
+            }
+            .chosen()
+        };
+        assert_eq!(chosen("ch"), Some(Action::Checkpoint));
+        assert_eq!(chosen("clear"), Some(Action::ClearMarks));
+        assert_eq!(chosen("zzz"), None);
+    }
+}
@@ -1,23 +1,38 @@
+mod actions;
 pub mod ui;
 mod util;
 mod widgets;
 // use crossterm::style::{SetForegroundColor, SetBackgroundColor, ResetColor, Color, Attribute};
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event as CEvent, KeyCode},
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event as CEvent, KeyCode, KeyEvent,
+        KeyModifiers,
+    },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
+use arboard::Clipboard;
 use parking_lot::{Mutex, MutexGuard};
 use std::{
     error::Error,
     io::stdout,
     io::Stdout,
+    process::Command,
     sync::Arc,
     thread::{self, JoinHandle},
     time::{Duration, Instant},
 };
+use actions::{Action, CommandPalette};
+use event_handle::event_handle::UiView;
 use tui::{backend::CrosstermBackend, Terminal};
 use ui::UI;
+use util::{Coalesced, FrameClock};
+
+static DEFAULT_EDITOR: &str = "vi";
+/// the most frames drawn per second
+static MAX_FPS: u64 = 30;
+/// how often the draw loop and the listeners look for changes
+static DRAW_POLL_INTERVAL: Duration = Duration::from_millis(10);
 
 pub enum Event<I> {
     Input(I),
@@ -42,14 +57,18 @@ fn listen_to_key_press(ui: Arc<Mutex<UI>>, tick_rate: Duration) -> JoinHandle<()
             let mut last_tick = Instant::now();
 
             loop {
-                let ui = ui.lock();
+                let mut ui = ui.lock();
                 // poll for tick rate duration, if no events, sent tick event.
                 let timeout = tick_rate
                     .checked_sub(last_tick.elapsed())
                     .unwrap_or_else(|| Duration::from_secs(0));
                 if event::poll(timeout).unwrap() {
-                    if let CEvent::Key(key) = event::read().unwrap() {
-                        ui.communication.key_to_ui.send(Event::Input(key)).unwrap();
+                    match event::read().unwrap() {
+                        CEvent::Key(key) => {
+                            ui.communication.key_to_ui.send(Event::Input(key)).unwrap();
+                        }
+                        CEvent::Resize(_, _) => ui.state.dirty = true,
+                        _ => {}
                     }
                 }
                 if last_tick.elapsed() >= tick_rate {
@@ -63,36 +82,170 @@ fn listen_to_key_press(ui: Arc<Mutex<UI>>, tick_rate: Duration) -> JoinHandle<()
         .unwrap()
 }
 
+///
+/// the clipboard is opened on the first copy and kept open, some platforms
+/// drop the copied text together with the clipboard handle
+///
+fn copy_to_clipboard(clipboard: &mut Option<Clipboard>, text: String) -> Result<(), String> {
+    if clipboard.is_none() {
+        *clipboard =
+            Some(Clipboard::new().map_err(|err| format!("No clipboard available: {}", err))?);
+    }
+    clipboard
+        .as_mut()
+        .unwrap()
+        .set_text(text)
+        .map_err(|err| format!("Could not copy to the clipboard: {}", err))
+}
+
 fn on_versions(ui: Arc<Mutex<UI>>) -> JoinHandle<()> {
+    let mut clipboard = None;
+    let mut views = Coalesced::new(ui.lock().config.view_coalesce);
     thread::spawn(move || loop {
         let mut ui = ui.lock();
-        if let Ok(res) = ui.communication.on_file_versions.try_recv() {
-            ui.state.file_versions = res.clone();
-            let state = &mut ui.state;
-            state.filenames.flush_display();
-            res.iter()
-                .filter(|file_versions| file_versions.is_some())
-                .for_each(|file_versions| {
-                    state
-                        .filenames
-                        .add_item(file_versions.as_ref().unwrap().path.clone());
-                });
+        // a burst is shown once, its pages go into the latest full view before them
+        while let Ok(view) = ui.communication.on_file_versions.try_recv() {
+            views.merge(view, Instant::now(), UiView::then);
+        }
+        if let Some(view) = views.take_due(Instant::now()) {
+            // keeps the file list and the versions in step
+            let view = match view {
+                UiView::Full(view) => {
+                    UiView::Full(view.into_iter().filter(Option::is_some).collect())
+                }
+                page => page,
+            };
+            ui.state.show_view(view);
+            let epoch = ui
+                .state
+                .file_versions
+                .iter()
+                .flatten()
+                .map(|file_versions| file_versions.epoch)
+                .max();
+            if let Some(epoch) = epoch {
+                ui.state.on_epoch(epoch);
+            }
             ui.state.update_pane_content();
+            ui.state.dirty = true;
+            // the selected snapshot may be a different version now
+            if let Some((path, version)) = ui.state.version_to_show() {
+                ui.communication.on_show_version(path, version);
+            }
+            if let Some((path, count)) = ui.state.versions_to_list() {
+                ui.communication.on_list_versions(path, count);
+            }
+        }
+        if let Ok((path, version, content)) = ui.communication.on_version_content.try_recv() {
+            ui.state.on_version_content(path, version, content);
+            ui.state.dirty = true;
+        }
+        if let Ok((path, epoch, versions)) = ui.communication.on_version_list.try_recv() {
+            ui.state.on_version_list(path, epoch, versions);
+            ui.state.dirty = true;
+        }
+        if let Ok((path, debounce)) = ui.communication.on_debounce.try_recv() {
+            ui.state.debounces.insert(path, debounce);
+            ui.state.dirty = true;
+        }
+        if let Ok(running) = ui.communication.on_maintenance.try_recv() {
+            ui.state.maintenance_running = running;
+            ui.state.dirty = true;
+        }
+        // only the latest progress is drawn, the ones before it are outdated
+        while let Ok(progress) = ui.communication.on_progress.try_recv() {
+            ui.state.progress = progress;
+            ui.state.dirty = true;
+        }
+        // the terminal only rewrites the cells that changed, metrics that
+        // moved redraw nothing besides the status bar
+        while let Ok(metrics) = ui.communication.on_metrics.try_recv() {
+            ui.state.dirty |= ui.state.metrics.as_ref() != Some(&metrics);
+            ui.state.metrics = Some(metrics);
+        }
+        if let Ok(available) = ui.communication.on_watch_root.try_recv() {
+            ui.state.watch_root_available = available;
+            ui.state.dirty = true;
+        }
+        if let Ok(text) = ui.communication.on_clipboard.try_recv() {
+            ui.state.status = match text.and_then(|text| copy_to_clipboard(&mut clipboard, text)) {
+                Ok(()) => String::from("Copied to the clipboard"),
+                Err(err) => err,
+            };
+            ui.state.dirty = true;
         }
         if ui.communication.on_quit.try_recv().is_ok() {
             break;
         }
+        // the draw loop gets the lock in between
+        drop(ui);
+        thread::sleep(DRAW_POLL_INTERVAL);
     })
 }
 
+///
+/// hands the terminal over to `$EDITOR` (or `$VISUAL`, falling back to vi)
+/// and takes it back once the editor exits. the key listener waits on the
+/// ui lock in the meantime, so it doesn't steal the editor's input.
+///
+fn open_in_editor(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    path: &str,
+) -> Result<(), Box<dyn Error>> {
+    let editor = std::env::var("EDITOR")
+        .or_else(|_| std::env::var("VISUAL"))
+        .unwrap_or_else(|_| DEFAULT_EDITOR.to_string());
+
+    suspend_terminal(terminal)?;
+    // $EDITOR may carry arguments, e.g. `code --wait`
+    let mut command = editor.split_whitespace();
+    let status = Command::new(command.next().unwrap_or(DEFAULT_EDITOR))
+        .args(command)
+        .arg(path)
+        .status();
+    resume_terminal(terminal)?;
+
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(format!("{} exited with {}", editor, status).into()),
+        Err(err) => Err(format!("Could not launch {}: {}", editor, err).into()),
+    }
+}
+
+///
+/// draws a frame only once the state changed, at most `MAX_FPS` times a
+/// second. the spinner of a running operation keeps it drawing.
+///
 fn draw(
     ui: Arc<Mutex<UI>>,
     mut terminal: Terminal<CrosstermBackend<Stdout>>,
     handles: Vec<JoinHandle<()>>,
 ) -> Result<(), Box<dyn Error>> {
+    let mut frames = FrameClock::new(Duration::from_millis(1000 / MAX_FPS));
+    let mut page = None;
     loop {
         let mut ui = ui.lock();
-        terminal.draw(|f| ui.draw(f))?;
+        if ui.state.should_open_editor {
+            ui.state.should_open_editor = false;
+            let path = ui.state.path_of_selected_file.clone();
+            if path.is_empty() {
+                ui.state.status = String::from("No file selected");
+            } else if let Err(err) = open_in_editor(&mut terminal, path.as_str()) {
+                ui.state.status = err.to_string();
+            }
+            ui.state.dirty = true;
+        }
+        let changed = ui.state.dirty || ui.state.progress.is_some();
+        if frames.should_draw(changed, Instant::now()) {
+            ui.state.dirty = false;
+            terminal.draw(|f| ui.draw(f))?;
+            // the handle only sends the files the list shows once they change
+            let visible = ui.state.visible_page();
+            if let Some(visible) = visible.filter(|visible| page != Some(*visible)) {
+                page = Some(visible);
+                let _ = ui.communication.visible_page_to_handle.send(visible);
+            }
+        }
 
         if ui.state.should_quit {
             quit(terminal)?;
@@ -107,31 +260,41 @@ fn draw(
             }
             break;
         }
+        drop(ui);
+        thread::sleep(DRAW_POLL_INTERVAL);
     }
 
     Ok(())
 }
 
 fn on_key(ui: Arc<Mutex<UI>>) -> JoinHandle<()> {
+    let on_key = ui.lock().communication.on_key.clone();
     thread::spawn(move || {
         loop {
+            // waits for a key without the lock, a replay steps on in between
+            let key = on_key.recv_timeout(DRAW_POLL_INTERVAL);
             let mut ui = ui.lock();
 
-            if let Ok(ev) = ui.communication.on_key.try_recv() {
+            if let Ok(ev) = key {
+                // a tick alone changes nothing to draw
+                ui.state.dirty |= matches!(ev, Event::Input(_));
                 match ev {
+                    Event::Input(ev) if ui.state.palette.is_some() => {
+                        ui.state.on_palette_key(ev.code);
+                    }
+                    Event::Input(ev) if opens_palette(&ev) => {
+                        ui.state.palette = Some(CommandPalette::default());
+                    }
                     Event::Input(ev) => match ev.code {
                         KeyCode::Char(c) => {
+                            ui.state.status.clear();
                             ui.state.on_key(c);
                         }
-                        // TODO
                         KeyCode::Esc => {
-                            let selected_path = ui.state.path_of_selected_file.clone();
-                            ui.communication.on_undo(selected_path, 1);
+                            ui.state.on_action(Action::Undo);
                         }
-                        // TODO
                         KeyCode::Tab => {
-                            let selected_path = ui.state.path_of_selected_file.clone();
-                            ui.communication.on_redo(selected_path, 1);
+                            ui.state.on_action(Action::Redo);
                         }
                         KeyCode::Up => {
                             ui.state.on_up();
@@ -139,20 +302,41 @@ fn on_key(ui: Arc<Mutex<UI>>) -> JoinHandle<()> {
                         KeyCode::Down => {
                             ui.state.on_down();
                         }
+                        KeyCode::PageUp => {
+                            ui.state.file_view.page_up();
+                        }
+                        KeyCode::PageDown => {
+                            ui.state.file_view.page_down();
+                        }
                         KeyCode::Left => {
                             ui.state.on_left();
                             let current_id = ui.state.tabs.get_index();
-                            ui.communication.on_timeslice_change(current_id);
+                            let time_frame = ui.timeslots.get(current_id);
+                            ui.communication.on_timeslice_change(time_frame);
                         }
                         KeyCode::Right => {
                             ui.state.on_right();
                             let current_id = ui.state.tabs.get_index();
-                            ui.communication.on_timeslice_change(current_id);
+                            let time_frame = ui.timeslots.get(current_id);
+                            ui.communication.on_timeslice_change(time_frame);
                         }
                         _ => {}
                     },
                     Event::Tick => {}
                 }
+                send_requests(&mut ui);
+                if let Some((path, version)) = ui.state.version_to_show() {
+                    ui.communication.on_show_version(path, version);
+                }
+                if let Some((path, count)) = ui.state.versions_to_list() {
+                    ui.communication.on_list_versions(path, count);
+                }
+            }
+            // a replaying file view asks for each version it steps to
+            if ui.state.on_replay_tick(Instant::now()) {
+                if let Some((path, version)) = ui.state.version_to_show() {
+                    ui.communication.on_show_version(path, version);
+                }
             }
             if ui.communication.on_quit.try_recv().is_ok() {
                 break;
@@ -161,7 +345,45 @@ fn on_key(ui: Arc<Mutex<UI>>) -> JoinHandle<()> {
     })
 }
 
-fn quit(mut terminal: Terminal<CrosstermBackend<Stdout>>) -> Result<(), Box<dyn Error>> {
+/// ctrl-p opens the command palette like ':'
+fn opens_palette(key: &KeyEvent) -> bool {
+    key.code == KeyCode::Char('p') && key.modifiers.contains(KeyModifiers::CONTROL)
+}
+
+/// hands what the last action asked for over to the handle
+fn send_requests(ui: &mut UI) {
+    if let Some(path) = ui.state.should_purge.take() {
+        ui.communication.on_purge(path);
+    }
+    if let Some(path) = ui.state.should_checkpoint.take() {
+        ui.communication.on_checkpoint(path);
+    }
+    if let Some(path) = ui.state.should_toggle_disabled.take() {
+        ui.communication.on_toggle_disabled(path);
+    }
+    if let Some(path) = ui.state.should_toggle_pinned.take() {
+        ui.communication.on_toggle_pinned(path);
+    }
+    if let Some(path) = ui.state.should_record_now.take() {
+        ui.communication.on_record_now(path);
+    }
+    if let Some((path, debounce)) = ui.state.should_set_debounce.take() {
+        ui.communication.on_set_debounce(path, debounce);
+    }
+    if let Some((target, path, version)) = ui.state.should_copy.take() {
+        ui.communication.on_copy(target, path, version);
+    }
+    if let Some(path) = ui.state.should_undo.take() {
+        ui.communication.on_undo(path, 1);
+    }
+    if let Some(path) = ui.state.should_redo.take() {
+        ui.communication.on_redo(path, 1);
+    }
+}
+
+fn suspend_terminal(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+) -> Result<(), Box<dyn Error>> {
     execute!(
         terminal.backend_mut(),
         LeaveAlternateScreen,
@@ -173,6 +395,18 @@ fn quit(mut terminal: Terminal<CrosstermBackend<Stdout>>) -> Result<(), Box<dyn
     Ok(())
 }
 
+fn resume_terminal(terminal: &mut Terminal<CrosstermBackend<Stdout>>) -> Result<(), Box<dyn Error>> {
+    enable_raw_mode()?;
+    execute!(terminal.backend_mut(), EnterAlternateScreen, EnableMouseCapture)?;
+    terminal.clear()?;
+
+    Ok(())
+}
+
+fn quit(mut terminal: Terminal<CrosstermBackend<Stdout>>) -> Result<(), Box<dyn Error>> {
+    suspend_terminal(&mut terminal)
+}
+
 ///
 /// sets up crossterm - terminal, inits event listeners,
 /// starts the rx-listeners, draws the terminal
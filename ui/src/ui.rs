@@ -1,30 +1,81 @@
-use crate::util::process_new_version;
-use crate::util::{StatefulList, TabsState};
+use crate::actions::{self, Action, CommandPalette};
+use crate::util::{process_long_lines_version, process_new_version};
+use crate::util::{self, FileOrder, FileView, Replay, StatefulList, TabsState, VersionRange};
+use crate::util::VersionList;
+pub use crate::util::Truncation;
 use crate::Event;
-use crossterm::event::KeyEvent;
+use crossterm::event::{KeyCode, KeyEvent};
 use diff::LineDifference;
+use event_handle::event_handle::{merge_page, Metrics, Progress, UiView};
 use flume::{Receiver, Sender};
-use store::store::{FileVersions, TimeFrame};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use store::retention::Retention;
+use store::store::{FileVersions, TimeFrame, VersionSummary};
 use tui::text::Spans;
 
 static GRAPH_X_WIDTH: usize = 100;
+/// how long views are merged before they get drawn, unless `with_view_coalesce` changed it
+static DEFAULT_VIEW_COALESCE: Duration = Duration::from_millis(100);
+/// the versions of a file the snapshot pane lists at first, loading more lists as many again
+static VERSION_WINDOW: usize = 100;
 ///
 /// contains channel pairs (tx & rx) which 
 /// are used for backend - frontend communication
 /// 
 pub struct UICommunication {
-    pub on_file_versions: Receiver<Vec<Option<FileVersions>>>,
+    pub on_file_versions: Receiver<UiView>,
+    pub on_watch_root: Receiver<bool>,
+    pub on_clipboard: Receiver<Result<String, String>>,
+    pub on_debounce: Receiver<(String, Duration)>,
+    pub on_maintenance: Receiver<bool>,
+    pub on_progress: Receiver<Option<Progress>>,
+    pub on_metrics: Receiver<Metrics>,
+    /// the content of a version asked for with `show_version_to_handle`
+    pub on_version_content: Receiver<(String, usize, Result<String, String>)>,
+    /// the latest versions of a file asked for with `list_versions_to_handle`, with the store epoch
+    pub on_version_list: Receiver<(String, u64, Vec<VersionSummary>)>,
     pub on_key: Receiver<Event<KeyEvent>>,
     pub on_quit: Receiver<()>,
     pub undo_to_handle: Sender<(String, usize)>,
     pub redo_to_handle: Sender<(String, usize)>,
     pub time_frame_change_to_handle: Sender<TimeFrame>,
+    pub purge_to_handle: Sender<String>,
+    pub checkpoint_to_handle: Sender<(String, Option<String>)>,
+    pub toggle_disabled_to_handle: Sender<String>,
+    pub set_debounce_to_handle: Sender<(String, Option<Duration>)>,
+    pub toggle_pinned_to_handle: Sender<String>,
+    /// a file to record without waiting for its debounce, `None` for every file
+    pub record_now_to_handle: Sender<Option<String>>,
+    pub copy_version_to_handle: Sender<(String, usize)>,
+    /// a version and the one to diff it from, `None` for the version before it
+    pub copy_diff_to_handle: Sender<(String, Option<usize>, usize)>,
+    pub show_version_to_handle: Sender<(String, usize)>,
+    /// a file and how many of its latest versions the snapshot pane lists
+    pub list_versions_to_handle: Sender<(String, usize)>,
+    /// the files the file list shows, see `UIState::visible_page`
+    pub visible_page_to_handle: Sender<(usize, usize)>,
+    /// where the handle sends the views to, see `reattach`
+    pub attach_to_handle: Sender<Sender<UiView>>,
     pub key_to_ui: Sender<Event<KeyEvent>>,
     pub quit_to_ui: Sender<()>,
     pub quit_to_handle: Sender<()>,
 }
 
 impl UICommunication {
+    ///
+    /// has the views sent to a new channel, starting with the full view.
+    /// a TUI started again after the last one went away gets them this way.
+    ///
+    pub fn reattach(&mut self) {
+        let (file_versions_to_ui, on_file_versions) = flume::unbounded();
+        self.on_file_versions = on_file_versions;
+        self.attach_to_handle
+            .send(file_versions_to_ui)
+            .unwrap_or_else(|err| {
+                eprintln!("Could not attach to the handle: {:?}", err);
+            });
+    }
     pub fn on_undo(&mut self, path: String, count: usize) {
         self.undo_to_handle
             .send((path, count))
@@ -39,15 +90,80 @@ impl UICommunication {
                 eprintln!("Could not redo step: {:?}", err);
             });
     }
-    pub fn on_timeslice_change(&mut self, selected_slot: usize) {
-        let time_frame: TimeFrame;
-        match selected_slot {
-            0 => time_frame = TimeFrame::HOUR,
-            1 => time_frame = TimeFrame::DAY,
-            2 => time_frame = TimeFrame::WEEK,
-            // satifsy compiler
-            _ => time_frame = TimeFrame::DAY,
-        }
+    pub fn on_purge(&mut self, path: String) {
+        self.purge_to_handle.send(path).unwrap_or_else(|err| {
+            eprintln!("Could not purge file: {:?}", err);
+        });
+    }
+    pub fn on_checkpoint(&mut self, path: String) {
+        self.checkpoint_to_handle
+            .send((path, None))
+            .unwrap_or_else(|err| {
+                eprintln!("Could not checkpoint file: {:?}", err);
+            });
+    }
+    pub fn on_toggle_disabled(&mut self, path: String) {
+        self.toggle_disabled_to_handle
+            .send(path)
+            .unwrap_or_else(|err| {
+                eprintln!("Could not toggle recording: {:?}", err);
+            });
+    }
+    pub fn on_toggle_pinned(&mut self, path: String) {
+        self.toggle_pinned_to_handle
+            .send(path)
+            .unwrap_or_else(|err| {
+                eprintln!("Could not toggle pinning: {:?}", err);
+            });
+    }
+    pub fn on_record_now(&mut self, path: String) {
+        self.record_now_to_handle
+            .send(Some(path))
+            .unwrap_or_else(|err| {
+                eprintln!("Could not record file: {:?}", err);
+            });
+    }
+    pub fn on_set_debounce(&mut self, path: String, debounce: Option<Duration>) {
+        self.set_debounce_to_handle
+            .send((path, debounce))
+            .unwrap_or_else(|err| {
+                eprintln!("Could not set debounce: {:?}", err);
+            });
+    }
+    pub fn on_copy(&mut self, target: CopyTarget, path: String, version: usize) {
+        let from = match target {
+            CopyTarget::Content => {
+                self.copy_version_to_handle
+                    .send((path, version))
+                    .unwrap_or_else(|err| {
+                        eprintln!("Could not copy version: {:?}", err);
+                    });
+                return;
+            }
+            CopyTarget::Diff => None,
+            CopyTarget::DiffFrom(from) => Some(from),
+        };
+        self.copy_diff_to_handle
+            .send((path, from, version))
+            .unwrap_or_else(|err| {
+                eprintln!("Could not copy diff: {:?}", err);
+            });
+    }
+    pub fn on_show_version(&mut self, path: String, version: usize) {
+        self.show_version_to_handle
+            .send((path, version))
+            .unwrap_or_else(|err| {
+                eprintln!("Could not load version: {:?}", err);
+            });
+    }
+    pub fn on_list_versions(&mut self, path: String, count: usize) {
+        self.list_versions_to_handle
+            .send((path, count))
+            .unwrap_or_else(|err| {
+                eprintln!("Could not list versions: {:?}", err);
+            });
+    }
+    pub fn on_timeslice_change(&mut self, time_frame: TimeFrame) {
         self.time_frame_change_to_handle
             .send(time_frame)
             .unwrap_or_else(|err| {
@@ -57,6 +173,19 @@ impl UICommunication {
 }
 
 
+///
+/// what of the selected version goes to the clipboard
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CopyTarget {
+    /// the whole file as it was at the version
+    Content,
+    /// the unified diff against the version before
+    Diff,
+    /// the unified diff against the given version, the start of a marked range
+    DiffFrom(usize),
+}
+
 ///
 /// contains a vector for selectable time slots
 /// 
@@ -64,12 +193,22 @@ pub struct UITimeSlots {
     pub slots: Vec<TimeFrame>,
 }
 
+impl UITimeSlots {
+    pub fn get(&self, selected_slot: usize) -> TimeFrame {
+        self.slots[selected_slot % self.slots.len()].clone()
+    }
+}
+
 
 ///
 /// defines the app's title
 /// 
 pub struct UIConfig {
     pub title: String,
+    /// views arriving within this long of each other are drawn once
+    pub view_coalesce: Duration,
+    /// how paths wider than the filename pane get shortened
+    pub filename_truncation: Truncation,
 }
 
 
@@ -92,6 +231,52 @@ pub struct UIState {
     pub processed_diffs: Vec<Spans<'static>>,
     pub path_of_selected_file: String,
     pub should_quit: bool,
+    pub should_open_editor: bool,
+    /// file waiting for the purge to be confirmed
+    pub purge_candidate: Option<String>,
+    pub should_purge: Option<String>,
+    pub should_checkpoint: Option<String>,
+    pub should_toggle_disabled: Option<String>,
+    /// a file and its next debounce, `None` for the global one
+    pub should_set_debounce: Option<(String, Option<Duration>)>,
+    pub should_toggle_pinned: Option<String>,
+    pub should_record_now: Option<String>,
+    /// how many of the latest versions of the selected file the snapshot pane lists
+    pub version_window: usize,
+    /// what the snapshot pane lists once it is current, see `versions_to_list`
+    pub version_list: VersionList,
+    pub should_copy: Option<(CopyTarget, String, usize)>,
+    pub should_undo: Option<String>,
+    pub should_redo: Option<String>,
+    /// last error or notice, shown below the panes
+    pub status: String,
+    /// recording pauses while the watch root is unmounted
+    pub watch_root_available: bool,
+    /// versions of the selected file marked for copy diff to span
+    pub marked_range: VersionRange,
+    /// the current adaptive debounce of each file, empty without adaptive debounce
+    pub debounces: HashMap<String, Duration>,
+    /// what the watcher holds every save back for, see `with_global_debounce`
+    pub global_debounce: Duration,
+    /// while the store is flushed or compacted after being idle
+    pub maintenance_running: bool,
+    /// the operation the handle is busy with, shown with a spinner
+    pub progress: Option<Progress>,
+    /// shown in the status bar at the bottom
+    pub metrics: Option<Metrics>,
+    /// the selected version as a whole, toggled with 'f'
+    pub file_view: FileView,
+    /// takes the keys while it is open
+    pub palette: Option<CommandPalette>,
+    /// plays back the versions of the selected file, started with 'v'
+    pub replay: Option<Replay>,
+    /// the store epoch of `file_versions`, see `FileVersions::epoch`
+    pub epoch: u64,
+    pub file_order: FileOrder,
+    /// something changed since the last frame, see `FrameClock`
+    pub dirty: bool,
+    /// how many files the file list had room for in the last frame
+    pub file_rows: usize,
 }
 
 impl UIState {
@@ -107,13 +292,10 @@ impl UIState {
         if let Some(i) = self.filenames.get_index() {
                 self.id_of_selected_file = i;
         }
-        let versions_for_selected_file = &self.file_versions[self.id_of_selected_file as usize];
-        
-        
-        if versions_for_selected_file.is_none() {
-            return;
-        }
-        let versions_for_selected_file = versions_for_selected_file.as_ref().unwrap();
+        let versions_for_selected_file = match self.file_versions.get(self.id_of_selected_file) {
+            Some(Some(versions_for_selected_file)) => versions_for_selected_file,
+            _ => return,
+        };
 
 
         // temporary solution: better shift into own function
@@ -139,31 +321,451 @@ impl UIState {
                 }
             }   
         }
+        if self.path_of_selected_file.ne(&versions_for_selected_file.path) {
+            self.marked_range.clear();
+            self.replay = None;
+            self.version_window = VERSION_WINDOW;
+        }
         self.path_of_selected_file = versions_for_selected_file.path.clone();
-        for v in &versions_for_selected_file.versions {
-            self.snapshots.add_item(v.datetime.to_string());
+        let versions = &versions_for_selected_file.versions;
+        let row = |datetime, source, summary: &str, label: &Option<String>| {
+            let label = label
+                .as_ref()
+                .map_or(String::new(), |label| format!("  {}", label));
+            format!("{}  {}  {}{}", datetime, source, summary, label)
+        };
+        if self
+            .version_list
+            .is_current(versions_for_selected_file, self.version_window)
+        {
+            let listed = versions.len().min(self.version_window);
+            for v in self.version_list.versions.iter().take(listed) {
+                let item = row(v.datetime, v.source, &v.summary.to_string(), &v.label);
+                self.snapshots.add_item(item);
+            }
+        } else {
+            // the counts are the store's, until it listed them they are left out
+            for v in versions.iter().take(self.version_window) {
+                let item = row(v.datetime, v.source, "…", &v.label);
+                self.snapshots.add_item(item);
+            }
+        }
+        // a busy file has thousands of versions, the older ones are listed on demand
+        let older = versions.len().saturating_sub(self.version_window);
+        if older > 0 {
+            self.snapshots.add_item(format!(
+                "… {} older versions, l lists {} more",
+                older,
+                older.min(VERSION_WINDOW)
+            ));
+        }
+    }
+    ///
+    /// the file and the window of versions to ask the store for, unless the
+    /// snapshot pane lists them already or they were asked for
+    ///
+    pub fn versions_to_list(&mut self) -> Option<(String, usize)> {
+        let file_versions = self.selected_file_versions()?;
+        if self
+            .version_list
+            .is_current(file_versions, self.version_window)
+        {
+            return None;
+        }
+        let path = file_versions.path.clone();
+        let requested = (
+            path.clone(),
+            self.version_window,
+            file_versions.version_count,
+            file_versions.epoch,
+        );
+        if self.version_list.requested.as_ref() == Some(&requested) {
+            return None;
+        }
+        self.version_list.requested = Some(requested);
+        Some((path, self.version_window))
+    }
+
+    /// lists `versions` in the snapshot pane if they are the ones of the selected file
+    pub fn on_version_list(&mut self, path: String, epoch: u64, versions: Vec<VersionSummary>) {
+        self.version_list.path = path;
+        self.version_list.epoch = epoch;
+        self.version_list.versions = versions;
+        let current = self.selected_file_versions().is_some_and(|file_versions| {
+            self.version_list
+                .is_current(file_versions, self.version_window)
+        });
+        if current {
+            self.update_file_pane();
+        }
+    }
+
+    ///
+    /// the version index behind the `position`th item of the snapshot pane
+    ///
+    pub fn version_at(&self, position: usize) -> Option<usize> {
+        self.file_versions
+            .get(self.id_of_selected_file)?
+            .as_ref()?
+            .versions
+            .get(position)
+            .filter(|_| position < self.version_window)
+            .map(|version| version.index)
+    }
+
+    /// how many versions of the selected file the snapshot pane lists
+    fn listed_versions(&self) -> usize {
+        self.selected_file_versions().map_or(0, |file_versions| {
+            file_versions.versions.len().min(self.version_window)
+        })
+    }
+
+    pub fn selected_file_versions(&self) -> Option<&FileVersions> {
+        self.file_versions.get(self.id_of_selected_file)?.as_ref()
+    }
+
+    /// whether the selected file has an older version to go back to
+    pub fn can_undo(&self) -> bool {
+        self.selected_file_versions()
+            .is_some_and(|file_versions| file_versions.head > 0)
+    }
+
+    /// whether an undo of the selected file can be redone
+    pub fn can_redo(&self) -> bool {
+        self.selected_file_versions()
+            .is_some_and(|file_versions| file_versions.head + 1 < file_versions.version_count)
+    }
+
+    pub fn selected_version(&mut self) -> Option<usize> {
+        self.snapshots.get_index().and_then(|i| self.version_at(i))
+    }
+
+    ///
+    /// asks the handle for the content or diff of the selected snapshot,
+    /// the answer ends up on the clipboard. with a range marked, the diff
+    /// spans it instead.
+    ///
+    pub fn on_copy(&mut self, target: CopyTarget) {
+        let path = self.path_of_selected_file.clone();
+        match (target, self.marked_range.bounds(), self.selected_version()) {
+            (CopyTarget::Diff, Some((lower, upper)), _) => {
+                self.should_copy = Some((CopyTarget::DiffFrom(lower), path, upper));
+            }
+            (_, _, Some(version)) => {
+                self.should_copy = Some((target, path, version));
+            }
+            (_, _, None) => {
+                self.status = String::from("No snapshot selected");
+            }
+        }
+    }
+
+    /// whether the file couldn't be read when it last changed
+    pub fn is_inaccessible(&self, path: &str) -> bool {
+        self.file_versions
+            .iter()
+            .flatten()
+            .any(|file_versions| file_versions.path == path && file_versions.inaccessible)
+    }
+
+    /// whether the changes of the file are left unrecorded
+    pub fn is_disabled(&self, path: &str) -> bool {
+        self.file_versions
+            .iter()
+            .flatten()
+            .any(|file_versions| file_versions.path == path && file_versions.disabled)
+    }
+
+    /// whether cleanups leave every version of the file alone, see `Retention::Forever`
+    pub fn is_pinned(&self, path: &str) -> bool {
+        self.file_versions.iter().flatten().any(|file_versions| {
+            file_versions.path == path && file_versions.retention == Some(Retention::Forever)
+        })
+    }
+
+    /// the debounce set for the file in place of the global one
+    pub fn debounce_of(&self, path: &str) -> Option<Duration> {
+        self.file_versions
+            .iter()
+            .flatten()
+            .find(|file_versions| file_versions.path == path)
+            .and_then(|file_versions| file_versions.debounce)
+    }
+
+    /// whether the file only keeps its changed lines, its versions can't be shown whole
+    pub fn is_context_only(&self, path: &str) -> bool {
+        self.file_versions
+            .iter()
+            .flatten()
+            .any(|file_versions| file_versions.path == path && file_versions.context_only)
+    }
+
+    /// whether the file has lines too long to diff, its versions keep every line
+    pub fn has_long_lines(&self, path: &str) -> bool {
+        self.file_versions
+            .iter()
+            .flatten()
+            .any(|file_versions| file_versions.path == path && file_versions.long_lines)
+    }
+
+    ///
+    /// the selected version if the file view shows it and its content
+    /// wasn't asked for yet
+    ///
+    pub fn version_to_show(&mut self) -> Option<(String, usize)> {
+        if !self.file_view.visible {
+            return None;
         }
+        let selected = (self.path_of_selected_file.clone(), self.selected_version()?);
+        if self.file_view.requested.as_ref() == Some(&selected) {
+            return None;
+        }
+        self.file_view.requested = Some(selected.clone());
+        self.file_view.show("");
+        Some(selected)
     }
+
+    /// shows `content` if it is still the one the file view waits for
+    pub fn on_version_content(
+        &mut self,
+        path: String,
+        version: usize,
+        content: Result<String, String>,
+    ) {
+        if self.file_view.requested != Some((path, version)) {
+            return;
+        }
+        match content {
+            Ok(content) => self.file_view.show(&content),
+            Err(err) => self.status = err,
+        }
+    }
+
+    ///
+    /// starts replaying the selected file from its oldest version, a
+    /// running replay is stopped instead. the snapshot pane takes the
+    /// selection, so the differences or the file view follow each step.
+    ///
+    pub fn on_replay(&mut self) {
+        if self.replay.take().is_some() {
+            self.status = String::from("Replay stopped");
+            return;
+        }
+        if self.snapshots.list_is_empty() {
+            self.status = String::from("No versions to replay");
+            return;
+        }
+        self.pane_ptr = -1;
+        // the latest version is listed first
+        self.snapshots
+            .state
+            .select(Some(self.listed_versions() - 1));
+        self.update_snapshot_pane();
+        self.replay = Some(Replay::new(Instant::now()));
+        self.status = format!("Replaying {}, press v to stop", self.path_of_selected_file);
+    }
+
+    ///
+    /// shows the version after the selected one. the replay pauses at the
+    /// latest version, playing it again starts over from the oldest.
+    ///
+    pub fn step_replay(&mut self, now: Instant) {
+        let replay = match self.replay.as_mut() {
+            Some(replay) => replay,
+            None => {
+                self.status = String::from("No replay running");
+                return;
+            }
+        };
+        replay.stepped(now);
+        match self.snapshots.get_index() {
+            Some(0) | None => {
+                replay.playing = false;
+                self.status = String::from("Replay finished");
+            }
+            Some(i) => {
+                self.snapshots.state.select(Some(i - 1));
+                self.update_snapshot_pane();
+            }
+        }
+    }
+
+    /// steps the replay once its interval is over, returns whether it did
+    pub fn on_replay_tick(&mut self, now: Instant) -> bool {
+        if !self
+            .replay
+            .as_ref()
+            .is_some_and(|replay| replay.is_due(now))
+        {
+            return false;
+        }
+        self.step_replay(now);
+        self.dirty = true;
+        true
+    }
+
+    fn on_pause_replay(&mut self) {
+        let at_latest = self.snapshots.get_index() == Some(0);
+        let replay = match self.replay.as_mut() {
+            Some(replay) => replay,
+            None => {
+                self.status = String::from("No replay running");
+                return;
+            }
+        };
+        replay.playing = !replay.playing;
+        replay.stepped(Instant::now());
+        if replay.playing && at_latest {
+            let oldest = self.listed_versions() - 1;
+            self.snapshots.state.select(Some(oldest));
+            self.update_snapshot_pane();
+        }
+        self.status.clear();
+    }
+
+    ///
+    /// marks the selected snapshot as one end of a version range,
+    /// marking a complete range again clears it
+    ///
+    pub fn on_mark(&mut self) {
+        let version = match self.selected_version() {
+            Some(version) => version,
+            None => {
+                self.status = String::from("No snapshot selected");
+                return;
+            }
+        };
+
+        self.marked_range.mark(version);
+        self.status = match (self.marked_range.bounds(), self.marked_range.anchor) {
+            (Some((lower, upper)), _) => {
+                format!(
+                    "Marked versions {} to {}, copy diff spans them",
+                    lower, upper
+                )
+            }
+            (None, Some(anchor)) => format!("Marked version {}, mark the other end", anchor),
+            (None, None) => String::from("Range cleared"),
+        };
+    }
+
+    ///
+    /// takes over the epoch of a new view. once maintenance dropped or
+    /// renumbered versions, the selected snapshot and the marked range may
+    /// point to other versions or none, so they start over.
+    ///
+    pub fn on_epoch(&mut self, epoch: u64) {
+        if epoch == self.epoch {
+            return;
+        }
+        self.epoch = epoch;
+        if self.snapshots.get_index().is_none() && self.marked_range == VersionRange::default() {
+            return;
+        }
+        self.snapshots.unselect();
+        self.update_file_pane();
+        self.marked_range.clear();
+        self.processed_diffs.clear();
+        self.file_view.requested = None;
+        self.file_view.show("");
+        self.status = String::from("Versions were pruned, the snapshot selection was reset");
+    }
+
     ///
     /// loads the changes for the selected file, places them into diffpane
     /// 
     pub fn update_snapshot_pane(&mut self) {
-        let selected_file = &self.file_versions[self.id_of_selected_file];
-        if selected_file.is_none() {
-            return;
-        }
-        let selected_file = selected_file.as_ref().unwrap();
+        let selected_file = match self.file_versions.get(self.id_of_selected_file) {
+            Some(Some(selected_file)) => selected_file,
+            _ => return,
+        };
         if !selected_file.versions.is_empty() {
-            if let Some(i) = self.snapshots.get_index() {
-                    let selected_version = &selected_file.versions[i];
-                    let diffs_for_this_version = &selected_version.changes;
-                    self.processed_diffs.clear();
-                    self.processed_diffs = process_new_version(diffs_for_this_version.clone());
-                    self.processed_diffs.reverse();
+            if let Some(selected_version) = self
+                .snapshots
+                .get_index()
+                .filter(|i| *i < self.version_window)
+                .and_then(|i| selected_file.versions.get(i))
+            {
+                let diffs_for_this_version = &selected_version.changes;
+                self.processed_diffs.clear();
+                self.processed_diffs = if selected_version.long_lines {
+                    process_long_lines_version(diffs_for_this_version)
+                } else {
+                    process_new_version(diffs_for_this_version.clone())
+                };
+                self.processed_diffs.reverse();
             }
         }
     }
 
+    /// a page of the handle goes into the files shown so far
+    pub fn show_view(&mut self, view: UiView) {
+        match view {
+            UiView::Full(view) => self.show_file_versions(view),
+            UiView::Page(files) => {
+                let mut view = std::mem::take(&mut self.file_versions);
+                merge_page(&mut view, files);
+                self.show_file_versions(view);
+            }
+        }
+    }
+
+    ///
+    /// the files the file list may show around the selected one, as an
+    /// offset and a count of the files ordered by path like the handle
+    /// orders them. ordered by time they can lie far apart, the page then
+    /// spans everything between them.
+    ///
+    pub fn visible_page(&self) -> Option<(usize, usize)> {
+        if self.file_rows == 0 {
+            return None;
+        }
+        let mut by_path = self
+            .file_versions
+            .iter()
+            .flatten()
+            .map(|file_versions| file_versions.path.as_str())
+            .collect::<Vec<_>>();
+        by_path.sort_unstable();
+        // the list scrolls the selected file anywhere into its rows
+        let selected = self.filenames.state.selected().unwrap_or(0);
+        let positions = self
+            .filenames
+            .items
+            .iter()
+            .skip(selected.saturating_sub(self.file_rows))
+            .take(self.file_rows * 2)
+            .filter_map(|path| by_path.binary_search(&path.as_str()).ok())
+            .collect::<Vec<_>>();
+        let first = *positions.iter().min()?;
+        let last = *positions.iter().max()?;
+        Some((first, last - first + 1))
+    }
+
+    ///
+    /// puts the files of a view into the filename pane in `file_order`,
+    /// the selection follows the file, not its position
+    ///
+    pub fn show_file_versions(&mut self, file_versions: Vec<Option<FileVersions>>) {
+        self.file_versions = file_versions;
+        self.file_order.sort(&mut self.file_versions);
+        self.filenames.flush_display();
+        for file_versions in self.file_versions.iter().flatten() {
+            self.filenames.add_item(file_versions.path.clone());
+        }
+        if let Some(i) = self
+            .filenames
+            .items
+            .iter()
+            .position(|path| path.eq(&self.path_of_selected_file))
+        {
+            self.filenames.state.select(Some(i));
+            self.id_of_selected_file = i;
+        }
+        // a purged file leaves the list shorter
+        self.filenames.clamp();
+    }
+
     /// Reads state of selected pane
     /// 0 -> Pane for stored files
     /// 1 -> Pane for available snapshot for selected file
@@ -230,18 +832,204 @@ impl UIState {
     }
 
     /// expects a char to process actions
-    /// ```
+    /// ```ignore
     /// //will quit the application
     /// on_key('q') {}
     /// ```
     pub fn on_key(&mut self, c: char) {
-        match c {
-            'q' => {
+        // purging can't be undone, so it waits for a confirming 'y'
+        if let Some(path) = self.purge_candidate.take() {
+            if c == 'y' {
+                self.status = format!("Purged {}", path);
+                self.should_purge = Some(path);
+            } else {
+                self.status = String::from("Purge cancelled");
+            }
+            return;
+        }
+        if c == ':' {
+            self.palette = Some(CommandPalette::default());
+            return;
+        }
+        if let Some(action) = actions::for_key(c) {
+            self.on_action(action);
+        }
+    }
+
+    /// runs `action` against the selected file or snapshot
+    pub fn on_action(&mut self, action: Action) {
+        match action {
+            Action::Quit => {
                 self.should_quit = true;
             }
-            's' => {
+            Action::SwitchPanes => {
                 self.pane_ptr *= -1;
             }
+            Action::OpenInEditor => {
+                self.should_open_editor = true;
+            }
+            Action::MarkRange => {
+                self.on_mark();
+            }
+            Action::SortFiles => {
+                self.file_order = self.file_order.next();
+                let file_versions = std::mem::take(&mut self.file_versions);
+                self.show_file_versions(file_versions);
+                self.status = format!("Files sorted by {}", self.file_order);
+            }
+            Action::ClearMarks => {
+                self.marked_range.clear();
+                self.status = String::from("Range cleared");
+            }
+            Action::ToggleFileView => {
+                self.file_view.visible = !self.file_view.visible;
+                self.file_view.requested = None;
+            }
+            Action::CopyVersion => {
+                self.on_copy(CopyTarget::Content);
+            }
+            Action::CopyDiff => {
+                self.on_copy(CopyTarget::Diff);
+            }
+            Action::Checkpoint => {
+                if self.path_of_selected_file.is_empty() {
+                    self.status = String::from("No file selected");
+                } else {
+                    self.status = format!("Checkpoint of {}", self.path_of_selected_file);
+                    self.should_checkpoint = Some(self.path_of_selected_file.clone());
+                }
+            }
+            Action::ToggleDisabled => {
+                let path = self.path_of_selected_file.clone();
+                if path.is_empty() {
+                    self.status = String::from("No file selected");
+                } else {
+                    self.status = match self.is_disabled(&path) {
+                        true => format!("Recording {} again", path),
+                        false => format!("Stopped recording {}", path),
+                    };
+                    self.should_toggle_disabled = Some(path);
+                }
+            }
+            Action::TogglePinned => {
+                let path = self.path_of_selected_file.clone();
+                if path.is_empty() {
+                    self.status = String::from("No file selected");
+                } else {
+                    self.status = match self.is_pinned(&path) {
+                        true => format!("{} is cleaned up by the retention again", path),
+                        false => format!("Pinned the history of {}", path),
+                    };
+                    self.should_toggle_pinned = Some(path);
+                }
+            }
+            Action::RecordNow => {
+                if self.path_of_selected_file.is_empty() {
+                    self.status = String::from("No file selected");
+                } else {
+                    self.status =
+                        format!("Recording {} now if it changed", self.path_of_selected_file);
+                    self.should_record_now = Some(self.path_of_selected_file.clone());
+                }
+            }
+            Action::LoadMoreVersions => {
+                let count = self
+                    .selected_file_versions()
+                    .map_or(0, |file_versions| file_versions.versions.len());
+                if count <= self.version_window {
+                    self.status = String::from("Every version is listed");
+                } else {
+                    self.version_window += VERSION_WINDOW;
+                    self.update_file_pane();
+                    self.status =
+                        format!("Listing {} of {} versions", self.listed_versions(), count);
+                }
+            }
+            Action::SetDebounce => {
+                let path = self.path_of_selected_file.clone();
+                if path.is_empty() {
+                    self.status = String::from("No file selected");
+                } else {
+                    let global = self.global_debounce;
+                    let debounce = util::next_debounce(self.debounce_of(&path), global);
+                    self.status = match debounce {
+                        Some(debounce) => {
+                            format!("{} waits {}ms for saves", path, debounce.as_millis())
+                        }
+                        None => format!(
+                            "{} uses the global debounce of {}ms",
+                            path,
+                            global.as_millis()
+                        ),
+                    };
+                    self.should_set_debounce = Some((path, debounce));
+                }
+            }
+            Action::Purge => {
+                if self.path_of_selected_file.is_empty() {
+                    self.status = String::from("No file selected");
+                } else {
+                    self.status = format!(
+                        "Purge all history of {}? Press y to confirm",
+                        self.path_of_selected_file
+                    );
+                    self.purge_candidate = Some(self.path_of_selected_file.clone());
+                }
+            }
+            Action::Undo => {
+                if self.can_undo() {
+                    self.should_undo = Some(self.path_of_selected_file.clone());
+                } else {
+                    self.status = String::from("Nothing to undo");
+                }
+            }
+            Action::Redo => {
+                if self.can_redo() {
+                    self.should_redo = Some(self.path_of_selected_file.clone());
+                } else {
+                    self.status = String::from("Nothing to redo");
+                }
+            }
+            Action::Replay => {
+                self.on_replay();
+            }
+            Action::PauseReplay => {
+                self.on_pause_replay();
+            }
+            Action::StepReplay => {
+                self.step_replay(Instant::now());
+            }
+            Action::ReplayFaster | Action::ReplaySlower => match self.replay.as_mut() {
+                Some(replay) if action == Action::ReplayFaster => replay.faster(),
+                Some(replay) => replay.slower(),
+                None => self.status = String::from("No replay running"),
+            },
+        }
+    }
+
+    ///
+    /// types into the open command palette, enter runs the chosen
+    /// action and esc closes it without running anything
+    ///
+    pub fn on_palette_key(&mut self, code: KeyCode) {
+        let palette = match self.palette.as_mut() {
+            Some(palette) => palette,
+            None => return,
+        };
+        match code {
+            KeyCode::Char(c) => palette.push(c),
+            KeyCode::Backspace => palette.pop(),
+            KeyCode::Up => palette.previous(),
+            KeyCode::Down | KeyCode::Tab => palette.next(),
+            KeyCode::Esc => self.palette = None,
+            KeyCode::Enter => {
+                let chosen = palette.chosen();
+                self.palette = None;
+                match chosen {
+                    Some(action) => self.on_action(action),
+                    None => self.status = String::from("No matching command"),
+                }
+            }
             _ => {}
         }
     }
@@ -259,18 +1047,53 @@ pub struct UI {
 }
 
 impl UI {
-    pub fn new(title: String, communication: UICommunication) -> UI {
+    ///
+    /// `time_frames` are the tabs with their labels, the first one is
+    /// selected. there has to be at least one.
+    ///
+    pub fn new(
+        title: String,
+        communication: UICommunication,
+        time_frames: Vec<(String, TimeFrame)>,
+    ) -> UI {
+        let (titles, slots): (Vec<String>, Vec<TimeFrame>) = time_frames.into_iter().unzip();
         UI {
             config: UIConfig {
                 title,
+                view_coalesce: DEFAULT_VIEW_COALESCE,
+                filename_truncation: Truncation::default(),
             },
             state: UIState {
-                tabs: TabsState::new(vec![
-                    "1h".to_string(),
-                    "24h".to_string(),
-                    "7 Tage".to_string(),
-                ]),
+                tabs: TabsState::new(titles),
                 should_quit: false,
+                should_open_editor: false,
+                purge_candidate: None,
+                should_purge: None,
+                should_checkpoint: None,
+                should_toggle_disabled: None,
+                should_set_debounce: None,
+                should_toggle_pinned: None,
+                should_record_now: None,
+                version_window: VERSION_WINDOW,
+                version_list: VersionList::default(),
+                should_copy: None,
+                should_undo: None,
+                should_redo: None,
+                status: String::new(),
+                watch_root_available: true,
+                marked_range: VersionRange::default(),
+                debounces: HashMap::new(),
+                global_debounce: Duration::ZERO,
+                maintenance_running: false,
+                progress: None,
+                metrics: None,
+                file_view: FileView::default(),
+                palette: None,
+                replay: None,
+                epoch: 0,
+                file_order: FileOrder::default(),
+                dirty: true,
+                file_rows: 0,
                 file_versions: Vec::new(),
                 snapshots: StatefulList::with_items(vec![]),
                 filenames: StatefulList::with_items(vec![String::from("loading...")]),
@@ -284,9 +1107,24 @@ impl UI {
                 pane_ptr: 1,
             },
             communication,
-            timeslots: UITimeSlots {
-                slots: vec![TimeFrame::HOUR, TimeFrame::DAY, TimeFrame::WEEK],
-            },
+            timeslots: UITimeSlots { slots },
         }
     }
+
+    /// merges the views arriving within `window`, zero draws each of them
+    pub fn with_view_coalesce(mut self, window: Duration) -> UI {
+        self.config.view_coalesce = window;
+        self
+    }
+
+    pub fn with_filename_truncation(mut self, truncation: Truncation) -> UI {
+        self.config.filename_truncation = truncation;
+        self
+    }
+
+    /// only per-file debounces above the one the watcher waits for are offered
+    pub fn with_global_debounce(mut self, debounce: Duration) -> UI {
+        self.state.global_debounce = debounce;
+        self
+    }
 }
@@ -1,4 +1,9 @@
 use diff::LineDifference;
+use std::cmp::Reverse;
+use std::fmt;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use serde::Deserialize;
+use store::store::{FileVersions, VersionSummary};
 use tui::style::{Color, Modifier, Style};
 use tui::text::{Span, Spans};
 use tui::widgets::ListState;
@@ -10,6 +15,12 @@ pub struct TabsState {
 static IS_DANGER: Color = Color::Rgb(191, 97, 106);
 static IS_SUCCESS: Color = Color::Rgb(163, 190, 140);
 static IS_LIGHT_WITE: Color = Color::Rgb(216, 222, 233);
+/// how long a replay shows each version at first
+static REPLAY_INTERVAL: Duration = Duration::from_secs(1);
+static REPLAY_FASTEST: Duration = Duration::from_millis(125);
+static REPLAY_SLOWEST: Duration = Duration::from_secs(8);
+/// the debounces in milliseconds a file can be given with 'b'
+static DEBOUNCE_STEPS: [u64; 5] = [250, 500, 1000, 2000, 5000];
 
 impl TabsState {
     pub fn new(titles: Vec<String>) -> TabsState {
@@ -30,6 +41,19 @@ impl TabsState {
     }
 }
 
+/// a version keeping every line, listing them would only repeat the file
+pub fn process_long_lines_version(diffs: &[LineDifference]) -> Vec<Spans<'static>> {
+    vec![Spans::from(Span::styled(
+        format!(
+            "{} lines kept whole, they are too long to diff",
+            diffs.len()
+        ),
+        Style::default()
+            .add_modifier(Modifier::ITALIC)
+            .fg(IS_LIGHT_WITE),
+    ))]
+}
+
 pub fn process_new_version(diffs: Vec<LineDifference>) -> Vec<Spans<'static>> {
     let mut v: Vec<Span> = vec![];
     let mut spans: Vec<Spans> = vec![];
@@ -49,6 +73,19 @@ pub fn process_new_version(diffs: Vec<LineDifference>) -> Vec<Spans<'static>> {
         ));
         v.push(Span::raw(" » "));
 
+        if let Some(moved) = &diff.moved {
+            v.push(Span::styled(
+                format!("moved {} lines from l{}", moved.len, moved.from_line),
+                Style::default()
+                    .add_modifier(Modifier::ITALIC)
+                    .fg(IS_LIGHT_WITE),
+            ));
+            v.push(Span::raw("\n"));
+            spans.push(Spans::from(v.clone()));
+            v.clear();
+            continue;
+        }
+
         let mut previous_line = diff.line.clone();
         if previous_line.is_empty() {
             previous_line = String::from("< empty line >");
@@ -80,6 +117,347 @@ pub fn process_new_version(diffs: Vec<LineDifference>) -> Vec<Spans<'static>> {
     spans
 }
 
+/// How the filename pane is ordered, cycled with 'o'.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum FileOrder {
+    /// the file changed last first
+    #[default]
+    Recent,
+    Name,
+    /// the file tracked last first
+    FirstSeen,
+}
+
+impl FileOrder {
+    pub fn next(self) -> FileOrder {
+        match self {
+            FileOrder::Recent => FileOrder::Name,
+            FileOrder::Name => FileOrder::FirstSeen,
+            FileOrder::FirstSeen => FileOrder::Recent,
+        }
+    }
+
+    /// files with the same time stay ordered by path
+    pub fn sort(self, file_versions: &mut [Option<FileVersions>]) {
+        file_versions.sort_by_cached_key(|file_versions| {
+            file_versions.as_ref().map(|versions| {
+                let time = match self {
+                    FileOrder::Recent => versions.last_modified,
+                    FileOrder::Name => UNIX_EPOCH,
+                    FileOrder::FirstSeen => versions.first_seen,
+                };
+                (Reverse(time), versions.path.clone())
+            })
+        });
+    }
+}
+
+impl fmt::Display for FileOrder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            FileOrder::Recent => "recently changed",
+            FileOrder::Name => "name",
+            FileOrder::FirstSeen => "first seen",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+///
+/// two version indices marked in the snapshot pane, the first mark sets
+/// the anchor, the second the other end and a third one clears the range
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct VersionRange {
+    pub anchor: Option<usize>,
+    pub end: Option<usize>,
+}
+
+impl VersionRange {
+    pub fn mark(&mut self, version: usize) {
+        match (self.anchor, self.end) {
+            (None, _) => self.anchor = Some(version),
+            (Some(_), None) => self.end = Some(version),
+            (Some(_), Some(_)) => self.clear(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.anchor = None;
+        self.end = None;
+    }
+
+    /// the lower and upper version of a complete range
+    pub fn bounds(&self) -> Option<(usize, usize)> {
+        match (self.anchor, self.end) {
+            (Some(anchor), Some(end)) => Some((anchor.min(end), anchor.max(end))),
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, version: usize) -> bool {
+        match self.bounds() {
+            Some((lower, upper)) => lower <= version && version <= upper,
+            None => self.anchor == Some(version),
+        }
+    }
+}
+
+///
+/// the full content of one version, shown in place of the differences.
+/// only the lines in view get rendered, so large files scroll as well.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FileView {
+    pub visible: bool,
+    /// the file and version the content was asked for
+    pub requested: Option<(String, usize)>,
+    pub lines: Vec<String>,
+    /// index of the first line in view
+    pub scroll: usize,
+    /// lines fitting into the pane at the last draw
+    pub height: usize,
+}
+
+impl FileView {
+    pub fn show(&mut self, content: &str) {
+        self.lines = content.lines().map(|line| line.to_string()).collect();
+        self.scroll = 0;
+    }
+
+    pub fn page_down(&mut self) {
+        let last_page = self.lines.len().saturating_sub(self.height);
+        self.scroll = (self.scroll + self.height.max(1)).min(last_page);
+    }
+
+    pub fn page_up(&mut self) {
+        self.scroll = self.scroll.saturating_sub(self.height.max(1));
+    }
+
+    /// the lines in view with their line number, counting from 1
+    pub fn visible_lines(&self) -> impl Iterator<Item = (usize, &String)> {
+        self.lines
+            .iter()
+            .enumerate()
+            .skip(self.scroll)
+            .take(self.height)
+            .map(|(index, line)| (index + 1, line))
+    }
+}
+
+///
+/// the latest versions of the selected file as the store sums them up,
+/// the snapshot pane lists them instead of counting the changes itself
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VersionList {
+    /// the file, the window, its version count and the epoch asked for last
+    pub requested: Option<(String, usize, usize, u64)>,
+    pub path: String,
+    /// the `Store::epoch` the versions were listed in
+    pub epoch: u64,
+    pub versions: Vec<VersionSummary>,
+}
+
+impl VersionList {
+    /// whether it holds the latest `window` versions of `file_versions` as they are now
+    pub fn is_current(&self, file_versions: &FileVersions, window: usize) -> bool {
+        let latest = self.versions.first().map(|version| version.index + 1);
+        self.path == file_versions.path
+            && self.epoch == file_versions.epoch
+            && latest == Some(file_versions.version_count)
+            && self.versions.len() >= window.min(file_versions.versions.len())
+    }
+}
+
+///
+/// which part of a path the filename pane drops once the path is wider
+/// than the pane, the dropped part is shown as an ellipsis
+///
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Truncation {
+    /// keeps the start of the path
+    Head,
+    /// keeps the top-level directory and as much of the end as fits
+    #[default]
+    Middle,
+    /// keeps the end of the path
+    Tail,
+}
+
+impl Truncation {
+    /// `path` shortened to at most `width` characters
+    pub fn apply(self, path: &str, width: usize) -> String {
+        let length = path.chars().count();
+        if length <= width {
+            return path.to_string();
+        }
+        let from_end = |count: usize| path.chars().skip(length - count).collect::<String>();
+        match self {
+            _ if width == 0 => String::new(),
+            Truncation::Head => format!("{}…", path.chars().take(width - 1).collect::<String>()),
+            Truncation::Tail => format!("…{}", from_end(width - 1)),
+            Truncation::Middle => {
+                let top_level = path
+                    .char_indices()
+                    .filter(|(_, c)| *c == '/')
+                    .map(|(index, _)| index + 1)
+                    .find(|end| *end > 1)
+                    .map_or(path, |end| &path[..end]);
+                let head = top_level.chars().count();
+                // the end is cut at a separator, the filename at least stays whole
+                let tail = path
+                    .char_indices()
+                    .filter(|(_, c)| *c == '/')
+                    .map(|(index, _)| path[index + 1..].chars().count())
+                    .filter(|tail| head + 2 + tail <= width && head + tail < length)
+                    .max();
+                match tail {
+                    Some(tail) => format!("{}…/{}", top_level, from_end(tail)),
+                    None => Truncation::Tail.apply(path, width),
+                }
+            }
+        }
+    }
+}
+
+///
+/// limits the redraws to one per `interval`, a change inside the interval
+/// is drawn once it is over
+///
+pub struct FrameClock {
+    interval: Duration,
+    last_frame: Option<Instant>,
+}
+
+impl FrameClock {
+    pub fn new(interval: Duration) -> FrameClock {
+        FrameClock {
+            interval,
+            last_frame: None,
+        }
+    }
+
+    /// whether to draw a frame now, nothing `changed` needs none
+    pub fn should_draw(&mut self, changed: bool, now: Instant) -> bool {
+        let due = self
+            .last_frame
+            .is_none_or(|last_frame| now.duration_since(last_frame) >= self.interval);
+        if changed && due {
+            self.last_frame = Some(now);
+        }
+        changed && due
+    }
+}
+
+///
+/// merges the values pushed within `window` of the first one into one,
+/// a burst of views gets shown once instead of one after another
+///
+pub struct Coalesced<T> {
+    window: Duration,
+    /// the latest value with the time the first one of the window arrived
+    pending: Option<(Instant, T)>,
+}
+
+impl<T> Coalesced<T> {
+    pub fn new(window: Duration) -> Coalesced<T> {
+        Coalesced {
+            window,
+            pending: None,
+        }
+    }
+
+    ///
+    /// `merge` combines a value still waiting with the new one, without
+    /// starting its window over
+    ///
+    pub fn merge(&mut self, value: T, now: Instant, merge: impl FnOnce(T, T) -> T) {
+        let (since, value) = match self.pending.take() {
+            Some((since, pending)) => (since, merge(pending, value)),
+            None => (now, value),
+        };
+        self.pending = Some((since, value));
+    }
+
+    /// the latest value once its window is over
+    pub fn take_due(&mut self, now: Instant) -> Option<T> {
+        match self.pending {
+            Some((since, _)) if now.duration_since(since) >= self.window => {
+                self.pending.take().map(|(_, value)| value)
+            }
+            _ => None,
+        }
+    }
+}
+
+///
+/// steps through the versions of the selected file from the oldest to the
+/// latest, by itself every `interval` while it is playing
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replay {
+    pub playing: bool,
+    pub interval: Duration,
+    /// when the version in view was stepped to
+    last_step: Instant,
+}
+
+impl Replay {
+    pub fn new(now: Instant) -> Replay {
+        Replay {
+            playing: true,
+            interval: REPLAY_INTERVAL,
+            last_step: now,
+        }
+    }
+
+    /// whether the next version is due to be shown
+    pub fn is_due(&self, now: Instant) -> bool {
+        self.playing && now.duration_since(self.last_step) >= self.interval
+    }
+
+    /// the next version is due one interval after `now`
+    pub fn stepped(&mut self, now: Instant) {
+        self.last_step = now;
+    }
+
+    pub fn faster(&mut self) {
+        self.interval = (self.interval / 2).max(REPLAY_FASTEST);
+    }
+
+    pub fn slower(&mut self) {
+        self.interval = (self.interval * 2).min(REPLAY_SLOWEST);
+    }
+}
+
+impl fmt::Display for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = if self.playing { "playing" } else { "paused" };
+        write!(
+            f,
+            "Replay {}, {}ms per version",
+            state,
+            self.interval.as_millis()
+        )
+    }
+}
+
+///
+/// the debounce following `current` in `DEBOUNCE_STEPS`. after the
+/// longest one it is `None`, the file goes back to the global debounce.
+/// the watcher holds every save back for `global` already, so only the
+/// steps above it are offered.
+///
+pub fn next_debounce(current: Option<Duration>, global: Duration) -> Option<Duration> {
+    DEBOUNCE_STEPS
+        .iter()
+        .map(|millis| Duration::from_millis(*millis))
+        .filter(|step| *step > global)
+        .find(|step| current.is_none_or(|current| *step > current))
+}
+
 pub struct StatefulList<T> {
     pub state: ListState,
     pub items: Vec<T>,
@@ -157,6 +535,15 @@ impl<T> StatefulList<T> {
     pub fn unselect(&mut self) {
         self.state.select(None);
     }
+
+    /// keeps the selection on an item after the list shrank, an empty list has none
+    pub fn clamp(&mut self) {
+        match (self.state.selected(), self.items.len()) {
+            (Some(_), 0) => self.unselect(),
+            (Some(i), len) if i >= len => self.state.select(Some(len - 1)),
+            _ => {}
+        }
+    }
 }
 
 #[cfg(test)]
@@ -171,6 +558,192 @@ mod tests {
             "".to_string(),
             "Hello World".to_string(),
         )];
-        assert_eq!(2, 2);
+        assert_eq!(process_new_version(diff).len(), 1);
+    }
+
+    #[test]
+    fn marking_a_version_range() {
+        let mut range = VersionRange::default();
+        range.mark(7);
+        assert!(range.contains(7) && !range.contains(5));
+        assert_eq!(range.bounds(), None);
+
+        range.mark(3);
+        assert_eq!(range.bounds(), Some((3, 7)));
+        assert!(range.contains(5) && !range.contains(8));
+
+        range.mark(4);
+        assert_eq!(range, VersionRange::default());
+    }
+
+    #[test]
+    fn selection_stays_inside_a_shrunk_list() {
+        let mut list = StatefulList::with_items(vec![1, 2, 3]);
+        list.state.select(Some(2));
+        list.items.truncate(2);
+        list.clamp();
+        assert_eq!(list.get_index(), Some(1));
+        list.clamp();
+        assert_eq!(list.get_index(), Some(1));
+
+        list.flush_display();
+        list.clamp();
+        assert_eq!(list.get_index(), None);
+    }
+
+    #[test]
+    fn file_order_puts_the_latest_change_first() {
+        let at = |secs: u64| UNIX_EPOCH + std::time::Duration::from_secs(secs);
+        let file = |path: &str, first_seen: u64, last_modified: u64| {
+            Some(FileVersions {
+                path: path.to_string(),
+                versions: Vec::new(),
+                hits_of_codes: Vec::new(),
+                head: 0,
+                version_count: 1,
+                inaccessible: false,
+                context_only: false,
+                long_lines: false,
+                first_seen: at(first_seen),
+                last_modified: at(last_modified),
+                disabled: false,
+                debounce: None,
+                retention: None,
+                epoch: 0,
+            })
+        };
+        let mut view = vec![file("a", 1, 5), file("b", 3, 9), file("c", 2, 5)];
+        let paths = |view: &[Option<FileVersions>]| {
+            view.iter()
+                .flatten()
+                .map(|file_versions| file_versions.path.as_str())
+                .collect::<String>()
+        };
+
+        let order = FileOrder::default();
+        order.sort(&mut view);
+        assert_eq!(paths(&view), "bac");
+        order.next().sort(&mut view);
+        assert_eq!(paths(&view), "abc");
+        order.next().next().sort(&mut view);
+        assert_eq!(paths(&view), "bca");
+        assert_eq!(order.next().next().next(), order);
+    }
+
+    #[test]
+    fn paths_are_truncated_to_the_width() {
+        let path = "/home/user/project/src/widgets/list.rs";
+        assert_eq!(Truncation::Middle.apply(path, 40), path);
+        assert_eq!(Truncation::Head.apply(path, 12), "/home/user/…");
+        assert_eq!(Truncation::Tail.apply(path, 12), "…ets/list.rs");
+        assert_eq!(
+            Truncation::Middle.apply(path, 30),
+            "/home/…/src/widgets/list.rs"
+        );
+        assert_eq!(Truncation::Middle.apply(path, 20), "/home/…/list.rs");
+        // without room for the top-level directory the filename is kept
+        assert_eq!(Truncation::Middle.apply(path, 10), "…s/list.rs");
+        assert_eq!(
+            Truncation::Middle.apply("src/a/b/main.rs", 13),
+            "src/…/main.rs"
+        );
+        assert_eq!(Truncation::Tail.apply(path, 0), "");
+    }
+
+    #[test]
+    fn frames_are_drawn_for_changes_at_most_once_per_interval() {
+        let mut clock = FrameClock::new(Duration::from_millis(30));
+        let start = Instant::now();
+        let at = |millis: u64| start + Duration::from_millis(millis);
+        assert!(clock.should_draw(true, at(0)));
+        assert!(!clock.should_draw(true, at(10)));
+        assert!(!clock.should_draw(false, at(40)));
+        assert!(clock.should_draw(true, at(40)));
+        assert!(!clock.should_draw(false, at(200)));
+    }
+
+    #[test]
+    fn views_of_a_burst_are_shown_once() {
+        let start = Instant::now();
+        let at = |millis: u64| start + Duration::from_millis(millis);
+        let latest = |_, latest| latest;
+        let mut views = Coalesced::new(Duration::from_millis(100));
+        assert_eq!(views.take_due(at(0)), None);
+        views.merge(1, at(0), latest);
+        views.merge(2, at(60), latest);
+        assert_eq!(views.take_due(at(90)), None);
+        // a steady stream still gets shown once per window
+        views.merge(3, at(99), latest);
+        assert_eq!(views.take_due(at(100)), Some(3));
+        assert_eq!(views.take_due(at(300)), None);
+        views.merge(4, at(300), latest);
+        assert_eq!(views.take_due(at(399)), None);
+        assert_eq!(views.take_due(at(400)), Some(4));
+
+        let mut views = Coalesced::new(Duration::from_millis(0));
+        views.merge(5, at(0), latest);
+        assert_eq!(views.take_due(at(0)), Some(5));
+
+        // e.g. a page goes into the full view before it
+        let mut views = Coalesced::new(Duration::from_millis(100));
+        views.merge(vec![1], at(0), |_, next| next);
+        views.merge(vec![2], at(10), |mut pending: Vec<_>, next| {
+            pending.extend(next);
+            pending
+        });
+        assert_eq!(views.take_due(at(100)), Some(vec![1, 2]));
+    }
+
+    #[test]
+    fn replay_steps_once_per_interval_while_playing() {
+        let start = Instant::now();
+        let at = |millis: u64| start + Duration::from_millis(millis);
+        let mut replay = Replay::new(start);
+        assert!(!replay.is_due(at(999)));
+        assert!(replay.is_due(at(1000)));
+        replay.stepped(at(1000));
+        replay.faster();
+        assert!(replay.is_due(at(1500)));
+
+        replay.playing = false;
+        assert!(!replay.is_due(at(5000)));
+        (0..10).for_each(|_| replay.slower());
+        assert_eq!(replay.interval, REPLAY_SLOWEST);
+        (0..10).for_each(|_| replay.faster());
+        assert_eq!(replay.interval, REPLAY_FASTEST);
+        assert_eq!(replay.to_string(), "Replay paused, 125ms per version");
+    }
+
+    #[test]
+    fn debounces_step_up_and_back_to_the_global_one() {
+        let ms = Duration::from_millis;
+        assert_eq!(next_debounce(None, ms(100)), Some(ms(250)));
+        assert_eq!(next_debounce(Some(ms(250)), ms(100)), Some(ms(500)));
+        // one set outside of the steps goes to the next longer one
+        assert_eq!(next_debounce(Some(ms(1500)), ms(100)), Some(ms(2000)));
+        assert_eq!(next_debounce(Some(ms(5000)), ms(100)), None);
+        // a step the global debounce waits for anyway would change nothing
+        assert_eq!(next_debounce(None, ms(500)), Some(ms(1000)));
+        assert_eq!(next_debounce(None, ms(5000)), None);
+    }
+
+    #[test]
+    fn file_view_scrolls_by_pages() {
+        let mut view = FileView {
+            height: 4,
+            ..FileView::default()
+        };
+        view.show("1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n");
+        let numbers = |view: &FileView| view.visible_lines().map(|(n, _)| n).collect::<Vec<_>>();
+
+        assert_eq!(numbers(&view), vec![1, 2, 3, 4]);
+        view.page_down();
+        assert_eq!(numbers(&view), vec![5, 6, 7, 8]);
+        view.page_down();
+        view.page_down();
+        assert_eq!(numbers(&view), vec![7, 8, 9, 10]);
+        view.page_up();
+        view.page_up();
+        assert_eq!(numbers(&view), vec![1, 2, 3, 4]);
     }
 }
@@ -1,4 +1,6 @@
+use crate::actions::CommandPalette;
 use crate::ui::UI;
+use std::time::{SystemTime, UNIX_EPOCH};
 use tui::{
     backend::Backend,
     layout::{Constraint, Direction, Layout, Rect},
@@ -6,7 +8,8 @@ use tui::{
     symbols,
     text::{Span, Spans},
     widgets::{
-        Axis, Block, BorderType, Borders, Chart, Dataset, List, ListItem, Paragraph, Tabs, Wrap,
+        Axis, Block, BorderType, Borders, Chart, Clear, Dataset, List, ListItem, ListState,
+        Paragraph, Tabs, Wrap,
     },
     Frame,
 };
@@ -15,13 +18,27 @@ static IS_HIGHLIGHTED: Color = Color::Rgb(235, 203, 139);
 static IS_BORDER: Color = Color::Rgb(129, 161, 193);
 static IS_HEADLINE: Color = Color::Rgb(136, 192, 208);
 static IS_LIGHT_WITE: Color = Color::Rgb(216, 222, 233);
-static _IS_BACKGROUND_TEXT: Color = Color::Rgb(76, 86, 106);
+static IS_BACKGROUND_TEXT: Color = Color::Rgb(76, 86, 106);
 static IS_WARNING: Color = Color::Rgb(208, 135, 112);
+static IS_MARKED: Color = Color::Rgb(180, 142, 173);
+static SPINNER: [&str; 4] = ["|", "/", "-", "\\"];
+/// how long the spinner shows each of its frames
+static SPINNER_FRAME_MILLIS: u128 = 100;
+/// columns of the command palette, it gets narrower on small terminals
+static PALETTE_WIDTH: u16 = 50;
 
 impl UI {
     pub fn draw<B: Backend>(&mut self, f: &mut Frame<B>) {
         let chunks = Layout::default()
-            .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
+            .constraints(
+                [
+                    Constraint::Length(3),
+                    Constraint::Min(0),
+                    Constraint::Length(1),
+                    Constraint::Length(1),
+                ]
+                .as_ref(),
+            )
             .split(f.size());
         let titles = self
             .state
@@ -45,7 +62,51 @@ impl UI {
             .highlight_style(Style::default().fg(IS_HIGHLIGHTED))
             .select(self.state.tabs.index);
         f.render_widget(tabs, chunks[0]);
-        self.draw_tab(f, chunks[1])
+        self.draw_tab(f, chunks[1]);
+        self.draw_status_line(f, chunks[2]);
+        self.draw_metrics_bar(f, chunks[3]);
+        if let Some(palette) = &self.state.palette {
+            draw_palette(f, palette, chunks[1]);
+        }
+    }
+
+    fn draw_status_line<B>(&self, f: &mut Frame<B>, area: Rect)
+    where
+        B: Backend,
+    {
+        let progress;
+        let status = if !self.state.watch_root_available {
+            "Watch root unavailable, recording is paused"
+        } else if let Some(running) = &self.state.progress {
+            progress = format!("{} {}", spinner_frame(), running);
+            progress.as_str()
+        } else if self.state.status.is_empty() && self.state.maintenance_running {
+            "Background maintenance running"
+        } else {
+            self.state.status.as_str()
+        };
+        let status = Paragraph::new(Spans::from(Span::styled(
+            status,
+            Style::default().fg(IS_WARNING),
+        )));
+        f.render_widget(status, area);
+    }
+
+    /// empty until the handle sent the first metrics
+    fn draw_metrics_bar<B>(&self, f: &mut Frame<B>, area: Rect)
+    where
+        B: Backend,
+    {
+        let metrics = self
+            .state
+            .metrics
+            .as_ref()
+            .map_or(String::new(), |metrics| metrics.to_string());
+        let metrics = Paragraph::new(Spans::from(Span::styled(
+            metrics,
+            Style::default().fg(IS_BACKGROUND_TEXT),
+        )));
+        f.render_widget(metrics, area);
     }
 
     fn draw_tab<B>(&mut self, f: &mut Frame<B>, area: Rect)
@@ -68,10 +129,13 @@ impl UI {
         self.draw_legend_pane(f, chunks[2]);
     }
 
-    fn draw_difference_pane<B>(&self, f: &mut Frame<B>, area: Rect)
+    fn draw_difference_pane<B>(&mut self, f: &mut Frame<B>, area: Rect)
     where
         B: Backend,
     {
+        if self.state.file_view.visible {
+            return self.draw_file_pane(f, area);
+        }
         Layout::default()
             .constraints(
                 [
@@ -109,27 +173,47 @@ impl UI {
             .margin(1)
             .split(area);
 
+        // keys at the ends of the history are grayed out
+        let key_color = |enabled: bool| {
+            if enabled {
+                IS_WARNING
+            } else {
+                IS_BACKGROUND_TEXT
+            }
+        };
+        let label_color = |enabled: bool| {
+            if enabled {
+                IS_LIGHT_WITE
+            } else {
+                IS_BACKGROUND_TEXT
+            }
+        };
+        let (can_undo, can_redo) = (self.state.can_undo(), self.state.can_redo());
         let undo_redo = Spans::from(vec![
             Span::styled(
                 "esc ",
-                Style::default().add_modifier(Modifier::BOLD).fg(IS_WARNING),
+                Style::default()
+                    .add_modifier(Modifier::BOLD)
+                    .fg(key_color(can_undo)),
             ),
             Span::styled(
                 "Undo",
                 Style::default()
                     .add_modifier(Modifier::DIM)
-                    .fg(IS_LIGHT_WITE),
+                    .fg(label_color(can_undo)),
             ),
             Span::from(" , "),
             Span::styled(
                 "tab ",
-                Style::default().add_modifier(Modifier::BOLD).fg(IS_WARNING),
+                Style::default()
+                    .add_modifier(Modifier::BOLD)
+                    .fg(key_color(can_redo)),
             ),
             Span::styled(
                 "Redo",
                 Style::default()
                     .add_modifier(Modifier::DIM)
-                    .fg(IS_LIGHT_WITE),
+                    .fg(label_color(can_redo)),
             ),
         ]);
         let modifier = Spans::from(vec![
@@ -143,6 +227,105 @@ impl UI {
                     .add_modifier(Modifier::DIM)
                     .fg(IS_LIGHT_WITE),
             ),
+            Span::from(" , "),
+            Span::styled(
+                "e ",
+                Style::default().add_modifier(Modifier::BOLD).fg(IS_WARNING),
+            ),
+            Span::styled(
+                "Open in editor",
+                Style::default()
+                    .add_modifier(Modifier::DIM)
+                    .fg(IS_LIGHT_WITE),
+            ),
+            Span::from(" , "),
+            Span::styled(
+                "m ",
+                Style::default().add_modifier(Modifier::BOLD).fg(IS_WARNING),
+            ),
+            Span::styled(
+                "Mark range",
+                Style::default()
+                    .add_modifier(Modifier::DIM)
+                    .fg(IS_LIGHT_WITE),
+            ),
+            Span::from(" , "),
+            Span::styled(
+                "f ",
+                Style::default().add_modifier(Modifier::BOLD).fg(IS_WARNING),
+            ),
+            Span::styled(
+                "Full file (pgup/pgdn)",
+                Style::default()
+                    .add_modifier(Modifier::DIM)
+                    .fg(IS_LIGHT_WITE),
+            ),
+            Span::from(" , "),
+            Span::styled(
+                "c/d ",
+                Style::default().add_modifier(Modifier::BOLD).fg(IS_WARNING),
+            ),
+            Span::styled(
+                "Copy version/diff",
+                Style::default()
+                    .add_modifier(Modifier::DIM)
+                    .fg(IS_LIGHT_WITE),
+            ),
+            Span::from(" , "),
+            Span::styled(
+                "p ",
+                Style::default().add_modifier(Modifier::BOLD).fg(IS_WARNING),
+            ),
+            Span::styled(
+                "Purge file",
+                Style::default()
+                    .add_modifier(Modifier::DIM)
+                    .fg(IS_LIGHT_WITE),
+            ),
+            Span::from(" , "),
+            Span::styled(
+                "k ",
+                Style::default().add_modifier(Modifier::BOLD).fg(IS_WARNING),
+            ),
+            Span::styled(
+                "Checkpoint",
+                Style::default()
+                    .add_modifier(Modifier::DIM)
+                    .fg(IS_LIGHT_WITE),
+            ),
+            Span::from(" , "),
+            Span::styled(
+                "o ",
+                Style::default().add_modifier(Modifier::BOLD).fg(IS_WARNING),
+            ),
+            Span::styled(
+                "Sort files",
+                Style::default()
+                    .add_modifier(Modifier::DIM)
+                    .fg(IS_LIGHT_WITE),
+            ),
+            Span::from(" , "),
+            Span::styled(
+                "r ",
+                Style::default().add_modifier(Modifier::BOLD).fg(IS_WARNING),
+            ),
+            Span::styled(
+                "Toggle recording",
+                Style::default()
+                    .add_modifier(Modifier::DIM)
+                    .fg(IS_LIGHT_WITE),
+            ),
+            Span::from(" , "),
+            Span::styled(
+                ": ",
+                Style::default().add_modifier(Modifier::BOLD).fg(IS_WARNING),
+            ),
+            Span::styled(
+                "Commands",
+                Style::default()
+                    .add_modifier(Modifier::DIM)
+                    .fg(IS_LIGHT_WITE),
+            ),
         ]);
         let arrow_up_down = Spans::from(vec![
             Span::styled(
@@ -211,6 +394,37 @@ impl UI {
         f.render_widget(paragraph, area);
     }
 
+    fn draw_file_pane<B>(&mut self, f: &mut Frame<B>, area: Rect)
+    where
+        B: Backend,
+    {
+        let view = &mut self.state.file_view;
+        // without the borders
+        view.height = area.height.saturating_sub(2) as usize;
+        let title = match &view.requested {
+            Some((path, version)) => format!("{} at version {}", path, version),
+            None => String::from("Select a snapshot to see the file"),
+        };
+        let number_width = view.lines.len().to_string().len();
+        let text: Vec<Spans> = view
+            .visible_lines()
+            .map(|(number, line)| {
+                Spans::from(vec![
+                    Span::styled(
+                        format!("{:>width$} ", number, width = number_width),
+                        Style::default().fg(IS_BACKGROUND_TEXT),
+                    ),
+                    Span::styled(line.as_str(), Style::default().fg(IS_LIGHT_WITE)),
+                ])
+            })
+            .collect();
+        let block = Block::default()
+            .border_style(Style::default().fg(IS_BORDER))
+            .borders(Borders::ALL)
+            .title(title);
+        f.render_widget(Paragraph::new(text).block(block), area);
+    }
+
     // pane 1
     fn draw_snapshot_pane<B>(&mut self, f: &mut Frame<B>, area: Rect)
     where
@@ -236,8 +450,25 @@ impl UI {
                     .snapshots
                     .items
                     .iter()
-                    .map(|i| ListItem::new(vec![Spans::from(Span::raw(i.as_str()))]))
+                    .enumerate()
+                    .map(|(position, i)| {
+                        let item = ListItem::new(vec![Spans::from(Span::raw(i.as_str()))]);
+                        match self.state.version_at(position) {
+                            Some(version) if self.state.marked_range.contains(version) => {
+                                item.style(Style::default().fg(IS_MARKED))
+                            }
+                            _ => item,
+                        }
+                    })
                     .collect();
+                let debounce = self.state.debounces.get(&self.state.path_of_selected_file);
+                let title = match (&self.state.replay, debounce) {
+                    (Some(replay), _) => format!("Available Snapshot ({})", replay),
+                    (None, Some(debounce)) => {
+                        format!("Available Snapshot (debounce {}ms)", debounce.as_millis())
+                    }
+                    (None, None) => String::from("Available Snapshot"),
+                };
                 let mut snapshots = List::new(snapshots)
                     .style(Style::default().fg(IS_LIGHT_WITE))
                     .highlight_symbol("►")
@@ -252,7 +483,7 @@ impl UI {
                             .borders(Borders::ALL)
                             .border_style(Style::default().fg(IS_BORDER))
                             .title(Span::styled(
-                                "Available Snapshot",
+                                title.as_str(),
                                 Style::default().fg(IS_HEADLINE),
                             )),
                     );
@@ -260,7 +491,7 @@ impl UI {
                     snapshots = snapshots.block(
                         Block::default()
                             .title(Span::styled(
-                                "Available Snapshot",
+                                title.as_str(),
                                 Style::default().fg(IS_HEADLINE),
                             ))
                             .border_style(Style::default().fg(IS_BORDER))
@@ -278,13 +509,46 @@ impl UI {
                     .direction(Direction::Horizontal)
                     .split(chunks[0]);
             }
-            let filenames_list: Vec<ListItem> = self
-                .state
-                .filenames
-                .items
-                .iter()
-                .map(|i| ListItem::new(vec![Spans::from(Span::raw(i.as_str()))]))
-                .collect();
+            // without the borders and the highlight symbol
+            let width = (chunks[1].width as usize).saturating_sub(3);
+            let truncation = self.config.filename_truncation;
+            let filenames_list: Vec<ListItem> =
+                self.state
+                    .filenames
+                    .items
+                    .iter()
+                    .map(|i| {
+                        let (state, style) = if self.state.is_inaccessible(i) {
+                            (" (unreadable)", Style::default().fg(IS_WARNING))
+                        } else if self.state.is_disabled(i) {
+                            (" (not recorded)", Style::default().fg(IS_BACKGROUND_TEXT))
+                        } else if self.state.is_context_only(i) {
+                            (" (changes only)", Style::default().fg(IS_BACKGROUND_TEXT))
+                        } else if self.state.has_long_lines(i) {
+                            (" (long lines)", Style::default().fg(IS_BACKGROUND_TEXT))
+                        } else {
+                            ("", Style::default())
+                        };
+                        let mut notes = Vec::new();
+                        if self.state.is_pinned(i) {
+                            notes.push(String::from(" (pinned)"));
+                        }
+                        if let Some(debounce) = self.state.debounce_of(i) {
+                            notes.push(format!(" (debounce {}ms)", debounce.as_millis()));
+                        }
+                        // the notes stay visible, the path makes room for them
+                        let taken = std::iter::once(state)
+                            .chain(notes.iter().map(String::as_str))
+                            .map(|note| note.chars().count())
+                            .sum::<usize>();
+                        let path = truncation.apply(i, width.saturating_sub(taken));
+                        let mut name = vec![Span::styled(format!("{}{}", path, state), style)];
+                        name.extend(notes.into_iter().map(|note| {
+                            Span::styled(note, Style::default().fg(IS_BACKGROUND_TEXT))
+                        }));
+                        ListItem::new(vec![Spans::from(name)])
+                    })
+                    .collect();
 
             let mut filenames = List::new(filenames_list)
                 .highlight_style(
@@ -295,13 +559,14 @@ impl UI {
                 .highlight_symbol("►")
                 .style(Style::default().fg(IS_LIGHT_WITE));
 
+            let title = format!("Filename by {}", self.state.file_order);
             if self.state.pane_ptr == 1 {
                 filenames = filenames.block(
                     Block::default()
                         .borders(Borders::ALL)
                         .border_style(Style::default().fg(IS_BORDER))
                         .border_type(BorderType::Thick)
-                        .title(Span::styled("Filename", Style::default().fg(IS_HEADLINE))),
+                        .title(Span::styled(title, Style::default().fg(IS_HEADLINE))),
                 );
             } else {
                 filenames = filenames.block(
@@ -309,11 +574,13 @@ impl UI {
                         .border_style(Style::default().fg(IS_BORDER))
                         .borders(Borders::ALL)
                         .title(Span::styled(
-                            "Filename",
+                            title,
                             Style::default().fg(Color::Rgb(94, 129, 172)),
                         )),
                 );
             }
+            // without the borders
+            self.state.file_rows = chunks[1].height.saturating_sub(2) as usize;
             f.render_stateful_widget(filenames, chunks[1], &mut self.state.filenames.state);
         }
         let x_labels = vec![];
@@ -374,3 +641,64 @@ impl UI {
         f.render_widget(chart, chunks[1]);
     }
 }
+
+///
+/// the actions matching the query of the palette with their keys, in a
+/// box at the top of `area` over the panes
+///
+fn draw_palette<B>(f: &mut Frame<B>, palette: &CommandPalette, area: Rect)
+where
+    B: Backend,
+{
+    let matches = palette.matches();
+    let width = PALETTE_WIDTH.min(area.width);
+    // the matches and the borders
+    let height = (matches.len() as u16 + 2).max(3).min(area.height);
+    let area = Rect::new(area.x + (area.width - width) / 2, area.y, width, height);
+
+    let items: Vec<ListItem> = matches
+        .iter()
+        .map(|binding| {
+            ListItem::new(Spans::from(vec![
+                Span::styled(binding.name, Style::default().fg(IS_LIGHT_WITE)),
+                Span::styled(
+                    binding
+                        .key
+                        .map_or(String::new(), |key| format!("  {}", key)),
+                    Style::default().fg(IS_BACKGROUND_TEXT),
+                ),
+            ]))
+        })
+        .collect();
+    let title = Span::styled(
+        format!(":{}", palette.query),
+        Style::default().fg(IS_HEADLINE),
+    );
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(IS_BORDER))
+                .border_type(BorderType::Thick)
+                .title(title),
+        )
+        .highlight_symbol("►")
+        .highlight_style(
+            Style::default()
+                .add_modifier(Modifier::BOLD)
+                .fg(IS_HIGHLIGHTED),
+        );
+    let mut state = ListState::default();
+    state.select((!matches.is_empty()).then_some(palette.selected));
+
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+/// the spinner turns with the clock, the ui redraws often enough for it
+fn spinner_frame() -> &'static str {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis());
+    SPINNER[(millis / SPINNER_FRAME_MILLIS) as usize % SPINNER.len()]
+}